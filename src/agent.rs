@@ -0,0 +1,490 @@
+//! The agent keeps a file unlocked for the command line, for a limited time.
+//!
+//! It serves requests over a Unix domain socket that only the user can access;
+//! every request and every response is a single line of JSON.
+//! The agent executes the subcommands that read entries itself, and never hands out the key
+//! of the file; still, everyone who can connect to the socket can read the secrets of the file,
+//! so the agent trades some security for convenience.
+
+use crate::{
+    cli::{Answer, CliError, Query},
+    data::{PlFile, Settings},
+};
+use anyhow::{Result, anyhow};
+use std::time::Duration;
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(tag = "request", rename_all = "snake_case")]
+enum Request {
+    // asks the agent to execute a subcommand on the file
+    Query {
+        file: String,
+        query: Query,
+    },
+    // hands over a session that was unlocked elsewhere, e.g. in the UI
+    Register {
+        file: String,
+        session_key: String,
+        key_slot: Option<String>,
+    },
+    Status,
+    Lock,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(tag = "response", rename_all = "snake_case")]
+enum Response {
+    Answer { answer: Answer },
+    // the entry or credential of the query does not exist
+    NotFound { message: String },
+    // the agent holds another file than the one of the query
+    OtherFile { file: String },
+    Status(Status),
+    Done,
+    Failed { message: String },
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Status {
+    pub file: String,
+    pub idle_timeout_secs: u64,
+    pub locks_in_secs: u64,
+}
+
+// Lets the agent answer the query; returns None if no agent holds the file.
+// If the agent fails, this is reported, and None is returned as well,
+// so that the file is opened with the password instead.
+pub fn query(settings: &Settings, file: String, query: &Query) -> Result<Option<Answer>> {
+    let request = Request::Query {
+        file,
+        query: query.clone(),
+    };
+    let e = match send(settings, &request) {
+        Ok(None | Some(Response::OtherFile { .. })) => return Ok(None),
+        Ok(Some(Response::Answer { answer })) => return Ok(Some(answer)),
+        Ok(Some(Response::NotFound { message })) => {
+            return Err(CliError::NotFound(message).into());
+        }
+        Ok(Some(response)) => unexpected(response),
+        Err(e) => e,
+    };
+    eprintln!("{}", t!("_agent_failed %{e}", e = format!("{e:#}")));
+    Ok(None)
+}
+
+// Returns None if no agent is running
+pub fn status(settings: &Settings) -> Result<Option<Status>> {
+    match send(settings, &Request::Status)? {
+        Some(Response::Status(status)) => Ok(Some(status)),
+        Some(response) => Err(unexpected(response)),
+        None => Ok(None),
+    }
+}
+
+// Stops the agent; returns false if no agent was running
+pub fn lock(settings: &Settings) -> Result<bool> {
+    match send(settings, &Request::Lock)? {
+        Some(Response::Done) => Ok(true),
+        Some(response) => Err(unexpected(response)),
+        None => Ok(false),
+    }
+}
+
+// Hands the unlocked file over to the agent, which is started if it is not yet running
+pub fn register(settings: &Settings, pl_file: &mut PlFile) -> Result<()> {
+    // the agent opens the file with the session key
+    pl_file.save_pending_upgrade();
+    let request = Request::Register {
+        file: pl_file.file_path(),
+        session_key: pl_file.session_key()?,
+        key_slot: pl_file.current_key_slot().map(ToString::to_string),
+    };
+    match send(settings, &request)? {
+        Some(Response::Done) => Ok(()),
+        Some(response) => Err(unexpected(response)),
+        None => spawn(settings, &request),
+    }
+}
+
+// Hands the file over again if the agent holds it, e.g. after its data key was replaced,
+// with which the agent cannot read the file anymore
+pub fn reregister(settings: &Settings, pl_file: &mut PlFile) -> Result<()> {
+    match status(settings)? {
+        Some(status) if status.file == pl_file.file_path() => register(settings, pl_file),
+        _ => Ok(()),
+    }
+}
+
+// Runs the agent with the session that is handed over on stdin, see `register`
+pub fn serve_session_from_stdin(settings: &Settings, idle_timeout: Duration) -> Result<()> {
+    let mut line = String::new();
+    std::io::stdin().read_line(&mut line)?;
+    match serde_json::from_str(&line)? {
+        Request::Register {
+            file,
+            session_key,
+            key_slot,
+        } => serve(
+            settings,
+            open_session(&file, &session_key, key_slot)?,
+            idle_timeout,
+        ),
+        request => Err(anyhow!("unexpected request {request:?}")),
+    }
+}
+
+fn open_session(file: &str, session_key: &str, key_slot: Option<String>) -> Result<PlFile> {
+    let mut pl_file = PlFile::read_or_create(std::path::Path::new(file))?;
+    pl_file.set_actionable_with_session_key(session_key, key_slot)?;
+    Ok(pl_file)
+}
+
+fn unexpected(response: Response) -> anyhow::Error {
+    match response {
+        Response::Failed { message } => anyhow!(message),
+        response => anyhow!("unexpected response {response:?}"),
+    }
+}
+
+#[cfg(unix)]
+pub use unix::serve;
+#[cfg(unix)]
+use unix::{send, spawn};
+
+#[cfg(unix)]
+mod unix {
+    use super::{Request, Response, Status, open_session};
+    use crate::{
+        cli::CliError,
+        data::{PlFile, Settings},
+    };
+    use anyhow::{Context, Result, anyhow};
+    use std::{
+        fs::{DirBuilder, Permissions, remove_file, set_permissions},
+        io::{BufRead, BufReader, ErrorKind, Write},
+        os::unix::{
+            fs::{DirBuilderExt, PermissionsExt},
+            net::{UnixListener, UnixStream},
+        },
+        path::Path,
+        process::{Command, Stdio},
+        time::{Duration, Instant},
+    };
+
+    const POLL_INTERVAL: Duration = Duration::from_millis(200);
+    const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+    // Returns None if no agent is running
+    pub(super) fn send(settings: &Settings, request: &Request) -> Result<Option<Response>> {
+        let Ok(stream) = UnixStream::connect(settings.agent_socket()?) else {
+            return Ok(None);
+        };
+        stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+        writeln!(&stream, "{}", serde_json::to_string(request)?)?;
+        let mut line = String::new();
+        BufReader::new(&stream).read_line(&mut line)?;
+        Ok(Some(serde_json::from_str(&line)?))
+    }
+
+    // Starts the agent in the background and hands the request over on its stdin
+    pub(super) fn spawn(settings: &Settings, request: &Request) -> Result<()> {
+        let mut command = Command::new(std::env::current_exe()?);
+        if settings.is_test() {
+            command.arg("--test");
+        }
+        let mut child = command
+            .args(["agent", "start", "--session_from_stdin"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .context("starting the agent")?;
+        let stdin = child.stdin.take().context("stdin of the agent")?;
+        writeln!(&stdin, "{}", serde_json::to_string(request)?)?;
+        // reap the agent when it ends
+        std::thread::spawn(move || child.wait());
+        Ok(())
+    }
+
+    // Runs the agent until it is locked, or until it was not used for too long
+    pub fn serve(settings: &Settings, pl_file: PlFile, idle_timeout: Duration) -> Result<()> {
+        let socket = settings.agent_socket()?;
+        let listener = bind(&socket)?;
+        eprintln!(
+            "{}",
+            t!(
+                "_agent_started %{f} %{m}",
+                f = pl_file.file_path(),
+                m = idle_timeout.as_secs() / 60
+            )
+        );
+        let mut agent = Agent {
+            pl_file,
+            idle_timeout,
+            last_use: Instant::now(),
+        };
+        let result = agent.run(&listener);
+        remove_file(&socket).ok();
+        result
+    }
+
+    fn bind(socket: &Path) -> Result<UnixListener> {
+        let folder = socket.parent().context("folder of the agent")?;
+        DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(folder)?;
+        // the folder might have been created with other permissions
+        set_permissions(folder, Permissions::from_mode(0o700))?;
+        if UnixStream::connect(socket).is_ok() {
+            return Err(anyhow!(t!("_agent_already_running").to_string()));
+        }
+        // the socket of an agent that was killed is left over
+        remove_file(socket).ok();
+        let listener = UnixListener::bind(socket).context("creating the socket of the agent")?;
+        set_permissions(socket, Permissions::from_mode(0o600))?;
+        Ok(listener)
+    }
+
+    struct Agent {
+        pl_file: PlFile,
+        idle_timeout: Duration,
+        last_use: Instant,
+    }
+    impl Agent {
+        fn run(&mut self, listener: &UnixListener) -> Result<()> {
+            listener.set_nonblocking(true)?;
+            loop {
+                match listener.accept() {
+                    Ok((stream, _addr)) => {
+                        // a broken request must not stop the agent
+                        if !self.handle(&stream).unwrap_or(true) {
+                            eprintln!("{}", t!("_agent_locked"));
+                            return Ok(());
+                        }
+                    }
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                        if self.last_use.elapsed() >= self.idle_timeout {
+                            eprintln!("{}", t!("_agent_timed_out"));
+                            return Ok(());
+                        }
+                        std::thread::sleep(POLL_INTERVAL);
+                    }
+                    Err(e) => return Err(e.into()),
+                }
+            }
+        }
+
+        // returns false if the agent is to be stopped
+        fn handle(&mut self, stream: &UnixStream) -> Result<bool> {
+            stream.set_nonblocking(false)?;
+            stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+            let mut line = String::new();
+            BufReader::new(stream).read_line(&mut line)?;
+            let request = serde_json::from_str(&line)?;
+            let go_on = !matches!(request, Request::Lock);
+            let response = self.respond(request).unwrap_or_else(|e| Response::Failed {
+                message: format!("{e:#}"),
+            });
+            writeln!(&*stream, "{}", serde_json::to_string(&response)?)?;
+            Ok(go_on)
+        }
+
+        fn respond(&mut self, request: Request) -> Result<Response> {
+            Ok(match request {
+                Request::Query { file, query } => {
+                    if file != self.pl_file.file_path() {
+                        return Ok(Response::OtherFile {
+                            file: self.pl_file.file_path(),
+                        });
+                    }
+                    // the file might have been changed meanwhile, e.g. in the UI
+                    self.pl_file.reload()?;
+                    self.last_use = Instant::now();
+                    match query.answer(&mut self.pl_file) {
+                        Ok(answer) => Response::Answer { answer },
+                        Err(e) => match e.downcast::<CliError>() {
+                            Ok(CliError::NotFound(message)) => Response::NotFound { message },
+                            Err(e) => return Err(e),
+                        },
+                    }
+                }
+                Request::Register {
+                    file,
+                    session_key,
+                    key_slot,
+                } => {
+                    self.pl_file = open_session(&file, &session_key, key_slot)?;
+                    self.last_use = Instant::now();
+                    Response::Done
+                }
+                Request::Status => Response::Status(Status {
+                    file: self.pl_file.file_path(),
+                    idle_timeout_secs: self.idle_timeout.as_secs(),
+                    locks_in_secs: self
+                        .idle_timeout
+                        .saturating_sub(self.last_use.elapsed())
+                        .as_secs(),
+                }),
+                Request::Lock => Response::Done,
+            })
+        }
+    }
+
+    #[cfg(test)]
+    mod test {
+        use super::{Agent, Request, Response, bind};
+        use crate::{
+            cli::{Answer, Query},
+            data::{Key, PlFile},
+            ui::viz::{VEditBundle, VEditCred},
+        };
+        use std::{
+            fs::{DirBuilder, metadata},
+            os::unix::fs::{DirBuilderExt, PermissionsExt},
+            path::Path,
+            time::{Duration, Instant},
+        };
+
+        fn add_bundle(pl_file: &mut PlFile, name: &str, cred: &str, secret: &str) {
+            let mut v_edit_bundle = VEditBundle::new();
+            v_edit_bundle.key = Key::new(name);
+            v_edit_bundle.v_edit_creds = vec![VEditCred {
+                name: cred.to_string(),
+                secret: secret.to_string(),
+            }];
+            pl_file.save_with_added_bundle(&v_edit_bundle).unwrap();
+        }
+
+        fn unlocked(path: &Path) -> PlFile {
+            let mut pl_file = PlFile::read_or_create(path).unwrap();
+            pl_file.set_actionable("pw", None).unwrap();
+            pl_file
+        }
+
+        #[test]
+        fn test_respond() {
+            let temp_dir = tempfile::tempdir().unwrap();
+            let path = temp_dir.path().join("agent_file");
+            let mut pl_file = unlocked(&path);
+            add_bundle(&mut pl_file, "mail", "alice", "s3cret");
+            let file = pl_file.file_path();
+            let mut agent = Agent {
+                pl_file,
+                idle_timeout: Duration::from_secs(60),
+                last_use: Instant::now(),
+            };
+            let get = |cred: &str| Request::Query {
+                file: file.clone(),
+                query: Query::Get {
+                    entry: "mail".to_string(),
+                    cred: cred.to_string(),
+                },
+            };
+
+            match agent.respond(get("alice")).unwrap() {
+                Response::Answer {
+                    answer: Answer::Secret(secret),
+                } => assert_eq!(secret.secret, "s3cret"),
+                response => panic!("unexpected response {response:?}"),
+            }
+            match agent.respond(get("bob")).unwrap() {
+                Response::NotFound { message } => assert!(message.contains("bob")),
+                response => panic!("unexpected response {response:?}"),
+            }
+
+            // what was changed elsewhere meanwhile is answered, too
+            add_bundle(&mut unlocked(&path), "bank", "alice", "1234");
+            let list = Request::Query {
+                file: file.clone(),
+                query: Query::List,
+            };
+            // as it is sent over the socket
+            let response = serde_json::to_string(&agent.respond(list).unwrap()).unwrap();
+            match serde_json::from_str(&response).unwrap() {
+                Response::Answer {
+                    answer: Answer::Entries(entries),
+                } => assert_eq!(entries.entries.len(), 2),
+                response => panic!("unexpected response {response:?}"),
+            }
+
+            // the command is run by the command line, with the variables from the agent
+            let run = Request::Query {
+                file: file.clone(),
+                query: Query::Run {
+                    env: vec!["PW=mail/alice".to_string()],
+                    command: vec!["env".to_string()],
+                },
+            };
+            // the command is not sent to the agent
+            let request = serde_json::to_string(&run).unwrap();
+            assert!(!request.contains("command"));
+            match agent
+                .respond(serde_json::from_str(&request).unwrap())
+                .unwrap()
+            {
+                Response::Answer {
+                    answer: Answer::Env { vars },
+                } => assert_eq!(vars, [("PW".to_string(), "s3cret".to_string())]),
+                response => panic!("unexpected response {response:?}"),
+            }
+
+            let other_file = Request::Query {
+                file: temp_dir.path().join("other").display().to_string(),
+                query: Query::List,
+            };
+            assert!(matches!(
+                agent.respond(other_file).unwrap(),
+                Response::OtherFile { .. }
+            ));
+
+            match agent.respond(Request::Status).unwrap() {
+                Response::Status(status) => {
+                    assert_eq!(status.file, file);
+                    assert_eq!(status.idle_timeout_secs, 60);
+                }
+                response => panic!("unexpected response {response:?}"),
+            }
+            assert!(matches!(
+                agent.respond(Request::Lock).unwrap(),
+                Response::Done
+            ));
+        }
+
+        #[test]
+        fn test_bind() {
+            let temp_dir = tempfile::tempdir().unwrap();
+            let folder = temp_dir.path().join("agent");
+            // is too open, and must be restricted
+            DirBuilder::new().mode(0o755).create(&folder).unwrap();
+            let socket = folder.join("socket");
+
+            let _listener = bind(&socket).unwrap();
+            assert_eq!(
+                metadata(&folder).unwrap().permissions().mode() & 0o777,
+                0o700
+            );
+            assert_eq!(
+                metadata(&socket).unwrap().permissions().mode() & 0o777,
+                0o600
+            );
+
+            // a second agent is refused
+            assert!(bind(&socket).is_err());
+        }
+    }
+}
+
+#[cfg(not(unix))]
+pub fn serve(_settings: &Settings, _pl_file: PlFile, _idle_timeout: Duration) -> Result<()> {
+    Err(anyhow!(t!("_agent_unsupported").to_string()))
+}
+#[cfg(not(unix))]
+#[allow(clippy::unnecessary_wraps)]
+fn send(_settings: &Settings, _request: &Request) -> Result<Option<Response>> {
+    Ok(None)
+}
+#[cfg(not(unix))]
+fn spawn(_settings: &Settings, _request: &Request) -> Result<()> {
+    Err(anyhow!(t!("_agent_unsupported").to_string()))
+}
@@ -1,7 +1,11 @@
 //! Hides all the clap stuff, exposes the commandline as struct Args.
 
+use crate::data::OnConflict;
 use clap::{Arg, ArgAction, ArgMatches, Command, crate_description};
 
+// maps e.g. https://github.com to the entry "https://github.com"
+const DEFAULT_NAMING_RULE: &str = "{protocol}://{host}";
+
 #[allow(clippy::let_and_return)]
 #[allow(clippy::too_many_lines)]
 fn get_clap_command() -> Command {
@@ -25,6 +29,7 @@ Authors: {author-with-newline}
                 .long("test")
                 .help("test-mode for ProLock; uses different file locations.")
                 .required(false)
+                .global(true)
                 .num_args(0)
                 .action(ArgAction::SetTrue),
         )
@@ -35,7 +40,30 @@ Authors: {author-with-newline}
                 .value_name("file")
                 .help("ProLock file to open; if not given, the last file is opened, or, on first start, a default file is chosen.")
                 .required(false)
+                .global(true)
+                .num_args(1)
+                .action(ArgAction::Set),
+        )
+        .arg(
+            Arg::new("key_file")
+                .long("key_file")
+                .value_name("file")
+                .help("Key file to use, in addition to the password, for opening the ProLock file.")
+                .required(false)
+                .global(true)
+                .num_args(1)
+                .action(ArgAction::Set),
+        )
+        .arg(
+            Arg::new("format")
+                .long("format")
+                .value_name("format")
+                .help("Output format of the command line operations; the JSON structures are versioned and documented.")
+                .required(false)
+                .global(true)
                 .num_args(1)
+                .value_parser(["text", "json"])
+                .default_value("text")
                 .action(ArgAction::Set),
         )
         .arg(
@@ -47,6 +75,32 @@ Authors: {author-with-newline}
                 .number_of_values(1)
                 .action(ArgAction::Set),
         )
+        .arg(
+            Arg::new("list_key_slots")
+                .long("list_key_slots")
+                .help("Print the labels of the key slots of the ProLock file.")
+                .num_args(0)
+                .required(false)
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("add_key_slot")
+                .long("add_key_slot")
+                .value_name("label")
+                .help("Add a key slot with the given label to the ProLock file; the passwords are prompted for.")
+                .required(false)
+                .num_args(1)
+                .action(ArgAction::Set),
+        )
+        .arg(
+            Arg::new("revoke_key_slot")
+                .long("revoke_key_slot")
+                .value_name("label")
+                .help("Remove the key slot with the given label from the ProLock file.")
+                .required(false)
+                .num_args(1)
+                .action(ArgAction::Set),
+        )
         .arg(
             Arg::new("list_known_files")
                 .long("list_known_files")
@@ -54,11 +108,582 @@ Authors: {author-with-newline}
                 .help("Print ProLock's list of known files.")
                 .number_of_values(0)
                 .required(false),
+        )
+        // subcommands work without the UI; the password is prompted for on the terminal
+        .subcommand(Command::new("info").about(
+            "Print what can be read without the password: the header of the file, \
+             and the names, descriptions and timestamps of entries and documents \
+             (unless they are concealed).",
+        ))
+        .subcommand(Command::new("list").about("Print the names of all entries."))
+        .subcommand(
+            Command::new("show")
+                .about("Print an entry; the secrets are only shown with --reveal.")
+                .arg(entry_arg())
+                .arg(
+                    Arg::new("reveal")
+                        .long("reveal")
+                        .help("Show also the secrets.")
+                        .num_args(0)
+                        .action(ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("get")
+                .about("Print the secret of the named credential of an entry.")
+                .arg(entry_arg())
+                .arg(cred_arg()),
+        )
+        .subcommand(
+            Command::new("totp")
+                .about("Print the current one-time code of a TOTP credential, i.e. of a credential whose secret is an otpauth:// URI.")
+                .arg(entry_arg())
+                .arg(cred_arg()),
+        )
+        .subcommand(
+            Command::new("add")
+                .about("Add an entry; the secrets of the credentials are prompted for.")
+                .arg(entry_arg())
+                .arg(description_arg())
+                .arg(
+                    Arg::new("cred")
+                        .long("cred")
+                        .value_name("cred-name")
+                        .help("Name of a credential, e.g. the username; can be given repeatedly.")
+                        .action(ArgAction::Append),
+                ),
+        )
+        .subcommand(
+            Command::new("edit")
+                .about("Change an entry; the secrets of the credentials are prompted for.")
+                .arg(entry_arg())
+                .arg(
+                    Arg::new("rename")
+                        .long("rename")
+                        .value_name("new-name")
+                        .help("New name of the entry.")
+                        .num_args(1),
+                )
+                .arg(description_arg())
+                .arg(
+                    Arg::new("set_cred")
+                        .long("set_cred")
+                        .value_name("cred-name")
+                        .help("Change the secret of a credential, or add the credential; can be given repeatedly.")
+                        .action(ArgAction::Append),
+                )
+                .arg(
+                    Arg::new("remove_cred")
+                        .long("remove_cred")
+                        .value_name("cred-name")
+                        .help("Remove a credential; can be given repeatedly.")
+                        .action(ArgAction::Append),
+                ),
+        )
+        .subcommand(
+            Command::new("rm")
+                .about("Delete an entry.")
+                .arg(entry_arg()),
+        )
+        .subcommand(
+            Command::new("doc")
+                .about("Work with documents.")
+                .subcommand_required(true)
+                .subcommand(Command::new("list").about("Print the names of all documents."))
+                .subcommand(
+                    Command::new("cat")
+                        .about("Print the text of a document.")
+                        .arg(document_arg()),
+                )
+                .subcommand(
+                    Command::new("put")
+                        .about("Store the text from stdin as document; an existing document is replaced.")
+                        .arg(document_arg()),
+                )
+                .subcommand(
+                    Command::new("rm")
+                        .about("Delete a document.")
+                        .arg(document_arg()),
+                ),
+        )
+        .subcommand(
+            Command::new("passwd").about(
+                "Change the password with which the file is opened; a new key file can be chosen as well.",
+            ),
+        )
+        .subcommand(
+            Command::new("run")
+                .about("Run a command with secrets from the file in its environment.")
+                .arg(
+                    Arg::new("env")
+                        .long("env")
+                        .value_name("VAR=entry/cred-name")
+                        .help("Set the environment variable to the secret of the credential; can be given repeatedly.")
+                        .action(ArgAction::Append),
+                )
+                .arg(
+                    Arg::new("command")
+                        .value_name("command")
+                        .help("The command to run, with its arguments, after --.")
+                        .required(true)
+                        .num_args(1..)
+                        .last(true),
+                ),
+        )
+        .subcommand(
+            Command::new("inject")
+                .about("Render a template: placeholders like {{ prolock://<entry>/<cred-name> }} are replaced with secrets, and {{ prolock-doc://<document> }} with the text of documents.")
+                .arg(
+                    Arg::new("input")
+                        .short('i')
+                        .long("input")
+                        .value_name("template")
+                        .help("The template file.")
+                        .required(true)
+                        .num_args(1),
+                )
+                .arg(
+                    Arg::new("output")
+                        .short('o')
+                        .long("output")
+                        .value_name("file")
+                        .help("The file to write, readable only for the user; if not given, the result is printed.")
+                        .num_args(1),
+                ),
+        )
+        .subcommand(
+            Command::new("git-credential")
+                .about("Act as credential helper for git, e.g. with `git config credential.helper '!prolock git-credential'`.")
+                .arg(
+                    Arg::new("operation")
+                        .value_name("operation")
+                        .help("get, store or erase; is given by git.")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("naming_rule")
+                        .long("naming_rule")
+                        .value_name("rule")
+                        .help("Name of the entry for a URL; {protocol}, {host} and {path} are replaced. Entries can also be found by a line \"url: <url>\" in their description.")
+                        .num_args(1)
+                        .default_value(DEFAULT_NAMING_RULE),
+                ),
+        )
+        .subcommand(
+            Command::new("import")
+                .about("Import entries and documents from other sources; everything is saved at once.")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("csv")
+                        .about("Import a CSV file with a header line; without column options, the columns are assigned by their headers, as far as they are recognized.")
+                        .arg(import_file_arg())
+                        .arg(
+                            Arg::new("key")
+                                .long("key")
+                                .value_name("column")
+                                .help("Column with the names of the entries; columns are given by header or by number, starting with 1.")
+                                .num_args(1),
+                        )
+                        .arg(
+                            Arg::new("description")
+                                .long("description")
+                                .value_name("column")
+                                .help("Column that goes into the description; can be given repeatedly.")
+                                .action(ArgAction::Append),
+                        )
+                        .arg(
+                            Arg::new("cred")
+                                .long("cred")
+                                .value_name("name-column:secret-column")
+                                .help("Columns with name and secret of a credential; a fixed name is given as =name:secret-column; can be given repeatedly.")
+                                .action(ArgAction::Append),
+                        )
+                        .arg(
+                            Arg::new("document")
+                                .long("document")
+                                .value_name("column")
+                                .help("Column with the text of a document that is named like the entry.")
+                                .num_args(1),
+                        )
+                        .arg(on_conflict_arg())
+                        .arg(dry_run_arg()),
+                )
+                .subcommand(
+                    Command::new("keepass")
+                        .about("Import a KeePass 2 database (.kdbx, format version 4) or an unencrypted XML export of it; the master password of a database is prompted for.")
+                        .arg(import_file_arg())
+                        .arg(
+                            Arg::new("keepass_key_file")
+                                .long("keepass_key_file")
+                                .value_name("file")
+                                .help("Key file of the KeePass database, if it uses one.")
+                                .num_args(1),
+                        )
+                        .arg(on_conflict_arg())
+                        .arg(dry_run_arg()),
+                )
+                .subcommand(
+                    Command::new("json")
+                        .about("Import the unencrypted JSON export of Bitwarden, or a file written with `prolock export json`.")
+                        .arg(import_file_arg())
+                        .arg(on_conflict_arg())
+                        .arg(dry_run_arg()),
+                )
+                .subcommand(
+                    Command::new("package")
+                        .about("Import a package that was written with `prolock export package` for the public key of this file.")
+                        .arg(import_file_arg())
+                        .arg(on_conflict_arg())
+                        .arg(dry_run_arg()),
+                ),
+        )
+        .subcommand(
+            Command::new("export")
+                .about("Export the file; the password is asked for once more.")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("json")
+                        .about("Write all entries and documents, with their secrets in plain text, as JSON; the output can be imported again.")
+                        .arg(
+                            Arg::new("file")
+                                .value_name("file")
+                                .help("The file to write.")
+                                .required(true),
+                        ),
+                )
+                .subcommand(
+                    Command::new("vault")
+                        .about("Create a new file with the selected entries and documents; its password and key file are prompted for.")
+                        .arg(
+                            Arg::new("file")
+                                .value_name("file")
+                                .help("The new file; must not exist yet.")
+                                .required(true),
+                        )
+                        .arg(
+                            Arg::new("entry")
+                                .long("entry")
+                                .value_name("entry")
+                                .help("Name of an entry to export; can be given repeatedly.")
+                                .action(ArgAction::Append),
+                        )
+                        .arg(
+                            Arg::new("document")
+                                .long("document")
+                                .value_name("document")
+                                .help("Name of a document to export; can be given repeatedly.")
+                                .action(ArgAction::Append),
+                        )
+                        .arg(
+                            Arg::new("register")
+                                .long("register")
+                                .help("Add the new file to the known files.")
+                                .num_args(0)
+                                .action(ArgAction::SetTrue),
+                        ),
+                )
+                .subcommand(
+                    Command::new("package")
+                        .about("Write the selected entries and documents into a package that only the given recipients can open (age format); no password is needed to open it.")
+                        .arg(
+                            Arg::new("file")
+                                .value_name("file")
+                                .help("The package file; must not exist yet.")
+                                .required(true),
+                        )
+                        .arg(
+                            Arg::new("recipient")
+                                .long("recipient")
+                                .value_name("public key")
+                                .help("Public key (age1…) of a recipient, as printed by `prolock identity show`; can be given repeatedly.")
+                                .required(true)
+                                .action(ArgAction::Append),
+                        )
+                        .arg(
+                            Arg::new("entry")
+                                .long("entry")
+                                .value_name("entry")
+                                .help("Name of an entry to export; can be given repeatedly.")
+                                .action(ArgAction::Append),
+                        )
+                        .arg(
+                            Arg::new("document")
+                                .long("document")
+                                .value_name("document")
+                                .help("Name of a document to export; can be given repeatedly.")
+                                .action(ArgAction::Append),
+                        ),
+                ),
+        )
+        .subcommand(
+            Command::new("identity")
+                .about("The key pair of this file for exchanging packages with others; the secret key is kept in the file.")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("show").about("Print the public key, which others need to export packages for you."),
+                )
+                .subcommand(
+                    Command::new("generate").about("Create the key pair, if the file has none yet, and print the public key."),
+                ),
+        )
+        .subcommand(
+            Command::new("merge")
+                .about("Compare another file, e.g. a copy from another machine, and take over entries and documents from it; its password is prompted for. Without --entry and --document, what exists only in the other file and what was changed there more recently is taken over.")
+                .arg(
+                    Arg::new("file")
+                        .value_name("file")
+                        .help("The other file.")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("other_key_file")
+                        .long("other_key_file")
+                        .value_name("file")
+                        .help("Key file of the other file, if it uses one.")
+                        .num_args(1),
+                )
+                .arg(
+                    Arg::new("entry")
+                        .long("entry")
+                        .value_name("entry")
+                        .help("Name of an entry to take over; can be given repeatedly.")
+                        .action(ArgAction::Append),
+                )
+                .arg(
+                    Arg::new("document")
+                        .long("document")
+                        .value_name("document")
+                        .help("Name of a document to take over; can be given repeatedly.")
+                        .action(ArgAction::Append),
+                )
+                .arg(dry_run_arg().help("Only print the comparison, and what would be taken over.")),
+        )
+        .subcommand(
+            Command::new("temp-file")
+                .about("Deal with the temp file that an interrupted save left over; until then, the file cannot be changed.")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("recover").about("Replace the file with the temp file, if the temp file is newer and can be decrypted."),
+                )
+                .subcommand(Command::new("discard").about("Delete the temp file.")),
+        )
+        .subcommand(
+            Command::new("agent")
+                .about("Keep the file unlocked for the command line, for a limited time.")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("start")
+                        .about("Unlock the file and keep it unlocked until the agent is locked or was not used for a while; runs in the foreground.")
+                        .arg(
+                            Arg::new("idle_timeout")
+                                .long("idle_timeout")
+                                .value_name("minutes")
+                                .help("Lock the agent after it was not used for so many minutes.")
+                                .num_args(1)
+                                .value_parser(clap::value_parser!(u64).range(1..))
+                                .default_value("15"),
+                        )
+                        .arg(
+                            // is used by the UI to hand over its session
+                            Arg::new("session_from_stdin")
+                                .long("session_from_stdin")
+                                .hide(true)
+                                .num_args(0)
+                                .action(ArgAction::SetTrue),
+                        ),
+                )
+                .subcommand(
+                    Command::new("status").about("Print which file the agent keeps unlocked."),
+                )
+                .subcommand(Command::new("lock").about("Stop the agent.")),
         );
 
     command
 }
 
+fn entry_arg() -> Arg {
+    Arg::new("entry")
+        .value_name("entry")
+        .help("Name of the entry.")
+        .required(true)
+}
+fn document_arg() -> Arg {
+    Arg::new("document")
+        .value_name("document")
+        .help("Name of the document.")
+        .required(true)
+}
+fn import_file_arg() -> Arg {
+    Arg::new("file")
+        .value_name("file")
+        .help("The file to import.")
+        .required(true)
+}
+fn on_conflict_arg() -> Arg {
+    Arg::new("on_conflict")
+        .long("on_conflict")
+        .value_name("action")
+        .help("What happens with an entry or document whose name exists already (case-insensitively): it is skipped, renamed, or merged into the existing one.")
+        .num_args(1)
+        .value_parser(["skip", "rename", "merge"])
+        .default_value("skip")
+}
+fn dry_run_arg() -> Arg {
+    Arg::new("dry_run")
+        .long("dry_run")
+        .help("Only print what would be imported; the password is still needed to find conflicts.")
+        .num_args(0)
+        .action(ArgAction::SetTrue)
+}
+fn cred_arg() -> Arg {
+    Arg::new("cred")
+        .value_name("cred-name")
+        .help("Name of the credential, e.g. the username.")
+        .required(true)
+}
+fn description_arg() -> Arg {
+    Arg::new("description")
+        .long("description")
+        .value_name("text")
+        .help("Description of the entry.")
+        .num_args(1)
+}
+
+// The operations of a git credential helper; unknown ones are to be ignored
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub(crate) enum GitCredentialOp {
+    Get,
+    Store,
+    Erase,
+    Unknown,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Format {
+    Text,
+    Json,
+}
+
+// A subcommand for working with the file without the UI
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum CliCommand {
+    Info,
+    List,
+    Show {
+        entry: String,
+        reveal: bool,
+    },
+    Get {
+        entry: String,
+        cred: String,
+    },
+    Totp {
+        entry: String,
+        cred: String,
+    },
+    Add {
+        entry: String,
+        description: Option<String>,
+        creds: Vec<String>,
+    },
+    Edit {
+        entry: String,
+        rename: Option<String>,
+        description: Option<String>,
+        set_creds: Vec<String>,
+        remove_creds: Vec<String>,
+    },
+    Rm {
+        entry: String,
+    },
+    DocList,
+    DocCat {
+        document: String,
+    },
+    DocPut {
+        document: String,
+    },
+    DocRm {
+        document: String,
+    },
+    Passwd,
+    AgentStart {
+        idle_timeout_minutes: u64,
+        session_from_stdin: bool,
+    },
+    AgentStatus,
+    AgentLock,
+    TempFileRecover,
+    TempFileDiscard,
+    GitCredential {
+        operation: GitCredentialOp,
+        naming_rule: String,
+    },
+    Run {
+        env: Vec<String>,
+        command: Vec<String>,
+    },
+    Inject {
+        input: String,
+        output: Option<String>,
+    },
+    ImportCsv {
+        file: String,
+        columns: CsvColumns,
+        on_conflict: OnConflict,
+        dry_run: bool,
+    },
+    ImportKeePass {
+        file: String,
+        key_file: Option<String>,
+        on_conflict: OnConflict,
+        dry_run: bool,
+    },
+    ImportJson {
+        file: String,
+        on_conflict: OnConflict,
+        dry_run: bool,
+    },
+    ImportPackage {
+        file: String,
+        on_conflict: OnConflict,
+        dry_run: bool,
+    },
+    ExportJson {
+        file: String,
+    },
+    ExportVault {
+        file: String,
+        entries: Vec<String>,
+        documents: Vec<String>,
+        register: bool,
+    },
+    ExportPackage {
+        file: String,
+        recipients: Vec<String>,
+        entries: Vec<String>,
+        documents: Vec<String>,
+    },
+    IdentityShow,
+    IdentityGenerate,
+    Merge {
+        file: String,
+        key_file: Option<String>,
+        entries: Vec<String>,
+        documents: Vec<String>,
+        dry_run: bool,
+    },
+}
+
+// The columns of a CSV import, as given on the command line
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct CsvColumns {
+    pub key: Option<String>,
+    pub description: Vec<String>,
+    pub creds: Vec<String>,
+    pub document: Option<String>,
+}
+
 #[derive(Default, Eq, PartialEq, Debug)]
 pub(crate) struct Args {
     arg_matches: ArgMatches,
@@ -80,17 +705,238 @@ impl Args {
     pub fn file(&self) -> Option<&String> {
         self.arg_matches.get_one::<String>("use_file")
     }
+    pub fn key_file(&self) -> Option<&String> {
+        self.arg_matches.get_one::<String>("key_file")
+    }
+    pub fn format(&self) -> Format {
+        match self
+            .arg_matches
+            .get_one::<String>("format")
+            .map(String::as_str)
+        {
+            Some("json") => Format::Json,
+            _ => Format::Text,
+        }
+    }
     pub fn list_known_files(&self) -> bool {
         self.arg_matches.get_flag("list_known_files")
     }
     pub fn forget_file(&self) -> Option<&String> {
         self.arg_matches.get_one::<String>("forget_file")
     }
+    pub fn list_key_slots(&self) -> bool {
+        self.arg_matches.get_flag("list_key_slots")
+    }
+    pub fn add_key_slot(&self) -> Option<&String> {
+        self.arg_matches.get_one::<String>("add_key_slot")
+    }
+    pub fn revoke_key_slot(&self) -> Option<&String> {
+        self.arg_matches.get_one::<String>("revoke_key_slot")
+    }
+    pub fn cli_command(&self) -> Option<CliCommand> {
+        let one = |matches: &ArgMatches, id: &str| matches.get_one::<String>(id).cloned();
+        let all = |matches: &ArgMatches, id: &str| {
+            matches
+                .get_many::<String>(id)
+                .map(|values| values.cloned().collect())
+                .unwrap_or_default()
+        };
+        let (name, matches) = self.arg_matches.subcommand()?;
+        let entry = || one(matches, "entry").unwrap_or_default();
+        Some(match name {
+            "info" => CliCommand::Info,
+            "list" => CliCommand::List,
+            "show" => CliCommand::Show {
+                entry: entry(),
+                reveal: matches.get_flag("reveal"),
+            },
+            "get" => CliCommand::Get {
+                entry: entry(),
+                cred: one(matches, "cred").unwrap_or_default(),
+            },
+            "totp" => CliCommand::Totp {
+                entry: entry(),
+                cred: one(matches, "cred").unwrap_or_default(),
+            },
+            "add" => CliCommand::Add {
+                entry: entry(),
+                description: one(matches, "description"),
+                creds: all(matches, "cred"),
+            },
+            "edit" => CliCommand::Edit {
+                entry: entry(),
+                rename: one(matches, "rename"),
+                description: one(matches, "description"),
+                set_creds: all(matches, "set_cred"),
+                remove_creds: all(matches, "remove_cred"),
+            },
+            "rm" => CliCommand::Rm { entry: entry() },
+            "doc" => {
+                let (doc_name, doc_matches) = matches.subcommand()?;
+                doc_command(doc_name, doc_matches)?
+            }
+            "passwd" => CliCommand::Passwd,
+            "run" => CliCommand::Run {
+                env: all(matches, "env"),
+                command: all(matches, "command"),
+            },
+            "inject" => CliCommand::Inject {
+                input: one(matches, "input").unwrap_or_default(),
+                output: one(matches, "output"),
+            },
+            "git-credential" => CliCommand::GitCredential {
+                operation: match one(matches, "operation").as_deref() {
+                    Some("get") => GitCredentialOp::Get,
+                    Some("store") => GitCredentialOp::Store,
+                    Some("erase") => GitCredentialOp::Erase,
+                    _ => GitCredentialOp::Unknown,
+                },
+                naming_rule: one(matches, "naming_rule").unwrap_or_default(),
+            },
+            "import" => {
+                let (import_name, import_matches) = matches.subcommand()?;
+                import_command(import_name, import_matches)?
+            }
+            "export" => {
+                let (export_name, export_matches) = matches.subcommand()?;
+                export_command(export_name, export_matches)?
+            }
+            "identity" => match matches.subcommand()? {
+                ("show", _) => CliCommand::IdentityShow,
+                ("generate", _) => CliCommand::IdentityGenerate,
+                _ => return None,
+            },
+            "merge" => CliCommand::Merge {
+                file: one(matches, "file").unwrap_or_default(),
+                key_file: one(matches, "other_key_file"),
+                entries: all(matches, "entry"),
+                documents: all(matches, "document"),
+                dry_run: matches.get_flag("dry_run"),
+            },
+            "agent" => match matches.subcommand()? {
+                ("start", start_matches) => CliCommand::AgentStart {
+                    idle_timeout_minutes: start_matches
+                        .get_one::<u64>("idle_timeout")
+                        .copied()
+                        .unwrap_or_default(),
+                    session_from_stdin: start_matches.get_flag("session_from_stdin"),
+                },
+                ("status", _) => CliCommand::AgentStatus,
+                ("lock", _) => CliCommand::AgentLock,
+                _ => return None,
+            },
+            "temp-file" => match matches.subcommand()? {
+                ("recover", _) => CliCommand::TempFileRecover,
+                ("discard", _) => CliCommand::TempFileDiscard,
+                _ => return None,
+            },
+            _ => return None,
+        })
+    }
+}
+
+fn doc_command(doc_name: &str, doc_matches: &ArgMatches) -> Option<CliCommand> {
+    if doc_name == "list" {
+        // has no document argument
+        return Some(CliCommand::DocList);
+    }
+    let document = doc_matches
+        .get_one::<String>("document")
+        .cloned()
+        .unwrap_or_default();
+    Some(match doc_name {
+        "cat" => CliCommand::DocCat { document },
+        "put" => CliCommand::DocPut { document },
+        "rm" => CliCommand::DocRm { document },
+        _ => return None,
+    })
+}
+
+fn export_command(export_name: &str, export_matches: &ArgMatches) -> Option<CliCommand> {
+    let one = |id: &str| {
+        export_matches
+            .get_one::<String>(id)
+            .cloned()
+            .unwrap_or_default()
+    };
+    let all = |id: &str| {
+        export_matches
+            .get_many::<String>(id)
+            .map(|values| values.cloned().collect())
+            .unwrap_or_default()
+    };
+    Some(match export_name {
+        "json" => CliCommand::ExportJson { file: one("file") },
+        "vault" => CliCommand::ExportVault {
+            file: one("file"),
+            entries: all("entry"),
+            documents: all("document"),
+            register: export_matches.get_flag("register"),
+        },
+        "package" => CliCommand::ExportPackage {
+            file: one("file"),
+            recipients: all("recipient"),
+            entries: all("entry"),
+            documents: all("document"),
+        },
+        _ => return None,
+    })
+}
+
+fn import_command(import_name: &str, import_matches: &ArgMatches) -> Option<CliCommand> {
+    let one = |id: &str| import_matches.get_one::<String>(id).cloned();
+    let all = |id: &str| {
+        import_matches
+            .get_many::<String>(id)
+            .map(|values| values.cloned().collect())
+            .unwrap_or_default()
+    };
+    let file = one("file").unwrap_or_default();
+    let on_conflict = match one("on_conflict").as_deref() {
+        Some("rename") => OnConflict::Rename,
+        Some("merge") => OnConflict::Merge,
+        _ => OnConflict::Skip,
+    };
+    let dry_run = import_matches.get_flag("dry_run");
+    Some(match import_name {
+        "csv" => CliCommand::ImportCsv {
+            file,
+            columns: CsvColumns {
+                key: one("key"),
+                description: all("description"),
+                creds: all("cred"),
+                document: one("document"),
+            },
+            on_conflict,
+            dry_run,
+        },
+        "keepass" => CliCommand::ImportKeePass {
+            file,
+            key_file: one("keepass_key_file"),
+            on_conflict,
+            dry_run,
+        },
+        "json" => CliCommand::ImportJson {
+            file,
+            on_conflict,
+            dry_run,
+        },
+        "package" => CliCommand::ImportPackage {
+            file,
+            on_conflict,
+            dry_run,
+        },
+        _ => return None,
+    })
 }
 
 #[cfg(test)]
 mod test {
-    use super::{Args, get_clap_command};
+    use super::{
+        Args, CliCommand, CsvColumns, DEFAULT_NAMING_RULE, Format, GitCredentialOp,
+        get_clap_command,
+    };
+    use crate::data::OnConflict;
 
     fn args_from<I, T>(itr: I) -> Args
     where
@@ -118,9 +964,288 @@ mod test {
             let args = args_from(vec!["prolock", "--use_file", "my_file2"]);
             assert_eq!(args.file().unwrap().as_str(), "my_file2");
         }
+        {
+            let args = args_from(vec!["prolock", "--key_file", "my_key_file"]);
+            assert_eq!(args.key_file().unwrap().as_str(), "my_key_file");
+        }
+        {
+            let args = args_from(vec!["prolock", "--add_key_slot", "bob"]);
+            assert_eq!(args.add_key_slot().unwrap().as_str(), "bob");
+            assert!(!args.list_key_slots());
+        }
         {
             let args = args_from(vec!["prolock", "--list_known_files"]);
             assert!(args.list_known_files());
+            assert!(args.cli_command().is_none());
+        }
+    }
+
+    #[test]
+    fn test_subcommands() {
+        {
+            let args = args_from(vec!["prolock", "get", "mail", "user", "-f", "my_file3"]);
+            assert_eq!(args.file().unwrap().as_str(), "my_file3");
+            assert_eq!(
+                args.cli_command(),
+                Some(CliCommand::Get {
+                    entry: "mail".to_string(),
+                    cred: "user".to_string()
+                })
+            );
+        }
+        {
+            let args = args_from(vec!["prolock", "totp", "github", "alice"]);
+            assert_eq!(
+                args.cli_command(),
+                Some(CliCommand::Totp {
+                    entry: "github".to_string(),
+                    cred: "alice".to_string()
+                })
+            );
+        }
+        {
+            let args = args_from(vec![
+                "prolock", "add", "mail", "--cred", "user", "--cred", "admin",
+            ]);
+            assert_eq!(
+                args.cli_command(),
+                Some(CliCommand::Add {
+                    entry: "mail".to_string(),
+                    description: None,
+                    creds: vec!["user".to_string(), "admin".to_string()]
+                })
+            );
+        }
+        {
+            let args = args_from(vec!["prolock", "info", "--format", "json"]);
+            assert_eq!(args.format(), Format::Json);
+            assert_eq!(args.cli_command(), Some(CliCommand::Info));
+        }
+        {
+            let args = args_from(vec!["prolock", "doc", "list"]);
+            assert_eq!(args.cli_command(), Some(CliCommand::DocList));
+        }
+        {
+            let args = args_from(vec!["prolock", "doc", "put", "notes"]);
+            assert_eq!(args.format(), Format::Text);
+            assert_eq!(
+                args.cli_command(),
+                Some(CliCommand::DocPut {
+                    document: "notes".to_string()
+                })
+            );
+        }
+        {
+            let args = args_from(vec!["prolock", "agent", "start", "--idle_timeout", "5"]);
+            assert_eq!(
+                args.cli_command(),
+                Some(CliCommand::AgentStart {
+                    idle_timeout_minutes: 5,
+                    session_from_stdin: false
+                })
+            );
+        }
+        {
+            let args = args_from(vec![
+                "prolock",
+                "run",
+                "--env",
+                "DB_PASS=prod-db/admin",
+                "--",
+                "./deploy.sh",
+                "--env",
+                "prod",
+            ]);
+            assert_eq!(
+                args.cli_command(),
+                Some(CliCommand::Run {
+                    env: vec!["DB_PASS=prod-db/admin".to_string()],
+                    command: vec![
+                        "./deploy.sh".to_string(),
+                        "--env".to_string(),
+                        "prod".to_string()
+                    ]
+                })
+            );
+        }
+        {
+            let args = args_from(vec!["prolock", "temp-file", "discard"]);
+            assert_eq!(args.cli_command(), Some(CliCommand::TempFileDiscard));
+        }
+        {
+            let args = args_from(vec!["prolock", "git-credential", "capability"]);
+            assert_eq!(
+                args.cli_command(),
+                Some(CliCommand::GitCredential {
+                    operation: GitCredentialOp::Unknown,
+                    naming_rule: DEFAULT_NAMING_RULE.to_string()
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn test_import_subcommands() {
+        {
+            let args = args_from(vec![
+                "prolock",
+                "import",
+                "csv",
+                "export.csv",
+                "--cred",
+                "=pin:PIN",
+                "--on_conflict",
+                "rename",
+            ]);
+            assert_eq!(
+                args.cli_command(),
+                Some(CliCommand::ImportCsv {
+                    file: "export.csv".to_string(),
+                    columns: CsvColumns {
+                        creds: vec!["=pin:PIN".to_string()],
+                        ..CsvColumns::default()
+                    },
+                    on_conflict: OnConflict::Rename,
+                    dry_run: false
+                })
+            );
+        }
+        {
+            let args = args_from(vec![
+                "prolock",
+                "import",
+                "keepass",
+                "team.kdbx",
+                "--keepass_key_file",
+                "team.keyx",
+                "--dry_run",
+            ]);
+            assert_eq!(
+                args.cli_command(),
+                Some(CliCommand::ImportKeePass {
+                    file: "team.kdbx".to_string(),
+                    key_file: Some("team.keyx".to_string()),
+                    on_conflict: OnConflict::Skip,
+                    dry_run: true
+                })
+            );
+        }
+        {
+            let args = args_from(vec!["prolock", "import", "json", "bitwarden.json"]);
+            assert_eq!(
+                args.cli_command(),
+                Some(CliCommand::ImportJson {
+                    file: "bitwarden.json".to_string(),
+                    on_conflict: OnConflict::Skip,
+                    dry_run: false
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn test_export_and_merge_subcommands() {
+        {
+            let args = args_from(vec!["prolock", "export", "json", "all.json"]);
+            assert_eq!(
+                args.cli_command(),
+                Some(CliCommand::ExportJson {
+                    file: "all.json".to_string(),
+                })
+            );
+        }
+        {
+            let args = args_from(vec![
+                "prolock",
+                "export",
+                "vault",
+                "team.prolock",
+                "--entry",
+                "prod-db",
+                "--entry",
+                "mail",
+                "--document",
+                "runbook",
+                "--register",
+            ]);
+            assert_eq!(
+                args.cli_command(),
+                Some(CliCommand::ExportVault {
+                    file: "team.prolock".to_string(),
+                    entries: vec!["prod-db".to_string(), "mail".to_string()],
+                    documents: vec!["runbook".to_string()],
+                    register: true,
+                })
+            );
+        }
+        {
+            let args = args_from(vec![
+                "prolock",
+                "merge",
+                "laptop.prolock",
+                "--other_key_file",
+                "laptop.key",
+                "--entry",
+                "mail",
+                "--dry_run",
+            ]);
+            assert_eq!(
+                args.cli_command(),
+                Some(CliCommand::Merge {
+                    file: "laptop.prolock".to_string(),
+                    key_file: Some("laptop.key".to_string()),
+                    entries: vec!["mail".to_string()],
+                    documents: vec![],
+                    dry_run: true,
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn test_package_subcommands() {
+        {
+            let args = args_from(vec!["prolock", "identity", "generate"]);
+            assert_eq!(args.cli_command(), Some(CliCommand::IdentityGenerate));
+        }
+        {
+            let args = args_from(vec![
+                "prolock",
+                "export",
+                "package",
+                "for_bob.age",
+                "--recipient",
+                "age1abc",
+                "--entry",
+                "prod-db",
+            ]);
+            assert_eq!(
+                args.cli_command(),
+                Some(CliCommand::ExportPackage {
+                    file: "for_bob.age".to_string(),
+                    recipients: vec!["age1abc".to_string()],
+                    entries: vec!["prod-db".to_string()],
+                    documents: vec![],
+                })
+            );
+        }
+        {
+            let args = args_from(vec![
+                "prolock",
+                "import",
+                "package",
+                "from_alice.age",
+                "--on_conflict",
+                "rename",
+            ]);
+            assert_eq!(
+                args.cli_command(),
+                Some(CliCommand::ImportPackage {
+                    file: "from_alice.age".to_string(),
+                    on_conflict: OnConflict::Rename,
+                    dry_run: false,
+                })
+            );
         }
     }
 
@@ -139,4 +1264,36 @@ mod test {
     fn test_bad_command_line3() {
         args_from(vec!["prolock", "-f", "my_file0", "--use_file", "my_file1"]);
     }
+    #[test]
+    #[should_panic = "called `Result::unwrap()` on an `Err` value"]
+    fn test_bad_command_line4() {
+        args_from(vec!["prolock", "doc"]);
+    }
+    #[test]
+    #[should_panic = "called `Result::unwrap()` on an `Err` value"]
+    fn test_bad_command_line5() {
+        args_from(vec!["prolock", "list", "--format", "yaml"]);
+    }
+    #[test]
+    #[should_panic = "called `Result::unwrap()` on an `Err` value"]
+    fn test_bad_command_line6() {
+        args_from(vec!["prolock", "agent", "start", "--idle_timeout", "0"]);
+    }
+    #[test]
+    #[should_panic = "called `Result::unwrap()` on an `Err` value"]
+    fn test_bad_command_line7() {
+        args_from(vec!["prolock", "run", "--env", "A=b/c"]);
+    }
+    #[test]
+    #[should_panic = "called `Result::unwrap()` on an `Err` value"]
+    fn test_bad_command_line8() {
+        args_from(vec![
+            "prolock",
+            "import",
+            "csv",
+            "a.csv",
+            "--on_conflict",
+            "ask",
+        ]);
+    }
 }
@@ -0,0 +1,701 @@
+//! Commandline operations that don't need the UI.
+
+mod env;
+mod export;
+mod git_credential;
+mod import;
+mod inject;
+mod merge;
+mod output;
+mod query;
+
+pub(crate) use query::{Answer, Query};
+
+use crate::{
+    agent,
+    args::{CliCommand, Format},
+    data::{
+        Bundle, ConcurrentUpdate, Key, MergeConflict, PlFile, Settings, Totp, WrongPassword,
+        open_package,
+    },
+    ui::viz::{VEditBundle, VEditCred, VEditDocument},
+};
+use anyhow::{Context, Result, anyhow};
+use output::{
+    AgentLocked, AgentStatus, DocumentDetails, DocumentList, DocumentText, ErrorInfo,
+    ExportedVault, Failure, Identity, Info, KeySlots, KnownFile, KnownFiles, Saved, TotpCode,
+    print,
+};
+use rpassword::prompt_password;
+use std::{
+    io::{Read, stdin},
+    path::Path,
+    time::Duration,
+};
+
+// Exit codes, in addition to 0 for success, 1 for other errors, and 2 for bad command lines
+pub const EXIT_WRONG_PASSWORD: u8 = 3;
+pub const EXIT_NOT_FOUND: u8 = 4;
+pub const EXIT_CONCURRENT_UPDATE: u8 = 5;
+
+// Errors that are distinguished by the exit code, in addition to those of the data layer
+#[derive(Debug)]
+pub(crate) enum CliError {
+    NotFound(String),
+}
+impl std::fmt::Display for CliError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotFound(s) => write!(f, "{s}"),
+        }
+    }
+}
+impl std::error::Error for CliError {}
+
+// the kind of the error, as it is used in the JSON output, and the exit code
+fn classify(e: &anyhow::Error) -> (&'static str, u8) {
+    match e.downcast_ref::<CliError>() {
+        Some(CliError::NotFound(_)) => ("not_found", EXIT_NOT_FOUND),
+        None if e.downcast_ref::<WrongPassword>().is_some() => {
+            ("wrong_password", EXIT_WRONG_PASSWORD)
+        }
+        None if e.downcast_ref::<ConcurrentUpdate>().is_some()
+            || e.downcast_ref::<MergeConflict>().is_some() =>
+        {
+            ("concurrent_update", EXIT_CONCURRENT_UPDATE)
+        }
+        None => ("other", 1),
+    }
+}
+
+pub fn exit_code(e: &anyhow::Error) -> u8 {
+    classify(e).1
+}
+
+pub fn report_error(e: &anyhow::Error, format: Format) {
+    let failure = Failure {
+        error: ErrorInfo {
+            kind: classify(e).0,
+            message: format!("{e:#}"),
+        },
+    };
+    print(&failure, format).ok();
+}
+
+pub fn list_known_files(settings: &Settings, format: Format) -> Result<()> {
+    let known_files = KnownFiles {
+        files: settings
+            .files
+            .iter()
+            .enumerate()
+            .map(|(idx, f)| KnownFile {
+                path: f.display().to_string(),
+                default: idx == 0,
+                current: idx == settings.current_file,
+            })
+            .collect(),
+    };
+    print(&known_files, format)
+}
+
+// Executes a subcommand on the current file
+pub fn run(
+    settings: &mut Settings,
+    command: CliCommand,
+    o_key_file: Option<&String>,
+    format: Format,
+) -> Result<()> {
+    if let Some(query) = Query::new(&command)? {
+        return answer(settings, o_key_file, &query, format);
+    }
+    match &command {
+        CliCommand::Info => {
+            let pl_file = read(settings)?;
+            return print(&Info::new(&pl_file), format);
+        }
+        CliCommand::Passwd => {
+            let pl_file = change_password(settings, o_key_file)?;
+            return print(&Saved::new(&pl_file), format);
+        }
+        CliCommand::AgentStart { .. } | CliCommand::AgentStatus | CliCommand::AgentLock => {
+            return agent_command(settings, &command, o_key_file, format);
+        }
+        CliCommand::TempFileRecover | CliCommand::TempFileDiscard => {
+            return temp_file_command(settings, &command, o_key_file, format);
+        }
+        CliCommand::ImportCsv { .. }
+        | CliCommand::ImportKeePass { .. }
+        | CliCommand::ImportJson { .. }
+        | CliCommand::ImportPackage { .. }
+        | CliCommand::ExportJson { .. }
+        | CliCommand::ExportVault { .. }
+        | CliCommand::ExportPackage { .. }
+        | CliCommand::Merge { .. } => {
+            return import_export_command(settings, &command, o_key_file, format);
+        }
+        _ => {}
+    }
+    let mut pl_file = open_for(settings, o_key_file, &command)?;
+    match command {
+        CliCommand::DocList => return print(&DocumentList::new(pl_file.documents()), format),
+        CliCommand::IdentityShow => {
+            let identity = Identity {
+                public_key: pl_file.public_key(),
+            };
+            return print(&identity, format);
+        }
+        CliCommand::IdentityGenerate => {
+            pl_file.save_with_new_identity()?;
+            let identity = Identity {
+                public_key: pl_file.public_key(),
+            };
+            return print(&identity, format);
+        }
+        _ => {}
+    }
+    modify(&mut pl_file, command)?;
+    print(&Saved::new(&pl_file), format)
+}
+
+// whether the subcommand changes the file; dry runs and `git-credential get` only read it
+fn changes_file(command: &CliCommand) -> bool {
+    match command {
+        CliCommand::Add { .. }
+        | CliCommand::Edit { .. }
+        | CliCommand::Rm { .. }
+        | CliCommand::DocPut { .. }
+        | CliCommand::DocRm { .. }
+        | CliCommand::IdentityGenerate => true,
+        CliCommand::ImportCsv { dry_run, .. }
+        | CliCommand::ImportKeePass { dry_run, .. }
+        | CliCommand::ImportJson { dry_run, .. }
+        | CliCommand::ImportPackage { dry_run, .. }
+        | CliCommand::Merge { dry_run, .. } => !dry_run,
+        _ => false,
+    }
+}
+
+// Lets the agent answer the query, if it is running and holds the file,
+// or else answers it with the file that is opened with the password
+fn answer(
+    settings: &Settings,
+    o_key_file: Option<&String>,
+    query: &Query,
+    format: Format,
+) -> Result<()> {
+    let file = read(settings)?.file_path();
+    let answer = if let Some(answer) = agent::query(settings, file, query)? {
+        answer
+    } else {
+        let mut pl_file = if query.changes_file() {
+            open_for_change(settings, o_key_file)?
+        } else {
+            open(settings, o_key_file)?
+        };
+        query.answer(&mut pl_file)?
+    };
+    answer.deliver(query, format)
+}
+
+// Executes a subcommand that deals with the agent
+fn agent_command(
+    settings: &Settings,
+    command: &CliCommand,
+    o_key_file: Option<&String>,
+    format: Format,
+) -> Result<()> {
+    match command {
+        CliCommand::AgentStart {
+            idle_timeout_minutes,
+            session_from_stdin,
+        } => {
+            let idle_timeout = Duration::from_secs(idle_timeout_minutes * 60);
+            if *session_from_stdin {
+                return agent::serve_session_from_stdin(settings, idle_timeout);
+            }
+            let (pl_file, _password) = open_with_password(settings, o_key_file)?;
+            warn_about_leftover_temp_file(&pl_file);
+            agent::serve(settings, pl_file, idle_timeout)
+        }
+        CliCommand::AgentStatus => {
+            let agent_status = AgentStatus {
+                agent: agent::status(settings)?,
+            };
+            print(&agent_status, format)
+        }
+        CliCommand::AgentLock => {
+            let agent_locked = AgentLocked {
+                was_running: agent::lock(settings)?,
+            };
+            print(&agent_locked, format)
+        }
+        _ => unreachable!("is not an agent command"),
+    }
+}
+
+// Executes a subcommand that deals with a temp file that was left over by an interrupted save
+fn temp_file_command(
+    settings: &Settings,
+    command: &CliCommand,
+    o_key_file: Option<&String>,
+    format: Format,
+) -> Result<()> {
+    let mut pl_file = open_unchecked(settings, o_key_file)?;
+    let leftover = pl_file
+        .leftover_temp_file()
+        .cloned()
+        .ok_or_else(|| CliError::NotFound(t!("_cli_no_leftover_temp_file").to_string()))?;
+    match command {
+        CliCommand::TempFileRecover => {
+            if !leftover.is_decryptable {
+                return Err(anyhow!(
+                    t!("_leftover_temp_file_not_decryptable").to_string()
+                ));
+            }
+            if !leftover.is_newer {
+                return Err(anyhow!(t!("_leftover_temp_file_is_older").to_string()));
+            }
+            pl_file.recover_leftover_temp_file()?;
+        }
+        CliCommand::TempFileDiscard => pl_file.discard_leftover_temp_file()?,
+        _ => unreachable!("is not a temp file command"),
+    }
+    print(&Saved::new(&pl_file), format)
+}
+
+// Executes a subcommand that imports into the file or exports from it;
+// the source of an import is read and checked before the password is prompted for
+fn import_export_command(
+    settings: &mut Settings,
+    command: &CliCommand,
+    o_key_file: Option<&String>,
+    format: Format,
+) -> Result<()> {
+    let (import, on_conflict, dry_run) = match command {
+        CliCommand::ImportCsv {
+            file,
+            columns,
+            on_conflict,
+            dry_run,
+        } => (import::read_csv(file, columns)?, on_conflict, dry_run),
+        CliCommand::ImportKeePass {
+            file,
+            key_file,
+            on_conflict,
+            dry_run,
+        } => (
+            import::read_keepass(file, key_file.as_deref())?,
+            on_conflict,
+            dry_run,
+        ),
+        CliCommand::ImportJson {
+            file,
+            on_conflict,
+            dry_run,
+        } => (
+            crate::import::json::read(Path::new(file))?,
+            on_conflict,
+            dry_run,
+        ),
+        CliCommand::ImportPackage {
+            file,
+            on_conflict,
+            dry_run,
+        } => {
+            // the package can only be decrypted with the identity in the file
+            let package = std::fs::read(file).context(format!("reading {file}"))?;
+            let mut pl_file = open_for(settings, o_key_file, command)?;
+            let import = open_package(&pl_file, &package)?;
+            return import::run(&mut pl_file, &import, *on_conflict, *dry_run, format);
+        }
+        CliCommand::ExportJson { file } => {
+            let pl_file = open(settings, o_key_file)?;
+            return export::json(&pl_file, file, o_key_file, format);
+        }
+        CliCommand::ExportVault {
+            file,
+            entries,
+            documents,
+            register,
+        } => {
+            let pl_file = open(settings, o_key_file)?;
+            let exported_vault = export::vault(&pl_file, file, entries, documents)?;
+            if *register {
+                settings.add_file(Path::new(file))?;
+            }
+            return print(
+                &ExportedVault {
+                    registered: *register,
+                    ..exported_vault
+                },
+                format,
+            );
+        }
+        CliCommand::ExportPackage {
+            file,
+            recipients,
+            entries,
+            documents,
+        } => {
+            let pl_file = open(settings, o_key_file)?;
+            let exported_package = export::package(&pl_file, file, recipients, entries, documents)?;
+            return print(&exported_package, format);
+        }
+        CliCommand::Merge {
+            file,
+            key_file,
+            entries,
+            documents,
+            dry_run,
+        } => {
+            let mut pl_file = open_for(settings, o_key_file, command)?;
+            return merge::run(
+                &mut pl_file,
+                file,
+                key_file.as_deref(),
+                entries,
+                documents,
+                *dry_run,
+                format,
+            );
+        }
+        _ => unreachable!("is not an import or export command"),
+    };
+    let mut pl_file = open_for(settings, o_key_file, command)?;
+    import::run(&mut pl_file, &import, *on_conflict, *dry_run, format)
+}
+
+// Executes a subcommand that changes the file
+fn modify(pl_file: &mut PlFile, command: CliCommand) -> Result<()> {
+    match command {
+        CliCommand::Add {
+            entry,
+            description,
+            creds,
+        } => {
+            let mut v_edit_bundle = VEditBundle::new();
+            v_edit_bundle.key = Key::new(entry);
+            v_edit_bundle.description = description.unwrap_or_default();
+            v_edit_bundle.v_edit_creds = creds
+                .into_iter()
+                .map(|name| {
+                    Ok(VEditCred {
+                        secret: prompt_secret(&name)?,
+                        name,
+                    })
+                })
+                .collect::<Result<_>>()?;
+            pl_file.save_with_added_bundle(&v_edit_bundle)
+        }
+        CliCommand::Edit {
+            entry,
+            rename,
+            description,
+            set_creds,
+            remove_creds,
+        } => edit(
+            pl_file,
+            &entry,
+            rename,
+            description,
+            set_creds,
+            remove_creds,
+        ),
+        CliCommand::Rm { entry } => {
+            bundle(pl_file, &entry)?;
+            pl_file.save_with_deleted_bundle(Key::new(entry))
+        }
+        CliCommand::DocPut { document } => {
+            let mut text = String::new();
+            stdin().read_to_string(&mut text)?;
+            let key = Key::new(document);
+            let v_edit_document = VEditDocument {
+                orig_key: key.clone(),
+                key: key.clone(),
+                text,
+                request_focus: false,
+            };
+            if pl_file.has_document(&key) {
+                pl_file.save_with_updated_document(&v_edit_document)
+            } else {
+                pl_file.save_with_added_document(&v_edit_document)
+            }
+        }
+        CliCommand::DocRm { document } => {
+            let key = Key::new(document);
+            if !pl_file.has_document(&key) {
+                return Err(document_not_found(&key).into());
+            }
+            pl_file.save_with_deleted_document(&key)
+        }
+        _ => unreachable!("is not a modification"),
+    }
+}
+
+fn edit(
+    pl_file: &mut PlFile,
+    entry: &str,
+    rename: Option<String>,
+    description: Option<String>,
+    set_creds: Vec<String>,
+    remove_creds: Vec<String>,
+) -> Result<()> {
+    let mut v_edit_bundle = VEditBundle::from_bundle(
+        &Key::new(entry),
+        bundle(pl_file, entry)?,
+        pl_file.transient().context("not unlocked")?,
+    );
+    if let Some(rename) = rename {
+        v_edit_bundle.key = Key::new(rename);
+    }
+    if let Some(description) = description {
+        v_edit_bundle.description = description;
+    }
+    for name in remove_creds {
+        let idx = cred_index(&v_edit_bundle, &name).ok_or_else(|| {
+            CliError::NotFound(t!("_cli_no_cred %{e} %{c}", e = entry, c = name).to_string())
+        })?;
+        v_edit_bundle.v_edit_creds.remove(idx);
+    }
+    for name in set_creds {
+        let secret = prompt_secret(&name)?;
+        set_cred(&mut v_edit_bundle, name, secret);
+    }
+    pl_file.save_with_updated_bundle(&v_edit_bundle)
+}
+
+fn bundle<'a>(pl_file: &'a PlFile, entry: &str) -> Result<&'a Bundle> {
+    pl_file.bundles().get(&Key::new(entry)).ok_or_else(|| {
+        CliError::NotFound(t!("_bundle_does_not_exist %{name}", name = entry).to_string()).into()
+    })
+}
+
+// A reference like `prod-db/admin` names the entry and the credential;
+// as entry names can contain slashes, the credential name is what follows the last slash
+fn cred_reference(reference: &str) -> Option<(&str, &str)> {
+    reference
+        .rsplit_once('/')
+        .filter(|(entry, cred)| !entry.is_empty() && !cred.is_empty())
+}
+
+fn cred_secret(pl_file: &PlFile, entry: &str, cred: &str) -> Result<String> {
+    let transient = pl_file.transient().context("not unlocked")?;
+    bundle(pl_file, entry)?
+        .creds()
+        .iter()
+        .find(|c| c.name(transient) == cred)
+        .map(|c| c.secret(transient).to_string())
+        .ok_or_else(|| {
+            CliError::NotFound(t!("_cli_no_cred %{e} %{c}", e = entry, c = cred).to_string()).into()
+        })
+}
+
+fn totp_code(pl_file: &PlFile, entry: &str, cred: &str) -> Result<TotpCode> {
+    let secret = cred_secret(pl_file, entry, cred)?;
+    if !Totp::is_totp(&secret) {
+        return Err(anyhow!(
+            t!("_cli_no_totp %{e} %{c}", e = entry, c = cred).to_string()
+        ));
+    }
+    let (code, remaining_seconds) = Totp::from_uri(&secret)?.current_code();
+    Ok(TotpCode {
+        code,
+        remaining_seconds,
+    })
+}
+
+fn document_text(pl_file: &PlFile, document: String) -> Result<DocumentText> {
+    let transient = pl_file.transient().context("not unlocked")?;
+    let key = Key::new(document);
+    let document = pl_file
+        .documents()
+        .get(&key)
+        .ok_or_else(|| document_not_found(&key))?;
+    Ok(DocumentText {
+        document: DocumentDetails {
+            name: key.to_string(),
+            last_changed_at: document.last_changed_at().clone(),
+            text: document.text(transient).to_string(),
+        },
+    })
+}
+
+fn document_not_found(key: &Key) -> CliError {
+    CliError::NotFound(t!("_document_does_not_exist %{name}", name = key).to_string())
+}
+
+fn cred_index(v_edit_bundle: &VEditBundle, name: &str) -> Option<usize> {
+    v_edit_bundle
+        .v_edit_creds
+        .iter()
+        .position(|v_edit_cred| v_edit_cred.name == name)
+}
+
+// changes the secret of the named credential, or adds the credential
+fn set_cred(v_edit_bundle: &mut VEditBundle, name: String, secret: String) {
+    match cred_index(v_edit_bundle, &name) {
+        Some(idx) => v_edit_bundle.v_edit_creds[idx].secret = secret,
+        None => v_edit_bundle.v_edit_creds.push(VEditCred { name, secret }),
+    }
+}
+
+fn prompt_secret(cred_name: &str) -> Result<String> {
+    Ok(prompt_password(t!("_cli_secret_for %{c}", c = cred_name))?)
+}
+
+// Changes the password of the key slot with which the file is opened
+fn change_password(settings: &Settings, o_key_file: Option<&String>) -> Result<PlFile> {
+    let (mut pl_file, old_password) = open_with_password(settings, o_key_file)?;
+    check_leftover_temp_file(&pl_file)?;
+    let new_password = prompt_password(t!("New password:") + " ")?;
+    if new_password != prompt_password(t!("Repeat:") + " ")? {
+        return Err(anyhow!(t!("_passwords_dont_match").to_string()));
+    }
+    eprintln!("{}", t!("_cli_new_key_file"));
+    let mut new_key_file = String::new();
+    stdin().read_line(&mut new_key_file)?;
+    let new_key_file = new_key_file.trim();
+    pl_file.change_password(
+        &old_password,
+        &new_password,
+        (!new_key_file.is_empty()).then(|| Path::new(new_key_file)),
+    )?;
+    Ok(pl_file)
+}
+
+pub fn list_key_slots(settings: &Settings, format: Format) -> Result<()> {
+    print(&KeySlots::new(read(settings)?.key_slots()), format)
+}
+
+pub fn add_key_slot(
+    settings: &Settings,
+    label: &str,
+    o_key_file: Option<&String>,
+    format: Format,
+) -> Result<()> {
+    let mut pl_file = open_for_change(settings, o_key_file)?;
+
+    let password = prompt_password(t!("_cli_new_key_slot_password %{l}", l = label))?;
+    if password != prompt_password(t!("Repeat:") + " ")? {
+        return Err(anyhow!(t!("_passwords_dont_match").to_string()));
+    }
+    eprintln!("{}", t!("_cli_new_key_slot_key_file"));
+    let mut new_key_file = String::new();
+    stdin().read_line(&mut new_key_file)?;
+    let new_key_file = new_key_file.trim();
+
+    pl_file.add_key_slot(
+        label,
+        &password,
+        (!new_key_file.is_empty()).then(|| Path::new(new_key_file)),
+    )?;
+    print(&Saved::new(&pl_file), format)
+}
+
+pub fn revoke_key_slot(
+    settings: &Settings,
+    label: &str,
+    o_key_file: Option<&String>,
+    format: Format,
+) -> Result<()> {
+    let mut pl_file = open_for_change(settings, o_key_file)?;
+    pl_file.revoke_key_slot(label)?;
+    // the agent cannot read the file anymore with its old session
+    if let Err(e) = agent::reregister(settings, &mut pl_file) {
+        eprintln!("{}", t!("_agent_not_updated %{e}", e = format!("{e:#}")));
+    }
+    print(&Saved::new(&pl_file), format)
+}
+
+// reads the current file, without unlocking it
+fn read(settings: &Settings) -> Result<PlFile> {
+    let pl_file = PlFile::read_or_create(settings.current_file())?;
+    if pl_file.update_counter().peek() == Some(0) {
+        return Err(CliError::NotFound(format!("{} does not exist", pl_file.file_path())).into());
+    }
+    Ok(pl_file)
+}
+
+// opens the current file for a subcommand that only reads it;
+// a leftover temp file is only reported
+fn open(settings: &Settings, o_key_file: Option<&String>) -> Result<PlFile> {
+    let pl_file = open_unchecked(settings, o_key_file)?;
+    warn_about_leftover_temp_file(&pl_file);
+    Ok(pl_file)
+}
+
+// opens the current file for a subcommand that changes it,
+// which is refused as long as a temp file is left over
+fn open_for_change(settings: &Settings, o_key_file: Option<&String>) -> Result<PlFile> {
+    let pl_file = open_unchecked(settings, o_key_file)?;
+    check_leftover_temp_file(&pl_file)?;
+    Ok(pl_file)
+}
+
+// opens the current file as the subcommand needs it
+fn open_for(
+    settings: &Settings,
+    o_key_file: Option<&String>,
+    command: &CliCommand,
+) -> Result<PlFile> {
+    if changes_file(command) {
+        open_for_change(settings, o_key_file)
+    } else {
+        open(settings, o_key_file)
+    }
+}
+
+// reads the current file and unlocks it with a password that is prompted for
+fn open_unchecked(settings: &Settings, o_key_file: Option<&String>) -> Result<PlFile> {
+    open_with_password(settings, o_key_file).map(|(pl_file, _password)| pl_file)
+}
+
+fn open_with_password(
+    settings: &Settings,
+    o_key_file: Option<&String>,
+) -> Result<(PlFile, String)> {
+    let mut pl_file = read(settings)?;
+    pl_file.set_backup_generations(settings.backup_generations);
+    let password = prompt_password(t!("Password:") + " ")?;
+    pl_file
+        .set_actionable(&password, o_key_file.map(Path::new))
+        .context(t!("_cli_cannot_open").to_string())?;
+    Ok((pl_file, password))
+}
+
+fn warn_about_leftover_temp_file(pl_file: &PlFile) {
+    if pl_file.leftover_temp_file().is_some() {
+        eprintln!("{}", t!("_cli_leftover_temp_file_warning"));
+    }
+}
+
+fn check_leftover_temp_file(pl_file: &PlFile) -> Result<()> {
+    if pl_file.leftover_temp_file().is_some() {
+        return Err(anyhow!(t!("_cli_leftover_temp_file").to_string()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{CliError, EXIT_CONCURRENT_UPDATE, EXIT_NOT_FOUND, EXIT_WRONG_PASSWORD, exit_code};
+    use crate::data::{ConcurrentUpdate, WrongPassword};
+    use anyhow::{Context, anyhow};
+
+    #[test]
+    fn test_exit_codes() {
+        let wrong_password = Err::<(), _>(anyhow!("decryption failed"))
+            .context(WrongPassword)
+            .context("cannot open the file")
+            .unwrap_err();
+        assert_eq!(exit_code(&wrong_password), EXIT_WRONG_PASSWORD);
+        let key_file_missing = Err::<(), _>(anyhow!("key file missing"))
+            .context("cannot open the file")
+            .unwrap_err();
+        assert_eq!(exit_code(&key_file_missing), 1);
+        let not_found = CliError::NotFound("no such entry".to_string()).into();
+        assert_eq!(exit_code(&not_found), EXIT_NOT_FOUND);
+        assert_eq!(exit_code(&ConcurrentUpdate.into()), EXIT_CONCURRENT_UPDATE);
+        assert_eq!(exit_code(&anyhow!("something else")), 1);
+    }
+}
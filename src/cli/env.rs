@@ -0,0 +1,63 @@
+//! Runs a command with secrets from the file in its environment.
+//!
+//! The secrets are only handed over to the child process; they are neither written to disk
+//! nor do they appear on the command line.
+
+use super::{cred_reference, cred_secret};
+use crate::data::PlFile;
+use anyhow::{Context, Result, anyhow};
+use std::process::Command;
+
+// All references are resolved before the command is started
+pub(super) fn resolve(pl_file: &PlFile, env: &[String]) -> Result<Vec<(String, String)>> {
+    env.iter()
+        .map(|assignment| {
+            let (var, entry, cred) = parse(assignment)?;
+            Ok((var.to_string(), cred_secret(pl_file, entry, cred)?))
+        })
+        .collect()
+}
+
+// Returns the exit code of the command
+pub(super) fn run(vars: &[(String, String)], command: &[String]) -> Result<i32> {
+    let (program, args) = command.split_first().context("no command given")?;
+    let status = Command::new(program)
+        .args(args)
+        .envs(vars.iter().map(|(var, value)| (var, value)))
+        .status()
+        .context(t!("_cli_cannot_run %{c}", c = program).to_string())?;
+    // a command that was killed by a signal has no exit code
+    Ok(status.code().unwrap_or(1))
+}
+
+// An assignment like `DB_PASS=prod-db/admin` names the variable, the entry and the credential
+fn parse(assignment: &str) -> Result<(&str, &str, &str)> {
+    assignment
+        .split_once('=')
+        .filter(|(var, _reference)| !var.is_empty())
+        .and_then(|(var, reference)| {
+            cred_reference(reference).map(|(entry, cred)| (var, entry, cred))
+        })
+        .ok_or_else(|| anyhow!(t!("_cli_bad_env %{a}", a = assignment).to_string()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::parse;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            parse("DB_PASS=prod-db/admin").unwrap(),
+            ("DB_PASS", "prod-db", "admin")
+        );
+        assert_eq!(
+            parse("TOKEN=https://github.com/bob").unwrap(),
+            ("TOKEN", "https://github.com", "bob")
+        );
+        assert!(parse("DB_PASS").is_err());
+        assert!(parse("=prod-db/admin").is_err());
+        assert!(parse("DB_PASS=prod-db").is_err());
+        assert!(parse("DB_PASS=prod-db/").is_err());
+    }
+}
@@ -0,0 +1,130 @@
+//! Exports the file in plain text, or a part of it into a new file or into a package for others.
+//!
+//! Exports are never executed by the agent: the file is always opened with the password,
+//! and since everything can be read from the plain text output, the password is asked for
+//! once more before it is written.
+
+use super::{
+    bundle, document_not_found,
+    output::{Exported, ExportedPackage, ExportedVault, print},
+};
+use crate::{
+    args::Format,
+    data::{Export, Key, PlFile, parse_recipient, seal_package},
+    util::write_private_file,
+};
+use anyhow::{Context, Result, anyhow};
+use rpassword::prompt_password;
+use std::{io::stdin, path::Path};
+
+pub(super) fn json(
+    pl_file: &PlFile,
+    file: &str,
+    o_key_file: Option<&String>,
+    format: Format,
+) -> Result<()> {
+    eprintln!("{}", t!("_export_warning"));
+    let password = prompt_password(t!("_export_password") + " ")?;
+    pl_file.check_password(&password, o_key_file.map(Path::new))?;
+    let export = Export::new(pl_file)?;
+    write_private_file(Path::new(file), &serde_json::to_string_pretty(&export)?)?;
+    print(
+        &Exported {
+            file: file.to_string(),
+            entries: export.entries.len(),
+            documents: export.documents.len(),
+        },
+        format,
+    )
+}
+
+// Creates the new file; its password and key file are prompted for
+pub(super) fn vault(
+    pl_file: &PlFile,
+    file: &str,
+    entries: &[String],
+    documents: &[String],
+) -> Result<ExportedVault> {
+    // the target is checked again when the file is created, but before the prompts
+    let (bundle_keys, document_keys) = selection(pl_file, file, entries, documents)?;
+
+    let password = prompt_password(t!("_cli_export_vault_password") + " ")?;
+    if password != prompt_password(t!("Repeat:") + " ")? {
+        return Err(anyhow!(t!("_passwords_dont_match").to_string()));
+    }
+    eprintln!("{}", t!("_cli_export_vault_key_file"));
+    let mut key_file = String::new();
+    stdin().read_line(&mut key_file)?;
+    let key_file = key_file.trim();
+
+    let mut new_file = pl_file.export_subset(
+        &bundle_keys,
+        &document_keys,
+        Path::new(file),
+        &password,
+        (!key_file.is_empty()).then(|| Path::new(key_file)),
+    )?;
+    Ok(ExportedVault {
+        file: file.to_string(),
+        entries: bundle_keys.len(),
+        documents: document_keys.len(),
+        recovery_code: new_file.take_recovery_code().unwrap_or_default(),
+        registered: false,
+    })
+}
+
+// Writes the package; it can only be opened with the identities of the recipients
+pub(super) fn package(
+    pl_file: &PlFile,
+    file: &str,
+    recipients: &[String],
+    entries: &[String],
+    documents: &[String],
+) -> Result<ExportedPackage> {
+    let (bundle_keys, document_keys) = selection(pl_file, file, entries, documents)?;
+    let recipients = recipients
+        .iter()
+        .map(|recipient| parse_recipient(recipient))
+        .collect::<Result<Vec<_>>>()?;
+    let package = seal_package(pl_file, &bundle_keys, &document_keys, &recipients)?;
+    std::fs::write(file, package).context(format!("writing {file}"))?;
+    Ok(ExportedPackage {
+        file: file.to_string(),
+        entries: bundle_keys.len(),
+        documents: document_keys.len(),
+        recipients: recipients.len(),
+    })
+}
+
+// The keys of the named entries and documents, if all exist, and if the target does not exist
+fn selection(
+    pl_file: &PlFile,
+    file: &str,
+    entries: &[String],
+    documents: &[String],
+) -> Result<(Vec<Key>, Vec<Key>)> {
+    if entries.is_empty() && documents.is_empty() {
+        return Err(anyhow!(t!("_export_nothing_selected").to_string()));
+    }
+    if Path::new(file).exists() {
+        return Err(anyhow!(
+            t!("_export_target_exists %{f}", f = file).to_string()
+        ));
+    }
+    let bundle_keys = entries
+        .iter()
+        .map(|entry| bundle(pl_file, entry).map(|_| Key::new(entry)))
+        .collect::<Result<Vec<_>>>()?;
+    let document_keys = documents
+        .iter()
+        .map(|document| {
+            let key = Key::new(document);
+            if pl_file.has_document(&key) {
+                Ok(key)
+            } else {
+                Err(document_not_found(&key).into())
+            }
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok((bundle_keys, document_keys))
+}
@@ -0,0 +1,449 @@
+//! A credential helper for git, see `gitcredentials(7)` and `git-credential(1)`.
+//!
+//! git writes the attributes of the wanted credential as `key=value` lines to stdin,
+//! and reads the answer of `get` in the same format from stdout.
+
+use super::{cred_index, set_cred};
+use crate::{
+    args::GitCredentialOp,
+    data::{Bundle, Key, PlFile},
+    ui::viz::{VEditBundle, VEditCred},
+};
+use anyhow::{Context, Result};
+use std::io::{BufRead, Write};
+
+// A line in the description of an entry that associates it with a URL
+const URL_FIELD: &str = "url:";
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub(crate) struct GitRequest {
+    protocol: String,
+    host: String,
+    path: String,
+    username: Option<String>,
+    password: Option<String>,
+}
+impl GitRequest {
+    // reads until an empty line or the end of the input; unknown attributes are ignored
+    pub(super) fn read(input: impl BufRead) -> Result<Self> {
+        let mut request = GitRequest::default();
+        for line in input.lines() {
+            let line = line?;
+            if line.is_empty() {
+                break;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let value = value.to_string();
+            match key {
+                "protocol" => request.protocol = value,
+                "host" => request.host = value,
+                "path" => request.path = value,
+                "username" => request.username = Some(value),
+                "password" => request.password = Some(value),
+                _ => {}
+            }
+        }
+        Ok(request)
+    }
+
+    fn url(&self) -> String {
+        if self.path.is_empty() {
+            format!("{}://{}", self.protocol, self.host)
+        } else {
+            format!("{}://{}/{}", self.protocol, self.host, self.path)
+        }
+    }
+
+    fn entry_name(&self, naming_rule: &str) -> String {
+        naming_rule
+            .replace("{protocol}", &self.protocol)
+            .replace("{host}", &self.host)
+            .replace("{path}", &self.path)
+    }
+}
+
+// Executes the operation; unknown operations are ignored, as git demands
+pub(super) fn run(
+    pl_file: &mut PlFile,
+    operation: GitCredentialOp,
+    naming_rule: &str,
+    request: &GitRequest,
+    output: impl Write,
+) -> Result<()> {
+    match operation {
+        GitCredentialOp::Get => get(pl_file, naming_rule, request, output),
+        GitCredentialOp::Store => store(pl_file, naming_rule, request),
+        GitCredentialOp::Erase => erase(pl_file, naming_rule, request),
+        GitCredentialOp::Unknown => Ok(()),
+    }
+}
+
+// Writes nothing if no credential is found, so that git tries other helpers or asks the user
+fn get(
+    pl_file: &PlFile,
+    naming_rule: &str,
+    request: &GitRequest,
+    mut output: impl Write,
+) -> Result<()> {
+    let Some(key) = find_entry(pl_file, naming_rule, request) else {
+        return Ok(());
+    };
+    let transient = pl_file.transient().context("not unlocked")?;
+    let bundle = pl_file.bundles().get(&key).context("entry vanished")?;
+    let o_cred = bundle.creds().iter().find(|cred| {
+        request
+            .username
+            .as_deref()
+            .is_none_or(|username| cred.name(transient) == username)
+    });
+    if let Some(cred) = o_cred {
+        writeln!(output, "username={}", cred.name(transient))?;
+        writeln!(output, "password={}", cred.secret(transient))?;
+    }
+    Ok(())
+}
+
+// Is called by git after a credential was used successfully;
+// the file is only saved if the credential is new or changed
+fn store(pl_file: &mut PlFile, naming_rule: &str, request: &GitRequest) -> Result<()> {
+    let (Some(username), Some(password)) = (&request.username, &request.password) else {
+        return Ok(());
+    };
+    let transient = pl_file.transient().context("not unlocked")?;
+    if let Some(key) = find_entry(pl_file, naming_rule, request) {
+        let bundle = pl_file.bundles().get(&key).context("entry vanished")?;
+        let mut v_edit_bundle = VEditBundle::from_bundle(&key, bundle, transient);
+        if cred_index(&v_edit_bundle, username)
+            .is_some_and(|idx| v_edit_bundle.v_edit_creds[idx].secret == *password)
+        {
+            return Ok(());
+        }
+        set_cred(&mut v_edit_bundle, username.clone(), password.clone());
+        pl_file.save_with_updated_bundle(&v_edit_bundle)
+    } else {
+        let mut v_edit_bundle = VEditBundle::new();
+        v_edit_bundle.key = Key::new(request.entry_name(naming_rule));
+        v_edit_bundle.description = format!("{URL_FIELD} {}", request.url());
+        v_edit_bundle.v_edit_creds = vec![VEditCred {
+            name: username.clone(),
+            secret: password.clone(),
+        }];
+        pl_file.save_with_added_bundle(&v_edit_bundle)
+    }
+}
+
+// Is called by git if a credential was rejected; removes the credential, but keeps the entry
+fn erase(pl_file: &mut PlFile, naming_rule: &str, request: &GitRequest) -> Result<()> {
+    let Some(username) = &request.username else {
+        return Ok(());
+    };
+    let Some(key) = find_entry(pl_file, naming_rule, request) else {
+        return Ok(());
+    };
+    let transient = pl_file.transient().context("not unlocked")?;
+    let bundle = pl_file.bundles().get(&key).context("entry vanished")?;
+    let mut v_edit_bundle = VEditBundle::from_bundle(&key, bundle, transient);
+    let Some(idx) = cred_index(&v_edit_bundle, username) else {
+        return Ok(());
+    };
+    if request
+        .password
+        .as_ref()
+        .is_some_and(|password| v_edit_bundle.v_edit_creds[idx].secret != *password)
+    {
+        return Ok(());
+    }
+    v_edit_bundle.v_edit_creds.remove(idx);
+    pl_file.save_with_updated_bundle(&v_edit_bundle)
+}
+
+// The entry that is named by the naming rule, or else the entry with the most specific
+// URL field that matches
+fn find_entry(pl_file: &PlFile, naming_rule: &str, request: &GitRequest) -> Option<Key> {
+    let key = Key::new(request.entry_name(naming_rule));
+    if pl_file.has_bundle(&key) {
+        return Some(key);
+    }
+    let url = request.url();
+    pl_file
+        .bundles()
+        .iter()
+        .filter_map(|(key, bundle)| {
+            url_field(bundle)
+                .filter(|entry_url| url_matches(entry_url, &url))
+                .map(|entry_url| (entry_url.len(), key))
+        })
+        .max_by_key(|(len, _key)| *len)
+        .map(|(_len, key)| key.clone())
+}
+
+fn url_field(bundle: &Bundle) -> Option<&str> {
+    bundle.description().lines().find_map(|line| {
+        let line = line.trim();
+        line.get(..URL_FIELD.len())
+            .filter(|prefix| prefix.eq_ignore_ascii_case(URL_FIELD))
+            .map(|_| line[URL_FIELD.len()..].trim().trim_end_matches('/'))
+    })
+}
+
+// The URL of the entry matches if it is the same as the requested URL, or a prefix of it
+// that ends at a path separator
+fn url_matches(entry_url: &str, url: &str) -> bool {
+    url.strip_prefix(entry_url)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{GitRequest, find_entry, run, url_matches};
+    use crate::{
+        args::GitCredentialOp,
+        data::{Key, PlFile},
+        ui::viz::{VEditBundle, VEditCred},
+    };
+    use std::path::Path;
+
+    const NAMING_RULE: &str = "{protocol}://{host}";
+
+    fn add_bundle(pl_file: &mut PlFile, name: &str, description: &str, creds: &[(&str, &str)]) {
+        let mut v_edit_bundle = VEditBundle::new();
+        v_edit_bundle.key = Key::new(name);
+        v_edit_bundle.description = description.to_string();
+        v_edit_bundle.v_edit_creds = creds
+            .iter()
+            .map(|(name, secret)| VEditCred {
+                name: (*name).to_string(),
+                secret: (*secret).to_string(),
+            })
+            .collect();
+        pl_file.save_with_added_bundle(&v_edit_bundle).unwrap();
+    }
+
+    fn request(input: &str) -> GitRequest {
+        GitRequest::read(input.as_bytes()).unwrap()
+    }
+
+    fn execute(pl_file: &mut PlFile, operation: GitCredentialOp, input: &str) -> String {
+        let mut output = Vec::new();
+        run(
+            pl_file,
+            operation,
+            NAMING_RULE,
+            &request(input),
+            &mut output,
+        )
+        .unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    fn update_counter(pl_file: &PlFile) -> Option<usize> {
+        pl_file.update_counter().peek()
+    }
+
+    #[test]
+    fn test_request() {
+        let input = "protocol=https\nhost=example.com\npath=team/repo.git\n\
+                     username=bob\nwwwauth[]=Basic realm=x\n\nhost=ignored\n";
+        let request = GitRequest::read(input.as_bytes()).unwrap();
+        assert_eq!(request.host, "example.com");
+        assert_eq!(request.username.as_deref(), Some("bob"));
+        assert!(request.password.is_none());
+        assert_eq!(request.url(), "https://example.com/team/repo.git");
+        assert_eq!(
+            request.entry_name("{protocol}://{host}"),
+            "https://example.com"
+        );
+        assert_eq!(request.entry_name("git {host}"), "git example.com");
+
+        assert!(url_matches("https://example.com", &request.url()));
+        assert!(url_matches("https://example.com/team", &request.url()));
+        assert!(!url_matches("https://example.com/te", &request.url()));
+        assert!(!url_matches("https://example.org", &request.url()));
+    }
+
+    // a file with an entry for https://example.com, which is named by the naming rule
+    fn example_file(path: &Path) -> PlFile {
+        let mut pl_file = PlFile::read_or_create(path).unwrap();
+        pl_file.set_actionable("pw", None).unwrap();
+        add_bundle(
+            &mut pl_file,
+            "https://example.com",
+            "",
+            &[("alice", "a-pw"), ("bob", "b-pw")],
+        );
+        pl_file
+    }
+
+    #[test]
+    fn test_get() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut pl_file = example_file(&temp_dir.path().join("git"));
+
+        // get: the first credential, or the one with the given username
+        assert_eq!(
+            execute(
+                &mut pl_file,
+                GitCredentialOp::Get,
+                "protocol=https\nhost=example.com\n"
+            ),
+            "username=alice\npassword=a-pw\n"
+        );
+        assert_eq!(
+            execute(
+                &mut pl_file,
+                GitCredentialOp::Get,
+                "protocol=https\nhost=example.com\nusername=bob\n"
+            ),
+            "username=bob\npassword=b-pw\n"
+        );
+        assert_eq!(
+            execute(
+                &mut pl_file,
+                GitCredentialOp::Get,
+                "protocol=https\nhost=example.com\nusername=carol\n"
+            ),
+            ""
+        );
+        assert_eq!(
+            execute(
+                &mut pl_file,
+                GitCredentialOp::Get,
+                "protocol=https\nhost=example.org\n"
+            ),
+            ""
+        );
+    }
+
+    #[test]
+    fn test_store() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut pl_file = example_file(&temp_dir.path().join("git"));
+
+        // store: an unchanged credential is not saved again
+        let counter = update_counter(&pl_file);
+        execute(
+            &mut pl_file,
+            GitCredentialOp::Store,
+            "protocol=https\nhost=example.com\nusername=bob\npassword=b-pw\n",
+        );
+        assert_eq!(update_counter(&pl_file), counter);
+
+        // store: a changed credential is saved
+        execute(
+            &mut pl_file,
+            GitCredentialOp::Store,
+            "protocol=https\nhost=example.com\nusername=bob\npassword=b-pw2\n",
+        );
+        assert!(update_counter(&pl_file) > counter);
+        assert_eq!(
+            execute(
+                &mut pl_file,
+                GitCredentialOp::Get,
+                "protocol=https\nhost=example.com\nusername=bob\n"
+            ),
+            "username=bob\npassword=b-pw2\n"
+        );
+
+        // store: a new entry is created, with the URL in its description
+        execute(
+            &mut pl_file,
+            GitCredentialOp::Store,
+            "protocol=https\nhost=git.example.org\npath=team/repo.git\n\
+             username=carol\npassword=c-pw\n",
+        );
+        let bundle = pl_file
+            .bundles()
+            .get(&Key::new("https://git.example.org"))
+            .unwrap();
+        assert_eq!(
+            bundle.description(),
+            "url: https://git.example.org/team/repo.git"
+        );
+        assert_eq!(bundle.creds().len(), 1);
+    }
+
+    #[test]
+    fn test_erase() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut pl_file = example_file(&temp_dir.path().join("git"));
+
+        // erase: a credential with another password is kept
+        let counter = update_counter(&pl_file);
+        execute(
+            &mut pl_file,
+            GitCredentialOp::Erase,
+            "protocol=https\nhost=example.com\nusername=alice\npassword=wrong\n",
+        );
+        assert_eq!(update_counter(&pl_file), counter);
+        assert_eq!(
+            execute(
+                &mut pl_file,
+                GitCredentialOp::Get,
+                "protocol=https\nhost=example.com\nusername=alice\n"
+            ),
+            "username=alice\npassword=a-pw\n"
+        );
+
+        // erase: the credential with the same password is removed, the entry is kept
+        execute(
+            &mut pl_file,
+            GitCredentialOp::Erase,
+            "protocol=https\nhost=example.com\nusername=alice\npassword=a-pw\n",
+        );
+        let bundle = pl_file
+            .bundles()
+            .get(&Key::new("https://example.com"))
+            .unwrap();
+        assert_eq!(bundle.creds().len(), 1);
+    }
+
+    #[test]
+    fn test_find_entry() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut pl_file = PlFile::read_or_create(&temp_dir.path().join("git")).unwrap();
+        pl_file.set_actionable("pw", None).unwrap();
+        add_bundle(
+            &mut pl_file,
+            "example",
+            "url: https://example.com",
+            &[("alice", "pw")],
+        );
+        add_bundle(
+            &mut pl_file,
+            "team",
+            "notes\n  URL: https://example.com/team/ ",
+            &[("alice", "pw")],
+        );
+        let find =
+            |pl_file: &PlFile, input: &str| find_entry(pl_file, NAMING_RULE, &request(input));
+
+        // the most specific URL field wins
+        assert_eq!(
+            find(
+                &pl_file,
+                "protocol=https\nhost=example.com\npath=team/repo.git\n"
+            ),
+            Some(Key::new("team"))
+        );
+        assert_eq!(
+            find(
+                &pl_file,
+                "protocol=https\nhost=example.com\npath=other/repo.git\n"
+            ),
+            Some(Key::new("example"))
+        );
+        assert_eq!(find(&pl_file, "protocol=https\nhost=example.org\n"), None);
+
+        // the entry that is named by the naming rule wins over URL fields
+        add_bundle(&mut pl_file, "https://example.com", "", &[("bob", "pw")]);
+        assert_eq!(
+            find(
+                &pl_file,
+                "protocol=https\nhost=example.com\npath=team/repo.git\n"
+            ),
+            Some(Key::new("https://example.com"))
+        );
+    }
+}
@@ -0,0 +1,126 @@
+//! Imports entries and documents from other sources.
+
+use super::output::{ImportReport, print};
+use crate::{
+    args::{CsvColumns, Format},
+    data::{Import, OnConflict, PlFile},
+    import::{CredMapping, CredName, CsvMapping, CsvTable, keepass},
+};
+use anyhow::{Result, anyhow};
+use rpassword::prompt_password;
+use std::path::Path;
+
+// Saves the import, or only prints what would be imported
+pub(super) fn run(
+    pl_file: &mut PlFile,
+    import: &Import,
+    on_conflict: OnConflict,
+    dry_run: bool,
+    format: Format,
+) -> Result<()> {
+    let import_report = if dry_run {
+        ImportReport::new(import, &pl_file.plan_import(import, on_conflict), None)
+    } else {
+        let plan = pl_file.save_with_import(import, on_conflict)?;
+        ImportReport::new(import, &plan, Some(pl_file))
+    };
+    print(&import_report, format)
+}
+
+// Reads the CSV file and applies the given columns, or else the guessed ones
+pub(super) fn read_csv(file: &str, columns: &CsvColumns) -> Result<Import> {
+    let table = CsvTable::read(Path::new(file))?;
+    Ok(mapping(&table, columns)?.apply(&table))
+}
+
+// A KeePass file needs its master password, an XML export is read as it is
+pub(super) fn read_keepass(file: &str, o_key_file: Option<&str>) -> Result<Import> {
+    let path = Path::new(file);
+    if keepass::is_encrypted(path)? {
+        let password = prompt_password(t!("_keepass_password") + " ")?;
+        keepass::read_kdbx(path, &password, o_key_file.map(Path::new))
+    } else {
+        keepass::read_xml(path)
+    }
+}
+
+// The columns that are given replace the guessed ones
+fn mapping(table: &CsvTable, columns: &CsvColumns) -> Result<CsvMapping> {
+    let mut mapping = CsvMapping::guess(&table.headers);
+    if let Some(key) = &columns.key {
+        mapping.key = table.column(key)?;
+    }
+    if !columns.description.is_empty() {
+        mapping.description = columns
+            .description
+            .iter()
+            .map(|column| table.column(column))
+            .collect::<Result<_>>()?;
+    }
+    if !columns.creds.is_empty() {
+        mapping.creds = columns
+            .creds
+            .iter()
+            .map(|spec| cred_mapping(table, spec))
+            .collect::<Result<_>>()?;
+    }
+    if let Some(document) = &columns.document {
+        mapping.document = Some(table.column(document)?);
+    }
+    Ok(mapping)
+}
+
+// `name-column:secret-column`, or `=name:secret-column` for a fixed name
+fn cred_mapping(table: &CsvTable, spec: &str) -> Result<CredMapping> {
+    let (name, secret) = spec
+        .rsplit_once(':')
+        .ok_or_else(|| anyhow!(t!("_cli_bad_cred_columns %{s}", s = spec).to_string()))?;
+    Ok(CredMapping {
+        name: match name.strip_prefix('=') {
+            Some(fixed) => CredName::Fixed(fixed.to_string()),
+            None => CredName::Column(table.column(name)?),
+        },
+        secret: table.column(secret)?,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::mapping;
+    use crate::{
+        args::CsvColumns,
+        import::{CredMapping, CredName, CsvTable},
+    };
+
+    #[test]
+    fn test_mapping() {
+        let table = CsvTable::parse("Account,Login,Secret,PIN,Remarks\n").unwrap();
+        let columns = CsvColumns {
+            key: None,
+            description: vec!["remarks".to_string()],
+            creds: vec!["Login:Secret".to_string(), "=pin:4".to_string()],
+            document: None,
+        };
+        let csv_mapping = mapping(&table, &columns).unwrap();
+        assert_eq!(csv_mapping.key, 0);
+        assert_eq!(csv_mapping.description, vec![4]);
+        assert_eq!(
+            csv_mapping.creds,
+            vec![
+                CredMapping {
+                    name: CredName::Column(1),
+                    secret: 2
+                },
+                CredMapping {
+                    name: CredName::Fixed("pin".to_string()),
+                    secret: 3
+                }
+            ]
+        );
+        let columns = CsvColumns {
+            creds: vec!["Secret".to_string()],
+            ..CsvColumns::default()
+        };
+        assert!(mapping(&table, &columns).is_err());
+    }
+}
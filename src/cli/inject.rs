@@ -0,0 +1,124 @@
+//! Renders a template by replacing the placeholders with secrets and documents from the file.
+//!
+//! `{{ prolock://<entry>/<cred-name> }}` is replaced with the secret of the credential,
+//! `{{ prolock-doc://<document> }}` with the text of the document.
+//! Other `{{ ... }}` constructs are left untouched.
+
+use super::{CliError, cred_reference, cred_secret, document_not_found};
+use crate::data::{Key, PlFile};
+use anyhow::{Context, Result, anyhow};
+
+const OPEN: &str = "{{";
+const CLOSE: &str = "}}";
+const CRED_SCHEME: &str = "prolock://";
+const DOCUMENT_SCHEME: &str = "prolock-doc://";
+
+// Fails if any placeholder cannot be resolved, and then names all of them
+pub(super) fn render(pl_file: &PlFile, template: &str) -> Result<String> {
+    let mut output = String::with_capacity(template.len());
+    let mut unresolved = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find(OPEN) {
+        output.push_str(&rest[..start]);
+        let after_open = &rest[start + OPEN.len()..];
+        let Some(end) = after_open.find(CLOSE) else {
+            if is_ours(after_open.trim_start()) {
+                return Err(anyhow!(t!("_cli_unterminated_placeholder").to_string()));
+            }
+            rest = &rest[start..];
+            break;
+        };
+        let placeholder = &rest[start..start + OPEN.len() + end + CLOSE.len()];
+        match resolve(pl_file, after_open[..end].trim()) {
+            None => output.push_str(placeholder),
+            Some(Ok(value)) => output.push_str(&value),
+            Some(Err(e)) => unresolved.push(format!("{placeholder}: {e}")),
+        }
+        rest = &rest[start + placeholder.len()..];
+    }
+    output.push_str(rest);
+    if unresolved.is_empty() {
+        Ok(output)
+    } else {
+        Err(CliError::NotFound(unresolved.join("\n")).into())
+    }
+}
+
+fn is_ours(text: &str) -> bool {
+    text.starts_with(CRED_SCHEME) || text.starts_with(DOCUMENT_SCHEME)
+}
+
+// Returns None if the placeholder is not a reference to the file
+fn resolve(pl_file: &PlFile, placeholder: &str) -> Option<Result<String>> {
+    if let Some(reference) = placeholder.strip_prefix(CRED_SCHEME) {
+        Some(
+            cred_reference(reference)
+                .ok_or_else(|| anyhow!(t!("_cli_bad_reference %{r}", r = reference).to_string()))
+                .and_then(|(entry, cred)| cred_secret(pl_file, entry, cred)),
+        )
+    } else if let Some(name) = placeholder.strip_prefix(DOCUMENT_SCHEME) {
+        let key = Key::new(name);
+        Some(
+            pl_file
+                .documents()
+                .get(&key)
+                .ok_or_else(|| document_not_found(&key).into())
+                .and_then(|document| {
+                    Ok(document
+                        .text(pl_file.transient().context("not unlocked")?)
+                        .to_string())
+                }),
+        )
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::render;
+    use crate::{
+        data::{Key, PlFile},
+        ui::viz::{VEditBundle, VEditCred, VEditDocument},
+    };
+
+    #[test]
+    fn test_render() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut pl_file = PlFile::read_or_create(&temp_dir.path().join("inject")).unwrap();
+        pl_file.set_actionable("pw", None).unwrap();
+        let mut v_edit_bundle = VEditBundle::new();
+        v_edit_bundle.key = Key::new("Prod DB");
+        v_edit_bundle.v_edit_creds = vec![VEditCred {
+            name: "admin".to_string(),
+            secret: "s3cret".to_string(),
+        }];
+        pl_file.save_with_added_bundle(&v_edit_bundle).unwrap();
+        let key = Key::new("cert");
+        pl_file
+            .save_with_added_document(&VEditDocument {
+                orig_key: key.clone(),
+                key,
+                text: "-----BEGIN-----".to_string(),
+                request_focus: false,
+            })
+            .unwrap();
+
+        assert_eq!(
+            render(
+                &pl_file,
+                "pw={{ prolock://Prod DB/admin }}\ncert={{prolock-doc://cert}}\n{{ other }}"
+            )
+            .unwrap(),
+            "pw=s3cret\ncert=-----BEGIN-----\n{{ other }}"
+        );
+        let error = render(
+            &pl_file,
+            "{{ prolock://Prod DB/root }} {{ prolock-doc://key }}",
+        )
+        .unwrap_err()
+        .to_string();
+        assert_eq!(error.lines().count(), 2);
+        assert!(render(&pl_file, "{{ prolock://Prod DB/admin").is_err());
+    }
+}
@@ -0,0 +1,61 @@
+//! Merges another file, e.g. a copy from another machine, into the current one.
+
+use super::{
+    CliError,
+    output::{MergeReport, print},
+};
+use crate::{
+    args::Format,
+    data::{Comparison, Key, PlFile},
+};
+use anyhow::{Context, Result};
+use rpassword::prompt_password;
+use std::path::Path;
+
+// Takes over the given entries and documents, or else the proposed ones,
+// or only prints the comparison
+pub(super) fn run(
+    pl_file: &mut PlFile,
+    file: &str,
+    o_key_file: Option<&str>,
+    entries: &[String],
+    documents: &[String],
+    dry_run: bool,
+    format: Format,
+) -> Result<()> {
+    let password = prompt_password(t!("_merge_password") + " ")?;
+    let theirs = pl_file.open_other(Path::new(file), &password, o_key_file.map(Path::new))?;
+    let comparison = Comparison::new(pl_file, &theirs).context("not unlocked")?;
+
+    let (bundle_keys, document_keys) = if entries.is_empty() && documents.is_empty() {
+        comparison.proposal()
+    } else {
+        (
+            entries.iter().map(Key::new).collect::<Vec<_>>(),
+            documents.iter().map(Key::new).collect::<Vec<_>>(),
+        )
+    };
+    if let Some(key) = bundle_keys.iter().find(|key| !theirs.has_bundle(key)) {
+        return Err(CliError::NotFound(
+            t!("_bundle_does_not_exist %{name}", name = key).to_string(),
+        )
+        .into());
+    }
+    if let Some(key) = document_keys.iter().find(|key| !theirs.has_document(key)) {
+        return Err(super::document_not_found(key).into());
+    }
+
+    let has_selection = !bundle_keys.is_empty() || !document_keys.is_empty();
+    let saved = !dry_run && has_selection;
+    if saved {
+        pl_file.save_with_merged_file(&theirs, &bundle_keys, &document_keys)?;
+    }
+    let merge_report = MergeReport::new(
+        &comparison,
+        &bundle_keys,
+        &document_keys,
+        dry_run,
+        saved.then_some(&*pl_file),
+    );
+    print(&merge_report, format)
+}
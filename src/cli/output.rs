@@ -0,0 +1,653 @@
+//! The output of the command line operations, as text or as JSON.
+//!
+//! The JSON structures are documented in `doc/json_output.md`; every structure has
+//! a field `version`, which is incremented with incompatible changes.
+
+use crate::{
+    agent,
+    args::Format,
+    data::{
+        Bundle, Bundles, Compared, Comparison, Difference, Documents, Import, Key, KeySlot,
+        Outcome, PlFile, Plan, Transient,
+    },
+};
+use anyhow::Result;
+use jiff::Zoned;
+
+pub const JSON_FORMAT_VERSION: u8 = 1;
+
+// Output of a command, which can be printed as text or as JSON
+pub trait Output: serde::Serialize {
+    fn print_text(&self);
+}
+
+pub fn print<O: Output>(output: &O, format: Format) -> Result<()> {
+    match format {
+        Format::Text => output.print_text(),
+        Format::Json => println!(
+            "{}",
+            serde_json::to_string_pretty(&Versioned {
+                version: JSON_FORMAT_VERSION,
+                output,
+            })?
+        ),
+    }
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct Versioned<'a, O: serde::Serialize> {
+    version: u8,
+    #[serde(flatten)]
+    output: &'a O,
+}
+
+#[derive(Serialize)]
+pub struct KnownFiles {
+    pub files: Vec<KnownFile>,
+}
+#[derive(Serialize)]
+pub struct KnownFile {
+    pub path: String,
+    pub default: bool,
+    pub current: bool,
+}
+impl Output for KnownFiles {
+    fn print_text(&self) {
+        for file in &self.files {
+            println!(
+                "{} {}{}",
+                file.path,
+                if file.default { "(default)" } else { "" },
+                if file.current { "(current)" } else { "" },
+            );
+        }
+    }
+}
+
+// What can be read without the password
+#[derive(Serialize)]
+pub struct Info {
+    pub file: FileInfo,
+    // are empty if the file is concealed
+    pub entries: Vec<EntryInfo>,
+    pub documents: Vec<DocumentInfo>,
+}
+#[derive(Serialize)]
+pub struct FileInfo {
+    pub path: String,
+    pub format_version: u8,
+    pub update_counter: usize,
+    pub concealed: bool,
+    pub key_slots: Vec<KeySlotInfo>,
+}
+impl Info {
+    pub fn new(pl_file: &PlFile) -> Self {
+        Info {
+            file: FileInfo {
+                path: pl_file.file_path(),
+                format_version: pl_file.format_version(),
+                update_counter: pl_file.update_counter().peek().unwrap_or_default(),
+                concealed: pl_file.is_concealed(),
+                key_slots: KeySlots::new(pl_file.key_slots()).key_slots,
+            },
+            entries: Entries::new(pl_file.bundles()).entries,
+            documents: DocumentList::new(pl_file.documents()).documents,
+        }
+    }
+}
+impl Output for Info {
+    fn print_text(&self) {
+        println!("{} {}", t!("_cli_info_file"), self.file.path);
+        println!(
+            "{} {}{}",
+            t!("_cli_info_format_version"),
+            self.file.format_version,
+            if self.file.concealed {
+                format!(" ({})", t!("_cli_info_concealed"))
+            } else {
+                String::new()
+            }
+        );
+        println!(
+            "{} {}",
+            t!("_cli_info_update_counter"),
+            self.file.update_counter
+        );
+        println!(
+            "{} {}",
+            t!("_cli_info_key_slots"),
+            self.file
+                .key_slots
+                .iter()
+                .map(|key_slot| key_slot.label.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        if !self.file.concealed {
+            println!("{}", t!("_cli_info_entries"));
+            for entry in &self.entries {
+                println!("  {} ({})", entry.name, entry.last_changed_at);
+            }
+            println!("{}", t!("_cli_info_documents"));
+            for document in &self.documents {
+                println!("  {} ({})", document.name, document.last_changed_at);
+            }
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct KeySlots {
+    pub key_slots: Vec<KeySlotInfo>,
+}
+#[derive(Serialize)]
+pub struct KeySlotInfo {
+    pub label: String,
+    pub uses_key_file: bool,
+}
+impl KeySlots {
+    pub fn new(key_slots: &[KeySlot]) -> Self {
+        KeySlots {
+            key_slots: key_slots
+                .iter()
+                .map(|key_slot| KeySlotInfo {
+                    label: key_slot.label().to_string(),
+                    uses_key_file: key_slot.uses_key_file(),
+                })
+                .collect(),
+        }
+    }
+}
+impl Output for KeySlots {
+    fn print_text(&self) {
+        for key_slot in &self.key_slots {
+            println!(
+                "{}{}",
+                key_slot.label,
+                if key_slot.uses_key_file {
+                    format!(" ({})", t!("_key_slot_uses_key_file"))
+                } else {
+                    String::new()
+                }
+            );
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Entries {
+    pub entries: Vec<EntryInfo>,
+}
+#[derive(Debug, Deserialize, Serialize)]
+pub struct EntryInfo {
+    pub name: String,
+    pub description: String,
+    pub last_changed_at: Zoned,
+}
+impl Entries {
+    pub fn new(bundles: &Bundles) -> Self {
+        Entries {
+            entries: bundles
+                .iter()
+                .map(|(key, bundle)| EntryInfo {
+                    name: key.to_string(),
+                    description: bundle.description().to_string(),
+                    last_changed_at: bundle.last_changed_at().clone(),
+                })
+                .collect(),
+        }
+    }
+}
+impl Output for Entries {
+    fn print_text(&self) {
+        for entry in &self.entries {
+            println!("{}", entry.name);
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Entry {
+    pub entry: EntryDetails,
+}
+#[derive(Debug, Deserialize, Serialize)]
+pub struct EntryDetails {
+    pub name: String,
+    pub description: String,
+    pub last_changed_at: Zoned,
+    pub creds: Vec<CredDetails>,
+}
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CredDetails {
+    pub name: String,
+    // is only given if the secrets are revealed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+}
+impl Entry {
+    // the secrets are only given if reveal is set
+    pub fn new(name: &str, bundle: &Bundle, transient: &Transient, reveal: bool) -> Self {
+        Entry {
+            entry: EntryDetails {
+                name: name.to_string(),
+                description: bundle.description().to_string(),
+                last_changed_at: bundle.last_changed_at().clone(),
+                creds: bundle
+                    .creds()
+                    .iter()
+                    .map(|cred| CredDetails {
+                        name: cred.name(transient).to_string(),
+                        secret: reveal.then(|| cred.secret(transient).to_string()),
+                    })
+                    .collect(),
+            },
+        }
+    }
+}
+impl Output for Entry {
+    fn print_text(&self) {
+        println!("{}", self.entry.name);
+        for line in self.entry.description.lines() {
+            println!("    {line}");
+        }
+        for cred in &self.entry.creds {
+            println!(
+                "  {}: {}",
+                cred.name,
+                cred.secret.as_deref().unwrap_or("********")
+            );
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Secret {
+    pub secret: String,
+}
+impl Output for Secret {
+    fn print_text(&self) {
+        println!("{}", self.secret);
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct TotpCode {
+    pub code: String,
+    pub remaining_seconds: u64,
+}
+impl Output for TotpCode {
+    fn print_text(&self) {
+        println!("{}", self.code);
+    }
+}
+
+#[derive(Serialize)]
+pub struct DocumentList {
+    pub documents: Vec<DocumentInfo>,
+}
+#[derive(Serialize)]
+pub struct DocumentInfo {
+    pub name: String,
+    pub last_changed_at: Zoned,
+}
+impl DocumentList {
+    pub fn new(documents: &Documents) -> Self {
+        DocumentList {
+            documents: documents
+                .iter()
+                .map(|(key, document)| DocumentInfo {
+                    name: key.to_string(),
+                    last_changed_at: document.last_changed_at().clone(),
+                })
+                .collect(),
+        }
+    }
+}
+impl Output for DocumentList {
+    fn print_text(&self) {
+        for document in &self.documents {
+            println!("{}", document.name);
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct DocumentText {
+    pub document: DocumentDetails,
+}
+#[derive(Debug, Deserialize, Serialize)]
+pub struct DocumentDetails {
+    pub name: String,
+    pub last_changed_at: Zoned,
+    pub text: String,
+}
+impl Output for DocumentText {
+    fn print_text(&self) {
+        print!("{}", self.document.text);
+    }
+}
+
+// Is printed after the file was changed, only as JSON
+#[derive(Serialize)]
+pub struct Saved {
+    pub update_counter: usize,
+}
+impl Saved {
+    pub fn new(pl_file: &PlFile) -> Self {
+        Saved {
+            update_counter: pl_file.update_counter().peek().unwrap_or_default(),
+        }
+    }
+}
+impl Output for Saved {
+    fn print_text(&self) {}
+}
+
+// agent is None if no agent is running
+#[derive(Serialize)]
+pub struct AgentStatus {
+    pub agent: Option<agent::Status>,
+}
+impl Output for AgentStatus {
+    fn print_text(&self) {
+        match &self.agent {
+            Some(status) => println!(
+                "{}",
+                t!(
+                    "_agent_status %{f} %{m}",
+                    f = status.file,
+                    m = status.locks_in_secs.div_ceil(60)
+                )
+            ),
+            None => println!("{}", t!("_agent_not_running")),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct AgentLocked {
+    pub was_running: bool,
+}
+impl Output for AgentLocked {
+    fn print_text(&self) {
+        if self.was_running {
+            println!("{}", t!("_agent_locked"));
+        } else {
+            println!("{}", t!("_agent_not_running"));
+        }
+    }
+}
+
+// What is or would be imported; update_counter is missing with a dry run
+#[derive(Serialize)]
+pub struct ImportReport {
+    pub entries: Vec<ImportedItem>,
+    pub documents: Vec<ImportedItem>,
+    // what could not be imported
+    pub skipped: Vec<String>,
+    pub dry_run: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub update_counter: Option<usize>,
+    #[serde(skip)]
+    summary: String,
+}
+#[derive(Serialize)]
+pub struct ImportedItem {
+    pub name: String,
+    // one of "add", "rename", "merge", "skip"
+    pub outcome: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub renamed_to: Option<String>,
+}
+impl ImportReport {
+    // o_pl_file is the file after the import, or None with a dry run
+    pub fn new(import: &Import, plan: &Plan, o_pl_file: Option<&PlFile>) -> Self {
+        let items = |keys: Vec<&Key>, outcomes: &[Outcome]| {
+            keys.into_iter()
+                .zip(outcomes)
+                .map(|(key, outcome)| ImportedItem {
+                    name: key.to_string(),
+                    outcome: match outcome {
+                        Outcome::Add => "add",
+                        Outcome::Rename(_) => "rename",
+                        Outcome::Merge => "merge",
+                        Outcome::Skip => "skip",
+                    },
+                    renamed_to: match outcome {
+                        Outcome::Rename(new_key) => Some(new_key.to_string()),
+                        _ => None,
+                    },
+                })
+                .collect()
+        };
+        ImportReport {
+            entries: items(
+                import.bundles.iter().map(|b| &b.key).collect(),
+                &plan.bundles,
+            ),
+            documents: items(
+                import.documents.iter().map(|d| &d.key).collect(),
+                &plan.documents,
+            ),
+            skipped: import.skipped.clone(),
+            dry_run: o_pl_file.is_none(),
+            update_counter: o_pl_file
+                .map(|pl_file| pl_file.update_counter().peek().unwrap_or_default()),
+            summary: plan.summary(),
+        }
+    }
+}
+impl Output for ImportReport {
+    fn print_text(&self) {
+        for (prefix, items) in [("", &self.entries), ("doc ", &self.documents)] {
+            for item in items {
+                match &item.renamed_to {
+                    Some(new_name) => {
+                        println!("{:<7}{prefix}{} -> {new_name}", item.outcome, item.name);
+                    }
+                    None => println!("{:<7}{prefix}{}", item.outcome, item.name),
+                }
+            }
+        }
+        for skipped in &self.skipped {
+            println!("! {skipped}");
+        }
+        println!("{}", self.summary);
+        if self.dry_run {
+            println!("{}", t!("_import_dry_run"));
+        }
+    }
+}
+
+// The comparison with the other file, and what is or would be taken over from it;
+// update_counter is missing if nothing was saved
+#[derive(Serialize)]
+pub struct MergeReport {
+    pub entries: Vec<MergedItem>,
+    pub documents: Vec<MergedItem>,
+    pub dry_run: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub update_counter: Option<usize>,
+}
+#[derive(Serialize)]
+pub struct MergedItem {
+    pub name: String,
+    // one of "only_mine", "only_theirs", "identical", "differing"
+    pub difference: &'static str,
+    // with "differing": "mine" or "theirs"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub newer: Option<&'static str>,
+    pub taken: bool,
+}
+impl MergeReport {
+    // o_pl_file is the file after the merge, or None if nothing was saved
+    pub fn new(
+        comparison: &Comparison,
+        bundle_keys: &[Key],
+        document_keys: &[Key],
+        dry_run: bool,
+        o_pl_file: Option<&PlFile>,
+    ) -> Self {
+        let items = |compared: &[Compared], keys: &[Key]| {
+            compared
+                .iter()
+                .map(|c| MergedItem {
+                    name: c.key.to_string(),
+                    difference: match c.difference {
+                        Difference::OnlyMine => "only_mine",
+                        Difference::OnlyTheirs => "only_theirs",
+                        Difference::Identical => "identical",
+                        Difference::Differing => "differing",
+                    },
+                    newer: (c.difference == Difference::Differing).then(|| {
+                        if c.theirs_is_newer() {
+                            "theirs"
+                        } else {
+                            "mine"
+                        }
+                    }),
+                    taken: keys.contains(&c.key),
+                })
+                .collect()
+        };
+        MergeReport {
+            entries: items(&comparison.bundles, bundle_keys),
+            documents: items(&comparison.documents, document_keys),
+            dry_run,
+            update_counter: o_pl_file
+                .map(|pl_file| pl_file.update_counter().peek().unwrap_or_default()),
+        }
+    }
+}
+impl Output for MergeReport {
+    fn print_text(&self) {
+        for (prefix, items) in [("", &self.entries), ("doc ", &self.documents)] {
+            for item in items {
+                let taken = if item.taken { "<-" } else { "" };
+                let newer = match item.newer {
+                    Some("theirs") => format!(" ({})", t!("_merge_newer_theirs")),
+                    Some(_) => format!(" ({})", t!("_merge_newer_mine")),
+                    None => String::new(),
+                };
+                println!(
+                    "{taken:<3}{:<12}{prefix}{}{newer}",
+                    item.difference, item.name
+                );
+            }
+        }
+        let count = |items: &[MergedItem]| items.iter().filter(|item| item.taken).count();
+        println!(
+            "{}",
+            t!(
+                "_merge_summary %{e} %{d}",
+                e = count(&self.entries),
+                d = count(&self.documents)
+            )
+        );
+        if self.dry_run {
+            println!("{}", t!("_import_dry_run"));
+        }
+    }
+}
+
+// What was written in plain text
+#[derive(Serialize)]
+pub struct Exported {
+    pub file: String,
+    pub entries: usize,
+    pub documents: usize,
+}
+impl Output for Exported {
+    fn print_text(&self) {
+        println!(
+            "{}",
+            t!(
+                "_exported %{e} %{d} %{f}",
+                e = self.entries,
+                d = self.documents,
+                f = self.file
+            )
+        );
+    }
+}
+
+// The new file with a part of the entries and documents
+#[derive(Serialize)]
+pub struct ExportedVault {
+    pub file: String,
+    pub entries: usize,
+    pub documents: usize,
+    pub recovery_code: String,
+    pub registered: bool,
+}
+impl Output for ExportedVault {
+    fn print_text(&self) {
+        println!(
+            "{}",
+            t!(
+                "_exported_vault %{e} %{d} %{f}",
+                e = self.entries,
+                d = self.documents,
+                f = self.file
+            )
+        );
+        println!("{}: {}", t!("_recovery_code"), self.recovery_code);
+        println!("{}", t!("_recovery_code_hint"));
+        if self.registered {
+            println!("{}", t!("_exported_vault_registered"));
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct ExportedPackage {
+    pub file: String,
+    pub entries: usize,
+    pub documents: usize,
+    pub recipients: usize,
+}
+impl Output for ExportedPackage {
+    fn print_text(&self) {
+        println!(
+            "{}",
+            t!(
+                "_exported_package %{e} %{d} %{f} %{r}",
+                e = self.entries,
+                d = self.documents,
+                f = self.file,
+                r = self.recipients
+            )
+        );
+    }
+}
+
+#[derive(Serialize)]
+pub struct Identity {
+    // None if the file has no identity yet
+    pub public_key: Option<String>,
+}
+impl Output for Identity {
+    fn print_text(&self) {
+        match &self.public_key {
+            Some(public_key) => println!("{public_key}"),
+            None => println!("{}", t!("_cli_no_identity")),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct Failure {
+    pub error: ErrorInfo,
+}
+#[derive(Serialize)]
+pub struct ErrorInfo {
+    // one of "wrong_password", "not_found", "concurrent_update", "other"
+    pub kind: &'static str,
+    pub message: String,
+}
+impl Output for Failure {
+    fn print_text(&self) {
+        eprintln!("Error: {}", self.error.message);
+    }
+}
@@ -0,0 +1,187 @@
+//! The subcommands that the agent executes on the file it holds, so that it never needs
+//! to hand out the key of the file.
+//!
+//! Without an agent, they are executed on the file that is opened with the password.
+//! What is done with the answer, e.g. running a command, is always done by the command line.
+
+use super::{
+    bundle, cred_secret, document_text, env,
+    git_credential::{self, GitRequest},
+    inject,
+    output::{DocumentText, Entries, Entry, Secret, TotpCode},
+    totp_code,
+};
+use crate::{
+    args::{CliCommand, Format, GitCredentialOp},
+    data::PlFile,
+    util::write_private_file,
+};
+use anyhow::{Context, Result, anyhow};
+use std::{io::stdin, path::Path};
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "query", rename_all = "snake_case")]
+pub(crate) enum Query {
+    List,
+    Show {
+        entry: String,
+        reveal: bool,
+    },
+    Get {
+        entry: String,
+        cred: String,
+    },
+    Totp {
+        entry: String,
+        cred: String,
+    },
+    GitCredential {
+        operation: GitCredentialOp,
+        naming_rule: String,
+        request: GitRequest,
+    },
+    DocCat {
+        document: String,
+    },
+    Inject {
+        template: String,
+        // is only used by the command line
+        #[serde(skip)]
+        output: Option<String>,
+    },
+    Run {
+        env: Vec<String>,
+        // is only used by the command line
+        #[serde(skip)]
+        command: Vec<String>,
+    },
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(tag = "answer", rename_all = "snake_case")]
+pub(crate) enum Answer {
+    Entries(Entries),
+    Entry(Entry),
+    Secret(Secret),
+    TotpCode(TotpCode),
+    // what git reads from stdout
+    GitCredential { output: String },
+    DocumentText(DocumentText),
+    Rendered { text: String },
+    // the variables with which the command is run
+    Env { vars: Vec<(String, String)> },
+}
+
+impl Query {
+    // is None for the subcommands that the agent does not execute
+    pub(super) fn new(command: &CliCommand) -> Result<Option<Self>> {
+        Ok(Some(match command {
+            CliCommand::List => Query::List,
+            CliCommand::Show { entry, reveal } => Query::Show {
+                entry: entry.clone(),
+                reveal: *reveal,
+            },
+            CliCommand::Get { entry, cred } => Query::Get {
+                entry: entry.clone(),
+                cred: cred.clone(),
+            },
+            CliCommand::Totp { entry, cred } => Query::Totp {
+                entry: entry.clone(),
+                cred: cred.clone(),
+            },
+            CliCommand::GitCredential {
+                operation,
+                naming_rule,
+            } => Query::GitCredential {
+                operation: *operation,
+                naming_rule: naming_rule.clone(),
+                // the request is read before the password is prompted for
+                request: GitRequest::read(stdin().lock())?,
+            },
+            CliCommand::DocCat { document } => Query::DocCat {
+                document: document.clone(),
+            },
+            CliCommand::Inject { input, output } => Query::Inject {
+                // the template is read before the password is prompted for
+                template: std::fs::read_to_string(input).context(format!("reading {input}"))?,
+                output: output.clone(),
+            },
+            CliCommand::Run { env, command } => Query::Run {
+                env: env.clone(),
+                command: command.clone(),
+            },
+            _ => return Ok(None),
+        }))
+    }
+
+    // `git-credential store` and `erase` can save the file
+    pub(super) fn changes_file(&self) -> bool {
+        matches!(self, Query::GitCredential { operation, .. } if *operation != GitCredentialOp::Get)
+    }
+
+    pub(crate) fn answer(&self, pl_file: &mut PlFile) -> Result<Answer> {
+        Ok(match self {
+            Query::List => Answer::Entries(Entries::new(pl_file.bundles())),
+            Query::Show { entry, reveal } => Answer::Entry(Entry::new(
+                entry,
+                bundle(pl_file, entry)?,
+                pl_file.transient().context("not unlocked")?,
+                *reveal,
+            )),
+            Query::Get { entry, cred } => Answer::Secret(Secret {
+                secret: cred_secret(pl_file, entry, cred)?,
+            }),
+            Query::Totp { entry, cred } => Answer::TotpCode(totp_code(pl_file, entry, cred)?),
+            Query::GitCredential {
+                operation,
+                naming_rule,
+                request,
+            } => {
+                let mut output = Vec::new();
+                git_credential::run(pl_file, *operation, naming_rule, request, &mut output)?;
+                Answer::GitCredential {
+                    output: String::from_utf8(output)?,
+                }
+            }
+            Query::DocCat { document } => {
+                Answer::DocumentText(document_text(pl_file, document.clone())?)
+            }
+            Query::Inject { template, .. } => Answer::Rendered {
+                text: inject::render(pl_file, template)?,
+            },
+            Query::Run { env, .. } => Answer::Env {
+                vars: env::resolve(pl_file, env)?,
+            },
+        })
+    }
+}
+
+impl Answer {
+    // `run` does not return, but exits with the exit code of the command
+    pub(super) fn deliver(&self, query: &Query, format: Format) -> Result<()> {
+        match (self, query) {
+            (Answer::Entries(entries), _) => super::print(entries, format),
+            (Answer::Entry(entry), _) => super::print(entry, format),
+            (Answer::Secret(secret), _) => super::print(secret, format),
+            (Answer::TotpCode(totp_code), _) => super::print(totp_code, format),
+            (Answer::DocumentText(document_text), _) => super::print(document_text, format),
+            (Answer::GitCredential { output: text }, _)
+            | (Answer::Rendered { text }, Query::Inject { output: None, .. }) => {
+                print!("{text}");
+                Ok(())
+            }
+            (
+                Answer::Rendered { text },
+                Query::Inject {
+                    output: Some(output),
+                    ..
+                },
+            ) => write_private_file(Path::new(output), text),
+            (Answer::Env { vars }, Query::Run { command, .. }) => {
+                // the exit code of the command is passed on
+                std::process::exit(env::run(vars, command)?);
+            }
+            (answer, query) => Err(anyhow!("answer {answer:?} does not fit to {query:?}")),
+        }
+    }
+}
@@ -0,0 +1,180 @@
+use crate::data::PlFile;
+use anyhow::{Context, Result};
+use jiff::{Timestamp, Zoned, tz::TimeZone};
+use std::{
+    fs::{copy, create_dir_all, read_dir, remove_file},
+    path::{Path, PathBuf},
+};
+
+// Number of backups that are kept if nothing else is configured
+pub(crate) const DEFAULT_BACKUP_GENERATIONS: usize = 5;
+const BACKUP_FOLDER_SUFFIX: &str = "_backups";
+
+// Describes a backup copy of the file; is read from the readable section, without password
+#[derive(Clone, Debug)]
+pub(crate) struct Backup {
+    pub path: PathBuf,
+    pub update_counter: usize,
+    pub created_at: Zoned,
+    // is None if the file conceals its entries
+    pub o_entries: Option<usize>,
+}
+
+// Backups are kept in a folder next to the file, e.g. `secrets_backups` for `secrets`
+pub(crate) fn backup_folder(file_path: &Path) -> Result<PathBuf> {
+    let mut folder = PathBuf::from(file_path);
+    let mut name = folder.file_name().context("file name")?.to_owned();
+    name.push(BACKUP_FOLDER_SUFFIX);
+    folder.set_file_name(name);
+    Ok(folder)
+}
+
+// Copies the current state of the file into the backup folder,
+// and removes the oldest backups if there are more than `generations`
+pub(crate) fn create_backup(file_path: &Path, generations: usize) -> Result<()> {
+    if generations == 0 || !file_path.exists() {
+        return Ok(());
+    }
+    let folder = backup_folder(file_path)?;
+    create_dir_all(&folder).context("creating backup folder")?;
+
+    // the timestamp comes first so that the names sort chronologically
+    let mut name = Timestamp::now().strftime("%Y%m%dT%H%M%S%.9fZ_").to_string();
+    name.push_str(
+        &file_path
+            .file_name()
+            .context("file name")?
+            .to_string_lossy(),
+    );
+    copy(file_path, folder.join(name)).context("copying file to backup folder")?;
+
+    let mut backup_paths = backup_paths(&folder)?;
+    while backup_paths.len() > generations {
+        remove_file(backup_paths.remove(0)).context("removing old backup")?;
+    }
+    Ok(())
+}
+
+// Returns the backups of the file, the newest first;
+// backups that cannot be read, e.g. because they were only partly written, are skipped
+pub(crate) fn list_backups(file_path: &Path) -> Result<Vec<Backup>> {
+    let folder = backup_folder(file_path)?;
+    if !folder.exists() {
+        return Ok(Vec::new());
+    }
+    Ok(backup_paths(&folder)?
+        .into_iter()
+        .rev()
+        .filter_map(|path| {
+            read_backup(&path)
+                .inspect_err(|e| log::warn!("reading backup {}: {e:?}", path.display()))
+                .ok()
+        })
+        .collect())
+}
+
+fn read_backup(path: &Path) -> Result<Backup> {
+    let pl_file = PlFile::read_or_create(path)?;
+    let created_at =
+        Timestamp::try_from(path.metadata()?.modified()?)?.to_zoned(TimeZone::system());
+    Ok(Backup {
+        path: path.to_path_buf(),
+        update_counter: pl_file.update_counter().peek().unwrap_or_default(),
+        created_at,
+        o_entries: if pl_file.is_concealed() {
+            None
+        } else {
+            Some(pl_file.bundles().len())
+        },
+    })
+}
+
+// Removes the backups that show the names of entries and documents in clear, e.g. after
+// the file was switched to concealment; unreadable backups are removed as well, since they
+// might show names, too. Returns the number of removed backups.
+pub(crate) fn remove_open_backups(file_path: &Path) -> Result<usize> {
+    let folder = backup_folder(file_path)?;
+    if !folder.exists() {
+        return Ok(0);
+    }
+    let mut removed = 0;
+    for path in backup_paths(&folder)? {
+        if !PlFile::read_or_create(&path).is_ok_and(|pl_file| pl_file.is_concealed()) {
+            remove_file(&path).context(format!("removing backup {}", path.display()))?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+fn backup_paths(folder: &Path) -> Result<Vec<PathBuf>> {
+    let mut paths = read_dir(folder)
+        .context("reading backup folder")?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file())
+        .collect::<Vec<PathBuf>>();
+    paths.sort();
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{backup_folder, list_backups};
+    use crate::{
+        data::{Key, PlFile, Settings},
+        ui::viz::VEditBundle,
+    };
+
+    #[test]
+    fn test_backups() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let file_path = temp_dir.path().join("with_backups");
+
+        let mut pl_file = PlFile::read_or_create(&file_path).unwrap();
+        pl_file.set_backup_generations(2);
+        pl_file.set_actionable("pw", None).unwrap();
+        for name in ["a", "b", "c"] {
+            let mut edit_bundle = VEditBundle::new();
+            edit_bundle.key = Key::new(name);
+            pl_file.save_with_added_bundle(&edit_bundle).unwrap();
+        }
+
+        // only the two latest states before the current one are kept
+        let backups = list_backups(&file_path).unwrap();
+        assert_eq!(backups.len(), 2);
+        assert_eq!(backups[0].o_entries, Some(2));
+        assert_eq!(backups[1].o_entries, Some(1));
+        assert!(backups[0].update_counter > backups[1].update_counter);
+
+        // the restored state is saved as a new version
+        let update_counter = pl_file.update_counter().peek();
+        pl_file.save_with_restored_backup(&backups[1].path).unwrap();
+        assert_eq!(
+            pl_file.update_counter().peek(),
+            update_counter.map(|counter| counter + 1)
+        );
+        assert!(!Settings::temp_document_file(&file_path).unwrap().exists());
+        let mut pl_file = PlFile::read_or_create(&file_path).unwrap();
+        pl_file.set_actionable("pw", None).unwrap();
+        assert!(pl_file.has_bundle(&Key::new("a")));
+        assert!(!pl_file.has_bundle(&Key::new("b")));
+
+        // the state before the restore was backed up
+        let backups = list_backups(&file_path).unwrap();
+        assert_eq!(backups[0].o_entries, Some(3));
+
+        // a broken backup does not prevent listing the others
+        let broken = backup_folder(&file_path).unwrap().join("broken");
+        std::fs::write(&broken, "{ \"readable\": ").unwrap();
+        assert_eq!(list_backups(&file_path).unwrap().len(), 2);
+
+        // with concealment, the backups that show names in clear are removed
+        pl_file.save_with_concealment(true).unwrap();
+        assert!(!broken.exists());
+        assert!(list_backups(&file_path).unwrap().is_empty());
+        pl_file.save_with_concealment(false).unwrap();
+        let backups = list_backups(&file_path).unwrap();
+        assert_eq!(backups.len(), 1);
+        assert_eq!(backups[0].o_entries, None);
+    }
+}
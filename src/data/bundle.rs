@@ -30,6 +30,11 @@ impl Bundle {
     pub(crate) fn last_changed_at(&self) -> &Zoned {
         &self.last_changed_at
     }
+    // for imports that know when the bundle was changed last
+    pub(crate) fn with_last_changed_at(mut self, last_changed_at: Zoned) -> Self {
+        self.last_changed_at = last_changed_at;
+        self
+    }
 
     pub(super) fn refs(&self) -> Vec<u64> {
         self.creds
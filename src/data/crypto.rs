@@ -0,0 +1,203 @@
+use anyhow::{Context, Result, anyhow};
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::{Engine as _, engine::general_purpose::STANDARD_NO_PAD as b64};
+use chacha20poly1305::{
+    ChaCha20Poly1305,
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+};
+use rand::{Rng, rng};
+use secstr::SecVec;
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+
+const KEY_LENGTH: usize = 32;
+const SALT_LENGTH: usize = 16;
+const NONCE_LENGTH: usize = 12;
+
+// Argon2id parameters for newly written files;
+// m_cost is given in KiB, i.e., we use 64 MiB of memory
+#[cfg(not(test))]
+const ARGON2_M_COST: u32 = 65_536;
+#[cfg(test)]
+const ARGON2_M_COST: u32 = 256;
+const ARGON2_T_COST: u32 = 3;
+const ARGON2_P_COST: u32 = 4;
+
+// The key derivation function that is used to derive the encryption key from the password.
+//
+// The parameters are stored in the header of the file, so that they can be changed
+// for new files without breaking existing files.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "algorithm", rename_all = "lowercase")]
+pub(crate) enum Kdf {
+    Argon2id {
+        m_cost: u32,
+        t_cost: u32,
+        p_cost: u32,
+        salt: String,
+    },
+}
+impl Kdf {
+    // Argon2id with the current default parameters and a fresh random salt
+    pub fn new_argon2id() -> Self {
+        let mut salt = [0_u8; SALT_LENGTH];
+        rng().fill(&mut salt);
+        Kdf::Argon2id {
+            m_cost: ARGON2_M_COST,
+            t_cost: ARGON2_T_COST,
+            p_cost: ARGON2_P_COST,
+            salt: b64.encode(salt),
+        }
+    }
+
+    // The key file, if given, is used as additional input
+    pub fn derive_key(&self, password: &str, o_key_file: Option<&KeyFile>) -> Result<SecVec<u8>> {
+        let mut input = password.as_bytes().to_vec();
+        if let Some(key_file) = o_key_file {
+            input.extend_from_slice(key_file.digest.unsecure());
+        }
+        let input = SecVec::new(input);
+        match self {
+            Kdf::Argon2id {
+                m_cost,
+                t_cost,
+                p_cost,
+                salt,
+            } => {
+                let params = Params::new(*m_cost, *t_cost, *p_cost, Some(KEY_LENGTH))
+                    .map_err(|e| anyhow!("invalid argon2 parameters: {e}"))?;
+                let salt = b64.decode(salt).context("decoding salt")?;
+                let mut key = vec![0_u8; KEY_LENGTH];
+                Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+                    .hash_password_into(input.unsecure(), &salt, &mut key)
+                    .map_err(|e| anyhow!("key derivation failed: {e}"))?;
+                Ok(SecVec::new(key))
+            }
+        }
+    }
+}
+
+// A random key, e.g. for encrypting the secrets of a file
+pub(crate) fn random_key() -> SecVec<u8> {
+    let mut key = vec![0_u8; KEY_LENGTH];
+    rng().fill(&mut key[..]);
+    SecVec::new(key)
+}
+
+// A file whose content is used as a second factor, in addition to the password.
+//
+// The file header only contains a fingerprint of the key file, which allows a clear error message
+// if the key file is wrong. The fingerprint is computed differently than the digest that is
+// used for the key derivation, so it does not reveal anything about the latter.
+#[derive(Clone, Debug)]
+pub(crate) struct KeyFile {
+    path: PathBuf,
+    digest: SecVec<u8>,
+    fingerprint: String,
+}
+impl KeyFile {
+    pub fn read(path: &Path) -> Result<Self> {
+        let content = SecVec::new(
+            std::fs::read(path).context(format!("reading key file {}", path.display()))?,
+        );
+        if content.unsecure().is_empty() {
+            return Err(anyhow!(t!("_key_file_empty %{f}", f = path.display())));
+        }
+        let digest = Sha256::new()
+            .chain_update(b"prolock key file")
+            .chain_update(content.unsecure())
+            .finalize();
+        let fingerprint = Sha256::new()
+            .chain_update(b"prolock key file fingerprint")
+            .chain_update(content.unsecure())
+            .finalize();
+        Ok(Self {
+            path: path.to_path_buf(),
+            digest: SecVec::new(digest.to_vec()),
+            fingerprint: b64.encode(&fingerprint[..8]),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn fingerprint(&self) -> &str {
+        &self.fingerprint
+    }
+}
+
+// Encrypts the secret with the given key, and returns a base64 encoded string
+// that contains the random nonce and the ciphertext.
+pub(crate) fn encrypt_with_key(key: &SecVec<u8>, secret: &[u8], auth: &[u8]) -> Result<String> {
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = ChaCha20Poly1305::new_from_slice(key.unsecure())
+        .map_err(|e| anyhow!("invalid key: {e}"))?
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: secret,
+                aad: auth,
+            },
+        )
+        .map_err(|e| anyhow!("encryption failed: {e}"))?;
+
+    let mut bytes = nonce.to_vec();
+    bytes.extend_from_slice(&ciphertext);
+    Ok(b64.encode(bytes))
+}
+
+// Reverts `encrypt_with_key()`.
+pub(crate) fn decrypt_with_key(key: &SecVec<u8>, cipher: &str, auth: &[u8]) -> Result<Vec<u8>> {
+    let bytes = b64.decode(cipher).context("decoding cipher")?;
+    if bytes.len() < NONCE_LENGTH {
+        return Err(anyhow!("cipher is too short"));
+    }
+    let (nonce, ciphertext) = bytes.split_at(NONCE_LENGTH);
+    ChaCha20Poly1305::new_from_slice(key.unsecure())
+        .map_err(|e| anyhow!("invalid key: {e}"))?
+        .decrypt(
+            nonce.into(),
+            Payload {
+                msg: ciphertext,
+                aad: auth,
+            },
+        )
+        .map_err(|e| anyhow!("decryption failed: {e}"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Kdf, KeyFile, decrypt_with_key, encrypt_with_key};
+
+    #[test]
+    fn test_derive_encrypt_decrypt() {
+        let kdf = Kdf::new_argon2id();
+        let key = kdf.derive_key("LOIUo98zkjhB", None).unwrap();
+        assert_eq!(key, kdf.derive_key("LOIUo98zkjhB", None).unwrap());
+        assert_ne!(key, kdf.derive_key("LOIUo98zkjhb", None).unwrap());
+        assert_ne!(
+            key,
+            Kdf::new_argon2id()
+                .derive_key("LOIUo98zkjhB", None)
+                .unwrap()
+        );
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let key_file_path = temp_dir.path().join("key_file");
+        std::fs::write(&key_file_path, "some content").unwrap();
+        let key_file = KeyFile::read(&key_file_path).unwrap();
+        assert_ne!(
+            key,
+            kdf.derive_key("LOIUo98zkjhB", Some(&key_file)).unwrap()
+        );
+
+        let secret = "daewörjlser,dk gjxlre.t98i1df.lskejr lewiri23r9ß iu4ötirjf";
+        let cipher = encrypt_with_key(&key, secret.as_bytes(), b"auth").unwrap();
+        assert_eq!(
+            decrypt_with_key(&key, &cipher, b"auth").unwrap(),
+            secret.as_bytes()
+        );
+        assert!(decrypt_with_key(&key, &cipher, b"other auth").is_err());
+    }
+}
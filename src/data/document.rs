@@ -27,6 +27,11 @@ impl Document {
     pub(crate) fn last_changed_at(&self) -> &Zoned {
         &self.last_changed_at
     }
+    // for imports that know when the document was changed last
+    pub(crate) fn with_last_changed_at(mut self, last_changed_at: Zoned) -> Self {
+        self.last_changed_at = last_changed_at;
+        self
+    }
 
     pub(super) fn reff(&self) -> u64 {
         self.secret.reff()
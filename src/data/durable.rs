@@ -0,0 +1,87 @@
+use anyhow::{Context, Result};
+use std::{
+    fs::{File, OpenOptions, rename},
+    io::Write,
+    path::Path,
+};
+
+// The steps of writing a file durably, at which tests can simulate a failure
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum SaveStep {
+    WriteTempFile,
+    SyncTempFile,
+    VerifyTempFile,
+    Rename,
+    SyncFolder,
+}
+
+// Writes the content to the file, and ensures that it has reached the disk
+pub(crate) fn write_synced(path: &Path, content: &[u8]) -> Result<()> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(path)
+        .context(format!("opening {}", path.display()))?;
+    file.write_all(content)
+        .context(format!("writing {}", path.display()))?;
+    fail_point(SaveStep::WriteTempFile)?;
+    file.sync_all()
+        .context(format!("syncing {}", path.display()))?;
+    fail_point(SaveStep::SyncTempFile)
+}
+
+// Replaces the target atomically with the source, and ensures that the rename has reached the
+// disk. If only the last step fails, the target is already replaced, so we only log the issue.
+pub(crate) fn replace(source: &Path, target: &Path) -> Result<()> {
+    fail_point(SaveStep::Rename)?;
+    rename(source, target).context(format!("renaming to {}", target.display()))?;
+    if let Err(e) = fail_point(SaveStep::SyncFolder).and_then(|()| sync_folder(target)) {
+        log::warn!("{}: {e:?}", t!("_sync_folder_failed"));
+    }
+    Ok(())
+}
+
+// A rename is only durable when the folder containing the file is synced
+#[cfg(unix)]
+fn sync_folder(path: &Path) -> Result<()> {
+    let folder = match path.parent() {
+        Some(folder) if !folder.as_os_str().is_empty() => folder,
+        _ => Path::new("."),
+    };
+    File::open(folder)
+        .and_then(|folder| folder.sync_all())
+        .context(format!("syncing {}", folder.display()))
+}
+// on Windows, directories cannot be opened as files; rename is durable there without it
+#[cfg(not(unix))]
+#[allow(clippy::unnecessary_wraps)]
+fn sync_folder(_path: &Path) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(not(test))]
+#[allow(clippy::unnecessary_wraps)]
+pub(crate) fn fail_point(_step: SaveStep) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+thread_local! {
+    static FAIL_AT: std::cell::Cell<Option<SaveStep>> = const { std::cell::Cell::new(None) };
+}
+
+#[cfg(test)]
+pub(crate) fn fail_point(step: SaveStep) -> Result<()> {
+    if FAIL_AT.with(std::cell::Cell::get) == Some(step) {
+        Err(anyhow::anyhow!("simulated failure at {step:?}"))
+    } else {
+        Ok(())
+    }
+}
+
+// Lets the next saves in the current thread fail at the given step
+#[cfg(test)]
+pub(crate) fn fail_at(o_step: Option<SaveStep>) {
+    FAIL_AT.with(|fail_at| fail_at.set(o_step));
+}
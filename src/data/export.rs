@@ -0,0 +1,111 @@
+use crate::{
+    data::{Import, Key, PlFile},
+    ui::viz::{VEditBundle, VEditCred, VEditDocument},
+};
+use anyhow::{Context, Result};
+use jiff::Zoned;
+
+// Identifies the export format; is incremented with incompatible changes
+pub(crate) const EXPORT_FORMAT_VERSION: u8 = 1;
+
+// The content of the file in plain text, as written by `prolock export json`;
+// importing it again restores the entries and documents with their change times
+#[derive(Serialize, Deserialize)]
+pub(crate) struct Export {
+    // identifies the format
+    #[serde(rename = "prolock_export")]
+    pub format_version: u8,
+    pub entries: Vec<ExportedEntry>,
+    pub documents: Vec<ExportedDocument>,
+}
+#[derive(Serialize, Deserialize)]
+pub(crate) struct ExportedEntry {
+    pub name: Key,
+    pub description: String,
+    pub creds: Vec<ExportedCred>,
+    pub last_changed_at: Zoned,
+}
+#[derive(Serialize, Deserialize)]
+pub(crate) struct ExportedCred {
+    pub name: String,
+    pub secret: String,
+}
+#[derive(Serialize, Deserialize)]
+pub(crate) struct ExportedDocument {
+    pub name: Key,
+    pub text: String,
+    pub last_changed_at: Zoned,
+}
+
+impl Export {
+    pub fn new(pl_file: &PlFile) -> Result<Self> {
+        let transient = pl_file.transient().context("not unlocked")?;
+        Ok(Self {
+            format_version: EXPORT_FORMAT_VERSION,
+            entries: pl_file
+                .bundles()
+                .iter()
+                .map(|(key, bundle)| ExportedEntry {
+                    name: key.clone(),
+                    description: bundle.description().to_string(),
+                    creds: bundle
+                        .creds()
+                        .iter()
+                        .map(|cred| ExportedCred {
+                            name: cred.name(transient).to_string(),
+                            secret: cred.secret(transient).to_string(),
+                        })
+                        .collect(),
+                    last_changed_at: bundle.last_changed_at().clone(),
+                })
+                .collect(),
+            documents: pl_file
+                .documents()
+                .iter()
+                .map(|(key, document)| ExportedDocument {
+                    name: key.clone(),
+                    text: document.text(transient).to_string(),
+                    last_changed_at: document.last_changed_at().clone(),
+                })
+                .collect(),
+        })
+    }
+
+    // Keeps only the given entries and documents
+    pub fn retain(&mut self, bundle_keys: &[Key], document_keys: &[Key]) {
+        self.entries
+            .retain(|entry| bundle_keys.contains(&entry.name));
+        self.documents
+            .retain(|document| document_keys.contains(&document.name));
+    }
+
+    pub fn into_import(self) -> Import {
+        let mut import = Import::default();
+        for entry in self.entries {
+            let mut v_edit_bundle = VEditBundle::new();
+            v_edit_bundle.orig_key = entry.name.clone();
+            v_edit_bundle.key = entry.name;
+            v_edit_bundle.description = entry.description;
+            v_edit_bundle.v_edit_creds = entry
+                .creds
+                .into_iter()
+                .map(|cred| VEditCred {
+                    name: cred.name,
+                    secret: cred.secret,
+                })
+                .collect();
+            import.bundles.push(v_edit_bundle);
+            import.bundles_changed_at.push(entry.last_changed_at);
+        }
+        for document in self.documents {
+            import.documents.push(VEditDocument {
+                orig_key: document.name.clone(),
+                key: document.name,
+                text: document.text,
+                request_focus: false,
+            });
+            import.documents_changed_at.push(document.last_changed_at);
+        }
+        import
+    }
+}
@@ -0,0 +1,157 @@
+use crate::{
+    data::{Key, PlFile},
+    ui::viz::{VEditBundle, VEditCred, VEditDocument},
+};
+use jiff::Zoned;
+use std::collections::BTreeSet;
+
+// What happens with an imported entry or document whose name exists already
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) enum OnConflict {
+    #[default]
+    Skip,
+    Rename,
+    Merge,
+}
+
+// Entries and documents that were read from another source, e.g. from a CSV file
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Import {
+    pub bundles: Vec<VEditBundle>,
+    pub documents: Vec<VEditDocument>,
+    // what could not be represented
+    pub skipped: Vec<String>,
+    // when the entries and documents were changed last, if the source knows it;
+    // either empty or in the order of the entries and documents
+    pub bundles_changed_at: Vec<Zoned>,
+    pub documents_changed_at: Vec<Zoned>,
+}
+
+// What happens with an imported entry or document
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Outcome {
+    Add,
+    Rename(Key),
+    Merge,
+    Skip,
+}
+
+// The outcomes, in the order of the entries and documents of the import
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Plan {
+    pub bundles: Vec<Outcome>,
+    pub documents: Vec<Outcome>,
+}
+impl Plan {
+    pub fn count(&self, outcome: &Outcome) -> usize {
+        let same = |o: &&Outcome| std::mem::discriminant(*o) == std::mem::discriminant(outcome);
+        self.bundles.iter().filter(same).count() + self.documents.iter().filter(same).count()
+    }
+
+    pub fn summary(&self) -> String {
+        t!(
+            "_import_summary %{a} %{r} %{m} %{s}",
+            a = self.count(&Outcome::Add),
+            r = self.count(&Outcome::Rename(Key::default())),
+            m = self.count(&Outcome::Merge),
+            s = self.count(&Outcome::Skip)
+        )
+        .to_string()
+    }
+}
+
+// Names compare case-insensitively, also among the imported entries and documents;
+// a later one that has the name of an earlier one is treated like a conflict with the file
+pub(crate) fn plan(import: &Import, on_conflict: OnConflict, pl_file: &PlFile) -> Plan {
+    let mut taken = BTreeSet::new();
+    let bundles = import
+        .bundles
+        .iter()
+        .map(|edit_bundle| {
+            outcome(&edit_bundle.key, on_conflict, &mut taken, |key| {
+                pl_file.has_bundle(key)
+            })
+        })
+        .collect();
+    let mut taken = BTreeSet::new();
+    let documents = import
+        .documents
+        .iter()
+        .map(|edit_document| {
+            outcome(&edit_document.key, on_conflict, &mut taken, |key| {
+                pl_file.has_document(key)
+            })
+        })
+        .collect();
+    Plan { bundles, documents }
+}
+
+fn outcome(
+    key: &Key,
+    on_conflict: OnConflict,
+    taken: &mut BTreeSet<Key>,
+    exists: impl Fn(&Key) -> bool,
+) -> Outcome {
+    let is_free = |key: &Key, taken: &BTreeSet<Key>| !exists(key) && !taken.contains(key);
+    if is_free(key, taken) {
+        taken.insert(key.clone());
+        return Outcome::Add;
+    }
+    match on_conflict {
+        OnConflict::Skip => Outcome::Skip,
+        OnConflict::Merge => Outcome::Merge,
+        OnConflict::Rename => {
+            let mut n = 2;
+            loop {
+                let new_key = Key::new(format!("{key} ({n})"));
+                if is_free(&new_key, taken) {
+                    taken.insert(new_key.clone());
+                    return Outcome::Rename(new_key);
+                }
+                n += 1;
+            }
+        }
+    }
+}
+
+// The description is appended, and so are the credentials, unless the same credential
+// exists already
+pub(crate) fn merge_bundle(mut existing: VEditBundle, imported: &VEditBundle) -> VEditBundle {
+    let description = imported.description.trim();
+    if !description.is_empty() && !existing.description.contains(description) {
+        if !existing.description.is_empty() {
+            existing.description.push('\n');
+        }
+        existing.description.push_str(description);
+    }
+    existing
+        .v_edit_creds
+        .retain(|cred| !(cred.name.is_empty() && cred.secret.is_empty()));
+    for cred in &imported.v_edit_creds {
+        if !existing
+            .v_edit_creds
+            .iter()
+            .any(|c| c.name == cred.name && c.secret == cred.secret)
+        {
+            existing.v_edit_creds.push(VEditCred {
+                name: cred.name.clone(),
+                secret: cred.secret.clone(),
+            });
+        }
+    }
+    existing
+}
+
+// The text is appended, unless it is contained already
+pub(crate) fn merge_document(
+    mut existing: VEditDocument,
+    imported: &VEditDocument,
+) -> VEditDocument {
+    if !existing.text.contains(&imported.text) {
+        if !existing.text.is_empty() && !existing.text.ends_with('\n') {
+            existing.text.push('\n');
+        }
+        existing.text.push_str(&imported.text);
+    }
+    existing
+}
@@ -0,0 +1,349 @@
+use crate::data::{
+    Kdf, KeyFile,
+    crypto::{decrypt_with_key, encrypt_with_key},
+};
+use age::{secrecy::ExposeSecret, x25519};
+use anyhow::{Context, Result, anyhow};
+use base64::{Engine as _, engine::general_purpose::STANDARD_NO_PAD as b64};
+use rand::{Rng, rng};
+use secstr::{SecUtf8, SecVec};
+
+// The label of the key slot that is created with a new file
+pub(crate) const DEFAULT_KEY_SLOT_LABEL: &str = "default";
+// The reserved label of the key slot that is opened with the recovery code
+pub(crate) const RECOVERY_KEY_SLOT_LABEL: &str = "recovery";
+
+// Crockford's base32 alphabet avoids characters that are easily confused when written down
+const RECOVERY_CODE_ALPHABET: &[u8] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+// 6 groups of 5 characters with 5 bits each, i.e. 150 bits of entropy
+const RECOVERY_CODE_GROUPS: usize = 6;
+const RECOVERY_CODE_GROUP_LENGTH: usize = 5;
+
+// Is returned when the password does not open the file
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct WrongPassword;
+impl std::fmt::Display for WrongPassword {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", t!("_no_key_slot_accepts_password"))
+    }
+}
+impl std::error::Error for WrongPassword {}
+
+// A key slot allows opening the file with its own password (and optional key file).
+//
+// The secrets are encrypted with a random data key. Each key slot has its own age identity,
+// which is encrypted with the key that is derived from the slot's password, and a copy of the
+// data key that is encrypted to the identity's public key. So slots can be added, changed and
+// revoked without affecting the other slots, and the data key can be replaced without
+// knowing the passwords of the other slots.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct KeySlot {
+    label: String,
+    kdf: Kdf,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    key_file_fingerprint: Option<String>,
+    wrapped_identity: String,
+    recipient: String,
+    sealed_key: String,
+}
+
+// What unlocking a key slot yields
+pub(crate) struct Unlocked {
+    pub label: String,
+    // allows unsealing the data key again after it was replaced
+    pub identity: SecUtf8,
+    pub data_key: SecVec<u8>,
+}
+
+impl KeySlot {
+    pub fn new(
+        label: &str,
+        password: &str,
+        o_key_file: Option<&KeyFile>,
+        data_key: &SecVec<u8>,
+    ) -> Result<Self> {
+        let kdf = Kdf::new_argon2id();
+        let slot_key = kdf.derive_key(password, o_key_file)?;
+        Self::with_slot_key(label, kdf, o_key_file, &slot_key, data_key)
+    }
+
+    // is used if the slot key was already derived
+    pub fn with_slot_key(
+        label: &str,
+        kdf: Kdf,
+        o_key_file: Option<&KeyFile>,
+        slot_key: &SecVec<u8>,
+        data_key: &SecVec<u8>,
+    ) -> Result<Self> {
+        let identity = x25519::Identity::generate();
+        let mut key_slot = Self::with_identity(
+            label,
+            kdf,
+            o_key_file,
+            slot_key,
+            &SecUtf8::from(identity.to_string().expose_secret()),
+        )?;
+        key_slot.seal(data_key)?;
+        Ok(key_slot)
+    }
+
+    fn with_identity(
+        label: &str,
+        kdf: Kdf,
+        o_key_file: Option<&KeyFile>,
+        slot_key: &SecVec<u8>,
+        identity: &SecUtf8,
+    ) -> Result<Self> {
+        let recipient = parse_identity(identity)?.to_public().to_string();
+        Ok(Self {
+            label: label.to_string(),
+            kdf,
+            key_file_fingerprint: o_key_file.map(|key_file| key_file.fingerprint().to_string()),
+            wrapped_identity: encrypt_with_key(
+                slot_key,
+                identity.unsecure().as_bytes(),
+                label.as_bytes(),
+            )?,
+            recipient,
+            sealed_key: String::new(),
+        })
+    }
+
+    // The same slot with another password (and optional key file); the identity is kept,
+    // so the slot's copy of the data key remains valid
+    pub fn with_new_password(
+        &self,
+        identity: &SecUtf8,
+        password: &str,
+        o_key_file: Option<&KeyFile>,
+    ) -> Result<Self> {
+        let kdf = Kdf::new_argon2id();
+        let slot_key = kdf.derive_key(password, o_key_file)?;
+        Ok(Self {
+            sealed_key: self.sealed_key.clone(),
+            ..Self::with_identity(&self.label, kdf, o_key_file, &slot_key, identity)?
+        })
+    }
+
+    // Stores a copy of the (new) data key, which only the slot's identity can decrypt
+    pub fn seal(&mut self, data_key: &SecVec<u8>) -> Result<()> {
+        let recipient: x25519::Recipient = self
+            .recipient
+            .parse()
+            .map_err(|e: &str| anyhow!("key slot {}: {e}", self.label))?;
+        self.sealed_key = b64.encode(age::encrypt(&recipient, data_key.unsecure())?);
+        Ok(())
+    }
+
+    pub fn unseal(&self, identity: &SecUtf8) -> Result<SecVec<u8>> {
+        let sealed_key = b64
+            .decode(&self.sealed_key)
+            .context("decoding sealed key")?;
+        Ok(SecVec::new(age::decrypt(
+            &parse_identity(identity)?,
+            &sealed_key,
+        )?))
+    }
+
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    pub fn is_recovery(&self) -> bool {
+        self.label == RECOVERY_KEY_SLOT_LABEL
+    }
+
+    pub fn uses_key_file(&self) -> bool {
+        self.key_file_fingerprint.is_some()
+    }
+
+    fn fits(&self, o_key_file: Option<&KeyFile>) -> bool {
+        self.key_file_fingerprint.as_deref() == o_key_file.map(KeyFile::fingerprint)
+    }
+
+    // Returns the identity if password and key file are correct
+    pub fn unwrap_identity(&self, password: &str, o_key_file: Option<&KeyFile>) -> Result<SecUtf8> {
+        if !self.fits(o_key_file) {
+            return Err(anyhow!(t!("_key_file_wrong").to_string()));
+        }
+        let slot_key = self.kdf.derive_key(password, o_key_file)?;
+        self.identity(&slot_key)
+    }
+
+    // is used if the slot key was already derived
+    pub fn identity(&self, slot_key: &SecVec<u8>) -> Result<SecUtf8> {
+        let identity = decrypt_with_key(slot_key, &self.wrapped_identity, self.label.as_bytes())?;
+        Ok(SecUtf8::from(String::from_utf8(identity)?))
+    }
+
+    fn unlock(&self, password: &str, o_key_file: Option<&KeyFile>) -> Result<Unlocked> {
+        let identity = self.unwrap_identity(password, o_key_file)?;
+        Ok(Unlocked {
+            label: self.label.clone(),
+            data_key: self.unseal(&identity)?,
+            identity,
+        })
+    }
+}
+
+fn parse_identity(identity: &SecUtf8) -> Result<x25519::Identity> {
+    identity
+        .unsecure()
+        .parse()
+        .map_err(|e: &str| anyhow!("invalid identity: {e}"))
+}
+
+// Tries the key slots that fit to the given key file, and unlocks the first slot
+// that accepts the password
+pub(crate) fn unlock(
+    key_slots: &[KeySlot],
+    password: &str,
+    o_key_file: Option<&KeyFile>,
+) -> Result<Unlocked> {
+    let mut candidates = key_slots
+        .iter()
+        .filter(|key_slot| !key_slot.is_recovery() && key_slot.fits(o_key_file))
+        .peekable();
+    if candidates.peek().is_none() {
+        return Err(anyhow!(
+            if o_key_file.is_none() {
+                t!("_key_file_missing")
+            } else if key_slots.iter().any(KeySlot::uses_key_file) {
+                t!("_key_file_wrong")
+            } else {
+                t!("_key_file_not_used")
+            }
+            .to_string()
+        ));
+    }
+    candidates
+        .find_map(|key_slot| key_slot.unlock(password, o_key_file).ok())
+        .ok_or_else(|| WrongPassword.into())
+}
+
+// Unlocks the recovery key slot if the recovery code is correct
+pub(crate) fn unlock_with_recovery_code(
+    key_slots: &[KeySlot],
+    recovery_code: &str,
+) -> Result<Unlocked> {
+    key_slots
+        .iter()
+        .find(|key_slot| key_slot.is_recovery())
+        .context(t!("_no_recovery_code").to_string())?
+        .unlock(&normalize_recovery_code(recovery_code), None)
+        .map_err(|_| anyhow!(t!("_recovery_code_wrong").to_string()))
+}
+
+// A random code like "4KZ7Q-..." that is meant to be written down
+pub(crate) fn generate_recovery_code() -> String {
+    let mut rng = rng();
+    (0..RECOVERY_CODE_GROUPS)
+        .map(|_| {
+            (0..RECOVERY_CODE_GROUP_LENGTH)
+                .map(|_| {
+                    char::from(
+                        RECOVERY_CODE_ALPHABET[rng.random_range(0..RECOVERY_CODE_ALPHABET.len())],
+                    )
+                })
+                .collect::<String>()
+        })
+        .collect::<Vec<String>>()
+        .join("-")
+}
+
+// Is used as password of the recovery key slot;
+// ignores separators and case, and maps confusable characters as Crockford's base32 does
+pub(crate) fn normalize_recovery_code(recovery_code: &str) -> String {
+    recovery_code
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| match c.to_ascii_uppercase() {
+            'O' => '0',
+            'I' | 'L' => '1',
+            c => c,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        KeySlot, RECOVERY_KEY_SLOT_LABEL, generate_recovery_code, normalize_recovery_code, unlock,
+        unlock_with_recovery_code,
+    };
+    use crate::data::crypto::random_key;
+
+    #[test]
+    fn test_key_slots() {
+        let data_key = random_key();
+        let key_slots = vec![
+            KeySlot::new("alice", "alice_pw", None, &data_key).unwrap(),
+            KeySlot::new("bob", "bob_pw", None, &data_key).unwrap(),
+        ];
+        let unlocked = unlock(&key_slots, "bob_pw", None).unwrap();
+        assert_eq!(unlocked.label, "bob");
+        assert_eq!(unlocked.data_key, data_key);
+        let unlocked = unlock(&key_slots, "alice_pw", None).unwrap();
+        assert_eq!(unlocked.label, "alice");
+        assert_eq!(unlocked.data_key, data_key);
+        assert!(unlock(&key_slots, "eve_pw", None).is_err());
+    }
+
+    #[test]
+    fn test_reseal() {
+        let data_key = random_key();
+        let mut key_slot = KeySlot::new("alice", "alice_pw", None, &data_key).unwrap();
+        let identity = key_slot.unwrap_identity("alice_pw", None).unwrap();
+
+        // the data key is replaced without the password
+        let new_data_key = random_key();
+        key_slot.seal(&new_data_key).unwrap();
+        assert_eq!(key_slot.unseal(&identity).unwrap(), new_data_key);
+        let key_slots = vec![key_slot.clone()];
+        assert_eq!(
+            unlock(&key_slots, "alice_pw", None).unwrap().data_key,
+            new_data_key
+        );
+
+        // a new password keeps the identity
+        let key_slots = vec![
+            key_slot
+                .with_new_password(&identity, "new_pw", None)
+                .unwrap(),
+        ];
+        assert!(unlock(&key_slots, "alice_pw", None).is_err());
+        let unlocked = unlock(&key_slots, "new_pw", None).unwrap();
+        assert_eq!(unlocked.identity, identity);
+        assert_eq!(unlocked.data_key, new_data_key);
+    }
+
+    #[test]
+    fn test_recovery_code() {
+        let data_key = random_key();
+        let recovery_code = generate_recovery_code();
+        assert_eq!(recovery_code.len(), 35);
+        let key_slots = vec![
+            KeySlot::new("alice", "alice_pw", None, &data_key).unwrap(),
+            KeySlot::new(
+                RECOVERY_KEY_SLOT_LABEL,
+                &normalize_recovery_code(&recovery_code),
+                None,
+                &data_key,
+            )
+            .unwrap(),
+        ];
+        let written_down = recovery_code
+            .to_lowercase()
+            .replace('-', " ")
+            .replace('0', "o");
+        assert_eq!(
+            unlock_with_recovery_code(&key_slots, &written_down)
+                .unwrap()
+                .data_key,
+            data_key
+        );
+        assert!(unlock_with_recovery_code(&key_slots, "alice_pw").is_err());
+        assert!(unlock(&key_slots, &recovery_code, None).is_err());
+    }
+}
@@ -0,0 +1,232 @@
+use crate::{
+    data::{Bundle, Document, Key, PlFile, Transient},
+    ui::viz::{VEditBundle, VEditDocument},
+};
+use jiff::Zoned;
+use std::collections::BTreeMap;
+
+// An entry or document that is affected by a pending change
+#[derive(Clone, Copy, Debug)]
+pub(super) enum Touched<'a> {
+    Bundle(&'a Key),
+    Document(&'a Key),
+}
+
+// A version of an entry or a document; None if it does not exist (anymore)
+#[derive(Clone, Debug)]
+pub(crate) enum Version {
+    Bundle(Option<VEditBundle>),
+    Document(Option<VEditDocument>),
+}
+
+// Is returned when a change cannot be saved because the file was updated concurrently,
+// and the concurrent update changed the same entry or document
+#[derive(Clone, Debug)]
+pub(crate) struct MergeConflict {
+    pub key: Key,
+    pub theirs: Version,
+}
+impl std::fmt::Display for MergeConflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", t!("_merge_conflict %{name}", name = self.key))
+    }
+}
+impl std::error::Error for MergeConflict {}
+
+// Is returned when a change cannot be saved because the file was updated concurrently,
+// and the newer file cannot be merged
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct ConcurrentUpdate;
+impl std::fmt::Display for ConcurrentUpdate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            t!("Cannot save because the file was updated concurrently")
+        )
+    }
+}
+impl std::error::Error for ConcurrentUpdate {}
+
+// Compares the touched entry or document in the base version and in the newer version
+// of the file, and returns a conflict if they differ
+pub(super) fn find_conflict(
+    touched: Touched,
+    base: &PlFile,
+    newer: &PlFile,
+) -> Option<MergeConflict> {
+    let base_transient = base.transient()?;
+    let newer_transient = newer.transient()?;
+    match touched {
+        Touched::Bundle(key) => {
+            let newer_bundle = newer.bundles().get(key);
+            let unchanged = match (base.bundles().get(key), newer_bundle) {
+                (None, None) => true,
+                (Some(b1), Some(b2)) => bundles_equal(b1, base_transient, b2, newer_transient),
+                _ => false,
+            };
+            (!unchanged).then(|| MergeConflict {
+                key: key.clone(),
+                theirs: Version::Bundle(
+                    newer_bundle.map(|b| VEditBundle::from_bundle(key, b, newer_transient)),
+                ),
+            })
+        }
+        Touched::Document(key) => {
+            let newer_document = newer.documents().get(key);
+            let unchanged = match (base.documents().get(key), newer_document) {
+                (None, None) => true,
+                (Some(d1), Some(d2)) => d1.text(base_transient) == d2.text(newer_transient),
+                _ => false,
+            };
+            (!unchanged).then(|| MergeConflict {
+                key: key.clone(),
+                theirs: Version::Document(
+                    newer_document.map(|d| VEditDocument::from_document(key, d, newer_transient)),
+                ),
+            })
+        }
+    }
+}
+
+// Compares the content, not the refs, which differ between files
+pub(super) fn bundles_equal(b1: &Bundle, t1: &Transient, b2: &Bundle, t2: &Transient) -> bool {
+    b1.description() == b2.description()
+        && b1.creds().len() == b2.creds().len()
+        && b1
+            .creds()
+            .iter()
+            .zip(b2.creds().iter())
+            .all(|(c1, c2)| c1.name(t1) == c2.name(t2) && c1.secret(t1) == c2.secret(t2))
+}
+
+// How an entry or document compares to the one with the same name in another file
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Difference {
+    OnlyMine,
+    OnlyTheirs,
+    Identical,
+    Differing,
+}
+
+// An entry or document of the own file or of the other file, or of both
+#[derive(Clone, Debug)]
+pub(crate) struct Compared {
+    pub key: Key,
+    pub difference: Difference,
+    pub mine_changed_at: Option<Zoned>,
+    pub theirs_changed_at: Option<Zoned>,
+}
+impl Compared {
+    pub fn theirs_is_newer(&self) -> bool {
+        match (&self.mine_changed_at, &self.theirs_changed_at) {
+            (Some(mine), Some(theirs)) => theirs.timestamp() > mine.timestamp(),
+            _ => false,
+        }
+    }
+    // can be taken over from the other file
+    pub fn is_selectable(&self) -> bool {
+        matches!(
+            self.difference,
+            Difference::OnlyTheirs | Difference::Differing
+        )
+    }
+    // is taken over, unless the user decides otherwise
+    pub fn is_proposed(&self) -> bool {
+        self.difference == Difference::OnlyTheirs
+            || (self.difference == Difference::Differing && self.theirs_is_newer())
+    }
+}
+
+// The entries and documents of two files, by name
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Comparison {
+    pub bundles: Vec<Compared>,
+    pub documents: Vec<Compared>,
+}
+impl Comparison {
+    pub fn new(mine: &PlFile, theirs: &PlFile) -> Option<Self> {
+        let mine_transient = mine.transient()?;
+        let theirs_transient = theirs.transient()?;
+
+        let mut bundles = BTreeMap::new();
+        for (key, bundle) in mine.bundles().iter() {
+            bundles.insert(key, (Some(bundle), None));
+        }
+        for (key, bundle) in theirs.bundles().iter() {
+            bundles.entry(key).or_insert((None, None)).1 = Some(bundle);
+        }
+        let bundles = bundles
+            .into_iter()
+            .map(|(key, (o_mine, o_theirs))| {
+                compared(
+                    key,
+                    o_mine.map(Bundle::last_changed_at),
+                    o_theirs.map(Bundle::last_changed_at),
+                    || {
+                        o_mine.zip(o_theirs).is_some_and(|(b1, b2)| {
+                            bundles_equal(b1, mine_transient, b2, theirs_transient)
+                        })
+                    },
+                )
+            })
+            .collect();
+
+        let mut documents = BTreeMap::new();
+        for (key, document) in mine.documents().iter() {
+            documents.insert(key, (Some(document), None));
+        }
+        for (key, document) in theirs.documents().iter() {
+            documents.entry(key).or_insert((None, None)).1 = Some(document);
+        }
+        let documents = documents
+            .into_iter()
+            .map(|(key, (o_mine, o_theirs))| {
+                compared(
+                    key,
+                    o_mine.map(Document::last_changed_at),
+                    o_theirs.map(Document::last_changed_at),
+                    || {
+                        o_mine.zip(o_theirs).is_some_and(|(d1, d2)| {
+                            d1.text(mine_transient) == d2.text(theirs_transient)
+                        })
+                    },
+                )
+            })
+            .collect();
+
+        Some(Self { bundles, documents })
+    }
+
+    // The entries and documents that are taken over if the user does not decide otherwise:
+    // those that exist only in the other file, and those that were changed there more recently
+    pub fn proposal(&self) -> (Vec<Key>, Vec<Key>) {
+        let proposed = |compared: &Vec<Compared>| {
+            compared
+                .iter()
+                .filter(|c| c.is_proposed())
+                .map(|c| c.key.clone())
+                .collect()
+        };
+        (proposed(&self.bundles), proposed(&self.documents))
+    }
+}
+
+fn compared(
+    key: &Key,
+    o_mine: Option<&Zoned>,
+    o_theirs: Option<&Zoned>,
+    equal: impl Fn() -> bool,
+) -> Compared {
+    Compared {
+        key: key.clone(),
+        difference: match (o_mine, o_theirs) {
+            (Some(_), None) => Difference::OnlyMine,
+            (None, _) => Difference::OnlyTheirs,
+            (Some(_), Some(_)) if equal() => Difference::Identical,
+            (Some(_), Some(_)) => Difference::Differing,
+        },
+        mine_changed_at: o_mine.cloned(),
+        theirs_changed_at: o_theirs.cloned(),
+    }
+}
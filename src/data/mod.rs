@@ -2,26 +2,45 @@
 // This is to avoid confusion with `std::collections::btree_map::Entry`, which plays a role in
 // the implementation of Bundles.
 
+mod backups;
 mod bundle;
 mod bundles;
 mod cred;
+mod crypto;
 mod document;
 mod documents;
+mod durable;
+mod export;
+mod import;
 mod key;
+mod key_slots;
+mod merge;
 mod pl_file;
 mod secret;
 mod secrets;
 mod settings;
+mod share;
+mod totp;
 mod transient;
 
+pub(crate) use backups::{Backup, DEFAULT_BACKUP_GENERATIONS, list_backups};
 pub(crate) use bundle::Bundle;
 pub(crate) use bundles::Bundles;
 pub(crate) use cred::Cred;
+pub(crate) use crypto::{Kdf, KeyFile};
 pub(crate) use document::Document;
 pub(crate) use documents::Documents;
+pub(crate) use export::{EXPORT_FORMAT_VERSION, Export};
+pub(crate) use import::{Import, OnConflict, Outcome, Plan};
 pub(crate) use key::Key;
-pub(crate) use pl_file::{PlFile, Readable};
+pub(crate) use key_slots::{KeySlot, WrongPassword};
+pub(crate) use merge::{
+    Compared, Comparison, ConcurrentUpdate, Difference, MergeConflict, Version,
+};
+pub(crate) use pl_file::{LeftoverTempFile, PlFile, Readable};
 pub(crate) use secret::Secret;
 pub(crate) use secrets::Secrets;
 pub(crate) use settings::Settings;
+pub(crate) use share::{open_package, parse_recipient, parse_recipients, seal_package};
+pub(crate) use totp::{Totp, check_totp_secret};
 pub(crate) use transient::Transient;
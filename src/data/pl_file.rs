@@ -1,17 +1,38 @@
 use crate::{
-    data::{Bundle, Bundles, Document, Documents, Key, Secrets, Settings, Transient},
+    data::{
+        Bundle, Bundles, DEFAULT_BACKUP_GENERATIONS, Document, Documents, Kdf, Key, KeyFile,
+        KeySlot, Secrets, Settings, Transient, backups, check_totp_secret,
+        crypto::random_key,
+        durable::{self, SaveStep},
+        import::{self, Import, OnConflict, Outcome, Plan},
+        key_slots::{
+            self, DEFAULT_KEY_SLOT_LABEL, RECOVERY_KEY_SLOT_LABEL, WrongPassword,
+            generate_recovery_code, normalize_recovery_code,
+        },
+        merge::{self, ConcurrentUpdate, Touched, Version},
+    },
     ui::viz::{VEditBundle, VEditDocument},
 };
+use age::secrecy::ExposeSecret;
 use anyhow::{Context, Result, anyhow};
+use base64::{Engine as _, engine::general_purpose::STANDARD_NO_PAD as b64};
 use fd_lock::RwLock as FdRwLock;
+use secstr::{SecUtf8, SecVec};
 use sequential::Sequence;
 use std::{
-    fs::{File, OpenOptions, create_dir_all},
-    io::{Read, Write as _},
+    borrow::Cow,
+    fs::{File, OpenOptions, create_dir_all, remove_file},
+    io::Read,
     path::{Path, PathBuf},
 };
 
-const CURRENT_FORMAT_VERSION: u8 = 1;
+// Version 1: key derivation with PBKDF2, no KDF in the header
+// Version 2: key derivation with Argon2id, parameters in the header;
+//            files with key slots have the parameters in each key slot
+// Version 3: like version 2, but the names and descriptions of entries and the names of
+//            documents are also encrypted; the readable section contains only the header
+const FORMAT_VERSION_OPEN: u8 = 2;
+const FORMAT_VERSION_CONCEALED: u8 = 3;
 const PREFACE: &str = "\
 # DO NOT EDIT THIS FILE
 #
@@ -27,6 +48,28 @@ pub(crate) struct PlFile {
     file_path: PathBuf,
     stored: Stored,
     o_transient: Option<Transient>,
+    o_key_file: Option<KeyFile>,
+    // the label of the key slot with which the file was unlocked
+    o_key_slot: Option<String>,
+    // the identity of that key slot, with which a replaced data key can be unsealed
+    o_slot_identity: Option<SecUtf8>,
+    // is only set after the recovery code was generated, until it was shown to the user
+    o_recovery_code: Option<String>,
+    backup_generations: usize,
+    o_leftover_temp_file: Option<LeftoverTempFile>,
+    // is set if the file was switched to key slots when it was unlocked,
+    // which is only written with the next save
+    upgrade_pending: bool,
+}
+
+// Describes a temp file that was left over by an interrupted save
+#[derive(Clone, Debug)]
+pub(crate) struct LeftoverTempFile {
+    // is None if the temp file cannot be parsed
+    pub o_update_counter: Option<usize>,
+    // than the file
+    pub is_newer: bool,
+    pub is_decryptable: bool,
 }
 
 // This is the structure that is serialized to the file (after the preface);
@@ -50,16 +93,66 @@ fn is_empty(documents: &Documents) -> bool {
     documents.is_empty()
 }
 
+impl Readable {
+    // None for files with format version 1
+    pub(crate) fn kdf(&self) -> Option<&Kdf> {
+        self.header.kdf.as_ref()
+    }
+
+    pub(crate) fn is_concealed(&self) -> bool {
+        self.header.format_version >= FORMAT_VERSION_CONCEALED
+    }
+
+    // The form in which the readable section is written to the file
+    fn storable(&self) -> Cow<'_, Readable> {
+        if self.is_concealed() {
+            Cow::Owned(Readable {
+                header: self.header.clone(),
+                bundles: Bundles::new(),
+                documents: Documents::new(),
+            })
+        } else {
+            Cow::Borrowed(self)
+        }
+    }
+}
+
+// The lock on the file for a pending save, together with the state before the change,
+// which is restored if the save fails
+struct PendingSave {
+    file_lock: FdRwLock<File>,
+    stored: Stored,
+    o_transient: Option<Transient>,
+    o_key_file: Option<KeyFile>,
+    o_slot_identity: Option<SecUtf8>,
+}
+
 #[derive(Clone, Default, Debug, Serialize, Deserialize)]
 struct FileHeader {
     format_version: u8,
     update_counter: Sequence<usize>,
+    // must not be serialized if not set, otherwise the auth tag of old files would not match
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    kdf: Option<Kdf>,
+    // is only set if a key file is required for opening the file
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    key_file_fingerprint: Option<String>,
+    // if key slots exist, kdf and key_file_fingerprint are not used
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    key_slots: Vec<KeySlot>,
 }
 
 impl PlFile {
     pub fn read_or_create(file_path: &Path) -> Result<Self> {
         if file_path.exists() {
             let result = Self::lock_and_read(file_path).context("lock_and_read")?.1;
+            // it can only be inspected when the file is unlocked
+            if Settings::temp_document_file(file_path)?.exists() {
+                log::warn!("{}", t!("_leftover_temp_file_found"));
+            }
             Ok(result)
         } else {
             // first start: ensure the folder exists, and start with initial PlFile
@@ -72,11 +165,21 @@ impl PlFile {
             Ok(Self {
                 file_path: file_path.to_path_buf(),
                 o_transient: None,
+                o_key_file: None,
+                o_key_slot: None,
+                o_slot_identity: None,
+                o_recovery_code: None,
+                backup_generations: DEFAULT_BACKUP_GENERATIONS,
+                o_leftover_temp_file: None,
+                upgrade_pending: false,
                 stored: Stored {
                     readable: Readable {
                         header: FileHeader {
                             update_counter: Sequence::new(),
-                            format_version: CURRENT_FORMAT_VERSION,
+                            format_version: FORMAT_VERSION_OPEN,
+                            kdf: Some(Kdf::new_argon2id()),
+                            key_file_fingerprint: None,
+                            key_slots: Vec::new(),
                         },
                         bundles: Bundles::new(),
                         documents: Documents::new(),
@@ -107,6 +210,13 @@ impl PlFile {
                 Self {
                     file_path: file_path.to_path_buf(),
                     o_transient: None,
+                    o_key_file: None,
+                    o_key_slot: None,
+                    o_slot_identity: None,
+                    o_recovery_code: None,
+                    backup_generations: DEFAULT_BACKUP_GENERATIONS,
+                    o_leftover_temp_file: None,
+                    upgrade_pending: false,
                     stored,
                 },
             ))
@@ -161,45 +271,456 @@ impl PlFile {
         self.file_path.display().to_string()
     }
 
+    pub fn format_version(&self) -> u8 {
+        self.stored.readable.header.format_version
+    }
+
+    pub fn is_concealed(&self) -> bool {
+        self.stored.readable.is_concealed()
+    }
+
+    // number of backups that are kept when the file is saved; 0 switches backups off
+    pub fn set_backup_generations(&mut self, backup_generations: usize) {
+        self.backup_generations = backup_generations;
+    }
+
     pub fn is_actionable(&self) -> bool {
         self.o_transient.is_some()
     }
-    pub fn set_actionable(&mut self, password: String) -> Result<()> {
+    pub fn requires_key_file(&self) -> bool {
+        let header = &self.stored.readable.header;
+        header.key_file_fingerprint.is_some() || header.key_slots.iter().any(KeySlot::uses_key_file)
+    }
+
+    pub fn key_file_path(&self) -> Option<&Path> {
+        self.o_key_file.as_ref().map(KeyFile::path)
+    }
+
+    pub fn key_slots(&self) -> &[KeySlot] {
+        &self.stored.readable.header.key_slots
+    }
+
+    // the label of the key slot with which the file was unlocked
+    pub fn current_key_slot(&self) -> Option<&str> {
+        self.o_key_slot.as_deref()
+    }
+
+    pub fn set_actionable(&mut self, password: &str, o_key_file_path: Option<&Path>) -> Result<()> {
+        self.unlock(password, o_key_file_path)?;
+        self.inspect_leftover_temp_file();
+        Ok(())
+    }
+
+    fn unlock(&mut self, password: &str, o_key_file_path: Option<&Path>) -> Result<()> {
+        let o_key_file = o_key_file_path.map(KeyFile::read).transpose()?;
         if self.stored.cipher.is_empty() {
-            self.o_transient = Some(Transient::new(password, Secrets::default()));
+            let kdf = self.stored.readable.kdf().context("KDF not set")?.clone();
+            let slot_key = kdf.derive_key(password, o_key_file.as_ref())?;
+            self.o_transient = Some(Transient::new(slot_key.clone(), Secrets::default()));
+            self.introduce_key_slots(kdf, &slot_key, o_key_file)?;
+            self.add_recovery_key_slot()?;
             let lock = self.lock_for_save()?;
             self.save(lock)?;
+            Ok(())
+        } else if !self.stored.readable.header.key_slots.is_empty() {
+            let unlocked = key_slots::unlock(
+                &self.stored.readable.header.key_slots,
+                password,
+                o_key_file.as_ref(),
+            )?;
+            self.decrypt(unlocked.data_key)?;
+            self.o_key_file = o_key_file;
+            self.o_key_slot = Some(unlocked.label);
+            self.o_slot_identity = Some(unlocked.identity);
+            Ok(())
+        } else if let Some(kdf) = self.stored.readable.kdf().cloned() {
+            // format version 2 or 3 without key slots:
+            // decrypt with the derived key, and switch to key slots with the next save
+            self.check_key_file(o_key_file.as_ref())?;
+            let slot_key = kdf.derive_key(password, o_key_file.as_ref())?;
+            self.decrypt(slot_key.clone()).context(WrongPassword)?;
+            self.introduce_key_slots(kdf, &slot_key, o_key_file)?;
+            self.upgrade_on_next_save();
+            Ok(())
         } else {
-            self.o_transient = Some(
-                Transient::from_cipher(password, &self.stored.readable, &self.stored.cipher)
-                    .context(format!(
-                        "{}\n{}\n  {}\n  {}",
-                        jiff::Timestamp::now(),
-                        t!("_decryption_failed"),
-                        t!("_decryption_failed_note1"),
-                        t!("_decryption_failed_note2")
-                    ))?,
-            );
+            // format version 1: decrypt with PBKDF2, and switch to the current KDF
+            // and to key slots with the next save
+            if o_key_file.is_some() {
+                return Err(anyhow!(t!("_key_file_not_used").to_string()));
+            }
+            let secrets = Transient::decrypt_legacy_cipher(
+                password,
+                &self.stored.readable,
+                &self.stored.cipher,
+            )
+            .context(decryption_failed())
+            .context(WrongPassword)?;
+            let kdf = Kdf::new_argon2id();
+            let slot_key = kdf.derive_key(password, None)?;
+            self.o_transient = Some(Transient::new(slot_key.clone(), secrets));
+            self.introduce_key_slots(kdf, &slot_key, None)?;
+            self.upgrade_on_next_save();
+            Ok(())
         }
+    }
+
+    // Opening the file must not write it, e.g. it can be read-only
+    fn upgrade_on_next_save(&mut self) {
+        log::info!("{}", t!("_upgrading_file_format"));
+        self.upgrade_pending = true;
+    }
 
+    // Writes a pending upgrade, which is needed before the session key can open the file
+    // elsewhere; a failure is only logged
+    pub fn save_pending_upgrade(&mut self) {
+        if !self.upgrade_pending {
+            return;
+        }
+        if let Err(e) = self.lock_for_save().and_then(|lock| self.save(lock)) {
+            log::warn!("{}: {e:?}", t!("_upgrade_not_saved"));
+        }
+    }
+
+    // Allows opening the file if the password was forgotten
+    pub fn set_actionable_with_recovery_code(&mut self, recovery_code: &str) -> Result<()> {
+        let unlocked = key_slots::unlock_with_recovery_code(
+            &self.stored.readable.header.key_slots,
+            recovery_code,
+        )?;
+        self.decrypt(unlocked.data_key)?;
+        self.o_key_file = None;
+        self.o_key_slot = Some(unlocked.label);
+        self.o_slot_identity = Some(unlocked.identity);
+        self.inspect_leftover_temp_file();
         Ok(())
     }
 
-    pub fn change_password(&mut self, old_pw: &str, new_pw: String) -> Result<()> {
+    // Allows opening the file with the key of a session that was unlocked elsewhere,
+    // e.g. in the agent
+    pub fn set_actionable_with_session_key(
+        &mut self,
+        session_key: &str,
+        key_slot: Option<String>,
+    ) -> Result<()> {
+        let data_key = SecVec::new(b64.decode(session_key).context(decryption_failed())?);
+        self.decrypt(data_key)?;
+        self.o_key_file = None;
+        self.o_key_slot = key_slot;
+        self.o_slot_identity = None;
+        self.inspect_leftover_temp_file();
+        Ok(())
+    }
+
+    // The key with which the file can be opened without a password; must be handled with care
+    pub fn session_key(&self) -> Result<String> {
+        Ok(b64.encode(self.data_key()?.unsecure()))
+    }
+
+    // A temp file is only left over if a save was interrupted, e.g. by a crash.
+    // It can only be inspected when the file is unlocked, because it is written with the same key.
+    fn inspect_leftover_temp_file(&mut self) {
+        self.o_leftover_temp_file = None;
+        let Ok(temp_path) = Settings::temp_document_file(&self.file_path) else {
+            return;
+        };
+        if !temp_path.exists() || self.stored.cipher.is_empty() {
+            return;
+        }
+        let (o_update_counter, is_decryptable) = match Self::lock_and_read(&temp_path) {
+            Ok((_lock, mut temp_pl_file)) => (
+                temp_pl_file.update_counter().peek(),
+                self.decrypt_version(&mut temp_pl_file).is_ok(),
+            ),
+            Err(_) => (None, false),
+        };
+        let leftover = LeftoverTempFile {
+            o_update_counter,
+            is_newer: o_update_counter > self.update_counter().peek(),
+            is_decryptable,
+        };
+        log::warn!("{}: {leftover:?}", t!("_leftover_temp_file_found"));
+        self.o_leftover_temp_file = Some(leftover);
+    }
+
+    pub fn leftover_temp_file(&self) -> Option<&LeftoverTempFile> {
+        self.o_leftover_temp_file.as_ref()
+    }
+
+    // Replaces the file with the leftover temp file, which must be decryptable
+    pub fn recover_leftover_temp_file(&mut self) -> Result<()> {
+        let temp_path = Settings::temp_document_file(&self.file_path)?;
+        let (temp_lock, mut temp_pl_file) = Self::lock_and_read(&temp_path)?;
+        self.decrypt_version(&mut temp_pl_file)
+            .context(t!("_leftover_temp_file_not_decryptable").to_string())?;
+        drop(temp_lock);
+
+        let _lock = self.lock_for_save()?;
+        if let Err(e) = backups::create_backup(&self.file_path, self.backup_generations) {
+            log::warn!("{}: {e:?}", t!("_backup_failed"));
+        }
+        durable::replace(&temp_path, &self.file_path)?;
+        self.stored = temp_pl_file.stored;
+        self.o_transient = temp_pl_file.o_transient;
+        self.o_leftover_temp_file = None;
+        log::info!(
+            "{} ({})",
+            t!("_leftover_temp_file_recovered"),
+            self.file_path.display()
+        );
+        Ok(())
+    }
+
+    pub fn discard_leftover_temp_file(&mut self) -> Result<()> {
+        let temp_path = Settings::temp_document_file(&self.file_path)?;
+        remove_file(&temp_path).context(format!("removing {}", temp_path.display()))?;
+        self.o_leftover_temp_file = None;
+        log::info!(
+            "{} ({})",
+            t!("_leftover_temp_file_discarded"),
+            temp_path.display()
+        );
+        Ok(())
+    }
+
+    pub fn has_recovery_code(&self) -> bool {
+        self.key_slots().iter().any(KeySlot::is_recovery)
+    }
+
+    // Returns the recovery code only once, after it was generated
+    pub fn take_recovery_code(&mut self) -> Option<String> {
+        self.o_recovery_code.take()
+    }
+
+    // Generates a new recovery code; the old one cannot be used anymore, since the data key
+    // is replaced as well, so that it cannot be obtained from an older copy of the file
+    pub fn regenerate_recovery_code(&mut self) -> Result<()> {
         let lock = self.lock_for_save()?;
+        self.add_recovery_key_slot()?;
+        self.rekey()?;
+        self.save(lock).inspect_err(|_| self.o_recovery_code = None)
+    }
+
+    // replaces an existing recovery key slot
+    fn add_recovery_key_slot(&mut self) -> Result<()> {
+        let recovery_code = generate_recovery_code();
+        let key_slot = KeySlot::new(
+            RECOVERY_KEY_SLOT_LABEL,
+            &normalize_recovery_code(&recovery_code),
+            None,
+            self.data_key()?,
+        )?;
+        let key_slots = &mut self.stored.readable.header.key_slots;
+        key_slots.retain(|key_slot| !key_slot.is_recovery());
+        key_slots.push(key_slot);
+        self.o_recovery_code = Some(recovery_code);
+        Ok(())
+    }
 
-        if let Some(ref mut transient) = self.o_transient {
-            if transient.get_storage_password() != old_pw {
-                return Err(anyhow!(
-                    t!("The current password is not correct").to_string()
-                ));
+    // Replaces the storage key with a random data key, which is wrapped in a default key slot.
+    // The slot key is the key that was derived from the password and the optional key file.
+    fn introduce_key_slots(
+        &mut self,
+        kdf: Kdf,
+        slot_key: &SecVec<u8>,
+        o_key_file: Option<KeyFile>,
+    ) -> Result<()> {
+        let data_key = random_key();
+        let key_slot = KeySlot::with_slot_key(
+            DEFAULT_KEY_SLOT_LABEL,
+            kdf,
+            o_key_file.as_ref(),
+            slot_key,
+            &data_key,
+        )?;
+        self.o_slot_identity = Some(key_slot.identity(slot_key)?);
+        let header = &mut self.stored.readable.header;
+        header.key_slots = vec![key_slot];
+        header.kdf = None;
+        header.key_file_fingerprint = None;
+        self.o_transient
+            .as_mut()
+            .context("not actionable")?
+            .set_storage_key(data_key);
+        self.o_key_file = o_key_file;
+        self.o_key_slot = Some(DEFAULT_KEY_SLOT_LABEL.to_string());
+        Ok(())
+    }
+
+    // gives a clear error message if the key file does not fit to a file without key slots
+    fn check_key_file(&self, o_key_file: Option<&KeyFile>) -> Result<()> {
+        match (
+            &self.stored.readable.header.key_file_fingerprint,
+            o_key_file,
+        ) {
+            (None, None) => Ok(()),
+            (Some(_), None) => Err(anyhow!(t!("_key_file_missing").to_string())),
+            (None, Some(_)) => Err(anyhow!(t!("_key_file_not_used").to_string())),
+            (Some(fingerprint), Some(key_file)) => {
+                if fingerprint == key_file.fingerprint() {
+                    Ok(())
+                } else {
+                    Err(anyhow!(t!("_key_file_wrong").to_string()))
+                }
             }
-            transient.set_storage_password(new_pw);
-            self.save(lock)?;
         }
+    }
+
+    // decrypt the cipher with the given key, without expensive key derivation
+    fn decrypt(&mut self, storage_key: SecVec<u8>) -> Result<()> {
+        let (transient, o_concealed) =
+            Transient::from_cipher(storage_key, &self.stored.readable, &self.stored.cipher)
+                .context(decryption_failed())?;
+        if let Some((bundles, documents)) = o_concealed {
+            self.stored.readable.bundles = bundles;
+            self.stored.readable.documents = documents;
+        }
+        self.o_transient = Some(transient);
         Ok(())
     }
 
+    // Changes only the key slot with which the file was unlocked;
+    // the key file is replaced as well, with None no key file will be required anymore
+    pub fn change_password(
+        &mut self,
+        old_pw: &str,
+        new_pw: &str,
+        o_new_key_file_path: Option<&Path>,
+    ) -> Result<()> {
+        let o_new_key_file = o_new_key_file_path.map(KeyFile::read).transpose()?;
+        let lock = self.lock_for_save()?;
+
+        let idx = self.current_key_slot_index()?;
+        let key_slot = &self.stored.readable.header.key_slots[idx];
+        if key_slot.is_recovery() {
+            return Err(anyhow!(t!("_recovery_change_password").to_string()));
+        }
+        let identity = key_slot
+            .unwrap_identity(old_pw, self.o_key_file.as_ref())
+            .map_err(|_| anyhow!(t!("The current password is not correct").to_string()))?;
+        let new_key_slot =
+            key_slot.with_new_password(&identity, new_pw, o_new_key_file.as_ref())?;
+        self.stored.readable.header.key_slots[idx] = new_key_slot;
+        self.o_key_file = o_new_key_file;
+        self.o_slot_identity = Some(identity);
+        self.save(lock)
+    }
+
+    // Checks the password once more, e.g. before the secrets are exported in plain text;
+    // without a key file, the one is used with which the file was opened
+    pub fn check_password(&self, password: &str, o_key_file_path: Option<&Path>) -> Result<()> {
+        let o_key_file = o_key_file_path.map(KeyFile::read).transpose()?;
+        key_slots::unlock(
+            self.key_slots(),
+            password,
+            o_key_file.as_ref().or(self.o_key_file.as_ref()),
+        )
+        .map(|_| ())
+    }
+
+    // Allows opening the file with an additional password (and optional key file)
+    pub fn add_key_slot(
+        &mut self,
+        label: &str,
+        password: &str,
+        o_key_file_path: Option<&Path>,
+    ) -> Result<()> {
+        let label = label.trim();
+        if label.is_empty() {
+            return Err(anyhow!(t!("_key_slot_label_empty").to_string()));
+        }
+        if label == RECOVERY_KEY_SLOT_LABEL {
+            return Err(anyhow!(
+                t!("_key_slot_label_reserved %{l}", l = label).to_string()
+            ));
+        }
+        if self
+            .key_slots()
+            .iter()
+            .any(|key_slot| key_slot.label() == label)
+        {
+            return Err(anyhow!(t!("_key_slot_exists %{l}", l = label).to_string()));
+        }
+        let o_key_file = o_key_file_path.map(KeyFile::read).transpose()?;
+        let lock = self.lock_for_save()?;
+        let key_slot = KeySlot::new(label, password, o_key_file.as_ref(), self.data_key()?)?;
+        self.stored.readable.header.key_slots.push(key_slot);
+        self.save(lock)
+    }
+
+    // The key slot with which the file was unlocked cannot be revoked.
+    // The data key is replaced, so that the revoked password cannot open the file anymore,
+    // not even with the header of an older copy or backup of the file.
+    pub fn revoke_key_slot(&mut self, label: &str) -> Result<()> {
+        if self.current_key_slot() == Some(label) {
+            return Err(anyhow!(t!("_key_slot_in_use").to_string()));
+        }
+        let idx = self
+            .key_slots()
+            .iter()
+            .position(|key_slot| key_slot.label() == label)
+            .context(t!("_key_slot_unknown %{l}", l = label).to_string())?;
+        if !self.key_slots()[idx].is_recovery()
+            && self
+                .key_slots()
+                .iter()
+                .filter(|key_slot| !key_slot.is_recovery())
+                .count()
+                == 1
+        {
+            return Err(anyhow!(t!("_key_slot_last").to_string()));
+        }
+        let lock = self.lock_for_save()?;
+        self.stored.readable.header.key_slots.remove(idx);
+        self.rekey()?;
+        self.save(lock)
+    }
+
+    // Replaces the data key, and seals it for all key slots
+    fn rekey(&mut self) -> Result<()> {
+        let data_key = random_key();
+        for key_slot in &mut self.stored.readable.header.key_slots {
+            key_slot.seal(&data_key)?;
+        }
+        self.o_transient
+            .as_mut()
+            .context("not actionable")?
+            .set_storage_key(data_key);
+        Ok(())
+    }
+
+    // Decrypts another version of this file, e.g. a newer one: with the data key,
+    // or if it was replaced meanwhile, with the one that is sealed for our key slot
+    fn decrypt_version(&self, other: &mut PlFile) -> Result<()> {
+        if other.decrypt(self.data_key()?.clone()).is_ok() {
+            return Ok(());
+        }
+        let (Some(label), Some(identity)) = (self.current_key_slot(), &self.o_slot_identity) else {
+            return Err(anyhow!(decryption_failed()));
+        };
+        let key_slot = other
+            .key_slots()
+            .iter()
+            .find(|key_slot| key_slot.label() == label)
+            .context(t!("_key_slot_revoked %{l}", l = label).to_string())?;
+        other.decrypt(key_slot.unseal(identity)?)
+    }
+
+    fn current_key_slot_index(&self) -> Result<usize> {
+        let label = self.current_key_slot().context("no current key slot")?;
+        self.key_slots()
+            .iter()
+            .position(|key_slot| key_slot.label() == label)
+            .context(t!("_key_slot_unknown %{l}", l = label).to_string())
+    }
+
+    fn data_key(&self) -> Result<&SecVec<u8>> {
+        Ok(self
+            .o_transient
+            .as_ref()
+            .context(t!("Cannot save because the password is not set").to_string())?
+            .storage_key())
+    }
+
     fn add_bundle(&mut self, key: Key, bundle: Bundle) -> Result<()> {
         if self.stored.readable.bundles.contains_key(&key) {
             Err(anyhow!(t!(
@@ -290,7 +811,8 @@ impl PlFile {
             .reff()
     }
 
-    fn lock_for_save(&mut self) -> Result<FdRwLock<File>> {
+    // Returns the newer file if the file was updated concurrently
+    fn lock_and_check(&mut self) -> Result<(FdRwLock<File>, Option<PlFile>)> {
         let mut used_refs = self
             .stored
             .readable
@@ -326,70 +848,162 @@ impl PlFile {
             .unwrap()
             == 0
         {
-            Self::lock_and_create_empty(&self.file_path)
+            Ok((Self::lock_and_create_empty(&self.file_path)?, None))
         } else {
             let (lock, old_pl_file) = Self::lock_and_read(&self.file_path)?;
-            // assert that the update_counter is not changed
             if old_pl_file.stored.readable.header.update_counter.peek()
-                != self.stored.readable.header.update_counter.peek()
+                == self.stored.readable.header.update_counter.peek()
             {
-                return Err(anyhow!(t!(
-                    "Cannot save because the file was updated concurrently"
-                )));
+                Ok((lock, None))
+            } else {
+                Ok((lock, Some(old_pl_file)))
             }
-            Ok(lock)
         }
     }
 
-    fn save(&mut self, mut prod_lock: FdRwLock<File>) -> Result<()> {
-        let _prod_guard = prod_lock.write();
+    fn lock_for_save(&mut self) -> Result<PendingSave> {
+        match self.lock_and_check()? {
+            (lock, None) => Ok(self.pending_save(lock)),
+            (_lock, Some(_newer_pl_file)) => Err(concurrent_update()),
+        }
+    }
+
+    fn pending_save(&self, file_lock: FdRwLock<File>) -> PendingSave {
+        PendingSave {
+            file_lock,
+            stored: self.stored.clone(),
+            o_transient: self.o_transient.clone(),
+            o_key_file: self.o_key_file.clone(),
+            o_slot_identity: self.o_slot_identity.clone(),
+        }
+    }
+
+    // Like lock_for_save, but if the file was updated concurrently, the newer file is adopted,
+    // so that the pending change can be applied on top of it;
+    // fails with a MergeConflict if the concurrent update changed one of the touched items
+    fn lock_for_merging_save(&mut self, touched: &[Touched]) -> Result<PendingSave> {
+        let (lock, mut newer_pl_file) = match self.lock_and_check()? {
+            (lock, None) => return Ok(self.pending_save(lock)),
+            (lock, Some(newer_pl_file)) => (lock, newer_pl_file),
+        };
+        // e.g. if our key slot was revoked, the newer file cannot be decrypted anymore
+        if self.decrypt_version(&mut newer_pl_file).is_err() {
+            return Err(concurrent_update());
+        }
+        if let Some(conflict) = touched
+            .iter()
+            .find_map(|touched| merge::find_conflict(*touched, self, &newer_pl_file))
+        {
+            return Err(conflict.into());
+        }
+        log::info!(
+            "{} ({})",
+            t!("_merged_concurrent_update"),
+            self.file_path.display()
+        );
+        self.stored = newer_pl_file.stored;
+        self.o_transient = newer_pl_file.o_transient;
+        Ok(self.pending_save(lock))
+    }
+
+    // Checks whether the file was updated by someone else since it was read or saved
+    pub fn is_outdated(&self) -> Result<bool> {
+        let (_lock, pl_file) = Self::lock_and_read(&self.file_path)?;
+        Ok(pl_file.update_counter().peek() != self.update_counter().peek())
+    }
+
+    // Reads the file again, e.g. to take over changes that were made concurrently
+    pub fn reload(&mut self) -> Result<()> {
+        let (_lock, mut pl_file) = Self::lock_and_read(&self.file_path)?;
+        // the file still has the old format, which the data key does not fit to
+        if self.upgrade_pending && pl_file.update_counter().peek() == self.update_counter().peek() {
+            return Ok(());
+        }
+        self.decrypt_version(&mut pl_file)?;
+        self.stored = pl_file.stored;
+        self.o_transient = pl_file.o_transient;
+        Ok(())
+    }
+
+    fn save(&mut self, pending_save: PendingSave) -> Result<()> {
+        let PendingSave {
+            mut file_lock,
+            stored,
+            o_transient,
+            o_key_file,
+            o_slot_identity,
+        } = pending_save;
+        let _prod_guard = file_lock.write();
 
         if self.o_transient.is_none() {
             return Err(anyhow!(t!("Cannot save because the password is not set")));
         }
 
+        // if the save fails, the state before the change is restored: it fits to the file,
+        // so that a later save is still possible, and does not contain the failed change
+        let temp_path = Settings::temp_document_file(&self.file_path)?;
+        self.write_and_replace(&temp_path)
+            .inspect(|()| self.upgrade_pending = false)
+            .inspect_err(|_| {
+                self.stored = stored;
+                self.o_transient = o_transient;
+                self.o_key_file = o_key_file;
+                self.o_slot_identity = o_slot_identity;
+                if temp_path.exists()
+                    && let Err(e) = remove_file(&temp_path)
+                {
+                    log::warn!("removing {}: {e}", temp_path.display());
+                }
+            })
+    }
+
+    // Writes the temp file durably, verifies it, and replaces the prod file with it
+    fn write_and_replace(&mut self, temp_path: &Path) -> Result<()> {
         // prepare for save
-        self.stored.readable.header.format_version = CURRENT_FORMAT_VERSION;
+        if self.stored.readable.header.format_version < FORMAT_VERSION_OPEN {
+            self.stored.readable.header.format_version = FORMAT_VERSION_OPEN;
+        }
         self.stored.readable.header.update_counter.next();
+        let readable = self.stored.readable.storable();
         self.stored.cipher = self
-        .o_transient
-        .as_mut()
-        .unwrap(/* cannot fail */)
-        .as_cipher(&self.stored.readable)?;
+            .o_transient
+            .as_mut()
+            .unwrap(/* cannot fail */)
+            .as_cipher(
+                &readable,
+                if readable.is_concealed() {
+                    Some((&self.stored.readable.bundles, &self.stored.readable.documents))
+                } else {
+                    None
+                },
+            )?;
 
         // store to temp file
-        let temp_path = Settings::temp_document_file(&self.file_path)?;
-        let mut temp_file = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .open(temp_path.clone())?;
-        temp_file.write_all(PREFACE.as_bytes())?;
-        temp_file.write_all(serde_json::ser::to_string_pretty(&self.stored)?.as_bytes())?;
-        temp_file.write_all(b"\n")?;
-        temp_file.flush()?;
+        let mut content = PREFACE.to_string();
+        content.push_str(&serde_json::ser::to_string_pretty(&Stored {
+            readable: readable.into_owned(),
+            cipher: self.stored.cipher.clone(),
+        })?);
+        content.push('\n');
+        durable::write_synced(temp_path, content.as_bytes())?;
 
         // read temp file and compare
-        let (_temp_lock, mut temp_pl_file) = PlFile::lock_and_read(&temp_path)?;
-        temp_pl_file.set_actionable(
-            self.o_transient
-                .as_ref()
-                .unwrap()
-                .get_storage_password()
-                .to_string(),
-        )?;
-        if !self.equals_logically(&temp_pl_file) {
-            return Err(anyhow!("save: write/read cycle failed"));
+        {
+            let (_temp_lock, mut temp_pl_file) = PlFile::lock_and_read(temp_path)?;
+            temp_pl_file.decrypt(self.o_transient.as_ref().unwrap().storage_key().clone())?;
+            if !self.equals_logically(&temp_pl_file) {
+                return Err(anyhow!("save: write/read cycle failed"));
+            }
+            durable::fail_point(SaveStep::VerifyTempFile)?;
         }
 
-        // copy temp file over prod file
-        match std::fs::rename(temp_path, &self.file_path) {
-            Ok(()) => Ok(()),
-            Err(e) => {
-                // TODO? rollback by discarding the temp file and re-reading the old file?
-                Err(e.into())
-            }
+        // keep the previous state as backup; a failing backup must not prevent saving
+        if let Err(e) = backups::create_backup(&self.file_path, self.backup_generations) {
+            log::warn!("{}: {e:?}", t!("_backup_failed"));
         }
+
+        // replace prod file with temp file
+        durable::replace(temp_path, &self.file_path)
     }
 
     fn equals_logically(&self, other: &PlFile) -> bool {
@@ -401,15 +1015,79 @@ impl PlFile {
             .iter()
             .zip(other.stored.readable.bundles.iter())
             .all(|((s1, b1), (s2, b2))| {
-                *s1 == *s2
-                    && b1.description() == b2.description()
-                    && b1.creds().iter().zip(b2.creds().iter()).all(|(my, other)| {
-                        my.name(my_transient) == other.name(other_transient)
-                            && my.secret(my_transient) == other.secret(other_transient)
-                    })
+                *s1 == *s2 && merge::bundles_equal(b1, my_transient, b2, other_transient)
             })
     }
 
+    // Takes over the entries and documents of a backup, as a new version of the file;
+    // the key slots are kept, so that a revoked password cannot open the file again.
+    // The current state is kept as backup, even if no backups are configured.
+    pub fn save_with_restored_backup(&mut self, backup_path: &Path) -> Result<()> {
+        let (backup_lock, mut backup) = Self::lock_and_read(backup_path)?;
+        self.decrypt_version(&mut backup)
+            .context(t!("_backup_not_decryptable").to_string())?;
+        drop(backup_lock);
+
+        let lock = self.lock_for_save()?;
+        let mut transient = backup.o_transient.context("not unlocked")?;
+        transient.set_storage_key(self.data_key()?.clone());
+        self.stored.readable.bundles = backup.stored.readable.bundles;
+        self.stored.readable.documents = backup.stored.readable.documents;
+        self.o_transient = Some(transient);
+        let backup_generations = self.backup_generations;
+        self.backup_generations = backup_generations.max(1);
+        let result = self.save(lock);
+        self.backup_generations = backup_generations;
+        result
+    }
+
+    // Switches between the file format that shows the names and descriptions of entries
+    // and the names of documents in clear, and the one that encrypts them
+    pub fn save_with_concealment(&mut self, conceal: bool) -> Result<()> {
+        if self.is_concealed() == conceal {
+            return Ok(());
+        }
+        let lock = self.lock_for_save()?;
+        self.stored.readable.header.format_version = if conceal {
+            FORMAT_VERSION_CONCEALED
+        } else {
+            FORMAT_VERSION_OPEN
+        };
+        self.save(lock)?;
+        if conceal {
+            // the older backups would still show the names in clear
+            match backups::remove_open_backups(&self.file_path) {
+                Ok(removed) => log::info!("{}", t!("_open_backups_removed %{n}", n = removed)),
+                Err(e) => log::error!("{}: {e:?}", t!("_open_backups_not_removed")),
+            }
+        }
+        Ok(())
+    }
+
+    // The public key to which others can encrypt packages for this file's owner
+    pub fn public_key(&self) -> Option<String> {
+        let identity: age::x25519::Identity = self.transient()?.identity()?.parse().ok()?;
+        Some(identity.to_public().to_string())
+    }
+
+    // An existing identity is never replaced, since packages for it could not be opened anymore
+    pub fn save_with_new_identity(&mut self) -> Result<()> {
+        if self
+            .transient()
+            .context("not unlocked")?
+            .identity()
+            .is_some()
+        {
+            return Err(anyhow!(t!("_share_identity_exists").to_string()));
+        }
+        let lock = self.lock_for_save()?;
+        let identity = age::x25519::Identity::generate();
+        self.transient_mut()
+            .context("not unlocked")?
+            .set_identity(identity.to_string().expose_secret().to_string());
+        self.save(lock)
+    }
+
     pub fn save_with_added_bundle(&mut self, edit_bundle: &VEditBundle) -> Result<()> {
         if edit_bundle.key.is_empty() {
             return Err(anyhow!("internal error: can't save with empty name"));
@@ -420,7 +1098,8 @@ impl PlFile {
                 name = &edit_bundle.key
             )));
         }
-        let lock = self.lock_for_save()?;
+        self.check_totp_secrets(edit_bundle, &edit_bundle.key)?;
+        let lock = self.lock_for_merging_save(&[Touched::Bundle(&edit_bundle.key)])?;
         let (_orig_key, key, bundle) =
             edit_bundle.as_oldkey_newkey_bundle(self.transient_mut().unwrap(/*OK*/));
 
@@ -435,14 +1114,50 @@ impl PlFile {
         if !self.has_bundle(&key) {
             return Err(anyhow!(t!("_bundle_does_not_exist %{name}", name = key)));
         }
-        let lock = self.lock_for_save()?;
+        let lock = self.lock_for_merging_save(&[Touched::Bundle(&key)])?;
         self.delete_bundle(key)?;
         self.save(lock)
     }
 
     pub fn save_with_updated_bundle(&mut self, edit_bundle: &VEditBundle) -> Result<()> {
-        let lock = self.lock_for_save()?;
+        self.check_totp_secrets(edit_bundle, &edit_bundle.orig_key)?;
+        let lock = self.lock_for_merging_save(&[
+            Touched::Bundle(&edit_bundle.orig_key),
+            Touched::Bundle(&edit_bundle.key),
+        ])?;
+        self.update_bundle(edit_bundle)?;
+        self.save(lock)
+    }
+
+    // Fails if a secret looks like an otpauth URI, but is not a valid one; only the secrets
+    // that the stored entry does not have yet are checked, so that an entry with an invalid
+    // URI from an older version can still be edited
+    fn check_totp_secrets(&self, edit_bundle: &VEditBundle, stored_key: &Key) -> Result<()> {
+        let transient = self.transient().context("not unlocked")?;
+        let stored = self.bundles().get(stored_key);
+        edit_bundle
+            .v_edit_creds
+            .iter()
+            .filter(|cred| {
+                !stored.is_some_and(|bundle| {
+                    bundle
+                        .creds()
+                        .iter()
+                        .any(|stored_cred| stored_cred.secret(transient) == cred.secret)
+                })
+            })
+            .try_for_each(|cred| {
+                check_totp_secret(&cred.secret).map_err(|e| {
+                    anyhow!(t!(
+                        "_totp_invalid_in_entry %{name} %{e}",
+                        name = &edit_bundle.key,
+                        e = e
+                    ))
+                })
+            })
+    }
 
+    fn update_bundle(&mut self, edit_bundle: &VEditBundle) -> Result<()> {
         if edit_bundle.key.is_empty() {
             return Err(anyhow!(t!("internal error: can't save with empty name")));
         }
@@ -485,8 +1200,7 @@ impl PlFile {
                 transient.remove_secret(reff);
             }
         }
-
-        self.save(lock)
+        Ok(())
     }
 
     pub fn save_with_added_document(&mut self, edit_document: &VEditDocument) -> Result<()> {
@@ -499,7 +1213,7 @@ impl PlFile {
                 name = &edit_document.key.0
             )));
         }
-        let lock = self.lock_for_save()?;
+        let lock = self.lock_for_merging_save(&[Touched::Document(&edit_document.key)])?;
         let (_orig_key, key, document) =
             edit_document.as_oldkey_newkey_document(self.transient_mut().unwrap(/*OK*/));
 
@@ -514,14 +1228,21 @@ impl PlFile {
         if !self.has_document(key) {
             return Err(anyhow!(t!("_document_does_not_exist %{name}", name = key)));
         }
-        let lock = self.lock_for_save()?;
+        let lock = self.lock_for_merging_save(&[Touched::Document(key)])?;
         self.delete_document(key)?;
         self.save(lock)
     }
 
     pub fn save_with_updated_document(&mut self, edit_document: &VEditDocument) -> Result<()> {
-        let lock = self.lock_for_save()?;
+        let lock = self.lock_for_merging_save(&[
+            Touched::Document(&edit_document.orig_key),
+            Touched::Document(&edit_document.key),
+        ])?;
+        self.update_document(edit_document)?;
+        self.save(lock)
+    }
 
+    fn update_document(&mut self, edit_document: &VEditDocument) -> Result<()> {
         if edit_document.key.is_empty() {
             return Err(anyhow!(t!("internal error: can't save with empty name")));
         }
@@ -552,13 +1273,256 @@ impl PlFile {
                 transient.remove_secret(old_ref);
             }
         }
+        Ok(())
+    }
+
+    // What save_with_import would do, for a preview
+    pub fn plan_import(&self, import: &Import, on_conflict: OnConflict) -> Plan {
+        import::plan(import, on_conflict, self)
+    }
+
+    // Imports all entries and documents with a single save;
+    // the plan is made after concurrent updates were taken over
+    pub fn save_with_import(&mut self, import: &Import, on_conflict: OnConflict) -> Result<Plan> {
+        let lock = self.lock_for_merging_save(&[])?;
+        let plan = self.plan_import(import, on_conflict);
+        for (edit_bundle, outcome) in import.bundles.iter().zip(&plan.bundles) {
+            match outcome {
+                Outcome::Skip => {}
+                Outcome::Add | Outcome::Merge => {
+                    self.check_totp_secrets(edit_bundle, &edit_bundle.key)?;
+                }
+                Outcome::Rename(key) => self.check_totp_secrets(edit_bundle, key)?,
+            }
+        }
+        for (idx, (edit_bundle, outcome)) in import.bundles.iter().zip(&plan.bundles).enumerate() {
+            let mut edit_bundle = edit_bundle.clone();
+            match outcome {
+                Outcome::Skip => continue,
+                Outcome::Add => {}
+                Outcome::Rename(key) => edit_bundle.key = key.clone(),
+                Outcome::Merge => {
+                    let transient = self.transient().context("not unlocked")?;
+                    let existing = self.bundles().get(&edit_bundle.key).context("vanished")?;
+                    let merged = import::merge_bundle(
+                        VEditBundle::from_bundle(&edit_bundle.key, existing, transient),
+                        &edit_bundle,
+                    );
+                    self.update_bundle(&merged)?;
+                    continue;
+                }
+            }
+            let (_orig_key, key, mut bundle) =
+                edit_bundle.as_oldkey_newkey_bundle(self.transient_mut().unwrap(/*OK*/));
+            if let Some(last_changed_at) = import.bundles_changed_at.get(idx) {
+                bundle = bundle.with_last_changed_at(last_changed_at.clone());
+            }
+            self.add_bundle(key, bundle)?;
+        }
+        for (idx, (edit_document, outcome)) in
+            import.documents.iter().zip(&plan.documents).enumerate()
+        {
+            let mut edit_document = edit_document.clone();
+            match outcome {
+                Outcome::Skip => continue,
+                Outcome::Add => {}
+                Outcome::Rename(key) => edit_document.key = key.clone(),
+                Outcome::Merge => {
+                    let transient = self.transient().context("not unlocked")?;
+                    let existing = self
+                        .documents()
+                        .get(&edit_document.key)
+                        .context("vanished")?;
+                    let merged = import::merge_document(
+                        VEditDocument::from_document(&edit_document.key, existing, transient),
+                        &edit_document,
+                    );
+                    self.update_document(&merged)?;
+                    continue;
+                }
+            }
+            let (_orig_key, key, mut document) =
+                edit_document.as_oldkey_newkey_document(self.transient_mut().unwrap(/*OK*/));
+            if let Some(last_changed_at) = import.documents_changed_at.get(idx) {
+                document = document.with_last_changed_at(last_changed_at.clone());
+            }
+            self.add_document(key, document)?;
+        }
+        self.save(lock)?;
+        Ok(plan)
+    }
 
+    // Creates a new file with only the given entries and documents, which keep their
+    // change times; the secrets are added to the new file's own Transient, and so get new refs.
+    // The new file comes with its own recovery code.
+    pub fn export_subset(
+        &self,
+        bundle_keys: &[Key],
+        document_keys: &[Key],
+        target: &Path,
+        password: &str,
+        o_key_file_path: Option<&Path>,
+    ) -> Result<PlFile> {
+        if target.exists() {
+            return Err(anyhow!(
+                t!("_export_target_exists %{f}", f = target.display()).to_string()
+            ));
+        }
+        let import = self.selection(bundle_keys, document_keys)?;
+
+        let mut new_file = PlFile::read_or_create(target)?;
+        // the names must not be written in clear, not even in a first version of the file,
+        // and the versions that are written while the file is created need no backups
+        if self.is_concealed() {
+            new_file.stored.readable.header.format_version = FORMAT_VERSION_CONCEALED;
+        }
+        new_file.set_backup_generations(0);
+        new_file.set_actionable(password, o_key_file_path)?;
+        new_file.save_with_import(&import, OnConflict::Skip)?;
+        new_file.set_backup_generations(self.backup_generations);
+        Ok(new_file)
+    }
+
+    // Opens another file, e.g. a copy of this one on another machine, to merge it into this one
+    pub fn open_other(
+        &self,
+        path: &Path,
+        password: &str,
+        o_key_file_path: Option<&Path>,
+    ) -> Result<PlFile> {
+        if !path.is_file() {
+            return Err(anyhow!(
+                t!("_merge_file_missing %{f}", f = path.display()).to_string()
+            ));
+        }
+        if path.canonicalize()? == self.file_path.canonicalize()? {
+            return Err(anyhow!(t!("_merge_same_file").to_string()));
+        }
+        let mut theirs = PlFile::read_or_create(path)?;
+        theirs.set_actionable(password, o_key_file_path)?;
+        Ok(theirs)
+    }
+
+    // Takes over the given entries and documents from the other file, with their change times;
+    // existing ones with the same name are replaced. Everything is saved at once.
+    pub fn save_with_merged_file(
+        &mut self,
+        theirs: &PlFile,
+        bundle_keys: &[Key],
+        document_keys: &[Key],
+    ) -> Result<()> {
+        let import = theirs.selection(bundle_keys, document_keys)?;
+        let lock = self.lock_for_merging_save(&[])?;
+        for edit_bundle in &import.bundles {
+            self.check_totp_secrets(edit_bundle, &edit_bundle.key)?;
+        }
+        for (edit_bundle, last_changed_at) in import.bundles.iter().zip(import.bundles_changed_at) {
+            if self.has_bundle(&edit_bundle.key) {
+                self.delete_bundle(edit_bundle.key.clone())?;
+            }
+            let (_orig_key, key, bundle) =
+                edit_bundle.as_oldkey_newkey_bundle(self.transient_mut().unwrap(/*OK*/));
+            self.add_bundle(key, bundle.with_last_changed_at(last_changed_at))?;
+        }
+        for (edit_document, last_changed_at) in
+            import.documents.iter().zip(import.documents_changed_at)
+        {
+            if self.has_document(&edit_document.key) {
+                self.delete_document(&edit_document.key)?;
+            }
+            let (_orig_key, key, document) =
+                edit_document.as_oldkey_newkey_document(self.transient_mut().unwrap(/*OK*/));
+            self.add_document(key, document.with_last_changed_at(last_changed_at))?;
+        }
         self.save(lock)
     }
 
+    // The given entries and documents, with their change times
+    fn selection(&self, bundle_keys: &[Key], document_keys: &[Key]) -> Result<Import> {
+        let transient = self.transient().context("not unlocked")?;
+        let mut import = Import::default();
+        for key in bundle_keys {
+            let bundle = self
+                .bundles()
+                .get(key)
+                .ok_or_else(|| anyhow!(t!("_bundle_does_not_exist %{name}", name = key)))?;
+            import
+                .bundles
+                .push(VEditBundle::from_bundle(key, bundle, transient));
+            import
+                .bundles_changed_at
+                .push(bundle.last_changed_at().clone());
+        }
+        for key in document_keys {
+            let document = self
+                .documents()
+                .get(key)
+                .ok_or_else(|| anyhow!(t!("_document_does_not_exist %{name}", name = key)))?;
+            import
+                .documents
+                .push(VEditDocument::from_document(key, document, transient));
+            import
+                .documents_changed_at
+                .push(document.last_changed_at().clone());
+        }
+        Ok(import)
+    }
+
+    // Resolves a merge conflict in favor of the own version: the file is reloaded,
+    // and the own version replaces whatever the concurrent update left
+    pub fn save_with_my_version(&mut self, key: &Key, mine: &Version) -> Result<()> {
+        self.reload()?;
+        match mine {
+            Version::Bundle(Some(edit_bundle)) => {
+                let mut edit_bundle = edit_bundle.clone();
+                if !self.has_bundle(&edit_bundle.orig_key) {
+                    edit_bundle.orig_key = edit_bundle.key.clone();
+                }
+                if self.has_bundle(&edit_bundle.orig_key) {
+                    self.save_with_updated_bundle(&edit_bundle)
+                } else {
+                    self.save_with_added_bundle(&edit_bundle)
+                }
+            }
+            Version::Bundle(None) if self.has_bundle(key) => {
+                self.save_with_deleted_bundle(key.clone())
+            }
+            Version::Document(Some(edit_document)) => {
+                let mut edit_document = edit_document.clone();
+                if !self.has_document(&edit_document.orig_key) {
+                    edit_document.orig_key = edit_document.key.clone();
+                }
+                if self.has_document(&edit_document.orig_key) {
+                    self.save_with_updated_document(&edit_document)
+                } else {
+                    self.save_with_added_document(&edit_document)
+                }
+            }
+            Version::Document(None) if self.has_document(key) => {
+                self.save_with_deleted_document(key)
+            }
+            // was deleted concurrently, too
+            Version::Bundle(None) | Version::Document(None) => Ok(()),
+        }
+    }
+
     ///////////////////
 }
 
+fn concurrent_update() -> anyhow::Error {
+    ConcurrentUpdate.into()
+}
+
+fn decryption_failed() -> String {
+    format!(
+        "{}\n{}\n  {}\n  {}",
+        jiff::Timestamp::now(),
+        t!("_decryption_failed"),
+        t!("_decryption_failed_note1"),
+        t!("_decryption_failed_note2")
+    )
+}
+
 fn skip_over_comments_and_empty_lines(file_content: &str) -> &str {
     let mut start = 0;
     while let Some(next_line_break) = file_content[start..].find('\n') {
@@ -570,3 +1534,786 @@ fn skip_over_comments_and_empty_lines(file_content: &str) -> &str {
     }
     &file_content[start..]
 }
+
+#[cfg(test)]
+mod test {
+    use super::{
+        DEFAULT_KEY_SLOT_LABEL, FORMAT_VERSION_OPEN, FileHeader, PREFACE, PlFile, Readable, Stored,
+    };
+    use crate::{
+        data::{
+            Bundles, Comparison, Difference, Documents, Import, Key, MergeConflict, OnConflict,
+            Outcome, Secrets, Settings, Version, backups,
+            durable::{SaveStep, fail_at},
+            transient::PBKDF2_ROUNDS,
+        },
+        ui::viz::{VEditBundle, VEditCred},
+    };
+    use pwsec::ChachaB64;
+    use sequential::Sequence;
+
+    #[test]
+    fn test_upgrade_from_format_version_1() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let file_path = temp_dir.path().join("legacy");
+
+        // write a file as version 1 did it
+        let mut update_counter = Sequence::new();
+        update_counter.next();
+        let readable = Readable {
+            header: FileHeader {
+                format_version: 1,
+                update_counter,
+                kdf: None,
+                key_file_fingerprint: None,
+                key_slots: Vec::new(),
+            },
+            bundles: Bundles::new(),
+            documents: Documents::new(),
+        };
+        let cipher = ChachaB64::with_pbkdf2_rounds(PBKDF2_ROUNDS)
+            .encrypt_auth(
+                serde_json::to_string(&Secrets::default())
+                    .unwrap()
+                    .as_bytes(),
+                serde_json::to_string(&readable).unwrap().as_bytes(),
+                "legacy_pw",
+            )
+            .unwrap()
+            .to_string();
+        std::fs::write(
+            &file_path,
+            format!(
+                "{PREFACE}{}\n",
+                serde_json::to_string_pretty(&Stored { readable, cipher }).unwrap()
+            ),
+        )
+        .unwrap();
+
+        // open it, and modify it
+        let mut pl_file = PlFile::read_or_create(&file_path).unwrap();
+        assert!(pl_file.stored.readable.kdf().is_none());
+        let legacy_content = std::fs::read(&file_path).unwrap();
+        pl_file.set_actionable("legacy_pw", None).unwrap();
+        // opening does not write the file, and it can still be reloaded
+        assert_eq!(std::fs::read(&file_path).unwrap(), legacy_content);
+        pl_file.reload().unwrap();
+        // the next save writes the upgrade, after which the session key fits to the file
+        let mut edit_bundle = VEditBundle::new();
+        edit_bundle.key = Key::new("some entry");
+        pl_file.save_with_added_bundle(&edit_bundle).unwrap();
+        let mut other_pl_file = PlFile::read_or_create(&file_path).unwrap();
+        assert_eq!(other_pl_file.key_slots().len(), 1);
+        other_pl_file
+            .set_actionable_with_session_key(&pl_file.session_key().unwrap(), None)
+            .unwrap();
+
+        // it was upgraded and can be opened with the same password
+        let mut pl_file = PlFile::read_or_create(&file_path).unwrap();
+        assert_eq!(
+            pl_file.stored.readable.header.format_version,
+            FORMAT_VERSION_OPEN
+        );
+        assert_eq!(pl_file.key_slots().len(), 1);
+        assert!(pl_file.set_actionable("wrong_pw", None).is_err());
+        pl_file.set_actionable("legacy_pw", None).unwrap();
+        assert!(pl_file.has_bundle(&Key::new("some entry")));
+    }
+
+    #[test]
+    fn test_concealment() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let file_path = temp_dir.path().join("concealed");
+
+        let mut pl_file = PlFile::read_or_create(&file_path).unwrap();
+        pl_file.set_actionable("pw", None).unwrap();
+        let mut edit_bundle = VEditBundle::new();
+        edit_bundle.key = Key::new("My bank account");
+        edit_bundle.description = "with a revealing description".to_string();
+        pl_file.save_with_added_bundle(&edit_bundle).unwrap();
+        let content = std::fs::read_to_string(&file_path).unwrap();
+        assert!(content.contains("My bank account"));
+        assert!(content.contains("with a revealing description"));
+
+        pl_file.save_with_concealment(true).unwrap();
+        assert!(pl_file.has_bundle(&Key::new("My bank account")));
+        let content = std::fs::read_to_string(&file_path).unwrap();
+        assert!(!content.contains("My bank account"));
+        assert!(!content.contains("with a revealing description"));
+
+        let mut pl_file = PlFile::read_or_create(&file_path).unwrap();
+        assert!(pl_file.is_concealed());
+        assert!(pl_file.bundles().is_empty());
+        pl_file.set_actionable("pw", None).unwrap();
+        assert_eq!(
+            pl_file
+                .bundles()
+                .get(&Key::new("My bank account"))
+                .unwrap()
+                .description(),
+            "with a revealing description"
+        );
+
+        pl_file.save_with_concealment(false).unwrap();
+        let content = std::fs::read_to_string(&file_path).unwrap();
+        assert!(content.contains("My bank account"));
+    }
+
+    #[test]
+    fn test_change_password() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let file_path = temp_dir.path().join("change_pw");
+
+        let mut pl_file = PlFile::read_or_create(&file_path).unwrap();
+        pl_file.set_actionable("old_pw", None).unwrap();
+        assert!(pl_file.change_password("wrong_pw", "new_pw", None).is_err());
+        pl_file.change_password("old_pw", "new_pw", None).unwrap();
+
+        let mut pl_file = PlFile::read_or_create(&file_path).unwrap();
+        assert!(pl_file.set_actionable("old_pw", None).is_err());
+        pl_file.set_actionable("new_pw", None).unwrap();
+    }
+
+    #[test]
+    fn test_key_file() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let file_path = temp_dir.path().join("with_key_file");
+        let key_file = temp_dir.path().join("key_file");
+        let other_key_file = temp_dir.path().join("other_key_file");
+        std::fs::write(&key_file, "key file content").unwrap();
+        std::fs::write(&other_key_file, "other key file content").unwrap();
+
+        let mut pl_file = PlFile::read_or_create(&file_path).unwrap();
+        pl_file.set_actionable("pw", Some(&key_file)).unwrap();
+
+        let mut pl_file = PlFile::read_or_create(&file_path).unwrap();
+        assert!(pl_file.requires_key_file());
+        assert!(pl_file.set_actionable("pw", None).is_err());
+        assert!(pl_file.set_actionable("pw", Some(&other_key_file)).is_err());
+        assert!(pl_file.set_actionable("wrong_pw", Some(&key_file)).is_err());
+        pl_file.set_actionable("pw", Some(&key_file)).unwrap();
+
+        // remove the key file with a password change
+        pl_file.change_password("pw", "pw", None).unwrap();
+        let mut pl_file = PlFile::read_or_create(&file_path).unwrap();
+        assert!(!pl_file.requires_key_file());
+        assert!(pl_file.set_actionable("pw", Some(&key_file)).is_err());
+        pl_file.set_actionable("pw", None).unwrap();
+    }
+
+    #[test]
+    fn test_key_slots() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let file_path = temp_dir.path().join("key_slots");
+
+        let mut pl_file = PlFile::read_or_create(&file_path).unwrap();
+        pl_file.set_actionable("alice_pw", None).unwrap();
+        pl_file.add_key_slot("bob", "bob_pw", None).unwrap();
+        assert!(pl_file.add_key_slot("bob", "other_pw", None).is_err());
+
+        // bob changes his password, alice is not affected
+        let mut pl_file = PlFile::read_or_create(&file_path).unwrap();
+        pl_file.set_actionable("bob_pw", None).unwrap();
+        assert_eq!(pl_file.current_key_slot(), Some("bob"));
+        assert!(pl_file.revoke_key_slot("bob").is_err());
+        pl_file
+            .change_password("bob_pw", "new_bob_pw", None)
+            .unwrap();
+
+        let mut pl_file = PlFile::read_or_create(&file_path).unwrap();
+        assert!(pl_file.set_actionable("bob_pw", None).is_err());
+        pl_file.set_actionable("alice_pw", None).unwrap();
+        assert_eq!(pl_file.current_key_slot(), Some(DEFAULT_KEY_SLOT_LABEL));
+
+        // bob keeps an old copy of the file, and alice has the file open elsewhere
+        let old_copy = temp_dir.path().join("old_copy");
+        std::fs::copy(&file_path, &old_copy).unwrap();
+        let mut alice_elsewhere = PlFile::read_or_create(&file_path).unwrap();
+        alice_elsewhere.set_actionable("alice_pw", None).unwrap();
+
+        // alice revokes bob's key slot
+        pl_file.revoke_key_slot("bob").unwrap();
+        let mut pl_file = PlFile::read_or_create(&file_path).unwrap();
+        assert_eq!(pl_file.key_slots().len(), 2);
+        assert!(pl_file.set_actionable("new_bob_pw", None).is_err());
+
+        // the data key from the old copy does not open the file anymore
+        let mut bobs_copy = PlFile::read_or_create(&old_copy).unwrap();
+        bobs_copy.set_actionable("new_bob_pw", None).unwrap();
+        let old_data_key = bobs_copy.session_key().unwrap();
+        assert!(
+            pl_file
+                .set_actionable_with_session_key(&old_data_key, None)
+                .is_err()
+        );
+
+        // while the other session of alice can still reload the file
+        alice_elsewhere.reload().unwrap();
+        assert_ne!(alice_elsewhere.session_key().unwrap(), old_data_key);
+        pl_file.set_actionable("alice_pw", None).unwrap();
+    }
+
+    #[test]
+    fn test_recovery_code() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let file_path = temp_dir.path().join("recovery");
+
+        let mut pl_file = PlFile::read_or_create(&file_path).unwrap();
+        pl_file.set_actionable("forgotten_pw", None).unwrap();
+        let recovery_code = pl_file.take_recovery_code().unwrap();
+        assert!(pl_file.take_recovery_code().is_none());
+
+        let mut pl_file = PlFile::read_or_create(&file_path).unwrap();
+        assert!(pl_file.has_recovery_code());
+        pl_file
+            .set_actionable_with_recovery_code(&recovery_code)
+            .unwrap();
+        assert!(pl_file.change_password(&recovery_code, "pw", None).is_err());
+        pl_file.add_key_slot("new", "new_pw", None).unwrap();
+        pl_file.revoke_key_slot(DEFAULT_KEY_SLOT_LABEL).unwrap();
+        assert!(pl_file.revoke_key_slot("new").is_err());
+
+        // the old recovery code is invalidated by a new one
+        let old_copy = temp_dir.path().join("old_copy");
+        std::fs::copy(&file_path, &old_copy).unwrap();
+        pl_file.regenerate_recovery_code().unwrap();
+        let new_recovery_code = pl_file.take_recovery_code().unwrap();
+        let mut pl_file = PlFile::read_or_create(&file_path).unwrap();
+        assert!(
+            pl_file
+                .set_actionable_with_recovery_code(&recovery_code)
+                .is_err()
+        );
+        // also together with an older copy of the file
+        let mut old_pl_file = PlFile::read_or_create(&old_copy).unwrap();
+        old_pl_file
+            .set_actionable_with_recovery_code(&recovery_code)
+            .unwrap();
+        assert!(
+            pl_file
+                .set_actionable_with_session_key(&old_pl_file.session_key().unwrap(), None)
+                .is_err()
+        );
+        pl_file
+            .set_actionable_with_recovery_code(&new_recovery_code)
+            .unwrap();
+        let mut pl_file = PlFile::read_or_create(&file_path).unwrap();
+        pl_file.set_actionable("new_pw", None).unwrap();
+    }
+
+    #[test]
+    fn test_session_key() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let file_path = temp_dir.path().join("session");
+
+        let mut pl_file = PlFile::read_or_create(&file_path).unwrap();
+        pl_file.set_actionable("pw", None).unwrap();
+        let session_key = pl_file.session_key().unwrap();
+
+        let mut other_pl_file = PlFile::read_or_create(&file_path).unwrap();
+        assert!(other_pl_file.session_key().is_err());
+        assert!(
+            other_pl_file
+                .set_actionable_with_session_key("bm90IHRoZSBrZXk", None)
+                .is_err()
+        );
+        other_pl_file
+            .set_actionable_with_session_key(&session_key, Some(DEFAULT_KEY_SLOT_LABEL.to_string()))
+            .unwrap();
+        assert!(other_pl_file.is_actionable());
+        assert_eq!(
+            other_pl_file.current_key_slot(),
+            Some(DEFAULT_KEY_SLOT_LABEL)
+        );
+    }
+
+    #[test]
+    fn test_save_failures() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let file_path = temp_dir.path().join("failures");
+        let temp_path = Settings::temp_document_file(&file_path).unwrap();
+
+        let mut pl_file = PlFile::read_or_create(&file_path).unwrap();
+        pl_file.set_actionable("pw", None).unwrap();
+        let mut edit_bundle = VEditBundle::new();
+        edit_bundle.key = Key::new("a");
+        edit_bundle.v_edit_creds = vec![VEditCred {
+            name: "user".to_string(),
+            secret: "secret".to_string(),
+        }];
+        pl_file.save_with_added_bundle(&edit_bundle).unwrap();
+
+        for step in [
+            SaveStep::WriteTempFile,
+            SaveStep::SyncTempFile,
+            SaveStep::VerifyTempFile,
+            SaveStep::Rename,
+        ] {
+            let mut pl_file = PlFile::read_or_create(&file_path).unwrap();
+            pl_file.set_actionable("pw", None).unwrap();
+            let update_counter = pl_file.update_counter().peek();
+
+            fail_at(Some(step));
+            edit_bundle.key = Key::new(format!("{step:?}"));
+            assert!(pl_file.save_with_added_bundle(&edit_bundle).is_err());
+            fail_at(None);
+
+            // the file is unchanged, no temp file is left, the failed change is gone,
+            // and saving is still possible
+            assert!(!temp_path.exists(), "{step:?}");
+            assert_eq!(pl_file.update_counter().peek(), update_counter, "{step:?}");
+            assert!(!pl_file.has_bundle(&Key::new(format!("{step:?}"))));
+            let mut pl_file2 = PlFile::read_or_create(&file_path).unwrap();
+            pl_file2.set_actionable("pw", None).unwrap();
+            assert!(!pl_file2.has_bundle(&Key::new(format!("{step:?}"))));
+            pl_file.save_with_concealment(true).unwrap();
+            pl_file.save_with_concealment(false).unwrap();
+            let mut pl_file2 = PlFile::read_or_create(&file_path).unwrap();
+            pl_file2.set_actionable("pw", None).unwrap();
+            assert!(
+                !pl_file2.has_bundle(&Key::new(format!("{step:?}"))),
+                "{step:?}"
+            );
+            // only the name and secret of the cred of "a"
+            assert_eq!(pl_file2.transient().unwrap().refs().count(), 2, "{step:?}");
+        }
+
+        // if only syncing the folder fails, the file was nevertheless replaced
+        let mut pl_file = PlFile::read_or_create(&file_path).unwrap();
+        pl_file.set_actionable("pw", None).unwrap();
+        fail_at(Some(SaveStep::SyncFolder));
+        edit_bundle.key = Key::new("c");
+        pl_file.save_with_added_bundle(&edit_bundle).unwrap();
+        fail_at(None);
+        let mut pl_file = PlFile::read_or_create(&file_path).unwrap();
+        pl_file.set_actionable("pw", None).unwrap();
+        assert!(pl_file.has_bundle(&Key::new("c")));
+    }
+
+    #[test]
+    fn test_failed_change_password() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let file_path = temp_dir.path().join("change_pw_fails");
+        let key_file = temp_dir.path().join("key_file");
+        std::fs::write(&key_file, "key file content").unwrap();
+        let mut pl_file = PlFile::read_or_create(&file_path).unwrap();
+        pl_file.set_actionable("pw", Some(&key_file)).unwrap();
+
+        // the key file is to be removed, but the save fails
+        fail_at(Some(SaveStep::Rename));
+        assert!(pl_file.change_password("pw", "new_pw", None).is_err());
+        fail_at(None);
+
+        // the key file with which the file was opened is still used
+        pl_file.check_password("pw", None).unwrap();
+        assert!(pl_file.check_password("new_pw", None).is_err());
+        pl_file.change_password("pw", "new_pw", None).unwrap();
+        let mut pl_file = PlFile::read_or_create(&file_path).unwrap();
+        pl_file.set_actionable("new_pw", None).unwrap();
+    }
+
+    #[test]
+    fn test_leftover_temp_file() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let file_path = temp_dir.path().join("interrupted");
+        let old_path = temp_dir.path().join("old");
+        let temp_path = Settings::temp_document_file(&file_path).unwrap();
+
+        let mut pl_file = PlFile::read_or_create(&file_path).unwrap();
+        pl_file.set_actionable("pw", None).unwrap();
+        let mut edit_bundle = VEditBundle::new();
+        edit_bundle.key = Key::new("a");
+        pl_file.save_with_added_bundle(&edit_bundle).unwrap();
+        std::fs::copy(&file_path, &old_path).unwrap();
+        edit_bundle.key = Key::new("b");
+        pl_file.save_with_added_bundle(&edit_bundle).unwrap();
+
+        // simulate a crash before the temp file replaced the file
+        std::fs::rename(&file_path, &temp_path).unwrap();
+        std::fs::copy(&old_path, &file_path).unwrap();
+
+        let mut pl_file = PlFile::read_or_create(&file_path).unwrap();
+        pl_file.set_actionable("pw", None).unwrap();
+        let leftover = pl_file.leftover_temp_file().unwrap();
+        assert!(leftover.is_newer && leftover.is_decryptable);
+        pl_file.recover_leftover_temp_file().unwrap();
+        assert!(pl_file.has_bundle(&Key::new("b")));
+        assert!(!temp_path.exists());
+
+        // an older temp file can only be discarded
+        std::fs::copy(&old_path, &temp_path).unwrap();
+        let mut pl_file = PlFile::read_or_create(&file_path).unwrap();
+        pl_file.set_actionable("pw", None).unwrap();
+        assert!(!pl_file.leftover_temp_file().unwrap().is_newer);
+        pl_file.discard_leftover_temp_file().unwrap();
+        assert!(!temp_path.exists());
+        assert!(pl_file.has_bundle(&Key::new("b")));
+    }
+
+    #[test]
+    fn test_merge() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let file_path = temp_dir.path().join("shared");
+        let open = || {
+            let mut pl_file = PlFile::read_or_create(&file_path).unwrap();
+            pl_file.set_actionable("pw", None).unwrap();
+            pl_file
+        };
+        let edit = |key: &str, description: &str| {
+            let mut edit_bundle = VEditBundle::new();
+            edit_bundle.orig_key = Key::new(key);
+            edit_bundle.key = Key::new(key);
+            edit_bundle.description = description.to_string();
+            edit_bundle
+        };
+
+        let mut pl_file = open();
+        pl_file.save_with_added_bundle(&edit("a", "")).unwrap();
+        pl_file.save_with_added_bundle(&edit("b", "")).unwrap();
+
+        // a concurrent update of another entry is merged
+        let mut mine = open();
+        let mut theirs = open();
+        theirs
+            .save_with_updated_bundle(&edit("a", "theirs"))
+            .unwrap();
+        mine.save_with_added_bundle(&edit("c", "mine")).unwrap();
+        let merged = open();
+        assert_eq!(
+            merged.bundles().get(&Key::new("a")).unwrap().description(),
+            "theirs"
+        );
+        assert!(merged.has_bundle(&Key::new("c")));
+
+        // a concurrent update of the same entry is a conflict
+        theirs.save_with_deleted_bundle(Key::new("b")).unwrap();
+        let e = mine
+            .save_with_updated_bundle(&edit("b", "mine"))
+            .unwrap_err();
+        let conflict = e.downcast::<MergeConflict>().unwrap();
+        assert_eq!(conflict.key, Key::new("b"));
+        assert!(matches!(conflict.theirs, Version::Bundle(None)));
+
+        // which can be resolved in favor of the own version
+        mine.save_with_my_version(&conflict.key, &Version::Bundle(Some(edit("b", "mine"))))
+            .unwrap();
+        let resolved = open();
+        assert_eq!(
+            resolved
+                .bundles()
+                .get(&Key::new("b"))
+                .unwrap()
+                .description(),
+            "mine"
+        );
+        assert_eq!(
+            resolved
+                .bundles()
+                .get(&Key::new("a"))
+                .unwrap()
+                .description(),
+            "theirs"
+        );
+
+        // a concurrent update is noticed and can be taken over without saving
+        let mut reader = open();
+        assert!(!reader.is_outdated().unwrap());
+        mine.save_with_deleted_bundle(Key::new("a")).unwrap();
+        assert!(reader.is_outdated().unwrap());
+        reader.reload().unwrap();
+        assert!(!reader.is_outdated().unwrap());
+        assert!(!reader.has_bundle(&Key::new("a")));
+    }
+
+    #[test]
+    fn test_import() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let file_path = temp_dir.path().join("import");
+        let bundle = |key: &str, description: &str, creds: &[(&str, &str)]| {
+            let mut edit_bundle = VEditBundle::new();
+            edit_bundle.key = Key::new(key);
+            edit_bundle.description = description.to_string();
+            edit_bundle.v_edit_creds = creds
+                .iter()
+                .map(|(name, secret)| VEditCred {
+                    name: name.to_string(),
+                    secret: secret.to_string(),
+                })
+                .collect();
+            edit_bundle
+        };
+        let import = Import {
+            bundles: vec![
+                bundle("mail", "imported", &[("bob", "x"), ("alice", "y")]),
+                bundle("New", "", &[]),
+                bundle("new", "", &[]),
+            ],
+            ..Import::default()
+        };
+
+        for (on_conflict, expected) in [
+            (
+                OnConflict::Skip,
+                vec![Outcome::Skip, Outcome::Add, Outcome::Skip],
+            ),
+            (
+                OnConflict::Rename,
+                vec![
+                    Outcome::Rename(Key::new("mail (2)")),
+                    Outcome::Add,
+                    Outcome::Rename(Key::new("new (2)")),
+                ],
+            ),
+            (
+                OnConflict::Merge,
+                vec![Outcome::Merge, Outcome::Add, Outcome::Merge],
+            ),
+        ] {
+            std::fs::remove_file(&file_path).ok();
+            let mut pl_file = PlFile::read_or_create(&file_path).unwrap();
+            pl_file.set_actionable("pw", None).unwrap();
+            pl_file
+                .save_with_added_bundle(&bundle("Mail", "mine", &[("bob", "x")]))
+                .unwrap();
+            let update_counter = pl_file.update_counter().peek();
+
+            assert_eq!(pl_file.plan_import(&import, on_conflict).bundles, expected);
+            let plan = pl_file.save_with_import(&import, on_conflict).unwrap();
+            assert_eq!(plan.bundles, expected);
+            // everything is saved at once
+            assert_eq!(
+                pl_file.update_counter().peek(),
+                update_counter.map(|counter| counter + 1)
+            );
+
+            let transient = pl_file.transient().unwrap();
+            let mail = pl_file.bundles().get(&Key::new("Mail")).unwrap();
+            match on_conflict {
+                OnConflict::Skip => assert_eq!(pl_file.bundles().len(), 2),
+                OnConflict::Rename => assert_eq!(pl_file.bundles().len(), 4),
+                OnConflict::Merge => {
+                    assert_eq!(pl_file.bundles().len(), 2);
+                    assert_eq!(mail.description(), "mine\nimported");
+                    let names: Vec<_> = mail.creds().iter().map(|c| c.name(transient)).collect();
+                    assert_eq!(names, ["bob", "alice"]);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_export_subset() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let file_path = temp_dir.path().join("source");
+        let target = temp_dir.path().join("target");
+        let mut pl_file = PlFile::read_or_create(&file_path).unwrap();
+        pl_file.set_actionable("pw", None).unwrap();
+        for (key, secret) in [("a", "x"), ("b", "y"), ("c", "z")] {
+            let mut edit_bundle = VEditBundle::new();
+            edit_bundle.key = Key::new(key);
+            edit_bundle.v_edit_creds = vec![VEditCred {
+                name: key.to_string(),
+                secret: secret.to_string(),
+            }];
+            pl_file.save_with_added_bundle(&edit_bundle).unwrap();
+        }
+        let changed_at = pl_file
+            .bundles()
+            .get(&Key::new("c"))
+            .unwrap()
+            .last_changed_at()
+            .clone();
+
+        let mut exported = pl_file
+            .export_subset(&[Key::new("c")], &[], &target, "new", None)
+            .unwrap();
+        assert!(exported.take_recovery_code().is_some());
+        // the existing file is not overwritten
+        assert!(
+            pl_file
+                .export_subset(&[Key::new("a")], &[], &target, "new", None)
+                .is_err()
+        );
+        assert_eq!(pl_file.bundles().len(), 3);
+
+        let mut reader = PlFile::read_or_create(&target).unwrap();
+        assert!(reader.set_actionable("pw", None).is_err());
+        reader.set_actionable("new", None).unwrap();
+        assert_eq!(reader.bundles().len(), 1);
+        let bundle = reader.bundles().get(&Key::new("c")).unwrap();
+        assert_eq!(bundle.last_changed_at(), &changed_at);
+        let transient = reader.transient().unwrap();
+        assert_eq!(bundle.creds()[0].secret(transient), "z");
+        assert!(!backups::backup_folder(&target).unwrap().exists());
+
+        // the names are concealed from the start, like in the source
+        pl_file.save_with_concealment(true).unwrap();
+        let concealed_target = temp_dir.path().join("concealed_target");
+        pl_file
+            .export_subset(&[Key::new("c")], &[], &concealed_target, "new", None)
+            .unwrap();
+        let reader = PlFile::read_or_create(&concealed_target).unwrap();
+        assert!(reader.is_concealed());
+        assert!(reader.bundles().is_empty());
+        assert!(!backups::backup_folder(&concealed_target).unwrap().exists());
+    }
+
+    #[test]
+    fn test_merge_file() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let add = |pl_file: &mut PlFile, key: &str, secret: &str| {
+            let mut edit_bundle = VEditBundle::new();
+            edit_bundle.key = Key::new(key);
+            edit_bundle.v_edit_creds = vec![VEditCred {
+                name: "user".to_string(),
+                secret: secret.to_string(),
+            }];
+            pl_file.save_with_added_bundle(&edit_bundle).unwrap();
+        };
+        let mut mine = PlFile::read_or_create(&temp_dir.path().join("mine")).unwrap();
+        mine.set_actionable("pw", None).unwrap();
+        let mut theirs = PlFile::read_or_create(&temp_dir.path().join("theirs")).unwrap();
+        theirs.set_actionable("other", None).unwrap();
+        add(&mut mine, "a", "1");
+        add(&mut mine, "b", "2");
+        add(&mut mine, "c", "3");
+        add(&mut theirs, "B", "22");
+        add(&mut theirs, "c", "3");
+        add(&mut theirs, "d", "4");
+
+        let comparison = Comparison::new(&mine, &theirs).unwrap();
+        let differences: Vec<_> = comparison
+            .bundles
+            .iter()
+            .map(|c| (c.key.as_str(), c.difference))
+            .collect();
+        assert_eq!(
+            differences,
+            [
+                ("a", Difference::OnlyMine),
+                ("b", Difference::Differing),
+                ("c", Difference::Identical),
+                ("d", Difference::OnlyTheirs)
+            ]
+        );
+        assert!(comparison.bundles[1].theirs_is_newer());
+        let (bundle_keys, document_keys) = comparison.proposal();
+        assert_eq!(bundle_keys, [Key::new("b"), Key::new("d")]);
+
+        let update_counter = mine.update_counter().peek();
+        mine.save_with_merged_file(&theirs, &bundle_keys, &document_keys)
+            .unwrap();
+        assert_eq!(
+            mine.update_counter().peek(),
+            update_counter.map(|counter| counter + 1)
+        );
+        assert_eq!(mine.bundles().len(), 4);
+        let transient = mine.transient().unwrap();
+        let b = mine.bundles().get(&Key::new("b")).unwrap();
+        assert_eq!(b.creds()[0].secret(transient), "22");
+        assert_eq!(
+            b.last_changed_at(),
+            theirs
+                .bundles()
+                .get(&Key::new("b"))
+                .unwrap()
+                .last_changed_at()
+        );
+        let d = mine.bundles().get(&Key::new("d")).unwrap();
+        assert_eq!(d.creds()[0].secret(transient), "4");
+        assert!(
+            Comparison::new(&mine, &theirs)
+                .unwrap()
+                .proposal()
+                .0
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_totp_check() {
+        const INVALID: &str = "otpauth://totp/mail?digits=6";
+        let temp_dir = tempfile::tempdir().unwrap();
+        let edit = |key: &str, creds: &[(&str, &str)]| {
+            let mut edit_bundle = VEditBundle::new();
+            edit_bundle.orig_key = Key::new(key);
+            edit_bundle.key = Key::new(key);
+            edit_bundle.v_edit_creds = creds
+                .iter()
+                .map(|(name, secret)| VEditCred {
+                    name: name.to_string(),
+                    secret: secret.to_string(),
+                })
+                .collect();
+            edit_bundle
+        };
+        // stores the invalid URI without the check, as older versions did
+        let open_with_invalid = |name: &str| {
+            let mut pl_file = PlFile::read_or_create(&temp_dir.path().join(name)).unwrap();
+            pl_file.set_actionable("pw", None).unwrap();
+            let lock = pl_file.lock_for_save().unwrap();
+            let (_orig_key, key, bundle) = edit("mail", &[("totp", INVALID)])
+                .as_oldkey_newkey_bundle(pl_file.transient_mut().unwrap());
+            pl_file.add_bundle(key, bundle).unwrap();
+            pl_file.save(lock).unwrap();
+            pl_file
+        };
+
+        let mut pl_file = open_with_invalid("mine");
+        assert!(
+            pl_file
+                .save_with_added_bundle(&edit("new", &[("totp", INVALID)]))
+                .is_err()
+        );
+        // the entry can still be edited, but the URI cannot be changed to another invalid one
+        pl_file
+            .save_with_updated_bundle(&edit("mail", &[("totp", INVALID), ("user", "bob")]))
+            .unwrap();
+        let err = pl_file
+            .save_with_updated_bundle(&edit("mail", &[("totp", "otpauth://totp/x")]))
+            .unwrap_err();
+        assert!(err.to_string().contains("mail"), "{err}");
+
+        // import: a new entry is checked, a merged entry only with what it adds
+        let import = |bundles| Import {
+            bundles,
+            ..Import::default()
+        };
+        assert!(
+            pl_file
+                .save_with_import(
+                    &import(vec![edit("new", &[("totp", INVALID)])]),
+                    OnConflict::Skip
+                )
+                .is_err()
+        );
+        assert!(
+            pl_file
+                .save_with_import(
+                    &import(vec![edit("mail", &[("totp", INVALID)])]),
+                    OnConflict::Rename
+                )
+                .is_err()
+        );
+        pl_file
+            .save_with_import(
+                &import(vec![edit("mail", &[("totp", INVALID)])]),
+                OnConflict::Merge,
+            )
+            .unwrap();
+        assert!(!pl_file.has_bundle(&Key::new("new")));
+
+        // merge with another file: only secrets that this file does not have are checked
+        let theirs = open_with_invalid("theirs");
+        pl_file
+            .save_with_merged_file(&theirs, &[Key::new("mail")], &[])
+            .unwrap();
+        pl_file.save_with_deleted_bundle(Key::new("mail")).unwrap();
+        assert!(
+            pl_file
+                .save_with_merged_file(&theirs, &[Key::new("mail")], &[])
+                .is_err()
+        );
+        assert!(!pl_file.has_bundle(&Key::new("mail")));
+    }
+}
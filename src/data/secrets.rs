@@ -6,6 +6,9 @@ use std::collections::{HashMap, hash_map::Keys};
 pub struct Secrets {
     random_string: String,
     content: HashMap<u64, String>,
+    // the X25519 identity for sharing, in the age format; is missing in older files
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    identity: Option<String>,
 }
 impl Secrets {
     pub fn prepare(&mut self) {
@@ -33,4 +36,13 @@ impl Secrets {
     pub fn get(&self, idx: u64) -> Option<&str> {
         self.content.get(&idx).map(String::as_str)
     }
+
+    #[must_use]
+    pub fn identity(&self) -> Option<&str> {
+        self.identity.as_deref()
+    }
+
+    pub fn set_identity(&mut self, identity: String) {
+        self.identity = Some(identity);
+    }
 }
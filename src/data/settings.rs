@@ -1,3 +1,4 @@
+use crate::data::DEFAULT_BACKUP_GENERATIONS;
 use anyhow::{Context, Result, anyhow};
 use fd_lock::RwLock as FdRwLock;
 use oxilangtag::LanguageTag;
@@ -12,6 +13,8 @@ const TEST_DOC_FOLDER: &str = ".prolock_test";
 const SETTINGS_FILE: &str = "settings";
 const DEFAULT_DATA_FILE: &str = "secrets";
 const TEMP_DATA_FILE_SUFFIX: &str = "_temp_file_for_secure_storing_780987z543w";
+const AGENT_FOLDER: &str = "agent";
+const AGENT_SOCKET: &str = "socket";
 
 const DEFAULT_LOCALE: &str = "en";
 
@@ -20,10 +23,16 @@ pub struct Settings {
     pub files: Vec<PathBuf>,
     pub current_file: usize,
     pub language: String,
+    #[serde(default = "default_backup_generations")]
+    pub backup_generations: usize,
     #[serde(default)]
     is_test: bool,
 }
 
+fn default_backup_generations() -> usize {
+    DEFAULT_BACKUP_GENERATIONS
+}
+
 fn default_language() -> String {
     let locale = sys_locale::get_locale().unwrap_or(DEFAULT_LOCALE.to_string());
     LanguageTag::parse(locale)
@@ -37,6 +46,7 @@ impl Settings {
             files: vec![Self::default_document_file(is_test)?],
             current_file: 0,
             language: default_language(),
+            backup_generations: DEFAULT_BACKUP_GENERATIONS,
             is_test,
         })
     }
@@ -121,14 +131,21 @@ impl Settings {
     }
 
     pub fn add_and_set_file(&mut self, file: &Path) -> Result<()> {
+        self.current_file = self.register(file)?;
+        self.save()
+    }
+    // Adds the file to the known files, without switching to it
+    pub fn add_file(&mut self, file: &Path) -> Result<()> {
+        self.register(file)?;
+        self.save()
+    }
+    fn register(&mut self, file: &Path) -> Result<usize> {
         let canonfile = canonicalize(file)?;
         if let Some(pos) = self.files.iter().position(|f| f == &canonfile) {
-            self.current_file = pos;
-            self.save()
+            Ok(pos)
         } else {
             self.files.push(canonfile);
-            self.current_file = self.files.len() - 1;
-            self.save()
+            Ok(self.files.len() - 1)
         }
     }
     pub fn forget_file(&mut self, file: &Path) -> Result<()> {
@@ -146,18 +163,6 @@ impl Settings {
         }
     }
 
-    #[allow(clippy::unused_self)]
-    pub fn default_marker(&self, idx: usize) -> &'static str {
-        if idx == 0 { "(default)" } else { "" }
-    }
-    pub fn current_marker(&self, idx: usize) -> &'static str {
-        if idx == self.current_file {
-            "(current)"
-        } else {
-            ""
-        }
-    }
-
     pub fn set_language(&mut self, lang: &str) -> Result<()> {
         self.language.clear();
         self.language.push_str(lang);
@@ -168,6 +173,11 @@ impl Settings {
         Ok(())
     }
 
+    pub fn set_backup_generations(&mut self, backup_generations: usize) -> Result<()> {
+        self.backup_generations = backup_generations;
+        self.save()
+    }
+
     fn document_folder(is_test: bool) -> Result<PathBuf> {
         let mut file_path = dirs::home_dir().context("Can't find home directory")?;
         file_path.push(if is_test || cfg!(test) {
@@ -190,6 +200,18 @@ impl Settings {
         Ok(file_path)
     }
 
+    pub fn is_test(&self) -> bool {
+        self.is_test
+    }
+
+    // The socket of the agent lives in its own folder, which must only be accessible for the user
+    pub fn agent_socket(&self) -> Result<PathBuf> {
+        let mut file_path = Self::document_folder(self.is_test)?;
+        file_path.push(AGENT_FOLDER);
+        file_path.push(AGENT_SOCKET);
+        Ok(file_path)
+    }
+
     pub fn temp_document_file(path: &Path) -> Result<PathBuf> {
         let mut file_path = PathBuf::from(path);
         let mut name = file_path.file_name().context("file name")?.to_owned();
@@ -0,0 +1,130 @@
+use crate::data::{Export, Import, Key, PlFile};
+use age::{
+    armor::{ArmoredReader, ArmoredWriter, Format},
+    x25519,
+};
+use anyhow::{Context, Result, anyhow};
+use std::io::{Read, Write};
+
+// A package is an age file in ASCII armor, encrypted to the public keys of the recipients;
+// it contains an export of the shared entries and documents, with their change times
+
+pub(crate) fn parse_recipient(recipient: &str) -> Result<x25519::Recipient> {
+    recipient
+        .trim()
+        .parse()
+        .map_err(|_| anyhow!(t!("_share_invalid_recipient %{r}", r = recipient.trim()).to_string()))
+}
+
+// Public keys are given one per line; empty lines and comments starting with # are ignored
+pub(crate) fn parse_recipients(text: &str) -> Result<Vec<x25519::Recipient>> {
+    text.lines()
+        .map(|line| line.split('#').next().unwrap_or_default().trim())
+        .filter(|line| !line.is_empty())
+        .map(parse_recipient)
+        .collect()
+}
+
+pub(crate) fn seal_package(
+    pl_file: &PlFile,
+    bundle_keys: &[Key],
+    document_keys: &[Key],
+    recipients: &[x25519::Recipient],
+) -> Result<String> {
+    if recipients.is_empty() {
+        return Err(anyhow!(t!("_share_no_recipients").to_string()));
+    }
+    let mut export = Export::new(pl_file)?;
+    export.retain(bundle_keys, document_keys);
+    let encryptor = age::Encryptor::with_recipients(
+        recipients
+            .iter()
+            .map(|recipient| recipient as &dyn age::Recipient),
+    )?;
+    let mut package = Vec::new();
+    let armored_writer = ArmoredWriter::wrap_output(&mut package, Format::AsciiArmor)?;
+    let mut writer = encryptor.wrap_output(armored_writer)?;
+    writer.write_all(serde_json::to_string(&export)?.as_bytes())?;
+    writer.finish()?.finish()?;
+    Ok(String::from_utf8(package)?)
+}
+
+// Decrypts the package with the identity of the file
+pub(crate) fn open_package(pl_file: &PlFile, package: &[u8]) -> Result<Import> {
+    let identity: x25519::Identity = pl_file
+        .transient()
+        .context("not unlocked")?
+        .identity()
+        .ok_or_else(|| anyhow!(t!("_share_no_identity").to_string()))?
+        .parse()
+        .map_err(|e: &str| anyhow!(e))?;
+    let decryptor = age::Decryptor::new_buffered(ArmoredReader::new(package))
+        .map_err(|_| anyhow!(t!("_share_not_a_package").to_string()))?;
+    let mut reader = decryptor
+        .decrypt(std::iter::once(&identity as &dyn age::Identity))
+        .map_err(|e| match e {
+            age::DecryptError::NoMatchingKeys => anyhow!(t!("_share_not_for_me").to_string()),
+            e => anyhow!(e),
+        })?;
+    let mut json = String::new();
+    reader.read_to_string(&mut json)?;
+    let export: Export =
+        serde_json::from_str(&json).map_err(|_| anyhow!(t!("_share_not_a_package").to_string()))?;
+    Ok(export.into_import())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{open_package, parse_recipients, seal_package};
+    use crate::{
+        data::{Key, PlFile},
+        ui::viz::{VEditBundle, VEditCred},
+    };
+
+    #[test]
+    fn test_share() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut sender = PlFile::read_or_create(&temp_dir.path().join("sender")).unwrap();
+        sender.set_actionable("pw", None).unwrap();
+        for key in ["shared", "private"] {
+            let mut edit_bundle = VEditBundle::new();
+            edit_bundle.key = Key::new(key);
+            edit_bundle.v_edit_creds = vec![VEditCred {
+                name: "admin".to_string(),
+                secret: format!("{key} secret"),
+            }];
+            sender.save_with_added_bundle(&edit_bundle).unwrap();
+        }
+
+        let receiver_path = temp_dir.path().join("receiver");
+        let mut receiver = PlFile::read_or_create(&receiver_path).unwrap();
+        receiver.set_actionable("pw", None).unwrap();
+        assert!(receiver.public_key().is_none());
+        receiver.save_with_new_identity().unwrap();
+        assert!(receiver.save_with_new_identity().is_err());
+        // the identity is stored in the file
+        let public_key = receiver.public_key().unwrap();
+        let mut receiver = PlFile::read_or_create(&receiver_path).unwrap();
+        receiver.set_actionable("pw", None).unwrap();
+        assert_eq!(receiver.public_key().unwrap(), public_key);
+
+        let recipients = parse_recipients(&format!("# Alice\n{public_key}\n\n")).unwrap();
+        assert!(parse_recipients("age1nonsense").is_err());
+        let package = seal_package(&sender, &[Key::new("shared")], &[], &recipients).unwrap();
+        assert!(package.starts_with("-----BEGIN AGE ENCRYPTED FILE-----"));
+
+        let import = open_package(&receiver, package.as_bytes()).unwrap();
+        assert_eq!(import.bundles.len(), 1);
+        assert_eq!(import.bundles[0].key, Key::new("shared"));
+        assert_eq!(import.bundles[0].v_edit_creds[0].secret, "shared secret");
+        receiver
+            .save_with_import(&import, crate::data::OnConflict::Skip)
+            .unwrap();
+        assert!(receiver.has_bundle(&Key::new("shared")));
+
+        // the sender has no identity, and another one would not fit
+        assert!(open_package(&sender, package.as_bytes()).is_err());
+        sender.save_with_new_identity().unwrap();
+        assert!(open_package(&sender, package.as_bytes()).is_err());
+    }
+}
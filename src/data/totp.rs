@@ -0,0 +1,279 @@
+use anyhow::{Context, Result, anyhow};
+use hmac::{Hmac, Mac};
+use percent_encoding::{AsciiSet, NON_ALPHANUMERIC, percent_decode_str, utf8_percent_encode};
+use sha1::Sha1;
+use sha2::{Sha256, Sha512};
+use std::{
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+// A cred whose secret is an otpauth URI is a TOTP cred (RFC 6238);
+// instead of the secret, the current one-time code is shown
+const SCHEME: &str = "otpauth://totp/";
+
+// what is not escaped in the label and in parameter values
+const URI_VALUE: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) enum TotpAlgorithm {
+    #[default]
+    Sha1,
+    Sha256,
+    Sha512,
+}
+impl TotpAlgorithm {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Sha1 => "SHA1",
+            Self::Sha256 => "SHA256",
+            Self::Sha512 => "SHA512",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Totp {
+    pub label: String,
+    pub issuer: Option<String>,
+    seed: Vec<u8>,
+    pub algorithm: TotpAlgorithm,
+    pub digits: u32,
+    pub period: u64,
+}
+impl Totp {
+    pub fn is_totp(secret: &str) -> bool {
+        secret
+            .trim_start()
+            .get(..SCHEME.len())
+            .is_some_and(|prefix| prefix.eq_ignore_ascii_case(SCHEME))
+    }
+
+    // Parses e.g. otpauth://totp/ACME:alice?secret=JBSWY3DPEHPK3PXP&issuer=ACME&digits=8;
+    // missing parameters get the usual defaults (SHA1, 6 digits, 30 seconds)
+    pub fn from_uri(uri: &str) -> Result<Self> {
+        let uri = uri.trim();
+        if !Self::is_totp(uri) {
+            return Err(anyhow!(t!("_totp_invalid_uri").to_string()));
+        }
+        let (label, query) = uri[SCHEME.len()..].split_once('?').unwrap_or_default();
+        let mut totp = Self {
+            label: decode(label),
+            issuer: None,
+            seed: Vec::new(),
+            algorithm: TotpAlgorithm::Sha1,
+            digits: 6,
+            period: 30,
+        };
+        for (name, value) in query
+            .split('&')
+            .filter_map(|parameter| parameter.split_once('='))
+        {
+            let value = decode(value);
+            let invalid = || anyhow!(t!("_totp_invalid_parameter %{p}", p = name).to_string());
+            match name.to_ascii_lowercase().as_str() {
+                "secret" => totp.seed = decode_seed(&value).ok_or_else(invalid)?,
+                "issuer" => totp.issuer = Some(value),
+                "algorithm" => {
+                    totp.algorithm = match value.to_ascii_uppercase().as_str() {
+                        "SHA1" => TotpAlgorithm::Sha1,
+                        "SHA256" => TotpAlgorithm::Sha256,
+                        "SHA512" => TotpAlgorithm::Sha512,
+                        _ => return Err(invalid()),
+                    }
+                }
+                "digits" => {
+                    totp.digits = value
+                        .parse()
+                        .ok()
+                        .filter(|digits| (6..=8).contains(digits))
+                        .ok_or_else(invalid)?;
+                }
+                "period" => {
+                    totp.period = value
+                        .parse()
+                        .ok()
+                        .filter(|period| *period > 0)
+                        .ok_or_else(invalid)?;
+                }
+                // e.g. image, which some apps add
+                _ => {}
+            }
+        }
+        if totp.seed.is_empty() {
+            return Err(anyhow!(
+                t!("_totp_invalid_parameter %{p}", p = "secret").to_string()
+            ));
+        }
+        Ok(totp)
+    }
+
+    // A bare base32 seed, as e.g. Bitwarden exports it, with the usual defaults
+    pub fn from_seed(label: &str, seed: &str) -> Option<Self> {
+        Some(Self {
+            label: label.to_string(),
+            issuer: None,
+            seed: decode_seed(seed)?,
+            algorithm: TotpAlgorithm::Sha1,
+            digits: 6,
+            period: 30,
+        })
+    }
+
+    // Reads the otpauth URI from a QR code in a PNG file, e.g. a screenshot of the setup page
+    pub fn from_qr_image(path: &Path) -> Result<Self> {
+        let image = image::open(path)
+            .context(format!("reading {}", path.display()))?
+            .to_luma8();
+        let mut prepared = rqrr::PreparedImage::prepare(image);
+        prepared
+            .detect_grids()
+            .iter()
+            .filter_map(|grid| grid.decode().ok())
+            .find_map(|(_meta_data, content)| Self::from_uri(&content).ok())
+            .ok_or_else(|| anyhow!(t!("_totp_no_qr_code").to_string()))
+    }
+
+    // The normalized URI, which is stored as the secret of the cred
+    pub fn to_uri(&self) -> String {
+        let issuer = self
+            .issuer
+            .as_ref()
+            .map(|issuer| format!("&issuer={}", utf8_percent_encode(issuer, URI_VALUE)))
+            .unwrap_or_default();
+        format!(
+            "{SCHEME}{}?secret={}{issuer}&algorithm={}&digits={}&period={}",
+            utf8_percent_encode(&self.label, URI_VALUE),
+            base32::encode(base32::Alphabet::Rfc4648 { padding: false }, &self.seed),
+            self.algorithm.as_str(),
+            self.digits,
+            self.period
+        )
+    }
+
+    pub fn code_at(&self, unix_time: u64) -> String {
+        let counter = (unix_time / self.period).to_be_bytes();
+        let hash = match self.algorithm {
+            TotpAlgorithm::Sha1 => hmac::<Hmac<Sha1>>(&self.seed, &counter),
+            TotpAlgorithm::Sha256 => hmac::<Hmac<Sha256>>(&self.seed, &counter),
+            TotpAlgorithm::Sha512 => hmac::<Hmac<Sha512>>(&self.seed, &counter),
+        };
+        // dynamic truncation, see RFC 4226
+        let offset = usize::from(hash[hash.len() - 1] & 0x0f);
+        let binary = u32::from_be_bytes([
+            hash[offset] & 0x7f,
+            hash[offset + 1],
+            hash[offset + 2],
+            hash[offset + 3],
+        ]);
+        format!(
+            "{:0width$}",
+            binary % 10_u32.pow(self.digits),
+            width = self.digits as usize
+        )
+    }
+
+    // The current code, and for how many seconds it is still valid
+    pub fn current_code(&self) -> (String, u64) {
+        let unix_time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or_default();
+        (
+            self.code_at(unix_time),
+            self.period - unix_time % self.period,
+        )
+    }
+}
+
+// Fails if the secret looks like an otpauth URI, but is not a valid one
+pub(crate) fn check_totp_secret(secret: &str) -> Result<()> {
+    if Totp::is_totp(secret) {
+        Totp::from_uri(secret)?;
+    }
+    Ok(())
+}
+
+fn hmac<M: Mac + hmac::digest::KeyInit>(key: &[u8], message: &[u8]) -> Vec<u8> {
+    let mut mac = <M as Mac>::new_from_slice(key).unwrap(/*OK, any key length works*/);
+    mac.update(message);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn decode(value: &str) -> String {
+    percent_decode_str(value).decode_utf8_lossy().to_string()
+}
+
+// seeds are often shown in groups, in lower case, or with padding
+fn decode_seed(value: &str) -> Option<Vec<u8>> {
+    let normalized: String = value
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '-' && *c != '=')
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+    base32::decode(base32::Alphabet::Rfc4648 { padding: false }, &normalized)
+        .filter(|seed| !seed.is_empty())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Totp, TotpAlgorithm};
+
+    fn totp(seed: &[u8], algorithm: &str) -> Totp {
+        let secret = base32::encode(base32::Alphabet::Rfc4648 { padding: true }, seed);
+        Totp::from_uri(&format!(
+            "otpauth://totp/ACME%20Corp:alice%40example.com?secret={}&algorithm={algorithm}&digits=8",
+            secret.to_lowercase()
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_rfc6238_vectors() {
+        let sha1 = totp(b"12345678901234567890", "SHA1");
+        let sha256 = totp(b"12345678901234567890123456789012", "sha256");
+        let sha512 = totp(
+            b"1234567890123456789012345678901234567890123456789012345678901234",
+            "SHA512",
+        );
+        assert_eq!(sha512.algorithm, TotpAlgorithm::Sha512);
+        for (time, expected_sha1, expected_sha256, expected_sha512) in [
+            (59, "94287082", "46119246", "90693936"),
+            (1_111_111_109, "07081804", "68084774", "25091201"),
+            (1_234_567_890, "89005924", "91819424", "93441116"),
+            (20_000_000_000, "65353130", "77737706", "47863826"),
+        ] {
+            assert_eq!(sha1.code_at(time), expected_sha1);
+            assert_eq!(sha256.code_at(time), expected_sha256);
+            assert_eq!(sha512.code_at(time), expected_sha512);
+        }
+    }
+
+    #[test]
+    fn test_uri() {
+        let totp = Totp::from_uri(
+            " otpauth://totp/ACME%20Corp:alice?secret=JBSW Y3DP EHPK 3PXP&issuer=ACME%20Corp&image=x ",
+        )
+        .unwrap();
+        assert_eq!(totp.label, "ACME Corp:alice");
+        assert_eq!(totp.issuer.as_deref(), Some("ACME Corp"));
+        assert_eq!((totp.digits, totp.period), (6, 30));
+        assert_eq!(
+            totp.to_uri(),
+            "otpauth://totp/ACME%20Corp%3Aalice?secret=JBSWY3DPEHPK3PXP&issuer=ACME%20Corp\
+             &algorithm=SHA1&digits=6&period=30"
+        );
+        assert_eq!(Totp::from_uri(&totp.to_uri()).unwrap(), totp);
+
+        assert!(Totp::from_uri("otpauth://hotp/x?secret=JBSWY3DPEHPK3PXP").is_err());
+        assert!(Totp::from_uri("otpauth://totp/x?issuer=ACME").is_err());
+        assert!(Totp::from_uri("otpauth://totp/x?secret=1111").is_err());
+        assert!(Totp::from_uri("otpauth://totp/x?secret=JBSWY3DPEHPK3PXP&digits=9").is_err());
+        assert!(Totp::from_uri("otpauth://totp/x?secret=JBSWY3DPEHPK3PXP&period=0").is_err());
+        assert!(Totp::from_uri("otpauth://totp/x?secret=JBSWY3DPEHPK3PXP&algorithm=MD5").is_err());
+    }
+}
@@ -1,40 +1,87 @@
-use crate::data::{Readable, Secrets};
+use crate::data::{
+    Bundles, Documents, Readable, Secrets,
+    crypto::{decrypt_with_key, encrypt_with_key},
+};
 use anyhow::{Context, Result};
 use pwsec::{ChachaB64, CipherB64};
-use secstr::SecUtf8;
+use secstr::SecVec;
 use sequential::Sequence;
 
-const PBKDF2_ROUNDS: u32 = 91_232;
+// Files written with format version 1 use PBKDF2 with a fixed number of rounds
+pub(super) const PBKDF2_ROUNDS: u32 = 91_232;
 
+// The content of the encrypted section, if the file conceals the names of its entries
+// and documents; otherwise the encrypted section contains only the Secrets.
+#[derive(Serialize)]
+struct ConcealedRef<'a> {
+    secrets: &'a Secrets,
+    bundles: &'a Bundles,
+    documents: &'a Documents,
+}
+#[derive(Deserialize)]
+struct Concealed {
+    secrets: Secrets,
+    bundles: Bundles,
+    documents: Documents,
+}
+
+// Holds the decrypted secrets and the key that was derived from the password.
+//
+// The key derivation is expensive by intention, so it is only done when the file is unlocked
+// or when the password is changed; saving the file re-uses the derived key.
 #[derive(Clone, Debug)]
 pub struct Transient {
-    storage_password: SecUtf8,
+    storage_key: SecVec<u8>,
     seq_for_secret_refs: Sequence<u64>,
     secrets: Secrets,
 }
 impl Transient {
-    pub fn new(password: String, secrets: Secrets) -> Self {
+    pub fn new(storage_key: SecVec<u8>, secrets: Secrets) -> Self {
         Self {
-            storage_password: SecUtf8::from(password),
+            storage_key,
             seq_for_secret_refs: Sequence::start_after_highest(&mut secrets.keys()),
             secrets,
         }
     }
 
-    pub fn from_cipher(password: String, readable: &Readable, cipher: &str) -> Result<Transient> {
-        let secrets = serde_json::from_slice(
-            &ChachaB64::with_pbkdf2_rounds(PBKDF2_ROUNDS)
-                .decrypt_auth(
-                    CipherB64::parse(cipher).context("cipher")?,
-                    serde_json::to_string(readable)
-                        .context("parse header")?
-                        .as_bytes(),
-                    &password,
-                )
-                .context("decrypt")?,
-        )
-        .context("parse secrets")?;
-        Ok(Transient::new(password, secrets))
+    // Returns additionally the bundles and documents if they were concealed in the cipher
+    pub fn from_cipher(
+        storage_key: SecVec<u8>,
+        readable: &Readable,
+        cipher: &str,
+    ) -> Result<(Transient, Option<(Bundles, Documents)>)> {
+        let auth_tag = serde_json::to_string(readable).context("parse header")?;
+        let plain =
+            decrypt_with_key(&storage_key, cipher, auth_tag.as_bytes()).context("decrypt")?;
+        if readable.is_concealed() {
+            let concealed: Concealed =
+                serde_json::from_slice(&plain).context("parse concealed secrets")?;
+            Ok((
+                Transient::new(storage_key, concealed.secrets),
+                Some((concealed.bundles, concealed.documents)),
+            ))
+        } else {
+            let secrets = serde_json::from_slice(&plain).context("parse secrets")?;
+            Ok((Transient::new(storage_key, secrets), None))
+        }
+    }
+
+    // Files with format version 1 were encrypted with PBKDF2 and a new salt with every save,
+    // so the password is needed here
+    pub fn decrypt_legacy_cipher(
+        password: &str,
+        readable: &Readable,
+        cipher: &str,
+    ) -> Result<Secrets> {
+        let auth_tag = serde_json::to_string(readable).context("parse header")?;
+        let plain = ChachaB64::with_pbkdf2_rounds(PBKDF2_ROUNDS)
+            .decrypt_auth(
+                CipherB64::parse(cipher).context("cipher")?,
+                auth_tag.as_bytes(),
+                password,
+            )
+            .context("decrypt")?;
+        serde_json::from_slice(&plain).context("parse secrets")
     }
 
     pub fn add_secret(&mut self, s: String) -> u64 {
@@ -51,25 +98,44 @@ impl Transient {
         self.secrets.get(idx)
     }
 
+    pub fn identity(&self) -> Option<&str> {
+        self.secrets.identity()
+    }
+
+    pub fn set_identity(&mut self, identity: String) {
+        self.secrets.set_identity(identity);
+    }
+
     pub fn refs(&self) -> Box<dyn Iterator<Item = u64> + '_> {
         Box::new(self.secrets.keys().copied())
     }
 
-    pub fn set_storage_password(&mut self, new_pw: String) {
-        self.storage_password = SecUtf8::from(new_pw);
+    pub fn set_storage_key(&mut self, storage_key: SecVec<u8>) {
+        self.storage_key = storage_key;
     }
-    pub fn get_storage_password(&self) -> &str {
-        self.storage_password.unsecure()
+    pub fn storage_key(&self) -> &SecVec<u8> {
+        &self.storage_key
     }
 
-    pub fn as_cipher(&mut self, auth_tag: &Readable) -> Result<String> {
+    // Bundles and documents are only given if they are to be concealed
+    pub fn as_cipher(
+        &mut self,
+        auth_tag: &Readable,
+        o_concealed: Option<(&Bundles, &Documents)>,
+    ) -> Result<String> {
         self.secrets.prepare();
-        Ok(ChachaB64::with_pbkdf2_rounds(PBKDF2_ROUNDS)
-            .encrypt_auth(
-                serde_json::to_string(&self.secrets)?.as_bytes(),
-                serde_json::to_string(auth_tag)?.as_bytes(),
-                self.storage_password.unsecure(),
-            )?
-            .to_string())
+        let plain = match o_concealed {
+            None => serde_json::to_string(&self.secrets)?,
+            Some((bundles, documents)) => serde_json::to_string(&ConcealedRef {
+                secrets: &self.secrets,
+                bundles,
+                documents,
+            })?,
+        };
+        encrypt_with_key(
+            &self.storage_key,
+            plain.as_bytes(),
+            serde_json::to_string(auth_tag)?.as_bytes(),
+        )
     }
 }
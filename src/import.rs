@@ -0,0 +1,19 @@
+//! Reading entries and documents from other sources.
+//!
+//! The sources are converted into an `Import`, which is then saved into the file
+//! with `PlFile::save_with_import`.
+
+mod csv;
+pub(crate) mod json;
+mod kdbx;
+pub(crate) mod keepass;
+
+pub(crate) use csv::{CredMapping, CredName, CsvMapping, CsvTable};
+
+// Notes that are longer become a document of their own
+const MAX_NOTES_LINES: usize = 5;
+const MAX_NOTES_CHARS: usize = 500;
+
+fn is_long(notes: &str) -> bool {
+    notes.lines().count() > MAX_NOTES_LINES || notes.chars().count() > MAX_NOTES_CHARS
+}
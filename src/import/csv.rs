@@ -0,0 +1,244 @@
+use crate::{
+    data::{Import, Key},
+    ui::viz::{VEditBundle, VEditCred, VEditDocument},
+};
+use anyhow::{Context, Result, anyhow};
+use std::path::Path;
+
+// The content of a CSV file with a header line
+#[derive(Clone, Debug, Default)]
+pub(crate) struct CsvTable {
+    pub headers: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+}
+impl CsvTable {
+    pub fn read(path: &Path) -> Result<Self> {
+        let content =
+            std::fs::read_to_string(path).context(format!("reading {}", path.display()))?;
+        Self::parse(&content)
+    }
+
+    // Spreadsheets export with commas, semicolons or tabs; the header line decides
+    pub fn parse(content: &str) -> Result<Self> {
+        let content = content.trim_start_matches('\u{feff}');
+        let header_line = content.lines().next().unwrap_or_default();
+        let delimiter = [b',', b';', b'\t']
+            .into_iter()
+            .max_by_key(|d| header_line.bytes().filter(|b| b == d).count())
+            .unwrap_or(b',');
+        let mut reader = ::csv::ReaderBuilder::new()
+            .delimiter(delimiter)
+            .flexible(true)
+            .from_reader(content.as_bytes());
+        let headers = reader
+            .headers()?
+            .iter()
+            .map(|header| header.trim().to_string())
+            .collect();
+        let rows = reader
+            .records()
+            .map(|record| Ok(record?.iter().map(ToString::to_string).collect()))
+            .collect::<Result<_>>()?;
+        Ok(CsvTable { headers, rows })
+    }
+
+    // A column can be given by its header (case-insensitively) or by its number, starting with 1
+    pub fn column(&self, spec: &str) -> Result<usize> {
+        self.headers
+            .iter()
+            .position(|header| header.eq_ignore_ascii_case(spec))
+            .or_else(|| {
+                spec.parse::<usize>()
+                    .ok()
+                    .filter(|n| (1..=self.headers.len()).contains(n))
+                    .map(|n| n - 1)
+            })
+            .ok_or_else(|| anyhow!(t!("_import_no_column %{c}", c = spec).to_string()))
+    }
+
+    fn cell(row: &[String], column: usize) -> String {
+        row.get(column)
+            .map(|s| s.trim())
+            .unwrap_or_default()
+            .to_string()
+    }
+}
+
+// Where the name of a credential comes from
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum CredName {
+    Column(usize),
+    Fixed(String),
+}
+
+// A credential whose secret is taken from a column
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct CredMapping {
+    pub name: CredName,
+    pub secret: usize,
+}
+
+// Which columns of the CSV file make up the entries and documents
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct CsvMapping {
+    pub key: usize,
+    pub description: Vec<usize>,
+    pub creds: Vec<CredMapping>,
+    // the text of a document that is named like the entry
+    pub document: Option<usize>,
+}
+impl CsvMapping {
+    // Recognizes the usual headers of the exports of other password managers
+    pub fn guess(headers: &[String]) -> Self {
+        let find = |names: &[&str]| {
+            headers
+                .iter()
+                .position(|header| names.contains(&header.to_ascii_lowercase().as_str()))
+        };
+        let key = find(&["name", "title", "entry", "account", "site"]).unwrap_or(0);
+        let description = [
+            find(&["url", "website", "login_uri", "web site"]),
+            find(&[
+                "description",
+                "notes",
+                "note",
+                "extra",
+                "comment",
+                "comments",
+            ]),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        let secret = find(&["password", "pass", "login_password", "secret"]);
+        let creds = secret
+            .map(|secret| CredMapping {
+                name: find(&[
+                    "username",
+                    "user",
+                    "user name",
+                    "login",
+                    "login_username",
+                    "email",
+                ])
+                .map_or_else(
+                    || CredName::Fixed(headers[secret].clone()),
+                    CredName::Column,
+                ),
+                secret,
+            })
+            .into_iter()
+            .collect();
+        CsvMapping {
+            key,
+            description,
+            creds,
+            document: None,
+        }
+    }
+
+    // Rows without a name are skipped, and so are credentials without name and secret
+    pub fn apply(&self, table: &CsvTable) -> Import {
+        let mut import = Import::default();
+        let with_bundles = !self.description.is_empty() || !self.creds.is_empty();
+        for (idx, row) in table.rows.iter().enumerate() {
+            let key = Key::new(CsvTable::cell(row, self.key));
+            if key.is_empty() {
+                // the header is line 1
+                import
+                    .skipped
+                    .push(t!("_import_row_without_name %{l}", l = idx + 2).to_string());
+                continue;
+            }
+            if with_bundles {
+                let mut v_edit_bundle = VEditBundle::new();
+                v_edit_bundle.key = key.clone();
+                v_edit_bundle.description = self
+                    .description
+                    .iter()
+                    .map(|column| CsvTable::cell(row, *column))
+                    .filter(|value| !value.is_empty())
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                v_edit_bundle.v_edit_creds = self
+                    .creds
+                    .iter()
+                    .filter_map(|cred_mapping| {
+                        // secrets are taken as they are, also with surrounding whitespace
+                        let secret = row.get(cred_mapping.secret).cloned().unwrap_or_default();
+                        let mut name = match &cred_mapping.name {
+                            CredName::Column(column) => CsvTable::cell(row, *column),
+                            CredName::Fixed(name) => name.clone(),
+                        };
+                        if name.is_empty() && secret.trim().is_empty() {
+                            return None;
+                        }
+                        if name.is_empty() {
+                            name.clone_from(&table.headers[cred_mapping.secret]);
+                        }
+                        Some(VEditCred { name, secret })
+                    })
+                    .collect();
+                import.bundles.push(v_edit_bundle);
+            }
+            if let Some(column) = self.document {
+                let text = row.get(column).cloned().unwrap_or_default();
+                if !text.trim().is_empty() {
+                    import.documents.push(VEditDocument {
+                        orig_key: key.clone(),
+                        key,
+                        text,
+                        request_focus: false,
+                    });
+                }
+            }
+        }
+        import
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{CredMapping, CredName, CsvMapping, CsvTable};
+
+    #[test]
+    fn test_csv() {
+        let table = CsvTable::parse(
+            "\u{feff}Title;Username;Password;URL;Notes\n\
+             Mail; bob ; s3cret ;https://mail.example.com;\n\
+             ;alice;pw;;no name\n\
+             Bank;;1234;;\"two\nlines\"\n",
+        )
+        .unwrap();
+        assert_eq!(table.rows.len(), 3);
+        assert_eq!(table.column("url").unwrap(), 3);
+        assert_eq!(table.column("2").unwrap(), 1);
+        assert!(table.column("otp").is_err());
+
+        let mut mapping = CsvMapping::guess(&table.headers);
+        assert_eq!(
+            mapping,
+            CsvMapping {
+                key: 0,
+                description: vec![3, 4],
+                creds: vec![CredMapping {
+                    name: CredName::Column(1),
+                    secret: 2
+                }],
+                document: None,
+            }
+        );
+        mapping.document = Some(4);
+        let import = mapping.apply(&table);
+        assert_eq!(import.skipped.len(), 1);
+        assert_eq!(import.bundles.len(), 2);
+        assert_eq!(import.bundles[0].description, "https://mail.example.com");
+        assert_eq!(import.bundles[0].v_edit_creds[0].name, "bob");
+        assert_eq!(import.bundles[0].v_edit_creds[0].secret, " s3cret ");
+        assert_eq!(import.bundles[1].description, "two\nlines");
+        // the name of a credential without name is taken from the header
+        assert_eq!(import.bundles[1].v_edit_creds[0].name, "Password");
+        assert_eq!(import.documents.len(), 1);
+        assert_eq!(import.documents[0].key.as_str(), "Bank");
+    }
+}
@@ -0,0 +1,389 @@
+//! Import of JSON files: the unencrypted export of Bitwarden, and the own export.
+//!
+//! Bitwarden logins, cards and identities become entries, secure notes become documents.
+//! The own export restores the entries and documents with their change times.
+
+use crate::{
+    data::{EXPORT_FORMAT_VERSION, Export, Import, Key, Totp},
+    ui::viz::{VEditBundle, VEditCred, VEditDocument},
+};
+use anyhow::{Context, Result, anyhow};
+use serde_json::{Map, Value};
+use std::path::Path;
+
+const LOGIN: u8 = 1;
+const SECURE_NOTE: u8 = 2;
+const CARD: u8 = 3;
+const IDENTITY: u8 = 4;
+
+// Bitwarden field types
+const TEXT: u8 = 0;
+const HIDDEN: u8 = 1;
+const BOOLEAN: u8 = 2;
+
+// Fields of cards and identities that become credentials
+const SECRET_FIELDS: [&str; 5] = ["number", "code", "ssn", "passportNumber", "licenseNumber"];
+
+pub(crate) fn read(path: &Path) -> Result<Import> {
+    let content = std::fs::read_to_string(path).context(format!("reading {}", path.display()))?;
+    parse(&content)
+}
+
+// The format is recognized by its content
+fn parse(content: &str) -> Result<Import> {
+    let value: Value = serde_json::from_str(content).context(t!("_json_unknown_format"))?;
+    if let Some(version) = value.get("prolock_export") {
+        if version.as_u64() != Some(u64::from(EXPORT_FORMAT_VERSION)) {
+            return Err(anyhow!(t!("_json_unsupported_export %{v}", v = version)));
+        }
+        let export: Export = serde_json::from_value(value).context(t!("_json_unknown_format"))?;
+        Ok(export.into_import())
+    } else if value.get("items").is_some() {
+        if value.get("encrypted").and_then(Value::as_bool) == Some(true) {
+            return Err(anyhow!(t!("_json_encrypted")));
+        }
+        let export: BitwardenExport =
+            serde_json::from_value(value).context(t!("_json_unknown_format"))?;
+        Ok(export.convert())
+    } else {
+        Err(anyhow!(t!("_json_unknown_format")))
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BitwardenExport {
+    #[serde(default)]
+    folders: Vec<BitwardenFolder>,
+    items: Vec<BitwardenItem>,
+}
+#[derive(Deserialize)]
+struct BitwardenFolder {
+    id: String,
+    name: String,
+}
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BitwardenItem {
+    #[serde(rename = "type")]
+    item_type: u8,
+    #[serde(default)]
+    name: String,
+    folder_id: Option<String>,
+    notes: Option<String>,
+    #[serde(default)]
+    fields: Vec<BitwardenField>,
+    login: Option<BitwardenLogin>,
+    card: Option<Map<String, Value>>,
+    identity: Option<Map<String, Value>>,
+    password_history: Option<Vec<Value>>,
+}
+#[derive(Deserialize)]
+struct BitwardenField {
+    name: Option<String>,
+    value: Option<String>,
+    #[serde(rename = "type")]
+    field_type: u8,
+}
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BitwardenLogin {
+    uris: Option<Vec<BitwardenUri>>,
+    username: Option<String>,
+    password: Option<String>,
+    totp: Option<String>,
+    fido2_credentials: Option<Vec<Value>>,
+}
+#[derive(Deserialize)]
+struct BitwardenUri {
+    uri: Option<String>,
+}
+
+impl BitwardenExport {
+    fn convert(self) -> Import {
+        let mut import = Import::default();
+        let mut with_history = 0;
+        for item in &self.items {
+            let name = item.name.trim();
+            if name.is_empty() {
+                import.skipped.push(t!("_json_unnamed_item").to_string());
+                continue;
+            }
+            if item
+                .password_history
+                .as_ref()
+                .is_some_and(|history| !history.is_empty())
+            {
+                with_history += 1;
+            }
+            match item.item_type {
+                LOGIN | CARD | IDENTITY => {
+                    let v_edit_bundle = self.bundle(item, &mut import);
+                    import.bundles.push(v_edit_bundle);
+                }
+                SECURE_NOTE => add_document(&mut import, Key::new(name), note_text(item)),
+                item_type => import.skipped.push(
+                    t!("_json_unsupported_item %{n} %{t}", n = name, t = item_type).to_string(),
+                ),
+            }
+        }
+        if with_history > 0 {
+            import
+                .skipped
+                .push(t!("_json_password_history %{n}", n = with_history).to_string());
+        }
+        import
+    }
+
+    fn bundle(&self, item: &BitwardenItem, import: &mut Import) -> VEditBundle {
+        let name = item.name.trim();
+        let mut description = Vec::new();
+        let mut v_edit_creds = Vec::new();
+        if let Some(folder) = item
+            .folder_id
+            .as_ref()
+            .and_then(|id| self.folders.iter().find(|folder| folder.id == *id))
+        {
+            description.push(format!("folder: {}", folder.name));
+        }
+        if let Some(login) = &item.login {
+            login_fields(login, name, &mut description, &mut v_edit_creds, import);
+        }
+        for object in [&item.card, &item.identity].into_iter().flatten() {
+            for (field, value) in object {
+                let value = match value {
+                    Value::String(s) => s.trim().to_string(),
+                    Value::Null => String::new(),
+                    other => other.to_string(),
+                };
+                if value.is_empty() {
+                    continue;
+                }
+                if SECRET_FIELDS.contains(&field.as_str()) {
+                    v_edit_creds.push(VEditCred {
+                        name: field.clone(),
+                        secret: value,
+                    });
+                } else {
+                    description.push(format!("{field}: {value}"));
+                }
+            }
+        }
+        for field in &item.fields {
+            let field_name = field.name.as_deref().unwrap_or_default();
+            let value = field.value.as_deref().unwrap_or_default();
+            match field.field_type {
+                TEXT | BOOLEAN if !value.is_empty() => {
+                    description.push(format!("{field_name}: {value}"));
+                }
+                HIDDEN if !value.is_empty() => v_edit_creds.push(VEditCred {
+                    name: field_name.to_string(),
+                    secret: value.to_string(),
+                }),
+                // linked fields only refer to other fields
+                _ => {}
+            }
+        }
+        let notes = item.notes.as_deref().unwrap_or_default();
+        if super::is_long(notes) {
+            add_document(import, Key::new(name), notes.to_string());
+        } else if !notes.trim().is_empty() {
+            description.push(notes.trim_end().to_string());
+        }
+
+        let mut v_edit_bundle = VEditBundle::new();
+        v_edit_bundle.orig_key = Key::new(name);
+        v_edit_bundle.key = Key::new(name);
+        v_edit_bundle.description = description.join("\n");
+        v_edit_bundle.v_edit_creds = v_edit_creds;
+        v_edit_bundle
+    }
+}
+
+fn login_fields(
+    login: &BitwardenLogin,
+    name: &str,
+    description: &mut Vec<String>,
+    v_edit_creds: &mut Vec<VEditCred>,
+    import: &mut Import,
+) {
+    for uri in login
+        .uris
+        .iter()
+        .flatten()
+        .filter_map(|uri| uri.uri.as_ref())
+    {
+        description.push(format!("url: {uri}"));
+    }
+    let username = login.username.as_deref().unwrap_or_default().trim();
+    let password = login.password.clone().unwrap_or_default();
+    if !username.is_empty() || !password.is_empty() {
+        v_edit_creds.push(VEditCred {
+            name: if username.is_empty() {
+                "Password".to_string()
+            } else {
+                username.to_string()
+            },
+            secret: password,
+        });
+    }
+    if let Some(totp) = login.totp.as_ref().filter(|totp| !totp.is_empty()) {
+        // a bare seed becomes an otpauth URI, so that the current code is shown
+        let secret = if Totp::is_totp(totp) {
+            totp.clone()
+        } else {
+            Totp::from_seed(name, totp).map_or_else(|| totp.clone(), |totp| totp.to_uri())
+        };
+        v_edit_creds.push(VEditCred {
+            name: "TOTP".to_string(),
+            secret,
+        });
+    }
+    if login
+        .fido2_credentials
+        .as_ref()
+        .is_some_and(|passkeys| !passkeys.is_empty())
+    {
+        import
+            .skipped
+            .push(t!("_json_passkey %{e}", e = name).to_string());
+    }
+}
+
+// The fields of a secure note are appended to its text
+fn note_text(item: &BitwardenItem) -> String {
+    let mut text = item.notes.clone().unwrap_or_default();
+    for field in &item.fields {
+        if let (Some(name), Some(value)) = (&field.name, &field.value) {
+            if !text.is_empty() && !text.ends_with('\n') {
+                text.push('\n');
+            }
+            text.push_str(name);
+            text.push_str(": ");
+            text.push_str(value);
+        }
+    }
+    text
+}
+
+fn add_document(import: &mut Import, key: Key, text: String) {
+    import.documents.push(VEditDocument {
+        orig_key: key.clone(),
+        key,
+        text,
+        request_focus: false,
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use super::parse;
+    use crate::{
+        data::{Export, Key, OnConflict, PlFile},
+        ui::viz::{VEditBundle, VEditCred, VEditDocument},
+    };
+
+    const BITWARDEN: &str = r#"{
+      "encrypted": false,
+      "folders": [ { "id": "f1", "name": "Work" } ],
+      "items": [
+        { "type": 1, "name": "Mail", "folderId": "f1", "notes": "use the app",
+          "fields": [ { "name": "PIN", "value": "1234", "type": 1 },
+                      { "name": "Account", "value": "4711", "type": 0 },
+                      { "name": "Username", "value": null, "type": 3, "linkedId": 100 } ],
+          "login": { "uris": [ { "uri": "https://mail.example.com", "match": null } ],
+                     "username": "bob", "password": "s3cret", "totp": "JBSWY3DPEHPK3PXP" },
+          "passwordHistory": [ { "lastUsedDate": "2024-01-01T00:00:00Z", "password": "old" } ] },
+        { "type": 2, "name": "Wifi", "notes": "guest network", "secureNote": { "type": 0 },
+          "fields": [ { "name": "SSID", "value": "guests", "type": 0 } ] },
+        { "type": 3, "name": "Visa", "card": { "cardholderName": "Bob", "brand": "Visa",
+          "number": "4111111111111111", "expMonth": "1", "expYear": "2030", "code": "123" } },
+        { "type": 5, "name": "Server key", "sshKey": { "privateKey": "-----BEGIN-----" } }
+      ]
+    }"#;
+
+    #[test]
+    fn test_bitwarden() {
+        let import = parse(BITWARDEN).unwrap();
+        assert_eq!(import.bundles.len(), 2);
+        let mail = &import.bundles[0];
+        assert_eq!(mail.key.as_str(), "Mail");
+        assert_eq!(
+            mail.description,
+            "folder: Work\nurl: https://mail.example.com\nAccount: 4711\nuse the app"
+        );
+        let creds = mail
+            .v_edit_creds
+            .iter()
+            .map(|cred| (cred.name.as_str(), cred.secret.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            creds,
+            [
+                ("bob", "s3cret"),
+                (
+                    "TOTP",
+                    "otpauth://totp/Mail?secret=JBSWY3DPEHPK3PXP&algorithm=SHA1&digits=6&period=30"
+                ),
+                ("PIN", "1234")
+            ]
+        );
+        let visa = &import.bundles[1];
+        assert_eq!(
+            visa.description,
+            "brand: Visa\ncardholderName: Bob\nexpMonth: 1\nexpYear: 2030"
+        );
+        assert_eq!(visa.v_edit_creds.len(), 2);
+
+        assert_eq!(import.documents.len(), 1);
+        assert_eq!(import.documents[0].text, "guest network\nSSID: guests");
+        // the ssh key and the password history
+        assert_eq!(import.skipped.len(), 2, "{:?}", import.skipped);
+
+        assert!(parse(r#"{ "encrypted": true, "items": [] }"#).is_err());
+        assert!(parse(r#"{ "something": "else" }"#).is_err());
+        assert!(parse(r#"{ "prolock_export": 99, "entries": [], "documents": [] }"#).is_err());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut pl_file = PlFile::read_or_create(&temp_dir.path().join("exporting")).unwrap();
+        pl_file.set_actionable("pw", None).unwrap();
+        let mut v_edit_bundle = VEditBundle::new();
+        v_edit_bundle.key = Key::new("Prod DB");
+        v_edit_bundle.description = "url: db.example.com".to_string();
+        v_edit_bundle.v_edit_creds = vec![VEditCred {
+            name: "admin".to_string(),
+            secret: "s3cret".to_string(),
+        }];
+        pl_file.save_with_added_bundle(&v_edit_bundle).unwrap();
+        let key = Key::new("cert");
+        pl_file
+            .save_with_added_document(&VEditDocument {
+                orig_key: key.clone(),
+                key,
+                text: "-----BEGIN-----".to_string(),
+                request_focus: false,
+            })
+            .unwrap();
+
+        let json = serde_json::to_string(&Export::new(&pl_file).unwrap()).unwrap();
+        let import = parse(&json).unwrap();
+        let mut other = PlFile::read_or_create(&temp_dir.path().join("importing")).unwrap();
+        other.set_actionable("other", None).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        other.save_with_import(&import, OnConflict::Skip).unwrap();
+
+        let transient = other.transient().unwrap();
+        let (key, bundle) = other.bundles().iter().next().unwrap();
+        let original = pl_file.bundles().get(key).unwrap();
+        assert_eq!(bundle.description(), original.description());
+        assert_eq!(bundle.last_changed_at(), original.last_changed_at());
+        assert_eq!(bundle.creds()[0].secret(transient), "s3cret");
+        let (key, document) = other.documents().iter().next().unwrap();
+        let original = pl_file.documents().get(key).unwrap();
+        assert_eq!(document.text(transient), "-----BEGIN-----");
+        assert_eq!(document.last_changed_at(), original.last_changed_at());
+    }
+}
@@ -0,0 +1,729 @@
+//! Decryption of `KeePass` files in the KDBX 4 format, see <https://keepass.info/help/kb/kdbx_4.html>.
+//!
+//! The outer header is followed by its SHA-256 hash and HMAC, and by the encrypted payload
+//! in HMAC-protected blocks. The decrypted payload consists of the inner header,
+//! with the attachments and the key of the stream cipher for protected values, and the XML.
+
+use aes::{
+    Aes256,
+    cipher::{
+        BlockDecryptMut, BlockEncrypt, KeyInit, KeyIvInit, StreamCipher, block_padding::Pkcs7,
+    },
+};
+use anyhow::{Context, Result, anyhow};
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::{Engine, engine::general_purpose::STANDARD as b64};
+use chacha20::ChaCha20;
+use hmac::{Hmac, Mac};
+use salsa20::Salsa20;
+use sha2::{Digest, Sha256, Sha512};
+use std::{collections::BTreeMap, io::Read, path::Path};
+use twofish::Twofish;
+
+const SIGNATURE: [u8; 8] = [0x03, 0xd9, 0xa2, 0x9a, 0x67, 0xfb, 0x4b, 0xb5];
+// KeePass 1 files start with the same four bytes
+const KDB_SIGNATURE: [u8; 8] = [0x03, 0xd9, 0xa2, 0x9a, 0x65, 0xfb, 0x4b, 0xb5];
+const MAJOR_VERSION: u16 = 4;
+
+// Outer header fields
+const END_OF_HEADER: u8 = 0;
+const CIPHER_ID: u8 = 2;
+const COMPRESSION_FLAGS: u8 = 3;
+const MASTER_SEED: u8 = 4;
+const ENCRYPTION_IV: u8 = 7;
+const KDF_PARAMETERS: u8 = 11;
+
+// Inner header fields
+const INNER_RANDOM_STREAM_ID: u8 = 1;
+const INNER_RANDOM_STREAM_KEY: u8 = 2;
+const BINARY: u8 = 3;
+
+const CIPHER_AES256: [u8; 16] = uuid(0x31c1_f2e6_bf71_4350_be58_0521_6afc_5aff);
+const CIPHER_TWOFISH: [u8; 16] = uuid(0xad68_f29f_576f_4bb9_a36a_d47a_f965_346c);
+const CIPHER_CHACHA20: [u8; 16] = uuid(0xd603_8a2b_8b6f_4cb5_a524_339a_31db_b59a);
+const KDF_AES: [u8; 16] = uuid(0xc9d9_f39a_628a_4460_bf74_0d08_c18a_4fea);
+const KDF_ARGON2D: [u8; 16] = uuid(0xef63_6ddf_8c29_444b_91f7_a9a4_03e3_0a0c);
+const KDF_ARGON2ID: [u8; 16] = uuid(0x9e29_8b19_56db_4773_b23d_fc3e_c6f0_a1e6);
+
+// The KDF parameters come from the file, and are limited before the key is derived,
+// so that a crafted file cannot keep the import busy for hours or exhaust the memory;
+// the limits are far above what KeePass and KeePassXC choose
+const MAX_AES_ROUNDS: u64 = 200_000_000;
+const MAX_ARGON2_MEMORY: u64 = 2 << 30;
+const MAX_ARGON2_ITERATIONS: u64 = 1_000;
+const MAX_ARGON2_PARALLELISM: u64 = 256;
+// a few bytes of compressed payload can expand to gigabytes
+const MAX_DECOMPRESSED_SIZE: u64 = 1 << 30;
+
+const SALSA20_NONCE: [u8; 8] = [0xe8, 0x30, 0x09, 0x4b, 0x97, 0x20, 0x5d, 0x2a];
+
+const fn uuid(value: u128) -> [u8; 16] {
+    value.to_be_bytes()
+}
+
+// The decrypted content of a KDBX file
+pub(crate) struct Kdbx {
+    pub xml: String,
+    // the attachments, by their index, which is referenced in the XML
+    pub binaries: BTreeMap<String, Vec<u8>>,
+    pub inner_stream: InnerStream,
+}
+
+// Decrypts the values that are marked as protected in the XML, in document order
+pub(crate) enum InnerStream {
+    None,
+    Salsa20(Box<Salsa20>),
+    ChaCha20(Box<ChaCha20>),
+}
+impl InnerStream {
+    fn new(id: u32, key: &[u8]) -> Result<Self> {
+        Ok(match id {
+            0 => Self::None,
+            2 => Self::Salsa20(Box::new(Salsa20::new(
+                Sha256::digest(key).as_slice().into(),
+                &SALSA20_NONCE.into(),
+            ))),
+            3 => {
+                let hash = Sha512::digest(key);
+                Self::ChaCha20(Box::new(ChaCha20::new(
+                    hash[..32].into(),
+                    hash[32..44].into(),
+                )))
+            }
+            _ => return Err(unsupported("inner stream cipher")),
+        })
+    }
+
+    pub fn decrypt(&mut self, data: &mut [u8]) {
+        match self {
+            Self::None => {}
+            Self::Salsa20(cipher) => cipher.apply_keystream(data),
+            Self::ChaCha20(cipher) => cipher.apply_keystream(data),
+        }
+    }
+}
+
+// True for KeePass files of any version
+pub(crate) fn is_keepass_file(content: &[u8]) -> bool {
+    content.starts_with(&SIGNATURE[..4])
+}
+
+pub(crate) fn read(content: &[u8], password: &str, o_key_file: Option<&Path>) -> Result<Kdbx> {
+    let mut reader = Reader::new(content);
+    let signature = reader.take(8)?;
+    if signature == KDB_SIGNATURE {
+        return Err(anyhow!(t!("_keepass_unsupported_version %{v}", v = 1)));
+    } else if signature != SIGNATURE {
+        return Err(anyhow!(t!("_keepass_not_kdbx")));
+    }
+    let _minor_version = reader.u16()?;
+    let major_version = reader.u16()?;
+    if major_version != MAJOR_VERSION {
+        return Err(anyhow!(t!(
+            "_keepass_unsupported_version %{v}",
+            v = major_version
+        )));
+    }
+    let header = OuterHeader::read(&mut reader)?;
+    let header_bytes = &content[..reader.pos];
+    let header_hash = reader.take(32)?;
+    let header_hmac = reader.take(32)?;
+    if Sha256::digest(header_bytes).as_slice() != header_hash {
+        return Err(anyhow!(t!("_keepass_damaged")));
+    }
+
+    let transformed_key = header
+        .kdf
+        .transform(&composite_key(password, o_key_file)?)?;
+    let hmac_key = Sha512::new()
+        .chain_update(&header.master_seed)
+        .chain_update(&transformed_key)
+        .chain_update([1])
+        .finalize();
+    // the header HMAC is the first thing that depends on the key
+    if block_hmac(&hmac_key, u64::MAX, &[header_bytes]) != header_hmac {
+        return Err(anyhow!(t!("_keepass_wrong_key")));
+    }
+
+    let mut payload = Vec::new();
+    for index in 0_u64.. {
+        let hmac = reader.take(32)?;
+        let size_bytes = reader.take(4)?;
+        let size = u32::from_le_bytes(size_bytes.try_into()?) as usize;
+        let block = reader.take(size)?;
+        if block_hmac(&hmac_key, index, &[&index.to_le_bytes(), size_bytes, block]) != hmac {
+            return Err(anyhow!(t!("_keepass_damaged")));
+        }
+        if size == 0 {
+            break;
+        }
+        payload.extend_from_slice(block);
+    }
+
+    let key = Sha256::new()
+        .chain_update(&header.master_seed)
+        .chain_update(&transformed_key)
+        .finalize();
+    let mut payload = header.decrypt(&key, payload)?;
+    if header.compressed {
+        payload = decompress(&payload, MAX_DECOMPRESSED_SIZE)?;
+    }
+    read_inner(&payload)
+}
+
+fn decompress(payload: &[u8], limit: u64) -> Result<Vec<u8>> {
+    let mut decompressed = Vec::new();
+    flate2::read::GzDecoder::new(payload)
+        .take(limit + 1)
+        .read_to_end(&mut decompressed)
+        .context(t!("_keepass_damaged"))?;
+    if decompressed.len() as u64 > limit {
+        return Err(anyhow!(t!("_keepass_too_large %{l}", l = limit)));
+    }
+    Ok(decompressed)
+}
+
+struct Reader<'a> {
+    content: &'a [u8],
+    pos: usize,
+}
+impl<'a> Reader<'a> {
+    fn new(content: &'a [u8]) -> Self {
+        Self { content, pos: 0 }
+    }
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|end| *end <= self.content.len())
+            .ok_or_else(|| anyhow!(t!("_keepass_damaged")))?;
+        let bytes = &self.content[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+    fn rest(&self) -> &'a [u8] {
+        &self.content[self.pos..]
+    }
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into()?))
+    }
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+    // a header field: id, length and data
+    fn field(&mut self) -> Result<(u8, &'a [u8])> {
+        let id = self.u8()?;
+        let len = self.u32()? as usize;
+        Ok((id, self.take(len)?))
+    }
+}
+
+struct OuterHeader {
+    cipher_id: Vec<u8>,
+    compressed: bool,
+    master_seed: Vec<u8>,
+    iv: Vec<u8>,
+    kdf: KeePassKdf,
+}
+impl OuterHeader {
+    fn read(reader: &mut Reader) -> Result<Self> {
+        let mut cipher_id = Vec::new();
+        let mut compressed = false;
+        let mut master_seed = Vec::new();
+        let mut iv = Vec::new();
+        let mut o_kdf = None;
+        loop {
+            let (id, data) = reader.field()?;
+            match id {
+                END_OF_HEADER => break,
+                CIPHER_ID => cipher_id = data.to_vec(),
+                COMPRESSION_FLAGS => compressed = data.first().is_some_and(|flags| *flags == 1),
+                MASTER_SEED => master_seed = data.to_vec(),
+                ENCRYPTION_IV => iv = data.to_vec(),
+                KDF_PARAMETERS => o_kdf = Some(KeePassKdf::read(data)?),
+                // e.g. public custom data
+                _ => {}
+            }
+        }
+        if master_seed.len() != 32 {
+            return Err(anyhow!(t!("_keepass_damaged")));
+        }
+        Ok(Self {
+            cipher_id,
+            compressed,
+            master_seed,
+            iv,
+            kdf: o_kdf.ok_or_else(|| anyhow!(t!("_keepass_damaged")))?,
+        })
+    }
+
+    fn decrypt(&self, key: &[u8], mut payload: Vec<u8>) -> Result<Vec<u8>> {
+        let damaged = |_| anyhow!(t!("_keepass_damaged"));
+        let len = if self.cipher_id == CIPHER_AES256 {
+            cbc::Decryptor::<Aes256>::new_from_slices(key, &self.iv)
+                .map_err(|_| anyhow!(t!("_keepass_damaged")))?
+                .decrypt_padded_mut::<Pkcs7>(&mut payload)
+                .map_err(damaged)?
+                .len()
+        } else if self.cipher_id == CIPHER_TWOFISH {
+            cbc::Decryptor::<Twofish>::new_from_slices(key, &self.iv)
+                .map_err(|_| anyhow!(t!("_keepass_damaged")))?
+                .decrypt_padded_mut::<Pkcs7>(&mut payload)
+                .map_err(damaged)?
+                .len()
+        } else if self.cipher_id == CIPHER_CHACHA20 {
+            ChaCha20::new_from_slices(key, &self.iv)
+                .map_err(|_| anyhow!(t!("_keepass_damaged")))?
+                .apply_keystream(&mut payload);
+            payload.len()
+        } else {
+            return Err(unsupported("cipher"));
+        };
+        payload.truncate(len);
+        Ok(payload)
+    }
+}
+
+enum KeePassKdf {
+    Aes {
+        rounds: u64,
+        seed: Vec<u8>,
+    },
+    Argon2 {
+        algorithm: Algorithm,
+        salt: Vec<u8>,
+        parallelism: u32,
+        memory: u64,
+        iterations: u64,
+        version: u32,
+    },
+}
+impl KeePassKdf {
+    // The parameters are a "variant dictionary" of typed values
+    fn read(data: &[u8]) -> Result<Self> {
+        let mut reader = Reader::new(data);
+        if reader.u16()? & 0xff00 != 0x0100 {
+            return Err(unsupported("key derivation"));
+        }
+        let mut values = BTreeMap::new();
+        loop {
+            let value_type = reader.u8()?;
+            if value_type == 0 {
+                break;
+            }
+            let key_len = reader.u32()? as usize;
+            let key = String::from_utf8_lossy(reader.take(key_len)?).to_string();
+            let value_len = reader.u32()? as usize;
+            values.insert(key, reader.take(value_len)?);
+        }
+        let bytes = |key: &str| {
+            values
+                .get(key)
+                .copied()
+                .ok_or_else(|| anyhow!(t!("_keepass_damaged")))
+        };
+        let number = |key: &str| -> Result<u64> {
+            let value = bytes(key)?;
+            Ok(match value.len() {
+                4 => u64::from(u32::from_le_bytes(value.try_into()?)),
+                8 => u64::from_le_bytes(value.try_into()?),
+                _ => return Err(anyhow!(t!("_keepass_damaged"))),
+            })
+        };
+        let uuid = bytes("$UUID")?;
+        let limited = |key: &str, limit: u64| -> Result<u64> {
+            let value = number(key)?;
+            if value > limit {
+                return Err(anyhow!(t!(
+                    "_keepass_kdf_too_expensive %{p} %{v} %{l}",
+                    p = key,
+                    v = value,
+                    l = limit
+                )));
+            }
+            Ok(value)
+        };
+        if uuid == KDF_AES {
+            Ok(Self::Aes {
+                rounds: limited("R", MAX_AES_ROUNDS)?,
+                seed: bytes("S")?.to_vec(),
+            })
+        } else if uuid == KDF_ARGON2D || uuid == KDF_ARGON2ID {
+            Ok(Self::Argon2 {
+                algorithm: if uuid == KDF_ARGON2D {
+                    Algorithm::Argon2d
+                } else {
+                    Algorithm::Argon2id
+                },
+                salt: bytes("S")?.to_vec(),
+                parallelism: u32::try_from(limited("P", MAX_ARGON2_PARALLELISM)?)?,
+                memory: limited("M", MAX_ARGON2_MEMORY)?,
+                iterations: limited("I", MAX_ARGON2_ITERATIONS)?,
+                version: u32::try_from(number("V")?)?,
+            })
+        } else {
+            Err(unsupported("key derivation"))
+        }
+    }
+
+    fn transform(&self, composite_key: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            Self::Aes { rounds, seed } => {
+                let cipher =
+                    Aes256::new_from_slice(seed).map_err(|_| anyhow!(t!("_keepass_damaged")))?;
+                let mut key = composite_key.to_vec();
+                for block in key.chunks_exact_mut(16) {
+                    for _ in 0..*rounds {
+                        cipher.encrypt_block(block.into());
+                    }
+                }
+                Ok(Sha256::digest(&key).to_vec())
+            }
+            Self::Argon2 {
+                algorithm,
+                salt,
+                parallelism,
+                memory,
+                iterations,
+                version,
+            } => {
+                let params = Params::new(
+                    u32::try_from(memory / 1024)?,
+                    u32::try_from(*iterations)?,
+                    *parallelism,
+                    Some(32),
+                )
+                .map_err(|e| anyhow!("invalid argon2 parameters: {e}"))?;
+                let version = if *version == 0x10 {
+                    Version::V0x10
+                } else {
+                    Version::V0x13
+                };
+                let mut key = vec![0; 32];
+                Argon2::new(*algorithm, version, params)
+                    .hash_password_into(composite_key, salt, &mut key)
+                    .map_err(|e| anyhow!("argon2 failed: {e}"))?;
+                Ok(key)
+            }
+        }
+    }
+}
+
+// The hashes of the password and of the key file
+fn composite_key(password: &str, o_key_file: Option<&Path>) -> Result<Vec<u8>> {
+    let mut hasher = Sha256::new();
+    if !password.is_empty() || o_key_file.is_none() {
+        hasher.update(Sha256::digest(password.as_bytes()));
+    }
+    if let Some(key_file) = o_key_file {
+        let content =
+            std::fs::read(key_file).context(format!("reading key file {}", key_file.display()))?;
+        hasher.update(key_file_key(&content)?);
+    }
+    Ok(hasher.finalize().to_vec())
+}
+
+// Key files are XML files with the key in base64 (version 1) or hex (version 2),
+// or files with 32 bytes, or with 64 hex digits; other files are hashed
+fn key_file_key(content: &[u8]) -> Result<Vec<u8>> {
+    if let Ok(document) = std::str::from_utf8(content)
+        .map_err(|_| ())
+        .and_then(|text| roxmltree::Document::parse(text).map_err(|_| ()))
+    {
+        let data = document
+            .descendants()
+            .find(|node| node.has_tag_name("Data"))
+            .and_then(|node| node.text())
+            .ok_or_else(|| anyhow!(t!("_keepass_bad_key_file")))?;
+        let version = document
+            .descendants()
+            .find(|node| node.has_tag_name("Version"))
+            .and_then(|node| node.text())
+            .unwrap_or_default();
+        return if version.starts_with("2.") {
+            decode_hex(&data.split_whitespace().collect::<String>())
+                .ok_or_else(|| anyhow!(t!("_keepass_bad_key_file")))
+        } else {
+            b64.decode(data.trim())
+                .map_err(|_| anyhow!(t!("_keepass_bad_key_file")))
+        };
+    }
+    if content.len() == 32 {
+        return Ok(content.to_vec());
+    }
+    if content.len() == 64 {
+        if let Some(key) = std::str::from_utf8(content).ok().and_then(decode_hex) {
+            return Ok(key);
+        }
+    }
+    Ok(Sha256::digest(content).to_vec())
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|idx| u8::from_str_radix(hex.get(idx..idx + 2)?, 16).ok())
+        .collect()
+}
+
+// Every block has its own HMAC key, which depends on the index of the block
+fn block_hmac(hmac_key: &[u8], index: u64, data: &[&[u8]]) -> Vec<u8> {
+    let key = Sha512::new()
+        .chain_update(index.to_le_bytes())
+        .chain_update(hmac_key)
+        .finalize();
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&key).unwrap(/*OK, any key length works*/);
+    for part in data {
+        mac.update(part);
+    }
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn read_inner(payload: &[u8]) -> Result<Kdbx> {
+    let mut reader = Reader::new(payload);
+    let mut stream_id = 0;
+    let mut stream_key = Vec::new();
+    let mut binaries = BTreeMap::new();
+    loop {
+        let (id, data) = reader.field()?;
+        match id {
+            END_OF_HEADER => break,
+            INNER_RANDOM_STREAM_ID => {
+                stream_id = u32::from_le_bytes(data.try_into().context(t!("_keepass_damaged"))?);
+            }
+            INNER_RANDOM_STREAM_KEY => stream_key = data.to_vec(),
+            // the first byte holds flags
+            BINARY if !data.is_empty() => {
+                binaries.insert(binaries.len().to_string(), data[1..].to_vec());
+            }
+            _ => {}
+        }
+    }
+    Ok(Kdbx {
+        xml: String::from_utf8(reader.rest().to_vec()).context(t!("_keepass_damaged"))?,
+        binaries,
+        inner_stream: InnerStream::new(stream_id, &stream_key)?,
+    })
+}
+
+fn unsupported(what: &str) -> anyhow::Error {
+    anyhow!(t!("_keepass_unsupported %{w}", w = what))
+}
+
+#[cfg(test)]
+pub(super) mod test {
+    use super::{
+        BINARY, CIPHER_AES256, CIPHER_CHACHA20, CIPHER_ID, CIPHER_TWOFISH, COMPRESSION_FLAGS,
+        ENCRYPTION_IV, END_OF_HEADER, INNER_RANDOM_STREAM_ID, INNER_RANDOM_STREAM_KEY, InnerStream,
+        KDF_AES, KDF_ARGON2ID, KDF_PARAMETERS, KeePassKdf, MASTER_SEED, MAX_AES_ROUNDS,
+        MAX_ARGON2_MEMORY, MAX_ARGON2_PARALLELISM, SIGNATURE, block_hmac, composite_key,
+        decompress, key_file_key, read,
+    };
+    use aes::{
+        Aes256,
+        cipher::{BlockEncryptMut, KeyIvInit, StreamCipher, block_padding::Pkcs7},
+    };
+    use chacha20::ChaCha20;
+    use sha2::{Digest, Sha256, Sha512};
+    use std::io::Write;
+    use twofish::Twofish;
+
+    pub const STREAM_KEY: [u8; 32] = [7; 32];
+
+    // The stream that the writer of the file uses for the protected values
+    pub fn inner_stream() -> InnerStream {
+        InnerStream::new(3, &STREAM_KEY).unwrap()
+    }
+
+    fn field(id: u8, data: &[u8]) -> Vec<u8> {
+        let mut field = vec![id];
+        field.extend_from_slice(&u32::try_from(data.len()).unwrap().to_le_bytes());
+        field.extend_from_slice(data);
+        field
+    }
+
+    // A "variant dictionary" of typed values, as used for the KDF parameters
+    fn variant_dictionary(entries: &[(u8, &str, Vec<u8>)]) -> Vec<u8> {
+        let mut dictionary = 0x0100_u16.to_le_bytes().to_vec();
+        for (value_type, key, value) in entries {
+            dictionary.push(*value_type);
+            dictionary.extend_from_slice(&u32::try_from(key.len()).unwrap().to_le_bytes());
+            dictionary.extend_from_slice(key.as_bytes());
+            dictionary.extend_from_slice(&u32::try_from(value.len()).unwrap().to_le_bytes());
+            dictionary.extend_from_slice(value);
+        }
+        dictionary.push(0);
+        dictionary
+    }
+
+    // Writes a KDBX 4 file with AES-KDF, AES-256, gzip and ChaCha20 for the protected values
+    pub fn write(xml: &str, binaries: &[&[u8]], password: &str) -> Vec<u8> {
+        write_with_cipher(xml, binaries, password, CIPHER_AES256)
+    }
+
+    fn write_with_cipher(
+        xml: &str,
+        binaries: &[&[u8]],
+        password: &str,
+        cipher_id: [u8; 16],
+    ) -> Vec<u8> {
+        let mut inner = field(INNER_RANDOM_STREAM_ID, &3_u32.to_le_bytes());
+        inner.extend(field(INNER_RANDOM_STREAM_KEY, &STREAM_KEY));
+        for binary in binaries {
+            inner.extend(field(BINARY, &[&[1_u8][..], binary].concat()));
+        }
+        inner.extend(field(END_OF_HEADER, &[]));
+        inner.extend_from_slice(xml.as_bytes());
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(&inner).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let master_seed = [1_u8; 32];
+        let iv = if cipher_id == CIPHER_CHACHA20 {
+            vec![2_u8; 12]
+        } else {
+            vec![2_u8; 16]
+        };
+        let seed = [3_u8; 32];
+        let kdf_parameters = variant_dictionary(&[
+            (0x42_u8, "$UUID", KDF_AES.to_vec()),
+            (0x05, "R", 10_u64.to_le_bytes().to_vec()),
+            (0x42, "S", seed.to_vec()),
+        ]);
+
+        let mut header = SIGNATURE.to_vec();
+        header.extend_from_slice(&1_u16.to_le_bytes());
+        header.extend_from_slice(&4_u16.to_le_bytes());
+        header.extend(field(CIPHER_ID, &cipher_id));
+        header.extend(field(COMPRESSION_FLAGS, &1_u32.to_le_bytes()));
+        header.extend(field(MASTER_SEED, &master_seed));
+        header.extend(field(ENCRYPTION_IV, &iv));
+        header.extend(field(KDF_PARAMETERS, &kdf_parameters));
+        header.extend(field(END_OF_HEADER, b"\r\n\r\n"));
+
+        let transformed_key = KeePassKdf::Aes {
+            rounds: 10,
+            seed: seed.to_vec(),
+        }
+        .transform(&composite_key(password, None).unwrap())
+        .unwrap();
+        let key = Sha256::new()
+            .chain_update(master_seed)
+            .chain_update(&transformed_key)
+            .finalize();
+        let mut payload = compressed.clone();
+        if cipher_id == CIPHER_CHACHA20 {
+            ChaCha20::new_from_slices(&key, &iv)
+                .unwrap()
+                .apply_keystream(&mut payload);
+        } else {
+            payload.resize(compressed.len() + 16, 0);
+            let len = if cipher_id == CIPHER_TWOFISH {
+                cbc::Encryptor::<Twofish>::new_from_slices(&key, &iv)
+                    .unwrap()
+                    .encrypt_padded_mut::<Pkcs7>(&mut payload, compressed.len())
+                    .unwrap()
+                    .len()
+            } else {
+                cbc::Encryptor::<Aes256>::new_from_slices(&key, &iv)
+                    .unwrap()
+                    .encrypt_padded_mut::<Pkcs7>(&mut payload, compressed.len())
+                    .unwrap()
+                    .len()
+            };
+            payload.truncate(len);
+        }
+
+        let hmac_key = Sha512::new()
+            .chain_update(master_seed)
+            .chain_update(&transformed_key)
+            .chain_update([1])
+            .finalize();
+        let mut file = header.clone();
+        file.extend_from_slice(&Sha256::digest(&header));
+        file.extend(block_hmac(&hmac_key, u64::MAX, &[&header]));
+        for (index, block) in [payload.as_slice(), &[]].into_iter().enumerate() {
+            let index = index as u64;
+            let size = u32::try_from(block.len()).unwrap().to_le_bytes();
+            file.extend(block_hmac(
+                &hmac_key,
+                index,
+                &[&index.to_le_bytes(), &size, block],
+            ));
+            file.extend_from_slice(&size);
+            file.extend_from_slice(block);
+        }
+        file
+    }
+
+    #[test]
+    fn test_ciphers() {
+        for cipher_id in [CIPHER_AES256, CIPHER_TWOFISH, CIPHER_CHACHA20] {
+            let content = write_with_cipher("<KeePassFile/>", &[b"x"], "pw", cipher_id);
+            let kdbx = read(&content, "pw", None).unwrap();
+            assert_eq!(kdbx.xml, "<KeePassFile/>");
+            assert_eq!(kdbx.binaries.len(), 1);
+        }
+    }
+
+    #[test]
+    fn test_kdf_limits() {
+        let aes = |rounds: u64| {
+            KeePassKdf::read(&variant_dictionary(&[
+                (0x42, "$UUID", KDF_AES.to_vec()),
+                (0x05, "R", rounds.to_le_bytes().to_vec()),
+                (0x42, "S", vec![3; 32]),
+            ]))
+        };
+        assert!(aes(MAX_AES_ROUNDS).is_ok());
+        assert!(aes(u64::MAX).is_err());
+
+        let argon2 = |memory: u64, iterations: u64, parallelism: u32| {
+            KeePassKdf::read(&variant_dictionary(&[
+                (0x42, "$UUID", KDF_ARGON2ID.to_vec()),
+                (0x42, "S", vec![3; 32]),
+                (0x04, "P", parallelism.to_le_bytes().to_vec()),
+                (0x05, "M", memory.to_le_bytes().to_vec()),
+                (0x05, "I", iterations.to_le_bytes().to_vec()),
+                (0x04, "V", 0x13_u32.to_le_bytes().to_vec()),
+            ]))
+        };
+        assert!(argon2(64 << 20, 2, 2).is_ok());
+        assert!(argon2(MAX_ARGON2_MEMORY + 1, 2, 2).is_err());
+        assert!(argon2(64 << 20, u64::MAX, 2).is_err());
+        assert!(argon2(64 << 20, 2, u32::MAX).is_err());
+        let max_parallelism = u32::try_from(MAX_ARGON2_PARALLELISM).unwrap();
+        assert!(argon2(64 << 20, 2, max_parallelism).is_ok());
+    }
+
+    #[test]
+    fn test_decompress() {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(&[0; 1000]).unwrap();
+        let compressed = encoder.finish().unwrap();
+        assert_eq!(decompress(&compressed, 1000).unwrap().len(), 1000);
+        assert!(decompress(&compressed, 999).is_err());
+    }
+
+    #[test]
+    fn test_key_file() {
+        let hex = "0123456789abcdef".repeat(4);
+        assert_eq!(key_file_key(hex.as_bytes()).unwrap()[..2], [0x01, 0x23]);
+        let xml = "<?xml version=\"1.0\"?><KeyFile><Meta><Version>2.0</Version></Meta>\
+                   <Key><Data Hash=\"x\">0123 4567 89AB CDEF</Data></Key></KeyFile>";
+        assert_eq!(
+            key_file_key(xml.as_bytes()).unwrap(),
+            [0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef]
+        );
+        assert_eq!(
+            key_file_key(b"some file").unwrap(),
+            Sha256::digest(b"some file").to_vec()
+        );
+    }
+}
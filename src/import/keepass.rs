@@ -0,0 +1,543 @@
+//! Import of `KeePass` 2 databases, from KDBX 4 files or from unencrypted XML exports.
+//!
+//! Every entry becomes an entry in the file; its group path, URL, unprotected custom fields,
+//! tags and notes go into the description, username and password and the protected
+//! custom fields become credentials. Long notes and text attachments become documents.
+
+use super::kdbx::{self, InnerStream};
+use crate::{
+    data::{Import, Key},
+    ui::viz::{VEditBundle, VEditCred, VEditDocument},
+};
+use anyhow::{Context, Result, anyhow};
+use base64::{Engine, engine::general_purpose::STANDARD as b64};
+use roxmltree::{Document, Node, NodeId};
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::Read,
+    path::Path,
+};
+
+const TITLE: &str = "Title";
+const USER_NAME: &str = "UserName";
+const PASSWORD: &str = "Password";
+const URL: &str = "URL";
+const NOTES: &str = "Notes";
+
+// Seconds from 0001-01-01 to 1970-01-01, as KDBX 4 counts time from the year 1
+const SECONDS_BEFORE_UNIX_EPOCH: i64 = 62_135_596_800;
+
+// Returns true if the file is encrypted and needs a password, false if it is an XML export
+pub(crate) fn is_encrypted(path: &Path) -> Result<bool> {
+    let mut signature = Vec::new();
+    std::fs::File::open(path)
+        .context(format!("reading {}", path.display()))?
+        .take(4)
+        .read_to_end(&mut signature)?;
+    Ok(kdbx::is_keepass_file(&signature))
+}
+
+pub(crate) fn read_kdbx(path: &Path, password: &str, o_key_file: Option<&Path>) -> Result<Import> {
+    let content = std::fs::read(path).context(format!("reading {}", path.display()))?;
+    let kdbx = kdbx::read(&content, password, o_key_file)?;
+    convert(&kdbx.xml, kdbx.binaries, kdbx.inner_stream)
+}
+
+pub(crate) fn read_xml(path: &Path) -> Result<Import> {
+    let content = std::fs::read(path).context(format!("reading {}", path.display()))?;
+    let xml = String::from_utf8(content).map_err(|_| anyhow!(t!("_keepass_not_keepass")))?;
+    convert(&xml, BTreeMap::new(), InnerStream::None)
+}
+
+fn convert(
+    xml: &str,
+    binaries: BTreeMap<String, Vec<u8>>,
+    mut inner_stream: InnerStream,
+) -> Result<Import> {
+    let document =
+        Document::parse(xml.trim_start_matches('\u{feff}')).context(t!("_keepass_damaged"))?;
+    let keepass_file = document.root_element();
+    if !keepass_file.has_tag_name("KeePassFile") {
+        return Err(anyhow!(t!("_keepass_not_keepass")));
+    }
+    // protected values must be decrypted in document order
+    let protected = document
+        .descendants()
+        .filter(|node| node.attribute("Protected") == Some("True"))
+        .map(|node| {
+            let mut value = b64
+                .decode(node.text().unwrap_or_default().trim())
+                .context(t!("_keepass_damaged"))?;
+            inner_stream.decrypt(&mut value);
+            Ok((node.id(), String::from_utf8_lossy(&value).to_string()))
+        })
+        .collect::<Result<HashMap<_, _>>>()?;
+
+    let meta = child(keepass_file, "Meta");
+    let mut converter = Converter {
+        protected,
+        binaries,
+        recycle_bin: meta
+            .filter(|meta| child_text(*meta, "RecycleBinEnabled") == "True")
+            .map(|meta| child_text(meta, "RecycleBinUUID"))
+            .filter(|uuid| !uuid.is_empty()),
+        import: Import::default(),
+        with_history: 0,
+        recycled: 0,
+    };
+    if let Some(meta) = meta {
+        converter.read_meta_binaries(meta)?;
+    }
+    // the name of the root group is not part of the group paths
+    let root_group = child(keepass_file, "Root")
+        .and_then(|root| child(root, "Group"))
+        .ok_or_else(|| anyhow!(t!("_keepass_not_keepass")))?;
+    converter.group(root_group, "");
+
+    let mut import = converter.import;
+    if converter.with_history > 0 {
+        import
+            .skipped
+            .push(t!("_keepass_history %{n}", n = converter.with_history).to_string());
+    }
+    if converter.recycled > 0 {
+        import
+            .skipped
+            .push(t!("_keepass_recycle_bin %{n}", n = converter.recycled).to_string());
+    }
+    Ok(import)
+}
+
+struct Converter {
+    protected: HashMap<NodeId, String>,
+    binaries: BTreeMap<String, Vec<u8>>,
+    recycle_bin: Option<String>,
+    import: Import,
+    with_history: usize,
+    recycled: usize,
+}
+impl Converter {
+    // XML exports contain the attachments in the meta data, possibly compressed
+    fn read_meta_binaries(&mut self, meta: Node) -> Result<()> {
+        let Some(binaries) = child(meta, "Binaries") else {
+            return Ok(());
+        };
+        for binary in binaries.children().filter(|n| n.has_tag_name("Binary")) {
+            let mut data = b64
+                .decode(binary.text().unwrap_or_default().trim())
+                .context(t!("_keepass_damaged"))?;
+            if binary.attribute("Compressed") == Some("True") {
+                let mut decompressed = Vec::new();
+                flate2::read::GzDecoder::new(data.as_slice())
+                    .read_to_end(&mut decompressed)
+                    .context(t!("_keepass_damaged"))?;
+                data = decompressed;
+            }
+            self.binaries
+                .insert(binary.attribute("ID").unwrap_or_default().to_string(), data);
+        }
+        Ok(())
+    }
+
+    fn group(&mut self, group: Node, path: &str) {
+        for node in group.children() {
+            if node.has_tag_name("Entry") {
+                self.entry(node, path);
+            } else if node.has_tag_name("Group") {
+                if self
+                    .recycle_bin
+                    .as_deref()
+                    .is_some_and(|uuid| child_text(node, "UUID") == uuid)
+                {
+                    self.recycled += node
+                        .descendants()
+                        .filter(|n| n.has_tag_name("Entry") && !is_history_entry(*n))
+                        .count();
+                    continue;
+                }
+                let name = child_text(node, "Name");
+                if path.is_empty() {
+                    self.group(node, &name);
+                } else {
+                    self.group(node, &format!("{path}/{name}"));
+                }
+            }
+        }
+    }
+
+    fn entry(&mut self, entry: Node, group_path: &str) {
+        let mut fields = BTreeMap::new();
+        let mut protected_fields = Vec::new();
+        for string in entry.children().filter(|n| n.has_tag_name("String")) {
+            let key = child_text(string, "Key");
+            let Some(value_node) = child(string, "Value") else {
+                continue;
+            };
+            let value = self.value(value_node);
+            if value.is_empty() {
+                continue;
+            }
+            match key.as_str() {
+                TITLE | USER_NAME | PASSWORD | URL | NOTES => {
+                    fields.insert(key, value);
+                }
+                _ if value_node.attribute("Protected") == Some("True")
+                    || value_node.attribute("ProtectInMemory") == Some("True") =>
+                {
+                    protected_fields.push((key, value));
+                }
+                _ => {
+                    fields.insert(key, value);
+                }
+            }
+        }
+        let field = |key: &str| fields.get(key).map_or("", |value| value.trim());
+
+        let name = [field(TITLE), field(URL), field(USER_NAME)]
+            .into_iter()
+            .find(|name| !name.is_empty())
+            .unwrap_or_default()
+            .to_string();
+        if name.is_empty() {
+            self.import.skipped.push(
+                t!(
+                    "_keepass_untitled %{g}",
+                    g = if group_path.is_empty() {
+                        "/"
+                    } else {
+                        group_path
+                    }
+                )
+                .to_string(),
+            );
+            return;
+        }
+        let key = Key::new(&name);
+
+        let mut description = Vec::new();
+        if !group_path.is_empty() {
+            description.push(format!("group: {group_path}"));
+        }
+        if !field(URL).is_empty() {
+            description.push(format!("url: {}", field(URL)));
+        }
+        for (field_key, value) in &fields {
+            if ![TITLE, USER_NAME, PASSWORD, URL, NOTES].contains(&field_key.as_str()) {
+                description.push(format!("{field_key}: {value}"));
+            }
+        }
+        let tags = child_text(entry, "Tags");
+        if !tags.is_empty() {
+            description.push(format!("tags: {tags}"));
+        }
+        let notes = fields.get(NOTES).map_or("", String::as_str);
+        if super::is_long(notes) {
+            self.add_document(&key, notes.to_string());
+        } else if !notes.trim().is_empty() {
+            description.push(notes.trim_end().to_string());
+        }
+
+        let v_edit_creds = creds(&fields, protected_fields);
+
+        self.attachments(entry, &name);
+        if child(entry, "History")
+            .is_some_and(|history| history.children().any(|n| n.has_tag_name("Entry")))
+        {
+            self.with_history += 1;
+        }
+        if let Some(times) = child(entry, "Times") {
+            if child_text(times, "Expires") == "True" {
+                self.import.skipped.push(
+                    t!(
+                        "_keepass_expiry %{e} %{t}",
+                        e = name,
+                        t = time(&child_text(times, "ExpiryTime"))
+                    )
+                    .to_string(),
+                );
+            }
+        }
+
+        let mut v_edit_bundle = VEditBundle::new();
+        v_edit_bundle.key = key;
+        v_edit_bundle.description = description.join("\n");
+        v_edit_bundle.v_edit_creds = v_edit_creds;
+        self.import.bundles.push(v_edit_bundle);
+    }
+
+    // Attachments that are text become documents, others cannot be represented
+    fn attachments(&mut self, entry: Node, entry_name: &str) {
+        for binary in entry.children().filter(|n| n.has_tag_name("Binary")) {
+            let attachment = child_text(binary, "Key");
+            let Some(value) = child(binary, "Value") else {
+                continue;
+            };
+            let o_data = match value.attribute("Ref") {
+                Some(reference) => self.binaries.get(reference).cloned(),
+                None => b64.decode(self.value(value)).ok(),
+            };
+            match o_data.map(String::from_utf8) {
+                Some(Ok(text)) => {
+                    self.add_document(&Key::new(format!("{entry_name}: {attachment}")), text);
+                }
+                _ => self.import.skipped.push(
+                    t!(
+                        "_keepass_binary_attachment %{e} %{a}",
+                        e = entry_name,
+                        a = attachment
+                    )
+                    .to_string(),
+                ),
+            }
+        }
+    }
+
+    fn add_document(&mut self, key: &Key, text: String) {
+        self.import.documents.push(VEditDocument {
+            orig_key: key.clone(),
+            key: key.clone(),
+            text,
+            request_focus: false,
+        });
+    }
+
+    fn value(&self, node: Node) -> String {
+        self.protected
+            .get(&node.id())
+            .cloned()
+            .unwrap_or_else(|| node.text().unwrap_or_default().to_string())
+    }
+}
+
+// username and password form the first credential, the protected custom fields follow
+fn creds(
+    fields: &BTreeMap<String, String>,
+    protected_fields: Vec<(String, String)>,
+) -> Vec<VEditCred> {
+    let user_name = fields.get(USER_NAME).map_or("", |value| value.trim());
+    let password = fields.get(PASSWORD).cloned().unwrap_or_default();
+    let mut v_edit_creds = Vec::new();
+    if !user_name.is_empty() || !password.is_empty() {
+        v_edit_creds.push(VEditCred {
+            name: if user_name.is_empty() {
+                PASSWORD.to_string()
+            } else {
+                user_name.to_string()
+            },
+            secret: password,
+        });
+    }
+    for (name, secret) in protected_fields {
+        v_edit_creds.push(VEditCred { name, secret });
+    }
+    v_edit_creds
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children().find(|n| n.has_tag_name(name))
+}
+
+fn child_text(node: Node, name: &str) -> String {
+    child(node, name)
+        .and_then(|n| n.text())
+        .unwrap_or_default()
+        .trim()
+        .to_string()
+}
+
+fn is_history_entry(entry: Node) -> bool {
+    entry
+        .parent()
+        .is_some_and(|parent| parent.has_tag_name("History"))
+}
+
+// KDBX 4 stores times as base64 encoded seconds since the year 1, XML exports as ISO 8601
+fn time(value: &str) -> String {
+    b64.decode(value)
+        .ok()
+        .and_then(|bytes| <[u8; 8]>::try_from(bytes).ok())
+        .map(i64::from_le_bytes)
+        .and_then(|seconds| jiff::Timestamp::from_second(seconds - SECONDS_BEFORE_UNIX_EPOCH).ok())
+        .map_or_else(|| value.to_string(), |timestamp| timestamp.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{convert, kdbx};
+    use base64::{Engine, engine::general_purpose::STANDARD as b64};
+    use std::collections::BTreeMap;
+
+    // The protected values are given as {0}, {1}, ... in the order of the document
+    fn protect(xml: &str, values: &[&str]) -> String {
+        let mut inner_stream = kdbx::test::inner_stream();
+        let mut xml = xml.to_string();
+        for (i, value) in values.iter().enumerate() {
+            let mut bytes = value.as_bytes().to_vec();
+            inner_stream.decrypt(&mut bytes);
+            xml = xml.replace(&format!("{{{i}}}"), &b64.encode(bytes));
+        }
+        xml
+    }
+
+    const KDBX_XML: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<KeePassFile><Meta><RecycleBinEnabled>True</RecycleBinEnabled>
+<RecycleBinUUID>cmVjeWNsZQ==</RecycleBinUUID></Meta>
+<Root><Group><Name>Database</Name>
+ <Entry>
+  <String><Key>Title</Key><Value>Mail</Value></String>
+  <String><Key>UserName</Key><Value>bob</Value></String>
+  <String><Key>Password</Key><Value Protected="True">{0}</Value></String>
+  <String><Key>URL</Key><Value>https://mail.example.com</Value></String>
+  <String><Key>Notes</Key><Value>use the app</Value></String>
+  <String><Key>PIN</Key><Value Protected="True">{1}</Value></String>
+  <String><Key>Account</Key><Value>4711</Value></String>
+  <Tags>private</Tags>
+  <Binary><Key>readme.txt</Key><Value Ref="0"/></Binary>
+  <Binary><Key>photo.jpg</Key><Value Ref="1"/></Binary>
+  <History><Entry>
+   <String><Key>Password</Key><Value Protected="True">{2}</Value></String>
+  </Entry></History>
+ </Entry>
+ <Group><Name>Internet</Name>
+  <Entry>
+   <String><Key>Title</Key><Value>Forum</Value></String>
+   <String><Key>Password</Key><Value Protected="True">{3}</Value></String>
+   <String><Key>Notes</Key><Value>1
+2
+3
+4
+5
+6</Value></String>
+   <Times><Expires>True</Expires><ExpiryTime>gKfM3g4AAAA=</ExpiryTime></Times>
+  </Entry>
+  <Entry><String><Key>Notes</Key><Value>no name</Value></String></Entry>
+ </Group>
+ <Group><UUID>cmVjeWNsZQ==</UUID><Name>Recycle Bin</Name>
+  <Entry><String><Key>Title</Key><Value>Old</Value></String></Entry>
+ </Group>
+</Group></Root></KeePassFile>"#;
+
+    #[test]
+    fn test_kdbx() {
+        let xml = protect(KDBX_XML, &["s3cret", "1234", "old", "forum-pw"]);
+        let content = kdbx::test::write(&xml, &[b"hello", &[0xff, 0xd8, 0xff]], "pw");
+        let kdbx = kdbx::read(&content, "pw", None).unwrap();
+        let import = convert(&kdbx.xml, kdbx.binaries, kdbx.inner_stream).unwrap();
+
+        assert_eq!(import.bundles.len(), 2);
+        let mail = &import.bundles[0];
+        assert_eq!(mail.key.as_str(), "Mail");
+        assert_eq!(
+            mail.description,
+            "url: https://mail.example.com\nAccount: 4711\ntags: private\nuse the app"
+        );
+        assert_eq!(mail.v_edit_creds.len(), 2);
+        assert_eq!(
+            (
+                mail.v_edit_creds[0].name.as_str(),
+                mail.v_edit_creds[0].secret.as_str()
+            ),
+            ("bob", "s3cret")
+        );
+        assert_eq!(
+            (
+                mail.v_edit_creds[1].name.as_str(),
+                mail.v_edit_creds[1].secret.as_str()
+            ),
+            ("PIN", "1234")
+        );
+        let forum = &import.bundles[1];
+        assert_eq!(forum.description, "group: Internet");
+        assert_eq!(forum.v_edit_creds[0].name, "Password");
+        assert_eq!(forum.v_edit_creds[0].secret, "forum-pw");
+
+        let documents = import
+            .documents
+            .iter()
+            .map(|d| (d.key.as_str(), d.text.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            documents,
+            [("Mail: readme.txt", "hello"), ("Forum", "1\n2\n3\n4\n5\n6")]
+        );
+        // binary attachment, expiry, untitled entry, history, recycle bin
+        assert_eq!(import.skipped.len(), 5, "{:?}", import.skipped);
+
+        assert!(kdbx::read(&content, "wrong", None).is_err());
+        assert!(kdbx::read(&content[..content.len() - 1], "pw", None).is_err());
+    }
+
+    // The files are written by an independent implementation of the format,
+    // see testdata/write_kdbx.py
+    #[test]
+    fn test_kdbx_fixtures() {
+        for content in [
+            &include_bytes!("testdata/argon2d_aes.kdbx")[..],
+            &include_bytes!("testdata/argon2id_chacha20.kdbx")[..],
+        ] {
+            assert!(kdbx::read(content, "wrong", None).is_err());
+            let kdbx = kdbx::read(content, "fixture-pw", None).unwrap();
+            let import = convert(&kdbx.xml, kdbx.binaries, kdbx.inner_stream).unwrap();
+
+            let bundles = import
+                .bundles
+                .iter()
+                .map(|bundle| {
+                    (
+                        bundle.key.as_str(),
+                        bundle.description.as_str(),
+                        bundle.v_edit_creds[0].name.as_str(),
+                        bundle.v_edit_creds[0].secret.as_str(),
+                    )
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(
+                bundles,
+                [
+                    (
+                        "Mail",
+                        "url: https://mail.example.com\nsee the attachment",
+                        "bob",
+                        "s3cret"
+                    ),
+                    ("Forum", "group: Internet", "alice", "forum-pw")
+                ]
+            );
+            let documents = import
+                .documents
+                .iter()
+                .map(|d| (d.key.as_str(), d.text.as_str()))
+                .collect::<Vec<_>>();
+            assert_eq!(
+                documents,
+                [("Mail: readme.txt", "hello from the attachment")]
+            );
+        }
+    }
+
+    #[test]
+    fn test_xml_export() {
+        let xml = r#"<KeePassFile><Meta><Binaries>
+<Binary ID="0" Compressed="False">bm90ZXM=</Binary></Binaries></Meta>
+<Root><Group><Name>Root</Name>
+ <Entry>
+  <String><Key>UserName</Key><Value>alice</Value></String>
+  <String><Key>Password</Key><Value ProtectInMemory="True">pw</Value></String>
+  <String><Key>Token</Key><Value ProtectInMemory="True">t0k3n</Value></String>
+  <Binary><Key>notes.txt</Key><Value Ref="0"/></Binary>
+ </Entry>
+</Group></Root></KeePassFile>"#;
+        let import = convert(xml, BTreeMap::new(), kdbx::InnerStream::None).unwrap();
+        assert_eq!(import.bundles.len(), 1);
+        let alice = &import.bundles[0];
+        assert_eq!(alice.key.as_str(), "alice");
+        assert_eq!(alice.description, "");
+        assert_eq!(alice.v_edit_creds[1].name, "Token");
+        assert_eq!(alice.v_edit_creds[1].secret, "t0k3n");
+        assert_eq!(import.documents[0].key.as_str(), "alice: notes.txt");
+        assert_eq!(import.documents[0].text, "notes");
+        assert!(import.skipped.is_empty());
+
+        assert!(convert("<Other/>", BTreeMap::new(), kdbx::InnerStream::None).is_err());
+    }
+}
@@ -0,0 +1,151 @@
+#!/usr/bin/env python3
+"""Writes the KDBX 4 test files in this folder.
+
+This is an implementation of the format that is independent of the reader in `kdbx.rs`,
+following https://keepass.info/help/kb/kdbx_4.html; it needs the `cryptography` package.
+The files are written with fixed seeds and small KDF parameters, so that they are
+reproducible and quick to open.
+"""
+
+import base64
+import gzip
+import hashlib
+import hmac
+import struct
+from pathlib import Path
+
+from cryptography.hazmat.primitives import padding
+from cryptography.hazmat.primitives.ciphers import Cipher, algorithms, modes
+from cryptography.hazmat.primitives.kdf.argon2 import Argon2d, Argon2id
+
+PASSWORD = b"fixture-pw"
+
+CIPHER_AES256 = bytes.fromhex("31c1f2e6bf714350be5805216afc5aff")
+CIPHER_CHACHA20 = bytes.fromhex("d6038a2b8b6f4cb5a524339a31dbb59a")
+KDF_ARGON2D = bytes.fromhex("ef636ddf8c29444b91f7a9a403e30a0c")
+KDF_ARGON2ID = bytes.fromhex("9e298b1956db4773b23dfc3ec6f0a1e6")
+
+XML = """<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<KeePassFile>
+\t<Meta>
+\t\t<Generator>write_kdbx.py</Generator>
+\t\t<DatabaseName>Fixture</DatabaseName>
+\t\t<RecycleBinEnabled>False</RecycleBinEnabled>
+\t</Meta>
+\t<Root>
+\t\t<Group>
+\t\t\t<Name>Root</Name>
+\t\t\t<Entry>
+\t\t\t\t<String><Key>Notes</Key><Value>see the attachment</Value></String>
+\t\t\t\t<String><Key>Password</Key><Value Protected="True">{0}</Value></String>
+\t\t\t\t<String><Key>Title</Key><Value>Mail</Value></String>
+\t\t\t\t<String><Key>URL</Key><Value>https://mail.example.com</Value></String>
+\t\t\t\t<String><Key>UserName</Key><Value>bob</Value></String>
+\t\t\t\t<Binary><Key>readme.txt</Key><Value Ref="0"/></Binary>
+\t\t\t</Entry>
+\t\t\t<Group>
+\t\t\t\t<Name>Internet</Name>
+\t\t\t\t<Entry>
+\t\t\t\t\t<String><Key>Password</Key><Value Protected="True">{1}</Value></String>
+\t\t\t\t\t<String><Key>Title</Key><Value>Forum</Value></String>
+\t\t\t\t\t<String><Key>UserName</Key><Value>alice</Value></String>
+\t\t\t\t</Entry>
+\t\t\t</Group>
+\t\t</Group>
+\t</Root>
+</KeePassFile>
+"""
+PROTECTED = ["s3cret", "forum-pw"]
+ATTACHMENT = b"hello from the attachment"
+
+
+def chacha20(key, nonce, data):
+    # the nonce of the cryptography package starts with the 32-bit block counter
+    cipher = Cipher(algorithms.ChaCha20(key, b"\0" * 4 + nonce), mode=None)
+    return cipher.encryptor().update(data)
+
+
+def field(field_id, data):
+    return struct.pack("<BI", field_id, len(data)) + data
+
+
+def variant_dictionary(entries):
+    data = struct.pack("<H", 0x0100)
+    for value_type, key, value in entries:
+        data += struct.pack("<BI", value_type, len(key)) + key.encode()
+        data += struct.pack("<I", len(value)) + value
+    return data + b"\0"
+
+
+def write(path, cipher_id, kdf_id, kdf_class, seed):
+    master_seed = hashlib.sha256(b"master" + seed).digest()
+    salt = hashlib.sha256(b"salt" + seed).digest()
+    iv = hashlib.sha256(b"iv" + seed).digest()[: 12 if cipher_id == CIPHER_CHACHA20 else 16]
+    stream_key = hashlib.sha512(b"stream" + seed).digest()
+    memory_kib, iterations, lanes = 1024, 2, 2
+
+    kdf_parameters = variant_dictionary(
+        [
+            (0x42, "$UUID", kdf_id),
+            (0x42, "S", salt),
+            (0x04, "P", struct.pack("<I", lanes)),
+            (0x05, "M", struct.pack("<Q", memory_kib * 1024)),
+            (0x05, "I", struct.pack("<Q", iterations)),
+            (0x04, "V", struct.pack("<I", 0x13)),
+        ]
+    )
+    header = bytes.fromhex("03d9a29a67fb4bb5") + struct.pack("<HH", 1, 4)
+    header += field(2, cipher_id)
+    header += field(3, struct.pack("<I", 1))
+    header += field(4, master_seed)
+    header += field(7, iv)
+    header += field(11, kdf_parameters)
+    header += field(0, b"\r\n\r\n")
+
+    # the protected values are encrypted with one key stream, in document order
+    stream = hashlib.sha512(stream_key).digest()
+    plain = b"".join(value.encode() for value in PROTECTED)
+    key_stream = chacha20(stream[:32], stream[32:44], plain)
+    values, pos = [], 0
+    for value in PROTECTED:
+        length = len(value.encode())
+        values.append(base64.b64encode(key_stream[pos : pos + length]).decode())
+        pos += length
+    xml = XML.format(*values).encode()
+
+    inner = field(1, struct.pack("<I", 3)) + field(2, stream_key)
+    inner += field(3, b"\1" + ATTACHMENT)
+    inner += field(0, b"")
+    payload = gzip.compress(inner + xml, mtime=0)
+
+    composite_key = hashlib.sha256(hashlib.sha256(PASSWORD).digest()).digest()
+    transformed_key = kdf_class(
+        salt=salt, length=32, iterations=iterations, lanes=lanes, memory_cost=memory_kib
+    ).derive(composite_key)
+    key = hashlib.sha256(master_seed + transformed_key).digest()
+    if cipher_id == CIPHER_CHACHA20:
+        payload = chacha20(key, iv, payload)
+    else:
+        padder = padding.PKCS7(128).padder()
+        padded = padder.update(payload) + padder.finalize()
+        encryptor = Cipher(algorithms.AES(key), modes.CBC(iv)).encryptor()
+        payload = encryptor.update(padded) + encryptor.finalize()
+
+    hmac_base = hashlib.sha512(master_seed + transformed_key + b"\1").digest()
+
+    def block_hmac(index, data):
+        block_key = hashlib.sha512(struct.pack("<Q", index) + hmac_base).digest()
+        return hmac.new(block_key, data, hashlib.sha256).digest()
+
+    content = header + hashlib.sha256(header).digest()
+    content += block_hmac(0xFFFF_FFFF_FFFF_FFFF, header)
+    for index, block in enumerate([payload, b""]):
+        size = struct.pack("<I", len(block))
+        content += block_hmac(index, struct.pack("<Q", index) + size + block) + size + block
+    path.write_bytes(content)
+
+
+if __name__ == "__main__":
+    folder = Path(__file__).parent
+    write(folder / "argon2d_aes.kdbx", CIPHER_AES256, KDF_ARGON2D, Argon2d, b"1")
+    write(folder / "argon2id_chacha20.kdbx", CIPHER_CHACHA20, KDF_ARGON2ID, Argon2id, b"2")
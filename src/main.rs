@@ -10,8 +10,11 @@ extern crate rust_i18n;
 
 i18n!("locales", fallback = "en");
 
+mod agent;
 mod args;
+mod cli;
 mod data;
+mod import;
 mod ui;
 mod util;
 
@@ -23,7 +26,7 @@ use crate::{
     },
 };
 use anyhow::{Result, anyhow};
-use args::Args;
+use args::{Args, CliCommand};
 use data::Settings;
 use eframe::{NativeOptions, run_native};
 use egui::{IconData, ViewportBuilder};
@@ -47,16 +50,22 @@ pub const DEFAULT_LANGUAGE: &Language = &SUPPORTED_LANGUAGES[0];
 fn main() -> ExitCode {
     i18n!("locales", fallback = "en");
 
-    match run() {
+    let args = Args::from_command_line();
+    match run(&args) {
         Ok(()) => ExitCode::SUCCESS,
         Err(e) => {
-            println!("Error occured: {e:?}");
-            ExitCode::FAILURE
+            if matches!(args.cli_command(), Some(CliCommand::GitCredential { .. })) {
+                // git reads the answer of a credential helper from stdout
+                eprintln!("{PROG_NAME}: {e:#}");
+            } else {
+                cli::report_error(&e, args.format());
+            }
+            ExitCode::from(cli::exit_code(&e))
         }
     }
 }
 
-fn run() -> Result<()> {
+fn run(args: &Args) -> Result<()> {
     // use a logspec that focuses on the "prolock" module and sets the default level to Info
     // log to a buffer and use a custom log format that fits to show_log()
     let logger_handle = Logger::with(
@@ -68,7 +77,6 @@ fn run() -> Result<()> {
     .log_to_buffer(60_000, Some(pl_action_log_format))
     .start()?;
 
-    let args = Args::from_command_line();
     let mut settings = Settings::read_or_create(args.is_test())?;
 
     log::info!(
@@ -86,15 +94,7 @@ fn run() -> Result<()> {
     }
 
     if args.list_known_files() {
-        settings.files.iter().enumerate().for_each(|(idx, f)| {
-            println!(
-                "{} {}{}",
-                f.display(),
-                settings.default_marker(idx),
-                settings.current_marker(idx)
-            );
-        });
-        return Ok(());
+        return cli::list_known_files(&settings, args.format());
     }
 
     if let Some(file) = args.forget_file() {
@@ -102,6 +102,19 @@ fn run() -> Result<()> {
         return Ok(());
     }
 
+    if args.list_key_slots() {
+        return cli::list_key_slots(&settings, args.format());
+    }
+    if let Some(label) = args.add_key_slot() {
+        return cli::add_key_slot(&settings, label, args.key_file(), args.format());
+    }
+    if let Some(label) = args.revoke_key_slot() {
+        return cli::revoke_key_slot(&settings, label, args.key_file(), args.format());
+    }
+    if let Some(command) = args.cli_command() {
+        return cli::run(&mut settings, command, args.key_file(), args.format());
+    }
+
     run_native(
         PROG_NAME,
         NativeOptions {
@@ -118,7 +131,7 @@ fn run() -> Result<()> {
             Ok(Box::new(
                 // build PlApp (which implements egui::App) and hand it over to eframe::run_native,
                 // which will then call its method `update()` in an endless loop
-                PlApp::new(logger_handle, settings)?,
+                PlApp::new(logger_handle, settings, args.key_file().cloned())?,
             ))
         }),
     )
@@ -12,7 +12,7 @@ use crate::ui::assets::{
     IMG_ADD_ENTRY, IMG_ADD_ENTRY_INACTIVE, IMG_BURGER, IMG_CANCEL, IMG_DELETE, IMG_DELETE_INACTIVE,
     IMG_EDIT, IMG_EDIT_INACTIVE, IMG_ERASE, IMG_LOGO, IMG_OK, IMG_RUST_LOGO, IMG_SAVE,
 };
-use egui::{Color32, RichText};
+use egui::{Color32, RichText, TextEdit};
 
 pub const LIGHT_GRAY: Color32 = Color32::from_rgb(230, 230, 230);
 pub const VERY_LIGHT_GRAY: Color32 = Color32::from_rgb(235, 235, 235);
@@ -24,3 +24,18 @@ pub fn show_error(e: &str, ui: &mut egui::Ui) {
     ui.label(RichText::new(e).color(Color32::RED));
     ui.add_space(15.);
 }
+
+// Grid row for entering the path of a key file, or for choosing it with a file dialog
+pub fn key_file_row(key_file: &mut String, ui: &mut egui::Ui) {
+    ui.label(t!("Key file:"));
+    ui.horizontal(|ui| {
+        ui.add(TextEdit::singleline(key_file).desired_width(200.))
+            .on_hover_text(t!("_key_file_hint"));
+        if ui.button("…").clicked() {
+            if let Some(path) = rfd::FileDialog::new().pick_file() {
+                *key_file = path.display().to_string();
+            }
+        }
+    });
+    ui.end_row();
+}
@@ -1,22 +1,72 @@
 use crate::{
-    PlFile, Settings,
-    data::Key,
+    PlFile, Settings, agent,
+    data::{
+        Comparison, Export, Key, MergeConflict, Version, list_backups, open_package,
+        parse_recipients, seal_package,
+    },
+    import::{CsvMapping, CsvTable, json, keepass},
     ui::viz::{
-        BundleState, DocumentState, MainState, ModalState, Pw, PwFocus, V, VEditBundle,
-        VEditDocument,
+        BundleState, DocumentState, ImportSource, MainState, ModalState, Pw, PwFocus, V, VBackups,
+        VEditBundle, VEditDocument, VExport, VExportPackage, VExportVault, VImport, VKeySlots,
+        VMergeFile,
+    },
+    util::{generate_password, write_private_file},
+};
+use anyhow::{Context, Result, anyhow};
+use std::{
+    fs::metadata,
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        mpsc::{Receiver, TryRecvError, channel},
     },
-    util::generate_password,
+    time::{Duration, Instant, SystemTime},
 };
-use anyhow::{Context, Result};
-use std::path::PathBuf;
+
+// How often the file is checked for changes by others
+pub const FILE_WATCH_INTERVAL: Duration = Duration::from_secs(2);
 
 // The controller is responsible for managing the state of the application and the UI,
 // and is the only place where the application data is modified.
 // The UI code calls Controller::set_action() to set the next action to be taken.
 // The main loop calls Controller::act() to execute the action.
+//
+// Actions that need to encrypt, write or decrypt the file are executed on a clone of the PlFile
+// in a background thread, so that the UI stays responsive; the result is delivered back
+// as Action::JobDone, and only then replaces the PlFile. While a job is running, no further
+// actions are accepted.
 #[derive(Default)]
 pub struct Controller {
     next_action: Action,
+    o_running_job: Option<RunningJob>,
+    file_watch: FileWatch,
+}
+// Notices changes of the file by polling its modification time
+#[derive(Default)]
+struct FileWatch {
+    o_last_check: Option<Instant>,
+    o_modified: Option<SystemTime>,
+}
+impl FileWatch {
+    fn file_changed(&mut self, file_path: &Path) -> bool {
+        if self
+            .o_last_check
+            .is_some_and(|last_check| last_check.elapsed() < FILE_WATCH_INTERVAL)
+        {
+            return false;
+        }
+        self.o_last_check = Some(Instant::now());
+        let o_modified = metadata(file_path)
+            .and_then(|metadata| metadata.modified())
+            .ok();
+        let changed = o_modified != self.o_modified;
+        self.o_modified = o_modified;
+        changed
+    }
+}
+struct RunningJob {
+    job: Job,
+    receiver: Receiver<Result<Box<PlFile>>>,
 }
 impl Controller {
     // Set the next action to be taken by the controller.
@@ -24,10 +74,57 @@ impl Controller {
         self.next_action = action;
     }
 
-    // Executes the action set by the UI code.
+    // Lets the controller check the file if it was changed, or reload it if a change
+    // by someone else could not be taken over so far because local edits were pending.
+    // Is called by the main loop after the UI code had the chance to set an action.
+    pub fn watch_file(&mut self, file_path: &Path, v: &V) {
+        if matches!(self.next_action, Action::None)
+            && self.o_running_job.is_none()
+            && (self.file_watch.file_changed(file_path)
+                || (v.file_changed_externally && !v.has_pending_edits()))
+        {
+            self.next_action = Action::CheckForExternalChange;
+        }
+    }
+
+    // Executes the given function on a clone of the PlFile in a background thread.
+    fn start_job<F>(&mut self, job: Job, pl_file: &PlFile, f: F)
+    where
+        F: FnOnce(&mut PlFile) -> Result<()> + Send + 'static,
+    {
+        let mut pl_file = pl_file.clone();
+        let (sender, receiver) = channel();
+        std::thread::spawn(move || {
+            // the receiver might be gone if the app was closed in the meantime
+            sender
+                .send(f(&mut pl_file).map(|()| Box::new(pl_file)))
+                .ok();
+        });
+        self.o_running_job = Some(RunningJob { job, receiver });
+    }
+
+    fn poll_running_job(&mut self) -> Option<Action> {
+        let running_job = self.o_running_job.as_ref()?;
+        let result = match running_job.receiver.try_recv() {
+            Ok(result) => result,
+            Err(TryRecvError::Empty) => return None,
+            Err(TryRecvError::Disconnected) => Err(anyhow!("background job failed")),
+        };
+        let job = running_job.job;
+        self.o_running_job = None;
+        Some(Action::JobDone(job, JobResult(result)))
+    }
+
+    // Executes the action set by the UI code, or the completion of a background job.
     #[allow(clippy::too_many_lines)]
     pub fn act(&mut self, pl_file: &mut PlFile, v: &mut V, settings: &mut Settings) {
-        let action = std::mem::take(&mut self.next_action);
+        let action = if let Some(action) = self.poll_running_job() {
+            action
+        } else if self.o_running_job.is_some() {
+            return;
+        } else {
+            std::mem::take(&mut self.next_action)
+        };
         action.log(&v.main_state, &v.modal_state);
 
         match (&mut v.main_state, &mut v.modal_state, action) {
@@ -98,8 +195,22 @@ impl Controller {
             }
 
             (_, ModalState::None, Action::SwitchToActionable) => {
-                match pl_file.set_actionable(v.pw.pw1.clone()) {
-                    Ok(()) => {
+                let password = v.pw.pw1.clone();
+                if v.pw.with_recovery_code {
+                    self.start_job(Job::Unlock, pl_file, move |pl_file| {
+                        pl_file.set_actionable_with_recovery_code(&password)
+                    });
+                } else {
+                    let o_key_file = key_file_path(&v.pw.key_file);
+                    self.start_job(Job::Unlock, pl_file, move |pl_file| {
+                        pl_file.set_actionable(&password, o_key_file.as_deref())
+                    });
+                }
+            }
+            (_, ModalState::None, Action::JobDone(Job::Unlock, JobResult(result))) => {
+                match result {
+                    Ok(unlocked_pl_file) => {
+                        *pl_file = *unlocked_pl_file;
                         v.pw.error = None;
                         v.reset_bundles(pl_file.bundles(), None);
                         v.reset_documents(pl_file.documents(), None);
@@ -107,6 +218,18 @@ impl Controller {
                         //     v.edit_b.bundle.prepare_for_create();
                         // }
                         v.find.request_focus = true;
+                        if v.pw.with_recovery_code {
+                            log::warn!("{}", t!("_opened_with_recovery_code"));
+                        }
+                        // a new file comes with a recovery code
+                        if let Some(recovery_code) = pl_file.take_recovery_code() {
+                            v.modal_state = ModalState::ShowRecoveryCode {
+                                recovery_code,
+                                error: None,
+                            };
+                        } else if pl_file.leftover_temp_file().is_some() {
+                            v.modal_state = ModalState::RecoverTempFile { error: None };
+                        }
                     }
                     Err(e) => {
                         // TODO mark all entered text to facilitate repetition
@@ -125,21 +248,529 @@ impl Controller {
                 v.apply_filter_to_documents(pl_file.documents());
             }
 
-            (_, ModalState::None, Action::StartChangePassword) => {
-                v.pw = Pw::default();
-                v.modal_state = ModalState::ChangePassword;
+            (_, ModalState::RecoverTempFile { .. }, Action::FinalizeRecoverTempFile) => {
+                match pl_file.recover_leftover_temp_file() {
+                    Ok(()) => {
+                        v.reset_bundles(pl_file.bundles(), None);
+                        v.reset_documents(pl_file.documents(), None);
+                        v.modal_state.close_modal();
+                    }
+                    Err(e) => {
+                        let s = e.to_string();
+                        log::error!("{s}");
+                        v.modal_state = ModalState::RecoverTempFile { error: Some(s) };
+                    }
+                }
             }
-            (_, ModalState::ChangePassword, Action::FinalizeChangePassword { old, new }) => {
-                match pl_file.change_password(&old, new) {
+            (_, ModalState::RecoverTempFile { .. }, Action::FinalizeDiscardTempFile) => {
+                match pl_file.discard_leftover_temp_file() {
+                    Ok(()) => v.modal_state.close_modal(),
+                    Err(e) => {
+                        let s = e.to_string();
+                        log::error!("{s}");
+                        v.modal_state = ModalState::RecoverTempFile { error: Some(s) };
+                    }
+                }
+            }
+
+            (_, ModalState::None, Action::StartRestoreBackup) => {
+                v.backups = VBackups {
+                    generations: settings.backup_generations,
+                    ..VBackups::default()
+                };
+                match list_backups(settings.current_file()) {
+                    Ok(backups) => v.backups.backups = backups,
+                    Err(e) => v.backups.error = Some(e.to_string()),
+                }
+                v.modal_state = ModalState::RestoreBackup;
+            }
+            (_, ModalState::RestoreBackup, Action::SetBackupGenerations(generations)) => {
+                pl_file.set_backup_generations(generations);
+                if let Err(e) = settings.set_backup_generations(generations) {
+                    v.backups.error = Some(e.to_string());
+                }
+            }
+            (_, ModalState::RestoreBackup, Action::FinalizeRestoreBackup(idx)) => {
+                let backup_path = v.backups.backups[idx].path.clone();
+                match pl_file.save_with_restored_backup(&backup_path) {
                     Ok(()) => {
+                        log::info!("{} {}", t!("_restored_backup"), backup_path.display());
+                        v.reset_bundles(pl_file.bundles(), None);
+                        v.reset_documents(pl_file.documents(), None);
                         v.modal_state.close_modal();
                     }
                     Err(e) => {
                         let s = e.to_string();
                         log::error!("{s}");
-                        v.pw.error = Some(s);
+                        v.backups.error = Some(s);
+                    }
+                }
+            }
+
+            (_, ModalState::None, Action::StartImport(source)) => {
+                v.import = VImport::new(source);
+                v.modal_state = ModalState::Import;
+            }
+            (_, ModalState::Import, Action::ReadImportFile(path)) => {
+                v.import = VImport {
+                    path: path.display().to_string(),
+                    ..VImport::new(v.import.source)
+                };
+                if let Err(e) = read_import_file(&path, pl_file, &mut v.import) {
+                    v.import.error = Some(format!("{e:#}"));
+                }
+            }
+            (_, ModalState::Import, Action::UnlockImportFile) => {
+                let path = PathBuf::from(&v.import.path);
+                let password = std::mem::take(&mut v.import.password);
+                let o_key_file = key_file_path(&v.import.key_file);
+                let decrypted = Arc::clone(&v.import.decrypted);
+                // the PlFile is not touched, the result is handed over in v.import.decrypted
+                self.start_job(Job::ReadImport, pl_file, move |_pl_file| {
+                    let import = keepass::read_kdbx(&path, &password, o_key_file.as_deref())?;
+                    if let Ok(mut o_import) = decrypted.lock() {
+                        *o_import = Some(import);
+                    }
+                    Ok(())
+                });
+            }
+            (_, ModalState::Import, Action::JobDone(Job::ReadImport, JobResult(result))) => {
+                match result {
+                    Ok(_unchanged_pl_file) => {
+                        let o_import = v.import.decrypted.lock().ok().and_then(|mut o| o.take());
+                        if let Some(import) = o_import {
+                            v.import.import = import;
+                            v.import.needs_password = false;
+                            v.import.error = None;
+                            v.import.update_preview(pl_file);
+                        }
+                    }
+                    Err(e) => {
+                        let s = e.to_string();
+                        log::error!("{s}");
+                        v.import.error = Some(s);
+                    }
+                }
+            }
+            (_, ModalState::Import, Action::UpdateImportPreview) => {
+                v.import.update_preview(pl_file);
+            }
+            (_, ModalState::Import, Action::FinalizeImport) => {
+                let import = v.import.import.clone();
+                let on_conflict = v.import.on_conflict;
+                self.start_job(Job::Import, pl_file, move |pl_file| {
+                    pl_file
+                        .save_with_import(&import, on_conflict)
+                        .map(|_plan| ())
+                });
+            }
+            (_, ModalState::Import, Action::JobDone(Job::Import, JobResult(result))) => {
+                match result {
+                    Ok(saved_pl_file) => {
+                        *pl_file = *saved_pl_file;
+                        log::info!(
+                            "{} {}: {}",
+                            t!("_imported"),
+                            v.import.path,
+                            v.import.plan.summary()
+                        );
+                        // the imported secrets are not kept
+                        v.import = VImport::default();
+                        v.modal_state.close_modal();
+                        v.reset_bundles(pl_file.bundles(), None);
+                        v.reset_documents(pl_file.documents(), None);
+                    }
+                    Err(e) => {
+                        let s = e.to_string();
+                        log::error!("{s}");
+                        v.import.error = Some(s);
+                    }
+                }
+            }
+            (_, ModalState::Import, Action::CloseModal) => {
+                v.import = VImport::default();
+                v.modal_state.close_modal();
+            }
+
+            (_, ModalState::None, Action::StartExportJson) => {
+                v.export = VExport::default();
+                v.modal_state = ModalState::ExportJson;
+            }
+            (_, ModalState::ExportJson, Action::FinalizeExportJson) => {
+                let path = PathBuf::from(&v.export.path);
+                let password = std::mem::take(&mut v.export.password);
+                // the PlFile is not touched
+                self.start_job(Job::Export, pl_file, move |pl_file| {
+                    pl_file.check_password(&password, None)?;
+                    let export = Export::new(pl_file)?;
+                    write_private_file(&path, &serde_json::to_string_pretty(&export)?)
+                });
+            }
+            (_, ModalState::ExportJson, Action::JobDone(Job::Export, JobResult(result))) => {
+                match result {
+                    Ok(_unchanged_pl_file) => {
+                        log::info!("{} {}", t!("_exported_to"), v.export.path);
+                        v.export = VExport::default();
+                        v.modal_state.close_modal();
+                    }
+                    Err(e) => {
+                        let s = e.to_string();
+                        log::error!("{s}");
+                        v.export.error = Some(s);
+                    }
+                }
+            }
+            (_, ModalState::ExportJson, Action::CloseModal) => {
+                v.export = VExport::default();
+                v.modal_state.close_modal();
+            }
+
+            (_, ModalState::None, Action::StartExportVault) => {
+                v.export_vault = VExportVault::default();
+                v.modal_state = ModalState::ExportVault;
+            }
+            (_, ModalState::ExportVault, Action::FinalizeExportVault) => {
+                let v_export_vault = &v.export_vault;
+                let bundle_keys: Vec<Key> = v_export_vault.bundles.iter().cloned().collect();
+                let document_keys: Vec<Key> = v_export_vault.documents.iter().cloned().collect();
+                let path = PathBuf::from(&v_export_vault.path);
+                let password = v_export_vault.pw.pw1.clone();
+                let o_key_file = key_file_path(&v_export_vault.pw.key_file);
+                let recovery_code = Arc::clone(&v_export_vault.recovery_code);
+                // the PlFile is not touched, the recovery code of the new file is handed over
+                self.start_job(Job::ExportVault, pl_file, move |pl_file| {
+                    let mut new_file = pl_file.export_subset(
+                        &bundle_keys,
+                        &document_keys,
+                        &path,
+                        &password,
+                        o_key_file.as_deref(),
+                    )?;
+                    if let Ok(mut o_recovery_code) = recovery_code.lock() {
+                        *o_recovery_code = new_file.take_recovery_code();
+                    }
+                    Ok(())
+                });
+            }
+            (_, ModalState::ExportVault, Action::JobDone(Job::ExportVault, JobResult(result))) => {
+                match result {
+                    Ok(_unchanged_pl_file) => {
+                        let v_export_vault = std::mem::take(&mut v.export_vault);
+                        log::info!(
+                            "{}",
+                            t!(
+                                "_exported_vault %{e} %{d} %{f}",
+                                e = v_export_vault.bundles.len(),
+                                d = v_export_vault.documents.len(),
+                                f = v_export_vault.path
+                            )
+                        );
+                        if v_export_vault.register {
+                            match settings.add_file(Path::new(&v_export_vault.path)) {
+                                Ok(()) => log::info!("{}", t!("_exported_vault_registered")),
+                                Err(e) => log::error!("{e}"),
+                            }
+                        }
+                        let o_recovery_code = v_export_vault
+                            .recovery_code
+                            .lock()
+                            .ok()
+                            .and_then(|mut o| o.take());
+                        if let Some(recovery_code) = o_recovery_code {
+                            v.modal_state = ModalState::ShowRecoveryCode {
+                                recovery_code,
+                                error: None,
+                            };
+                        } else {
+                            v.modal_state.close_modal();
+                        }
+                    }
+                    Err(e) => {
+                        let s = e.to_string();
+                        log::error!("{s}");
+                        v.export_vault.pw.error = Some(s);
+                    }
+                }
+            }
+            (_, ModalState::ExportVault, Action::CloseModal) => {
+                v.export_vault = VExportVault::default();
+                v.modal_state.close_modal();
+            }
+
+            (_, ModalState::None, Action::StartExportPackage) => {
+                v.export_package = VExportPackage::default();
+                v.modal_state = ModalState::ExportPackage;
+            }
+            (_, ModalState::ExportPackage, Action::FinalizeExportPackage) => {
+                let v_export_package = &mut v.export_package;
+                match parse_recipients(&v_export_package.recipients) {
+                    Ok(recipients) => {
+                        let bundle_keys: Vec<Key> =
+                            v_export_package.bundles.iter().cloned().collect();
+                        let document_keys: Vec<Key> =
+                            v_export_package.documents.iter().cloned().collect();
+                        let path = PathBuf::from(&v_export_package.path);
+                        // the PlFile is not touched
+                        self.start_job(Job::ExportPackage, pl_file, move |pl_file| {
+                            let package =
+                                seal_package(pl_file, &bundle_keys, &document_keys, &recipients)?;
+                            std::fs::write(&path, package)
+                                .context(format!("writing {}", path.display()))
+                        });
+                    }
+                    Err(e) => v_export_package.error = Some(e.to_string()),
+                }
+            }
+            (
+                _,
+                ModalState::ExportPackage,
+                Action::JobDone(Job::ExportPackage, JobResult(result)),
+            ) => match result {
+                Ok(_unchanged_pl_file) => {
+                    let v_export_package = std::mem::take(&mut v.export_package);
+                    log::info!(
+                        "{}",
+                        t!(
+                            "_exported_package %{e} %{d} %{f} %{r}",
+                            e = v_export_package.bundles.len(),
+                            d = v_export_package.documents.len(),
+                            f = v_export_package.path,
+                            r = parse_recipients(&v_export_package.recipients)
+                                .map(|recipients| recipients.len())
+                                .unwrap_or_default()
+                        )
+                    );
+                    v.modal_state.close_modal();
+                }
+                Err(e) => {
+                    let s = e.to_string();
+                    log::error!("{s}");
+                    v.export_package.error = Some(s);
+                }
+            },
+            (_, ModalState::ExportPackage, Action::CloseModal) => {
+                v.export_package = VExportPackage::default();
+                v.modal_state.close_modal();
+            }
+
+            (_, ModalState::None, Action::StartShowIdentity) => {
+                v.modal_state = ModalState::ShowIdentity { error: None };
+            }
+            (_, ModalState::ShowIdentity { .. }, Action::GenerateIdentity) => {
+                self.start_job(Job::GenerateIdentity, pl_file, |pl_file| {
+                    pl_file.save_with_new_identity()
+                });
+            }
+            (
+                _,
+                ModalState::ShowIdentity { error },
+                Action::JobDone(Job::GenerateIdentity, JobResult(result)),
+            ) => match result {
+                Ok(saved_pl_file) => {
+                    *pl_file = *saved_pl_file;
+                    *error = None;
+                }
+                Err(e) => {
+                    let s = e.to_string();
+                    log::error!("{s}");
+                    *error = Some(s);
+                }
+            },
+
+            (_, ModalState::None, Action::StartMergeFile) => {
+                v.merge_file = VMergeFile::default();
+                v.modal_state = ModalState::MergeFile;
+            }
+            (_, ModalState::MergeFile, Action::OpenMergeFile) => {
+                let path = PathBuf::from(&v.merge_file.path);
+                let password = std::mem::take(&mut v.merge_file.pw.pw1);
+                let o_key_file = key_file_path(&v.merge_file.pw.key_file);
+                let opened = Arc::clone(&v.merge_file.opened);
+                // the PlFile is not touched, the other file is handed over in v.merge_file.opened
+                self.start_job(Job::OpenMergeFile, pl_file, move |pl_file| {
+                    let theirs = pl_file.open_other(&path, &password, o_key_file.as_deref())?;
+                    if let Ok(mut o_theirs) = opened.lock() {
+                        *o_theirs = Some(theirs);
+                    }
+                    Ok(())
+                });
+            }
+            (_, ModalState::MergeFile, Action::JobDone(Job::OpenMergeFile, JobResult(result))) => {
+                match result {
+                    Ok(_unchanged_pl_file) => {
+                        let o_theirs = v.merge_file.opened.lock().ok().and_then(|mut o| o.take());
+                        if let Some(theirs) = o_theirs {
+                            let comparison = Comparison::new(pl_file, &theirs).unwrap_or_default();
+                            let (bundle_keys, document_keys) = comparison.proposal();
+                            v.merge_file.bundles = bundle_keys.into_iter().collect();
+                            v.merge_file.documents = document_keys.into_iter().collect();
+                            v.merge_file.comparison = comparison;
+                            v.merge_file.theirs = Some(theirs);
+                            v.merge_file.pw.error = None;
+                        }
+                    }
+                    Err(e) => {
+                        let s = e.to_string();
+                        log::error!("{s}");
+                        v.merge_file.pw.error = Some(s);
+                    }
+                }
+            }
+            (_, ModalState::MergeFile, Action::FinalizeMergeFile) => {
+                if let Some(theirs) = v.merge_file.theirs.clone() {
+                    let bundle_keys: Vec<Key> = v.merge_file.bundles.iter().cloned().collect();
+                    let document_keys: Vec<Key> = v.merge_file.documents.iter().cloned().collect();
+                    self.start_job(Job::MergeFile, pl_file, move |pl_file| {
+                        pl_file.save_with_merged_file(&theirs, &bundle_keys, &document_keys)
+                    });
+                }
+            }
+            (_, ModalState::MergeFile, Action::JobDone(Job::MergeFile, JobResult(result))) => {
+                match result {
+                    Ok(saved_pl_file) => {
+                        *pl_file = *saved_pl_file;
+                        log::info!(
+                            "{} ({})",
+                            t!(
+                                "_merge_summary %{e} %{d}",
+                                e = v.merge_file.bundles.len(),
+                                d = v.merge_file.documents.len()
+                            ),
+                            v.merge_file.path
+                        );
+                        // the other file with its secrets is not kept
+                        v.merge_file = VMergeFile::default();
+                        v.modal_state.close_modal();
+                        v.reset_bundles(pl_file.bundles(), None);
+                        v.reset_documents(pl_file.documents(), None);
+                    }
+                    Err(e) => {
+                        let s = e.to_string();
+                        log::error!("{s}");
+                        v.merge_file.pw.error = Some(s);
+                    }
+                }
+            }
+            (_, ModalState::MergeFile, Action::CloseModal) => {
+                v.merge_file = VMergeFile::default();
+                v.modal_state.close_modal();
+            }
+
+            (_, ModalState::None, Action::StartChangePassword) => {
+                v.pw = Pw::default();
+                if let Some(path) = pl_file.key_file_path() {
+                    v.pw.key_file = path.display().to_string();
+                }
+                v.modal_state = ModalState::ChangePassword;
+            }
+            (
+                _,
+                ModalState::ChangePassword,
+                Action::FinalizeChangePassword { old, new, key_file },
+            ) => {
+                let o_key_file = key_file_path(&key_file);
+                self.start_job(Job::ChangePassword, pl_file, move |pl_file| {
+                    pl_file.change_password(&old, &new, o_key_file.as_deref())
+                });
+            }
+            (
+                _,
+                ModalState::ChangePassword,
+                Action::JobDone(Job::ChangePassword, JobResult(result)),
+            ) => match result {
+                Ok(saved_pl_file) => {
+                    *pl_file = *saved_pl_file;
+                    v.modal_state.close_modal();
+                }
+                Err(e) => {
+                    let s = e.to_string();
+                    log::error!("{s}");
+                    v.pw.error = Some(s);
+                }
+            },
+
+            (_, ModalState::None, Action::StartManageKeySlots) => {
+                v.key_slots = VKeySlots::default();
+                v.modal_state = ModalState::ManageKeySlots;
+            }
+            (
+                _,
+                ModalState::ManageKeySlots,
+                Action::FinalizeAddKeySlot {
+                    label,
+                    pw,
+                    key_file,
+                },
+            ) => {
+                let o_key_file = key_file_path(&key_file);
+                self.start_job(Job::AddKeySlot, pl_file, move |pl_file| {
+                    pl_file.add_key_slot(&label, &pw, o_key_file.as_deref())
+                });
+            }
+            (_, ModalState::ManageKeySlots, Action::FinalizeRevokeKeySlot(label)) => {
+                self.start_job(Job::RevokeKeySlot, pl_file, move |pl_file| {
+                    pl_file.revoke_key_slot(&label)
+                });
+            }
+            (
+                _,
+                ModalState::ManageKeySlots,
+                Action::JobDone(job @ (Job::AddKeySlot | Job::RevokeKeySlot), JobResult(result)),
+            ) => match result {
+                Ok(saved_pl_file) => {
+                    *pl_file = *saved_pl_file;
+                    v.key_slots = VKeySlots::default();
+                    if matches!(job, Job::RevokeKeySlot) {
+                        reregister_with_agent(settings, pl_file);
                     }
                 }
+                Err(e) => {
+                    let s = e.to_string();
+                    log::error!("{s}");
+                    v.key_slots.pw.error = Some(s);
+                }
+            },
+
+            (_, ModalState::None, Action::RegenerateRecoveryCode) => {
+                self.start_job(Job::RegenerateRecoveryCode, pl_file, |pl_file| {
+                    pl_file.regenerate_recovery_code()
+                });
+            }
+            (
+                _,
+                ModalState::None,
+                Action::JobDone(Job::RegenerateRecoveryCode, JobResult(result)),
+            ) => match result {
+                Ok(saved_pl_file) => {
+                    *pl_file = *saved_pl_file;
+                    reregister_with_agent(settings, pl_file);
+                    if let Some(recovery_code) = pl_file.take_recovery_code() {
+                        v.modal_state = ModalState::ShowRecoveryCode {
+                            recovery_code,
+                            error: None,
+                        };
+                    }
+                }
+                Err(e) => log::error!("{e}"),
+            },
+
+            (_, ModalState::None, Action::SetConcealment(conceal)) => {
+                self.start_job(Job::SetConcealment, pl_file, move |pl_file| {
+                    pl_file.save_with_concealment(conceal)
+                });
+            }
+            (_, ModalState::None, Action::JobDone(Job::SetConcealment, JobResult(result))) => {
+                match result {
+                    Ok(saved_pl_file) => *pl_file = *saved_pl_file,
+                    Err(e) => log::error!("{e}"),
+                }
+            }
+
+            (_, ModalState::None, Action::RegisterWithAgent) => {
+                match agent::register(settings, pl_file) {
+                    Ok(()) => log::info!("{}", t!("_registered_with_agent")),
+                    Err(e) => log::error!("{e}"),
+                }
             }
 
             (
@@ -159,19 +790,35 @@ impl Controller {
                 ModalState::AddBundle {
                     v_edit_bundle,
                     generate_pw: false,
-                    error,
+                    ..
                 },
                 Action::FinalizeAddBundle,
-            ) => match pl_file.save_with_added_bundle(v_edit_bundle) {
-                Ok(()) => {
+            ) => {
+                let v_edit_bundle = v_edit_bundle.clone();
+                self.start_job(Job::AddBundle, pl_file, move |pl_file| {
+                    pl_file.save_with_added_bundle(&v_edit_bundle)
+                });
+            }
+            (
+                MainState::Bundles(BundleState::Default),
+                ModalState::AddBundle {
+                    v_edit_bundle,
+                    error,
+                    ..
+                },
+                Action::JobDone(Job::AddBundle, JobResult(result)),
+            ) => match result {
+                Ok(saved_pl_file) => {
+                    *pl_file = *saved_pl_file;
                     let key = v_edit_bundle.key.clone();
                     v.modal_state.close_modal();
                     v.reset_bundles(pl_file.bundles(), Some(&key));
                 }
                 Err(e) => {
-                    let s = e.to_string();
-                    log::error!("{s}");
-                    *error = Some(s);
+                    let mine = Version::Bundle(Some(v_edit_bundle.clone()));
+                    if let Some(modal_state) = on_save_error(e, mine, error) {
+                        v.modal_state = modal_state;
+                    }
                 }
             },
 
@@ -190,29 +837,37 @@ impl Controller {
                     error: None,
                 });
             }
+            (
+                MainState::Bundles(BundleState::ModifyBundle { v_edit_bundle, .. }),
+                ModalState::None,
+                Action::FinalizeModifyBundle,
+            ) => {
+                let v_edit_bundle = v_edit_bundle.clone();
+                self.start_job(Job::ModifyBundle, pl_file, move |pl_file| {
+                    pl_file.save_with_updated_bundle(&v_edit_bundle)
+                });
+            }
             (
                 MainState::Bundles(BundleState::ModifyBundle {
-                    v_edit_bundle: bundle,
+                    v_edit_bundle,
                     error,
                 }),
                 ModalState::None,
-                Action::FinalizeModifyBundle,
-            ) => {
-                match pl_file.save_with_updated_bundle(bundle) {
-                    Ok(()) => {
-                        v.modal_state.close_modal();
-                    }
-                    Err(e) => {
-                        let s = e.to_string();
-                        log::error!("{s}");
-                        *error = Some(s);
+                Action::JobDone(Job::ModifyBundle, JobResult(result)),
+            ) => match result {
+                Ok(saved_pl_file) => {
+                    *pl_file = *saved_pl_file;
+                    v.reset_bundles(pl_file.bundles(), None);
+                    v.main_state = MainState::Bundles(BundleState::Default);
+                    v.modal_state = ModalState::None;
+                }
+                Err(e) => {
+                    let mine = Version::Bundle(Some(v_edit_bundle.clone()));
+                    if let Some(modal_state) = on_save_error(e, mine, error) {
+                        v.modal_state = modal_state;
                     }
                 }
-
-                v.reset_bundles(pl_file.bundles(), None);
-                v.main_state = MainState::Bundles(BundleState::Default);
-                v.modal_state = ModalState::None;
-            }
+            },
 
             (
                 MainState::Bundles(BundleState::Default),
@@ -227,17 +882,28 @@ impl Controller {
 
             (
                 MainState::Bundles(BundleState::Default),
-                ModalState::DeleteBundle { key, error },
+                ModalState::DeleteBundle { key, .. },
                 Action::FinalizeDeleteBundle,
-            ) => match pl_file.save_with_deleted_bundle(key.clone()) {
-                Ok(()) => {
+            ) => {
+                let key = key.clone();
+                self.start_job(Job::DeleteBundle, pl_file, move |pl_file| {
+                    pl_file.save_with_deleted_bundle(key)
+                });
+            }
+            (
+                MainState::Bundles(BundleState::Default),
+                ModalState::DeleteBundle { error, .. },
+                Action::JobDone(Job::DeleteBundle, JobResult(result)),
+            ) => match result {
+                Ok(saved_pl_file) => {
+                    *pl_file = *saved_pl_file;
                     v.reset_bundles(pl_file.bundles(), None);
                     v.modal_state.close_modal();
                 }
                 Err(e) => {
-                    let s = e.to_string();
-                    log::error!("{s}");
-                    *error = Some(s);
+                    if let Some(modal_state) = on_save_error(e, Version::Bundle(None), error) {
+                        v.modal_state = modal_state;
+                    }
                 }
             },
 
@@ -252,24 +918,38 @@ impl Controller {
                 };
             }
 
+            (
+                MainState::Documents(DocumentState::Default(_)),
+                ModalState::AddDocument {
+                    v_edit_document, ..
+                },
+                Action::FinalizeAddDocument,
+            ) => {
+                let v_edit_document = v_edit_document.clone();
+                self.start_job(Job::AddDocument, pl_file, move |pl_file| {
+                    pl_file.save_with_added_document(&v_edit_document)
+                });
+            }
             (
                 MainState::Documents(DocumentState::Default(_)),
                 ModalState::AddDocument {
                     v_edit_document,
                     error,
                 },
-                Action::FinalizeAddDocument,
-            ) => match pl_file.save_with_added_document(v_edit_document) {
-                Ok(()) => {
+                Action::JobDone(Job::AddDocument, JobResult(result)),
+            ) => match result {
+                Ok(saved_pl_file) => {
+                    *pl_file = *saved_pl_file;
                     let key = v_edit_document.key.clone();
                     v.modal_state.close_modal();
                     v.reset_documents(pl_file.documents(), Some(&key));
                     v.main_state = MainState::Documents(DocumentState::Default(Some(key)));
                 }
                 Err(e) => {
-                    let s = e.to_string();
-                    log::error!("{s}");
-                    *error = Some(s);
+                    let mine = Version::Document(Some(v_edit_document.clone()));
+                    if let Some(modal_state) = on_save_error(e, mine, error) {
+                        v.modal_state = modal_state;
+                    }
                 }
             },
 
@@ -290,27 +970,38 @@ impl Controller {
 
             (
                 MainState::Documents(DocumentState::ModifyDocument {
-                    v_edit_document,
-                    error,
+                    v_edit_document, ..
                 }),
                 ModalState::None,
                 Action::FinalizeModifyDocument,
             ) => {
-                match pl_file.save_with_updated_document(v_edit_document) {
-                    Ok(()) => {
-                        v.modal_state.close_modal();
-                    }
-                    Err(e) => {
-                        let s = e.to_string();
-                        log::error!("{s}");
-                        *error = Some(s);
+                let v_edit_document = v_edit_document.clone();
+                self.start_job(Job::ModifyDocument, pl_file, move |pl_file| {
+                    pl_file.save_with_updated_document(&v_edit_document)
+                });
+            }
+            (
+                MainState::Documents(DocumentState::ModifyDocument {
+                    v_edit_document,
+                    error,
+                }),
+                ModalState::None,
+                Action::JobDone(Job::ModifyDocument, JobResult(result)),
+            ) => match result {
+                Ok(saved_pl_file) => {
+                    *pl_file = *saved_pl_file;
+                    v.main_state = MainState::Documents(DocumentState::Default(Some(
+                        v_edit_document.key.clone(),
+                    )));
+                    v.reset_documents(pl_file.documents(), None);
+                }
+                Err(e) => {
+                    let mine = Version::Document(Some(v_edit_document.clone()));
+                    if let Some(modal_state) = on_save_error(e, mine, error) {
+                        v.modal_state = modal_state;
                     }
                 }
-
-                v.main_state =
-                    MainState::Documents(DocumentState::Default(Some(v_edit_document.key.clone())));
-                v.reset_documents(pl_file.documents(), None);
-            }
+            },
 
             (
                 MainState::Documents(DocumentState::Default(_)),
@@ -321,13 +1012,52 @@ impl Controller {
             }
             (
                 MainState::Documents(DocumentState::Default(_)),
-                ModalState::DeleteDocument { key, error },
+                ModalState::DeleteDocument { key, .. },
                 Action::FinalizeDeleteDocument,
-            ) => match pl_file.save_with_deleted_document(key) {
-                Ok(()) => {
+            ) => {
+                let key = key.clone();
+                self.start_job(Job::DeleteDocument, pl_file, move |pl_file| {
+                    pl_file.save_with_deleted_document(&key)
+                });
+            }
+            (
+                MainState::Documents(DocumentState::Default(_)),
+                ModalState::DeleteDocument { error, .. },
+                Action::JobDone(Job::DeleteDocument, JobResult(result)),
+            ) => match result {
+                Ok(saved_pl_file) => {
+                    *pl_file = *saved_pl_file;
                     v.reset_documents(pl_file.documents(), None);
                     v.modal_state.close_modal();
                 }
+                Err(e) => {
+                    if let Some(modal_state) = on_save_error(e, Version::Document(None), error) {
+                        v.modal_state = modal_state;
+                    }
+                }
+            },
+
+            (_, ModalState::ResolveConflict { conflict, mine, .. }, Action::FinalizeKeepMine) => {
+                let key = conflict.key.clone();
+                let mine = mine.clone();
+                self.start_job(Job::ResolveConflict, pl_file, move |pl_file| {
+                    pl_file.save_with_my_version(&key, &mine)
+                });
+            }
+            (_, ModalState::ResolveConflict { .. }, Action::FinalizeTakeTheirs) => {
+                self.start_job(Job::ResolveConflict, pl_file, PlFile::reload);
+            }
+            (
+                _,
+                ModalState::ResolveConflict { error, .. },
+                Action::JobDone(Job::ResolveConflict, JobResult(result)),
+            ) => match result {
+                Ok(resolved_pl_file) => {
+                    *pl_file = *resolved_pl_file;
+                    v.reset_bundles(pl_file.bundles(), None);
+                    v.reset_documents(pl_file.documents(), None);
+                    v.stop_editing(pl_file.documents());
+                }
                 Err(e) => {
                     let s = e.to_string();
                     log::error!("{s}");
@@ -385,6 +1115,69 @@ impl Controller {
                 }
             }
 
+            (
+                MainState::Bundles(BundleState::ModifyBundle {
+                    v_edit_bundle,
+                    error,
+                }),
+                ModalState::None,
+                Action::ReadTotpQrImage(cred_idx, path),
+            )
+            | (
+                MainState::Bundles(BundleState::Default),
+                ModalState::AddBundle {
+                    v_edit_bundle,
+                    error,
+                    ..
+                },
+                Action::ReadTotpQrImage(cred_idx, path),
+            ) => {
+                *error = v_edit_bundle
+                    .read_totp_qr_image(cred_idx, &path)
+                    .err()
+                    .map(|e| e.to_string());
+            }
+
+            (_, _, Action::CheckForExternalChange) => match pl_file.is_outdated() {
+                Ok(false) => v.file_changed_externally = false,
+                Ok(true) if !pl_file.is_actionable() => {
+                    // nothing is decrypted yet, so the file can simply be read again
+                    match PlFile::read_or_create(settings.current_file()) {
+                        Ok(new_pl_file) => {
+                            *pl_file = new_pl_file;
+                            pl_file.set_backup_generations(settings.backup_generations);
+                        }
+                        Err(e) => log::error!("{e}"),
+                    }
+                }
+                Ok(true) if !v.has_pending_edits() => {
+                    self.start_job(Job::Reload, pl_file, PlFile::reload);
+                }
+                Ok(true) => {
+                    if !v.file_changed_externally {
+                        log::warn!("{}", t!("_file_changed_externally"));
+                    }
+                    v.file_changed_externally = true;
+                }
+                Err(e) => log::warn!("{e}"),
+            },
+            (_, _, Action::ReloadFile) => {
+                self.start_job(Job::Reload, pl_file, PlFile::reload);
+            }
+            (_, _, Action::JobDone(Job::Reload, JobResult(result))) => {
+                v.file_changed_externally = false;
+                match result {
+                    Ok(reloaded_pl_file) => {
+                        *pl_file = *reloaded_pl_file;
+                        v.stop_editing(pl_file.documents());
+                        v.reset_bundles(pl_file.bundles(), None);
+                        v.reset_documents(pl_file.documents(), None);
+                        log::info!("{} {}", t!("_file_reloaded"), pl_file.file_path());
+                    }
+                    Err(e) => log::error!("{e}"),
+                }
+            }
+
             (_, _, Action::CloseModal) => {
                 v.modal_state.close_modal();
             }
@@ -405,6 +1198,18 @@ impl Controller {
                 };
             }
 
+            (main_state, modal_state, Action::JobDone(job, JobResult(result))) => {
+                // should not happen, since the state cannot change while a job is running
+                log::warn!(
+                    "Unexpected completion of {job:?} in {main_state:?}, {}",
+                    modal_state.get_id()
+                );
+                match result {
+                    Ok(saved_pl_file) => *pl_file = *saved_pl_file,
+                    Err(e) => log::error!("{e}"),
+                }
+            }
+
             (main_state, modal_state, action) => {
                 if !matches!(modal_state, ModalState::None) && !matches!(action, Action::None) {
                     log::warn!(
@@ -414,17 +1219,90 @@ impl Controller {
                 }
             }
         }
+
+        // let the UI show the progress
+        v.running_job = self
+            .o_running_job
+            .as_ref()
+            .map(|running_job| running_job.job);
     }
 }
 
 fn switch_to_current_file(pl_file: &mut PlFile, v: &mut V, settings: &mut Settings) -> Result<()> {
     *pl_file = PlFile::read_or_create(settings.current_file()).context("File open error")?;
+    pl_file.set_backup_generations(settings.backup_generations);
     log::info!("{} {}", t!("Switch to file"), pl_file.file_path());
     *v = V::default();
     v.file_selection.reset(settings.current_file);
     Ok(())
 }
 
+// After the data key was replaced, the agent cannot read the file anymore with its old session
+fn reregister_with_agent(settings: &Settings, pl_file: &mut PlFile) {
+    if let Err(e) = agent::reregister(settings, pl_file) {
+        log::error!("{}", t!("_agent_not_updated %{e}", e = format!("{e:#}")));
+    }
+}
+
+// If saving failed because a concurrent update changed the same entry or document,
+// the user has to resolve the conflict; other errors are shown where they occurred
+fn on_save_error(
+    e: anyhow::Error,
+    mine: Version,
+    error: &mut Option<String>,
+) -> Option<ModalState> {
+    match e.downcast::<MergeConflict>() {
+        Ok(conflict) => {
+            log::warn!("{conflict}");
+            Some(ModalState::ResolveConflict {
+                conflict,
+                mine,
+                show_secrets: false,
+                error: None,
+            })
+        }
+        Err(e) => {
+            let s = e.to_string();
+            log::error!("{s}");
+            *error = Some(s);
+            None
+        }
+    }
+}
+
+// A CSV file or a KeePass XML export is read right away,
+// a KeePass file only after its password was entered
+fn read_import_file(path: &Path, pl_file: &PlFile, v_import: &mut VImport) -> Result<()> {
+    match v_import.source {
+        ImportSource::Csv => {
+            let table = CsvTable::read(path)?;
+            v_import.mapping = CsvMapping::guess(&table.headers);
+            v_import.table = table;
+        }
+        ImportSource::KeePass => {
+            if keepass::is_encrypted(path)? {
+                v_import.needs_password = true;
+                return Ok(());
+            }
+            v_import.import = keepass::read_xml(path)?;
+        }
+        ImportSource::Json => v_import.import = json::read(path)?,
+        ImportSource::Package => v_import.import = open_package(pl_file, &std::fs::read(path)?)?,
+    }
+    v_import.update_preview(pl_file);
+    Ok(())
+}
+
+// an empty entry means that no key file is used
+fn key_file_path(key_file: &str) -> Option<PathBuf> {
+    let key_file = key_file.trim();
+    if key_file.is_empty() {
+        None
+    } else {
+        Some(PathBuf::from(key_file))
+    }
+}
+
 #[derive(Default, Debug)]
 pub(crate) enum Action {
     #[default]
@@ -437,14 +1315,58 @@ pub(crate) enum Action {
     SwitchToKnownFile(usize),
     SwitchToNewFile(String),
 
+    FinalizeRecoverTempFile,
+    FinalizeDiscardTempFile,
+
+    StartRestoreBackup,
+    SetBackupGenerations(usize),
+    FinalizeRestoreBackup(usize),
+
+    StartImport(ImportSource),
+    ReadImportFile(PathBuf),
+    UnlockImportFile,
+    UpdateImportPreview,
+    FinalizeImport,
+
+    StartExportJson,
+    FinalizeExportJson,
+
+    StartExportVault,
+    FinalizeExportVault,
+
+    StartExportPackage,
+    FinalizeExportPackage,
+
+    StartShowIdentity,
+    GenerateIdentity,
+
+    StartMergeFile,
+    OpenMergeFile,
+    FinalizeMergeFile,
+
     StartChangePassword,
     FinalizeChangePassword {
         old: String,
         new: String,
+        key_file: String,
     },
 
     SwitchToActionable,
 
+    StartManageKeySlots,
+    FinalizeAddKeySlot {
+        label: String,
+        pw: String,
+        key_file: String,
+    },
+    FinalizeRevokeKeySlot(String),
+
+    RegenerateRecoveryCode,
+
+    SetConcealment(bool),
+
+    RegisterWithAgent,
+
     StartFilter,
 
     StartChangeLanguage,
@@ -471,20 +1393,99 @@ pub(crate) enum Action {
     StartGeneratePassword(usize),
     FinalizeGeneratePassword,
 
+    ReadTotpQrImage(usize, PathBuf),
+
+    FinalizeKeepMine,
+    FinalizeTakeTheirs,
+
+    CheckForExternalChange,
+    ReloadFile,
+
+    JobDone(Job, JobResult),
+
     Cancel,
     CloseModal,
 }
+
+// Actions that are executed in a background thread
+#[derive(Clone, Copy, Debug)]
+pub enum Job {
+    Unlock,
+    ChangePassword,
+    AddKeySlot,
+    RevokeKeySlot,
+    RegenerateRecoveryCode,
+    SetConcealment,
+    AddBundle,
+    ModifyBundle,
+    DeleteBundle,
+    AddDocument,
+    ModifyDocument,
+    DeleteDocument,
+    ResolveConflict,
+    ReadImport,
+    Import,
+    Export,
+    ExportVault,
+    ExportPackage,
+    GenerateIdentity,
+    OpenMergeFile,
+    MergeFile,
+    Reload,
+}
+
+// The outcome of a job; on success, the PlFile replaces the one used so far
+pub(crate) struct JobResult(Result<Box<PlFile>>);
+impl std::fmt::Debug for JobResult {
+    // do not expose the PlFile with its decrypted secrets
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.0 {
+            Ok(_) => write!(f, "Ok"),
+            Err(e) => write!(f, "Err({e})"),
+        }
+    }
+}
 impl Action {
     fn log(&self, main_state: &MainState, modal_state: &ModalState) {
         match self {
-            Action::None | Action::StartFilter | Action::ShowLog | Action::CloseModal => {}
+            Action::None
+            | Action::StartFilter
+            | Action::ShowLog
+            | Action::CloseModal
+            | Action::UpdateImportPreview
+            | Action::CheckForExternalChange => {}
 
             Action::ShowAbout
             | Action::StartChangeFile
             | Action::SwitchToKnownFile(_)
             | Action::SwitchToNewFile(_)
             | Action::StartChangePassword
+            | Action::FinalizeRecoverTempFile
+            | Action::FinalizeDiscardTempFile
+            | Action::StartRestoreBackup
+            | Action::SetBackupGenerations(_)
+            | Action::FinalizeRestoreBackup(_)
+            | Action::StartImport(_)
+            | Action::ReadImportFile(_)
+            | Action::UnlockImportFile
+            | Action::FinalizeImport
+            | Action::StartExportJson
+            | Action::FinalizeExportJson
+            | Action::StartExportVault
+            | Action::FinalizeExportVault
+            | Action::StartExportPackage
+            | Action::FinalizeExportPackage
+            | Action::StartShowIdentity
+            | Action::GenerateIdentity
+            | Action::StartMergeFile
+            | Action::OpenMergeFile
+            | Action::FinalizeMergeFile
             | Action::SwitchToActionable
+            | Action::SetConcealment(_)
+            | Action::RegisterWithAgent
+            | Action::StartManageKeySlots
+            | Action::FinalizeRevokeKeySlot(_)
+            | Action::RegenerateRecoveryCode
             | Action::StartChangeLanguage
             | Action::FinalizeChangeLanguage
             | Action::StartAddBundle
@@ -501,10 +1502,22 @@ impl Action {
             | Action::FinalizeDeleteDocument
             | Action::StartGeneratePassword(_)
             | Action::FinalizeGeneratePassword
+            | Action::ReadTotpQrImage(..)
+            | Action::FinalizeKeepMine
+            | Action::FinalizeTakeTheirs
+            | Action::ReloadFile
+            | Action::JobDone(..)
             | Action::Cancel
             | Action::FinalizeChangePassword { .. } => {
                 log::info!("[Action::{self:?}] [{main_state:?}] [{modal_state:?}]");
             }
+            // do not log the password
+            Action::FinalizeAddKeySlot { label, .. } => {
+                log::info!(
+                    "[Action::FinalizeAddKeySlot {{ label: {label:?}, .. }}] \
+                     [{main_state:?}] [{modal_state:?}]"
+                );
+            }
         }
     }
 }
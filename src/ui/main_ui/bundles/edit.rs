@@ -89,6 +89,7 @@ fn single_cred(
         .size(Size::exact(210.))
         .size(Size::exact(158.))
         .size(Size::exact(10.))
+        .size(Size::exact(20.))
         .horizontal(|mut cred_strip| {
             cred_strip.cell(|ui| {
                 ui.add(
@@ -128,5 +129,21 @@ fn single_cred(
                     controller.set_action(Action::StartGeneratePassword(cred_idx));
                 }
             });
+            cred_strip.cell(|ui| {
+                if ui
+                    .small_button("📷")
+                    .on_hover_ui(|ui| {
+                        ui.label(t!("_totp_load_qr"));
+                    })
+                    .clicked()
+                {
+                    if let Some(path) = rfd::FileDialog::new()
+                        .add_filter("PNG", &["png"])
+                        .pick_file()
+                    {
+                        controller.set_action(Action::ReadTotpQrImage(cred_idx, path));
+                    }
+                }
+            });
         });
 }
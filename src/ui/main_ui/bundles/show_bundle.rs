@@ -1,5 +1,5 @@
 use crate::{
-    data::{Bundle, Cred, Key, Transient},
+    data::{Bundle, Cred, Key, Totp, Transient},
     ui::{
         colors::{COLOR_SECRET, COLOR_USER},
         viz::{VBundle, VCred},
@@ -11,6 +11,7 @@ use egui::{
 };
 use egui_extras::{Size, Strip, StripBuilder};
 use jiff::Zoned;
+use std::time::{Duration, Instant};
 
 pub fn show_bundle(
     ctx: &Context,
@@ -135,6 +136,11 @@ pub fn show_cred(
                 if first {
                     set_faded_bg_color(ui, 95., alternate, false);
                 }
+                let secret = cred.secret(transient);
+                if let Some(Ok(totp)) = Totp::is_totp(secret).then(|| Totp::from_uri(secret)) {
+                    show_totp_code(&totp, v_cred, ui, ctx);
+                    return;
+                }
                 let response = ui
                     .add(
                         TextEdit::singleline(&mut cred.secret(transient))
@@ -145,30 +151,61 @@ pub fn show_cred(
                             .interactive(true),
                     )
                     .on_hover_ui(|ui| {
-                        ui.style_mut().interaction.selectable_labels = true;
-                        match v_cred.copied_at {
-                            None => {
-                                if ui
-                                    .add(Button::new(t!("_copy")).min_size([60., 10.].into()))
-                                    .clicked()
-                                {
-                                    ctx.copy_text(cred.secret(transient).to_string());
-                                    v_cred.copied_at = Some(std::time::Instant::now());
-                                }
-                            }
-                            Some(instant) => {
-                                ui.label(t!("_copied"));
-                                if instant.elapsed() > std::time::Duration::from_millis(800) {
-                                    v_cred.copied_at = None;
-                                }
-                            }
-                        }
+                        copy_button(secret, v_cred, ui, ctx);
                     });
                 v_cred.show_secret = response.hovered();
             });
         });
 }
 
+// The current one-time code, with the seconds until it changes
+fn show_totp_code(totp: &Totp, v_cred: &mut VCred, ui: &mut Ui, ctx: &Context) {
+    let (code, remaining) = totp.current_code();
+    ui.horizontal(|ui| {
+        ui.add(
+            TextEdit::singleline(&mut code.as_str())
+                .desired_width(80.)
+                .font(TextStyle::Monospace)
+                .text_color(COLOR_SECRET)
+                .interactive(true),
+        )
+        .on_hover_ui(|ui| {
+            copy_button(&code, v_cred, ui, ctx);
+        });
+        ui.label(
+            RichText::new(format!("⏱ {remaining} s"))
+                .color(if remaining <= 5 {
+                    Color32::DARK_RED
+                } else {
+                    Color32::GRAY
+                })
+                .small(),
+        );
+    });
+    ctx.request_repaint_after(Duration::from_secs(1));
+}
+
+fn copy_button(text: &str, v_cred: &mut VCred, ui: &mut Ui, ctx: &Context) {
+    ui.style_mut().interaction.selectable_labels = true;
+    match v_cred.copied_at {
+        None => {
+            if ui
+                .add(Button::new(t!("_copy")).min_size([60., 10.].into()))
+                .clicked()
+            {
+                ctx.copy_text(text.to_string());
+                v_cred.copied_at = Some(Instant::now());
+            }
+        }
+        Some(instant) => {
+            ui.label(t!("_copied"));
+            if instant.elapsed() > Duration::from_millis(800) {
+                v_cred.copied_at = None;
+            }
+        }
+    }
+}
+
 fn set_faded_bg_color(ui: &mut Ui, height: f32, color_switch: bool, left: bool) {
     let bg_color = ui.visuals().window_fill();
     let t = if color_switch { 0.91 } else { 0.8 };
@@ -7,8 +7,20 @@ mod create_bundle;
 mod create_document;
 mod delete_bundle;
 mod delete_document;
+mod export_json;
+mod export_package;
+mod export_vault;
+mod import;
+mod manage_key_slots;
+mod merge_file;
+mod recover_temp_file;
+mod resolve_conflict;
+mod restore_backup;
 mod show_about;
+mod show_identity;
 mod show_log;
+mod show_progress;
+mod show_recovery_code;
 
 pub use ask_for_password_to_open::ask_for_password_to_open;
 pub use change_file::change_file;
@@ -19,5 +31,17 @@ pub use create_bundle::create_bundle;
 pub use create_document::create_document;
 pub use delete_bundle::delete_bundle;
 pub use delete_document::delete_document;
+pub use export_json::export_json;
+pub use export_package::export_package;
+pub use export_vault::export_vault;
+pub use import::import;
+pub use manage_key_slots::manage_key_slots;
+pub use merge_file::merge_file;
+pub use recover_temp_file::recover_temp_file;
+pub use resolve_conflict::resolve_conflict;
+pub use restore_backup::restore_backup;
 pub use show_about::show_about;
+pub use show_identity::show_identity;
 pub use show_log::show_log;
+pub use show_progress::show_progress;
+pub use show_recovery_code::show_recovery_code;
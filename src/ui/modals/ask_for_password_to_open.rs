@@ -1,12 +1,13 @@
 use crate::ui::{
     controller::{Action, Controller},
-    show_error,
+    key_file_row, show_error,
     viz::{PwFocus, V},
 };
 use egui::{CentralPanel, Color32, Context, Grid, Key, RichText, TextEdit};
 
 pub fn ask_for_password_to_open(
     is_first_start: bool,
+    requires_key_file: bool,
     v: &mut V,
     controller: &mut Controller,
     ctx: &Context,
@@ -15,7 +16,7 @@ pub fn ask_for_password_to_open(
         if is_first_start {
             ask_twice(v, controller, ui);
         } else {
-            ask_once(v, controller, ui);
+            ask_once(requires_key_file, v, controller, ui);
         }
 
         if let Some(e) = &v.pw.error {
@@ -24,7 +25,7 @@ pub fn ask_for_password_to_open(
     });
 }
 
-fn ask_once(v: &mut V, controller: &mut Controller, ui: &mut egui::Ui) {
+fn ask_once(requires_key_file: bool, v: &mut V, controller: &mut Controller, ui: &mut egui::Ui) {
     ui.add_space(15.);
     ui.label(
         RichText::new(t!("_opening_the_prolock_file"))
@@ -38,12 +39,17 @@ fn ask_once(v: &mut V, controller: &mut Controller, ui: &mut egui::Ui) {
     ui.horizontal(|ui| {
         ui.add_space(50.);
         Grid::new("Password once").num_columns(2).show(ui, |ui| {
-            ui.label(t!("Password:"));
-            let response = ui.add(
-                TextEdit::singleline(&mut v.pw.pw1)
-                    .desired_width(120.)
-                    .password(true),
-            );
+            let response = if v.pw.with_recovery_code {
+                ui.label(t!("_recovery_code_label"));
+                ui.add(TextEdit::singleline(&mut v.pw.pw1).desired_width(320.))
+            } else {
+                ui.label(t!("Password:"));
+                ui.add(
+                    TextEdit::singleline(&mut v.pw.pw1)
+                        .desired_width(120.)
+                        .password(true),
+                )
+            };
 
             let mut go_forward = false;
             if matches!(v.pw.focus, PwFocus::Pw1) {
@@ -61,6 +67,21 @@ fn ask_once(v: &mut V, controller: &mut Controller, ui: &mut egui::Ui) {
                 controller.set_action(Action::SwitchToActionable);
             }
             ui.end_row();
+
+            // the key file row is only shown if it is needed or was given on the command line
+            if !v.pw.with_recovery_code && (requires_key_file || !v.pw.key_file.is_empty()) {
+                key_file_row(&mut v.pw.key_file, ui);
+            }
+
+            ui.label("");
+            if ui
+                .checkbox(&mut v.pw.with_recovery_code, t!("_open_with_recovery_code"))
+                .changed()
+            {
+                v.pw.pw1.clear();
+                v.pw.focus = PwFocus::Pw1;
+            }
+            ui.end_row();
         });
     });
 }
@@ -121,6 +142,8 @@ fn ask_twice(v: &mut V, controller: &mut Controller, ui: &mut egui::Ui) {
                 }
             }
             ui.end_row();
+
+            key_file_row(&mut v.pw.key_file, ui);
         });
     });
 }
@@ -1,6 +1,6 @@
 use crate::ui::{
     controller::{Action, Controller},
-    show_error,
+    key_file_row, show_error,
     sizes::MODAL_WIDTH,
     viz::{Pw, PwFocus},
 };
@@ -76,6 +76,8 @@ pub fn change_password(pw: &mut Pw, controller: &mut Controller, ctx: &Context)
                         go_for_it = true;
                     }
                     ui.end_row();
+
+                    key_file_row(&mut pw.key_file, ui);
                 });
 
                 if let Some(e) = &pw.error {
@@ -111,6 +113,7 @@ pub fn change_password(pw: &mut Pw, controller: &mut Controller, ctx: &Context)
                 controller.set_action(Action::FinalizeChangePassword {
                     old: pw.pw1.clone(),
                     new: pw.pw2.clone(),
+                    key_file: pw.key_file.clone(),
                 });
             } else {
                 pw.error = Some(t!("_passwords_dont_match").to_string());
@@ -0,0 +1,96 @@
+use crate::ui::{
+    controller::{Action, Controller},
+    show_error,
+    sizes::MODAL_WIDTH,
+    viz::VExport,
+};
+use egui::{
+    Button, Color32, Context, FontFamily, FontId, Grid, Key, Modal, RichText, Sides, TextEdit,
+};
+
+pub fn export_json(v_export: &mut VExport, controller: &mut Controller, ctx: &Context) {
+    let modal_response = Modal::new("export_json".into()).show(ctx, |ui| {
+        ui.set_width(MODAL_WIDTH);
+        let ready = !v_export.path.is_empty() && !v_export.password.is_empty();
+
+        ui.horizontal(|ui| {
+            ui.vertical(|ui| {
+                ui.set_width(140.);
+                ui.set_height(140.);
+                ui.add_space(50.);
+                ui.label(RichText::new("📤").font(FontId::new(128., FontFamily::Proportional)));
+            });
+            ui.vertical(|ui| {
+                ui.add_space(50.);
+                ui.label(RichText::new(t!("_export_json")).size(24.));
+                ui.add_space(10.);
+                ui.label(RichText::new(t!("_export_warning")).color(Color32::DARK_RED));
+                ui.add_space(15.);
+
+                Grid::new("Export").num_columns(2).show(ui, |ui| {
+                    ui.label(t!("_export_file"));
+                    ui.horizontal(|ui| {
+                        if v_export.path.is_empty() {
+                            ui.label(RichText::new(t!("_import_no_file")).italics());
+                        } else {
+                            ui.label(RichText::new(&v_export.path).monospace());
+                        }
+                        if ui.button("…").clicked() {
+                            if let Some(path) = rfd::FileDialog::new()
+                                .add_filter("JSON", &["json"])
+                                .set_file_name("prolock-export.json")
+                                .save_file()
+                            {
+                                v_export.path = path.display().to_string();
+                            }
+                        }
+                    });
+                    ui.end_row();
+
+                    ui.label(t!("_export_password"));
+                    let response = ui.add(
+                        TextEdit::singleline(&mut v_export.password)
+                            .desired_width(120.)
+                            .password(true),
+                    );
+                    if ready && response.lost_focus() && ui.input(|i| i.key_pressed(Key::Enter)) {
+                        controller.set_action(Action::FinalizeExportJson);
+                    }
+                    ui.end_row();
+                });
+
+                if let Some(e) = &v_export.error {
+                    show_error(e, ui);
+                }
+            });
+        });
+
+        ui.add_space(15.);
+        ui.separator();
+
+        Sides::new().show(
+            ui,
+            |_ui| {},
+            |ui| {
+                if ui
+                    .add_enabled(
+                        ready,
+                        Button::new(RichText::new(t!("_export")).color(Color32::DARK_GREEN)),
+                    )
+                    .clicked()
+                {
+                    controller.set_action(Action::FinalizeExportJson);
+                }
+                if ui
+                    .button(RichText::new(t!("_cancel_with_icon")).color(Color32::DARK_RED))
+                    .clicked()
+                {
+                    controller.set_action(Action::CloseModal);
+                }
+            },
+        );
+    });
+    if modal_response.should_close() {
+        controller.set_action(Action::CloseModal);
+    }
+}
@@ -0,0 +1,125 @@
+use super::export_vault::selection;
+use crate::{
+    data::PlFile,
+    ui::{
+        controller::{Action, Controller},
+        show_error,
+        sizes::MODAL_WIDTH,
+        viz::VExportPackage,
+    },
+};
+use egui::{Button, Color32, Context, FontFamily, FontId, Grid, Modal, RichText, Sides, TextEdit};
+
+pub fn export_package(
+    v_export_package: &mut VExportPackage,
+    pl_file: &PlFile,
+    controller: &mut Controller,
+    ctx: &Context,
+) {
+    let modal_response = Modal::new("export_package".into()).show(ctx, |ui| {
+        ui.set_width(MODAL_WIDTH);
+        let has_selection =
+            !v_export_package.bundles.is_empty() || !v_export_package.documents.is_empty();
+        let ready = has_selection
+            && !v_export_package.path.is_empty()
+            && !v_export_package.recipients.trim().is_empty();
+
+        ui.horizontal(|ui| {
+            ui.vertical(|ui| {
+                ui.set_width(140.);
+                ui.set_height(140.);
+                ui.add_space(50.);
+                ui.label(RichText::new("📦").font(FontId::new(128., FontFamily::Proportional)));
+            });
+            ui.vertical(|ui| {
+                ui.add_space(50.);
+                ui.label(RichText::new(t!("_export_package")).size(24.));
+                ui.add_space(10.);
+                ui.label(t!("_export_package_hint"));
+                ui.add_space(15.);
+
+                ui.horizontal(|ui| {
+                    selection(
+                        t!("Structured entries").as_ref(),
+                        "export_package_bundles",
+                        pl_file.bundles().iter().map(|(key, _)| key),
+                        &mut v_export_package.bundles,
+                        ui,
+                    );
+                    ui.add_space(20.);
+                    selection(
+                        t!("Documents").as_ref(),
+                        "export_package_documents",
+                        pl_file.documents().iter_keys(),
+                        &mut v_export_package.documents,
+                        ui,
+                    );
+                });
+                ui.add_space(15.);
+
+                Grid::new("Export package").num_columns(2).show(ui, |ui| {
+                    ui.label(t!("_export_package_recipients"));
+                    ui.add(
+                        TextEdit::multiline(&mut v_export_package.recipients)
+                            .hint_text("age1…")
+                            .font(egui::TextStyle::Monospace)
+                            .desired_rows(3)
+                            .desired_width(420.),
+                    );
+                    ui.end_row();
+
+                    ui.label(t!("_export_file"));
+                    ui.horizontal(|ui| {
+                        if v_export_package.path.is_empty() {
+                            ui.label(RichText::new(t!("_import_no_file")).italics());
+                        } else {
+                            ui.label(RichText::new(&v_export_package.path).monospace());
+                        }
+                        if ui.button("…").clicked() {
+                            if let Some(path) = rfd::FileDialog::new()
+                                .add_filter("age", &["age"])
+                                .set_file_name("prolock_package.age")
+                                .save_file()
+                            {
+                                v_export_package.path = path.display().to_string();
+                            }
+                        }
+                    });
+                    ui.end_row();
+                });
+
+                if let Some(e) = &v_export_package.error {
+                    show_error(e, ui);
+                }
+            });
+        });
+
+        ui.add_space(15.);
+        ui.separator();
+
+        Sides::new().show(
+            ui,
+            |_ui| {},
+            |ui| {
+                if ui
+                    .add_enabled(
+                        ready,
+                        Button::new(RichText::new(t!("_export")).color(Color32::DARK_GREEN)),
+                    )
+                    .clicked()
+                {
+                    controller.set_action(Action::FinalizeExportPackage);
+                }
+                if ui
+                    .button(RichText::new(t!("_cancel_with_icon")).color(Color32::DARK_RED))
+                    .clicked()
+                {
+                    controller.set_action(Action::CloseModal);
+                }
+            },
+        );
+    });
+    if modal_response.should_close() {
+        controller.set_action(Action::CloseModal);
+    }
+}
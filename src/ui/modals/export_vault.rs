@@ -0,0 +1,202 @@
+use crate::{
+    data::{Key, PlFile},
+    ui::{
+        controller::{Action, Controller},
+        key_file_row, show_error,
+        sizes::MODAL_WIDTH,
+        viz::{PwFocus, VExportVault},
+    },
+};
+use egui::{
+    Button, Color32, Context, FontFamily, FontId, Grid, Modal, RichText, ScrollArea, Sides,
+    TextEdit, Ui,
+};
+use std::collections::BTreeSet;
+
+#[allow(clippy::too_many_lines)]
+pub fn export_vault(
+    v_export_vault: &mut VExportVault,
+    pl_file: &PlFile,
+    controller: &mut Controller,
+    ctx: &Context,
+) {
+    let modal_response = Modal::new("export_vault".into()).show(ctx, |ui| {
+        let mut go_for_it = false;
+        ui.set_width(MODAL_WIDTH);
+        let has_selection =
+            !v_export_vault.bundles.is_empty() || !v_export_vault.documents.is_empty();
+        let ready =
+            has_selection && !v_export_vault.path.is_empty() && !v_export_vault.pw.pw1.is_empty();
+
+        ui.horizontal(|ui| {
+            ui.vertical(|ui| {
+                ui.set_width(140.);
+                ui.set_height(140.);
+                ui.add_space(50.);
+                ui.label(RichText::new("📤").font(FontId::new(128., FontFamily::Proportional)));
+            });
+            ui.vertical(|ui| {
+                ui.add_space(50.);
+                ui.label(RichText::new(t!("_export_vault")).size(24.));
+                ui.add_space(10.);
+                ui.label(t!("_export_vault_hint"));
+                ui.add_space(15.);
+
+                ui.horizontal(|ui| {
+                    selection(
+                        t!("Structured entries").as_ref(),
+                        "export_vault_bundles",
+                        pl_file.bundles().iter().map(|(key, _)| key),
+                        &mut v_export_vault.bundles,
+                        ui,
+                    );
+                    ui.add_space(20.);
+                    selection(
+                        t!("Documents").as_ref(),
+                        "export_vault_documents",
+                        pl_file.documents().iter_keys(),
+                        &mut v_export_vault.documents,
+                        ui,
+                    );
+                });
+                ui.add_space(15.);
+
+                Grid::new("Export vault").num_columns(2).show(ui, |ui| {
+                    ui.label(t!("_export_file"));
+                    ui.horizontal(|ui| {
+                        if v_export_vault.path.is_empty() {
+                            ui.label(RichText::new(t!("_import_no_file")).italics());
+                        } else {
+                            ui.label(RichText::new(&v_export_vault.path).monospace());
+                        }
+                        if ui.button("…").clicked() {
+                            if let Some(path) =
+                                rfd::FileDialog::new().set_file_name("secrets").save_file()
+                            {
+                                v_export_vault.path = path.display().to_string();
+                            }
+                        }
+                    });
+                    ui.end_row();
+
+                    let pw = &mut v_export_vault.pw;
+                    ui.label(t!("New password:"));
+                    let response = ui.add(
+                        TextEdit::singleline(&mut pw.pw1)
+                            .desired_width(120.)
+                            .password(true),
+                    );
+                    if response.lost_focus()
+                        && ui.input(|i| {
+                            i.key_pressed(egui::Key::Enter) || i.key_pressed(egui::Key::Tab)
+                        })
+                    {
+                        pw.focus = PwFocus::Pw2;
+                    }
+                    ui.end_row();
+
+                    ui.label(t!("Repeat new password:"));
+                    let response = ui.add(
+                        TextEdit::singleline(&mut pw.pw2)
+                            .desired_width(120.)
+                            .password(true),
+                    );
+                    if matches!(pw.focus, PwFocus::Pw2) {
+                        response.request_focus();
+                        pw.focus = PwFocus::None;
+                    }
+                    if ready
+                        && response.lost_focus()
+                        && ui.input(|i| i.key_pressed(egui::Key::Enter))
+                    {
+                        go_for_it = true;
+                    }
+                    ui.end_row();
+
+                    key_file_row(&mut pw.key_file, ui);
+
+                    ui.label("");
+                    ui.checkbox(&mut v_export_vault.register, t!("_export_vault_register"));
+                    ui.end_row();
+                });
+
+                if let Some(e) = &v_export_vault.pw.error {
+                    show_error(e, ui);
+                }
+            });
+        });
+
+        ui.add_space(15.);
+        ui.separator();
+
+        Sides::new().show(
+            ui,
+            |_ui| {},
+            |ui| {
+                if ui
+                    .add_enabled(
+                        ready,
+                        Button::new(RichText::new(t!("_export")).color(Color32::DARK_GREEN)),
+                    )
+                    .clicked()
+                {
+                    go_for_it = true;
+                }
+                if ui
+                    .button(RichText::new(t!("_cancel_with_icon")).color(Color32::DARK_RED))
+                    .clicked()
+                {
+                    controller.set_action(Action::CloseModal);
+                }
+            },
+        );
+
+        if go_for_it {
+            if v_export_vault.pw.pw1 == v_export_vault.pw.pw2 {
+                controller.set_action(Action::FinalizeExportVault);
+            } else {
+                v_export_vault.pw.error = Some(t!("_passwords_dont_match").to_string());
+            }
+        }
+    });
+    if modal_response.should_close() {
+        controller.set_action(Action::CloseModal);
+    }
+}
+
+// A column of checkboxes, with buttons to select all or nothing
+pub(super) fn selection<'a>(
+    title: &str,
+    id: &str,
+    keys: impl Iterator<Item = &'a Key>,
+    selected: &mut BTreeSet<Key>,
+    ui: &mut Ui,
+) {
+    let keys: Vec<&Key> = keys.collect();
+    ui.vertical(|ui| {
+        ui.horizontal(|ui| {
+            ui.label(RichText::new(title).strong());
+            if ui.small_button(t!("_select_all")).clicked() {
+                selected.extend(keys.iter().map(|key| (*key).clone()));
+            }
+            if ui.small_button(t!("_select_none")).clicked() {
+                selected.clear();
+            }
+        });
+        ScrollArea::vertical()
+            .id_salt(id)
+            .max_height(200.)
+            .show(ui, |ui| {
+                for key in &keys {
+                    let mut is_selected = selected.contains(*key);
+                    if ui.checkbox(&mut is_selected, key.as_str()).changed() {
+                        if is_selected {
+                            selected.insert((*key).clone());
+                        } else {
+                            selected.remove(*key);
+                        }
+                    }
+                }
+            });
+    });
+}
@@ -0,0 +1,388 @@
+use crate::{
+    data::{OnConflict, Outcome},
+    import::{CredMapping, CredName, CsvMapping},
+    ui::{
+        controller::{Action, Controller},
+        key_file_row, show_error,
+        sizes::MODAL_WIDTH,
+        viz::{ImportSource, VImport},
+    },
+};
+use egui::{
+    Button, Color32, ComboBox, Context, FontFamily, FontId, Grid, Key, Modal, RichText, ScrollArea,
+    Sides, TextEdit,
+};
+use std::hash::Hash;
+
+pub fn import(v_import: &mut VImport, controller: &mut Controller, ctx: &Context) {
+    let (title, hint, file_filter) = match v_import.source {
+        ImportSource::Csv => (
+            t!("_import_csv"),
+            t!("_import_csv_hint"),
+            ("CSV", &["csv", "txt"][..]),
+        ),
+        ImportSource::KeePass => (
+            t!("_import_keepass"),
+            t!("_import_keepass_hint"),
+            ("KeePass", &["kdbx", "xml"][..]),
+        ),
+        ImportSource::Json => (
+            t!("_import_json"),
+            t!("_import_json_hint"),
+            ("JSON", &["json"][..]),
+        ),
+        ImportSource::Package => (
+            t!("_import_package"),
+            t!("_import_package_hint"),
+            ("age", &["age"][..]),
+        ),
+    };
+    let modal_response = Modal::new("import".into()).show(ctx, |ui| {
+        ui.set_width(MODAL_WIDTH);
+
+        ui.horizontal(|ui| {
+            ui.vertical(|ui| {
+                ui.set_width(140.);
+                ui.set_height(140.);
+                ui.add_space(50.);
+                ui.label(RichText::new("📥").font(FontId::new(128., FontFamily::Proportional)));
+            });
+            ui.vertical(|ui| {
+                ui.add_space(50.);
+                ui.label(RichText::new(title).size(24.));
+                ui.add_space(10.);
+                ui.label(hint);
+                ui.add_space(15.);
+
+                file_row(&v_import.path, file_filter, controller, ui);
+
+                if v_import.needs_password {
+                    ui.add_space(15.);
+                    unlock(v_import, controller, ui);
+                } else if v_import.source != ImportSource::Csv
+                    && !v_import.path.is_empty()
+                    && v_import.error.is_none()
+                {
+                    ui.add_space(15.);
+                    on_conflict_radios(&mut v_import.on_conflict, controller, ui);
+                    ui.add_space(15.);
+                    preview(v_import, ui);
+                } else if !v_import.table.headers.is_empty() {
+                    ui.add_space(15.);
+                    if mapping(
+                        &mut v_import.mapping,
+                        &mut v_import.on_conflict,
+                        &v_import.table.headers,
+                        ui,
+                    ) {
+                        controller.set_action(Action::UpdateImportPreview);
+                    }
+                    ui.add_space(15.);
+                    preview(v_import, ui);
+                }
+
+                if let Some(e) = &v_import.error {
+                    show_error(e, ui);
+                }
+            });
+        });
+
+        ui.add_space(15.);
+        ui.separator();
+
+        Sides::new().show(
+            ui,
+            |_ui| {},
+            |ui| {
+                let plan = &v_import.plan;
+                if ui
+                    .add_enabled(
+                        plan.bundles.len() + plan.documents.len() > plan.count(&Outcome::Skip),
+                        Button::new(RichText::new(t!("_import")).color(Color32::DARK_GREEN)),
+                    )
+                    .clicked()
+                {
+                    controller.set_action(Action::FinalizeImport);
+                }
+                if ui
+                    .button(RichText::new(t!("_cancel_with_icon")).color(Color32::DARK_RED))
+                    .clicked()
+                {
+                    controller.set_action(Action::CloseModal);
+                }
+            },
+        );
+    });
+    if modal_response.should_close() {
+        controller.set_action(Action::CloseModal);
+    }
+}
+
+fn file_row(
+    path: &str,
+    (filter_name, extensions): (&str, &[&str]),
+    controller: &mut Controller,
+    ui: &mut egui::Ui,
+) {
+    ui.horizontal(|ui| {
+        if path.is_empty() {
+            ui.label(RichText::new(t!("_import_no_file")).italics());
+        } else {
+            ui.label(RichText::new(path).monospace());
+        }
+        if ui.button("…").clicked() {
+            if let Some(path) = rfd::FileDialog::new()
+                .add_filter(filter_name, extensions)
+                .pick_file()
+            {
+                controller.set_action(Action::ReadImportFile(path));
+            }
+        }
+    });
+}
+
+// returns true if something was changed
+fn mapping(
+    mapping: &mut CsvMapping,
+    on_conflict: &mut OnConflict,
+    headers: &[String],
+    ui: &mut egui::Ui,
+) -> bool {
+    let mut changed = false;
+    Grid::new("Import mapping").num_columns(2).show(ui, |ui| {
+        ui.label(t!("_import_name_column"));
+        changed |= column_combo("key column", &mut mapping.key, headers, ui);
+        ui.end_row();
+
+        ui.label(t!("_import_description_columns"));
+        ui.horizontal_wrapped(|ui| {
+            for (column, header) in headers.iter().enumerate() {
+                let mut selected = mapping.description.contains(&column);
+                if ui.checkbox(&mut selected, header).changed() {
+                    if selected {
+                        mapping.description.push(column);
+                        mapping.description.sort_unstable();
+                    } else {
+                        mapping.description.retain(|c| *c != column);
+                    }
+                    changed = true;
+                }
+            }
+        });
+        ui.end_row();
+
+        let mut o_remove = None;
+        for (idx, cred_mapping) in mapping.creds.iter_mut().enumerate() {
+            ui.label(t!("_import_cred_columns"));
+            ui.horizontal(|ui| {
+                changed |= cred_name_combo(idx, &mut cred_mapping.name, headers, ui);
+                if let CredName::Fixed(name) = &mut cred_mapping.name {
+                    changed |= ui
+                        .add(TextEdit::singleline(name).desired_width(80.))
+                        .changed();
+                }
+                ui.label(":");
+                changed |= column_combo(
+                    ("secret column", idx),
+                    &mut cred_mapping.secret,
+                    headers,
+                    ui,
+                );
+                if ui.button("🗑").clicked() {
+                    o_remove = Some(idx);
+                }
+            });
+            ui.end_row();
+        }
+        if let Some(idx) = o_remove {
+            mapping.creds.remove(idx);
+            changed = true;
+        }
+        ui.label("");
+        if ui.button(format!("+ {}", t!("_import_add_cred"))).clicked() {
+            mapping.creds.push(CredMapping {
+                name: CredName::Column(0),
+                secret: 0,
+            });
+            changed = true;
+        }
+        ui.end_row();
+
+        ui.label(t!("_import_document_column"));
+        ComboBox::new("document column", "")
+            .selected_text(mapping.document.map_or("–", |c| &headers[c]))
+            .show_ui(ui, |ui| {
+                changed |= ui
+                    .selectable_value(&mut mapping.document, None, "–")
+                    .changed();
+                for (column, header) in headers.iter().enumerate() {
+                    changed |= ui
+                        .selectable_value(&mut mapping.document, Some(column), header)
+                        .changed();
+                }
+            });
+        ui.end_row();
+
+        ui.label(t!("_import_on_conflict"));
+        changed |= on_conflict_choice(on_conflict, ui);
+        ui.end_row();
+    });
+    changed
+}
+
+// returns true if something was changed
+fn on_conflict_choice(on_conflict: &mut OnConflict, ui: &mut egui::Ui) -> bool {
+    let mut changed = false;
+    ui.horizontal(|ui| {
+        for (value, text) in [
+            (OnConflict::Skip, t!("_import_skip")),
+            (OnConflict::Rename, t!("_import_rename")),
+            (OnConflict::Merge, t!("_import_merge")),
+        ] {
+            changed |= ui.radio_value(on_conflict, value, text).changed();
+        }
+    });
+    changed
+}
+
+fn on_conflict_radios(
+    on_conflict: &mut OnConflict,
+    controller: &mut Controller,
+    ui: &mut egui::Ui,
+) {
+    Grid::new("Import on conflict")
+        .num_columns(2)
+        .show(ui, |ui| {
+            ui.label(t!("_import_on_conflict"));
+            if on_conflict_choice(on_conflict, ui) {
+                controller.set_action(Action::UpdateImportPreview);
+            }
+            ui.end_row();
+        });
+}
+
+// the KeePass file is decrypted in the background
+fn unlock(v_import: &mut VImport, controller: &mut Controller, ui: &mut egui::Ui) {
+    Grid::new("Import password").num_columns(2).show(ui, |ui| {
+        ui.label(t!("_keepass_password"));
+        ui.horizontal(|ui| {
+            let response = ui.add(
+                TextEdit::singleline(&mut v_import.password)
+                    .desired_width(120.)
+                    .password(true),
+            );
+            if (response.lost_focus() && ui.input(|i| i.key_pressed(Key::Enter)))
+                || ui.button(t!("_import_unlock")).clicked()
+            {
+                controller.set_action(Action::UnlockImportFile);
+            }
+        });
+        ui.end_row();
+        key_file_row(&mut v_import.key_file, ui);
+    });
+}
+
+fn column_combo(
+    id_salt: impl Hash,
+    column: &mut usize,
+    headers: &[String],
+    ui: &mut egui::Ui,
+) -> bool {
+    let mut changed = false;
+    ComboBox::new(id_salt, "")
+        .selected_text(headers.get(*column).map_or("", String::as_str))
+        .show_ui(ui, |ui| {
+            for (idx, header) in headers.iter().enumerate() {
+                changed |= ui.selectable_value(column, idx, header).changed();
+            }
+        });
+    changed
+}
+
+// the name of a credential is taken from a column, or is the same for all entries
+fn cred_name_combo(idx: usize, name: &mut CredName, headers: &[String], ui: &mut egui::Ui) -> bool {
+    let mut changed = false;
+    ComboBox::new(("cred name column", idx), "")
+        .selected_text(match name {
+            CredName::Column(column) => headers.get(*column).cloned().unwrap_or_default(),
+            CredName::Fixed(_) => t!("_import_fixed_name").to_string(),
+        })
+        .show_ui(ui, |ui| {
+            for (column, header) in headers.iter().enumerate() {
+                if ui
+                    .selectable_label(*name == CredName::Column(column), header)
+                    .clicked()
+                {
+                    *name = CredName::Column(column);
+                    changed = true;
+                }
+            }
+            if ui
+                .selectable_label(matches!(name, CredName::Fixed(_)), t!("_import_fixed_name"))
+                .clicked()
+                && !matches!(name, CredName::Fixed(_))
+            {
+                *name = CredName::Fixed(String::new());
+                changed = true;
+            }
+        });
+    changed
+}
+
+// shows what happens with every entry and document, but not the secrets
+fn preview(v_import: &VImport, ui: &mut egui::Ui) {
+    ui.label(RichText::new(t!("_import_preview")).strong());
+    ScrollArea::vertical().max_height(200.).show(ui, |ui| {
+        Grid::new("Import preview")
+            .num_columns(3)
+            .striped(true)
+            .show(ui, |ui| {
+                for (v_edit_bundle, outcome) in
+                    v_import.import.bundles.iter().zip(&v_import.plan.bundles)
+                {
+                    outcome_row(outcome, v_edit_bundle.key.as_str(), ui);
+                    ui.label(t!(
+                        "_import_creds %{n}",
+                        n = v_edit_bundle.v_edit_creds.len()
+                    ));
+                    ui.end_row();
+                }
+                for (v_edit_document, outcome) in v_import
+                    .import
+                    .documents
+                    .iter()
+                    .zip(&v_import.plan.documents)
+                {
+                    outcome_row(outcome, &format!("📄 {}", v_edit_document.key), ui);
+                    ui.label("");
+                    ui.end_row();
+                }
+            });
+        for skipped in &v_import.import.skipped {
+            ui.label(RichText::new(skipped).italics());
+        }
+    });
+    ui.add_space(5.);
+    ui.label(v_import.plan.summary());
+}
+
+fn outcome_row(outcome: &Outcome, name: &str, ui: &mut egui::Ui) {
+    match outcome {
+        Outcome::Add => {
+            ui.label(RichText::new(t!("_import_add")).color(Color32::DARK_GREEN));
+            ui.label(name);
+        }
+        Outcome::Rename(new_key) => {
+            ui.label(t!("_import_rename"));
+            ui.label(format!("{name} → {new_key}"));
+        }
+        Outcome::Merge => {
+            ui.label(t!("_import_merge"));
+            ui.label(name);
+        }
+        Outcome::Skip => {
+            ui.label(RichText::new(t!("_import_skip")).color(Color32::GRAY));
+            ui.label(name);
+        }
+    }
+}
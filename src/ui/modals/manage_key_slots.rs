@@ -0,0 +1,134 @@
+use crate::{
+    data::PlFile,
+    ui::{
+        controller::{Action, Controller},
+        key_file_row, show_error,
+        sizes::MODAL_WIDTH,
+        viz::VKeySlots,
+    },
+};
+use egui::{Color32, Context, FontFamily, FontId, Grid, Modal, RichText, Sides, TextEdit};
+
+#[allow(clippy::too_many_lines)]
+pub fn manage_key_slots(
+    pl_file: &PlFile,
+    v_key_slots: &mut VKeySlots,
+    controller: &mut Controller,
+    ctx: &Context,
+) {
+    let modal_response = Modal::new("manage_key_slots".into()).show(ctx, |ui| {
+        ui.set_width(MODAL_WIDTH);
+
+        ui.horizontal(|ui| {
+            ui.vertical(|ui| {
+                ui.set_width(140.);
+                ui.set_height(140.);
+                ui.add_space(50.);
+                ui.label(RichText::new("🔑").font(FontId::new(128., FontFamily::Proportional)));
+            });
+            ui.vertical(|ui| {
+                ui.add_space(50.);
+                ui.label(RichText::new(t!("_key_slots")).size(24.));
+                ui.add_space(10.);
+                ui.label(t!("_key_slots_hint"));
+                ui.add_space(15.);
+
+                Grid::new("Key slots").num_columns(3).show(ui, |ui| {
+                    for key_slot in pl_file.key_slots() {
+                        ui.label(RichText::new(key_slot.label()).monospace());
+                        if key_slot.uses_key_file() {
+                            ui.label("🗝").on_hover_text(t!("_key_slot_uses_key_file"));
+                        } else {
+                            ui.label("");
+                        }
+                        if pl_file.current_key_slot() == Some(key_slot.label()) {
+                            ui.label(t!("_key_slot_current"));
+                        } else if ui
+                            .button(RichText::new(t!("_revoke")).color(Color32::DARK_RED))
+                            .clicked()
+                        {
+                            controller.set_action(Action::FinalizeRevokeKeySlot(
+                                key_slot.label().to_string(),
+                            ));
+                        }
+                        ui.end_row();
+                    }
+                });
+
+                ui.add_space(15.);
+                ui.separator();
+                ui.label(RichText::new(t!("_add_key_slot")).size(16.));
+                ui.add_space(10.);
+
+                let mut go_for_it = false;
+                Grid::new("Add key slot").num_columns(2).show(ui, |ui| {
+                    ui.label(t!("_key_slot_label"));
+                    ui.add(TextEdit::singleline(&mut v_key_slots.label).desired_width(120.));
+                    ui.end_row();
+
+                    ui.label(t!("Password:"));
+                    ui.add(
+                        TextEdit::singleline(&mut v_key_slots.pw.pw1)
+                            .desired_width(120.)
+                            .password(true),
+                    );
+                    ui.end_row();
+
+                    ui.label(t!("Repeat:"));
+                    ui.add(
+                        TextEdit::singleline(&mut v_key_slots.pw.pw2)
+                            .desired_width(120.)
+                            .password(true),
+                    );
+                    ui.end_row();
+
+                    key_file_row(&mut v_key_slots.pw.key_file, ui);
+
+                    ui.label("");
+                    if ui
+                        .button(RichText::new(t!("_add_key_slot")).color(Color32::DARK_GREEN))
+                        .clicked()
+                    {
+                        go_for_it = true;
+                    }
+                    ui.end_row();
+                });
+
+                if go_for_it {
+                    if v_key_slots.pw.pw1 == v_key_slots.pw.pw2 {
+                        controller.set_action(Action::FinalizeAddKeySlot {
+                            label: v_key_slots.label.clone(),
+                            pw: v_key_slots.pw.pw1.clone(),
+                            key_file: v_key_slots.pw.key_file.clone(),
+                        });
+                    } else {
+                        v_key_slots.pw.error = Some(t!("_passwords_dont_match").to_string());
+                    }
+                }
+
+                if let Some(e) = &v_key_slots.pw.error {
+                    show_error(e, ui);
+                }
+            });
+        });
+
+        ui.add_space(15.);
+        ui.separator();
+
+        Sides::new().show(
+            ui,
+            |_ui| {},
+            |ui| {
+                if ui
+                    .button(RichText::new(t!("_ok_with_icon")).color(Color32::DARK_GREEN))
+                    .clicked()
+                {
+                    controller.set_action(Action::CloseModal);
+                }
+            },
+        );
+    });
+    if modal_response.should_close() {
+        controller.set_action(Action::CloseModal);
+    }
+}
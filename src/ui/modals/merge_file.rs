@@ -0,0 +1,212 @@
+use crate::{
+    data::{Compared, Difference, Key},
+    ui::{
+        controller::{Action, Controller},
+        key_file_row, show_error,
+        sizes::MODAL_WIDTH,
+        viz::VMergeFile,
+    },
+};
+use egui::{
+    Button, Color32, Context, FontFamily, FontId, Grid, Modal, RichText, ScrollArea, Sides,
+    TextEdit, Ui,
+};
+use jiff::Zoned;
+use std::collections::BTreeSet;
+
+// First the other file is opened, then the comparison is shown
+pub fn merge_file(v_merge_file: &mut VMergeFile, controller: &mut Controller, ctx: &Context) {
+    let modal_response = Modal::new("merge_file".into()).show(ctx, |ui| {
+        ui.set_width(MODAL_WIDTH);
+
+        ui.horizontal(|ui| {
+            ui.vertical(|ui| {
+                ui.set_width(140.);
+                ui.set_height(140.);
+                ui.add_space(50.);
+                ui.label(RichText::new("🔀").font(FontId::new(128., FontFamily::Proportional)));
+            });
+            ui.vertical(|ui| {
+                ui.add_space(50.);
+                ui.label(RichText::new(t!("_merge_file")).size(24.));
+                ui.add_space(10.);
+                if v_merge_file.theirs.is_none() {
+                    ui.label(t!("_merge_file_hint"));
+                    ui.add_space(15.);
+                    open_other(v_merge_file, controller, ui);
+                } else {
+                    ui.label(RichText::new(&v_merge_file.path).monospace());
+                    ui.add_space(15.);
+                    comparison(v_merge_file, ui);
+                }
+
+                if let Some(e) = &v_merge_file.pw.error {
+                    show_error(e, ui);
+                }
+            });
+        });
+
+        ui.add_space(15.);
+        ui.separator();
+
+        Sides::new().show(
+            ui,
+            |_ui| {},
+            |ui| {
+                if v_merge_file.theirs.is_none() {
+                    if ui
+                        .add_enabled(
+                            !v_merge_file.path.is_empty(),
+                            Button::new(
+                                RichText::new(t!("_merge_compare")).color(Color32::DARK_GREEN),
+                            ),
+                        )
+                        .clicked()
+                    {
+                        controller.set_action(Action::OpenMergeFile);
+                    }
+                } else if ui
+                    .add_enabled(
+                        !(v_merge_file.bundles.is_empty() && v_merge_file.documents.is_empty()),
+                        Button::new(
+                            RichText::new(t!("_merge_take_over")).color(Color32::DARK_GREEN),
+                        ),
+                    )
+                    .clicked()
+                {
+                    controller.set_action(Action::FinalizeMergeFile);
+                }
+                if ui
+                    .button(RichText::new(t!("_cancel_with_icon")).color(Color32::DARK_RED))
+                    .clicked()
+                {
+                    controller.set_action(Action::CloseModal);
+                }
+            },
+        );
+    });
+    if modal_response.should_close() {
+        controller.set_action(Action::CloseModal);
+    }
+}
+
+fn open_other(v_merge_file: &mut VMergeFile, controller: &mut Controller, ui: &mut Ui) {
+    Grid::new("Merge file").num_columns(2).show(ui, |ui| {
+        ui.label(t!("_merge_other_file"));
+        ui.horizontal(|ui| {
+            if v_merge_file.path.is_empty() {
+                ui.label(RichText::new(t!("_import_no_file")).italics());
+            } else {
+                ui.label(RichText::new(&v_merge_file.path).monospace());
+            }
+            if ui.button("…").clicked() {
+                if let Some(path) = rfd::FileDialog::new().pick_file() {
+                    v_merge_file.path = path.display().to_string();
+                }
+            }
+        });
+        ui.end_row();
+
+        ui.label(t!("_merge_password"));
+        let response = ui.add(
+            TextEdit::singleline(&mut v_merge_file.pw.pw1)
+                .desired_width(120.)
+                .password(true),
+        );
+        if !v_merge_file.path.is_empty()
+            && response.lost_focus()
+            && ui.input(|i| i.key_pressed(egui::Key::Enter))
+        {
+            controller.set_action(Action::OpenMergeFile);
+        }
+        ui.end_row();
+
+        key_file_row(&mut v_merge_file.pw.key_file, ui);
+    });
+}
+
+// The entries and documents of both files; what differs can be selected,
+// and the more recent change time is highlighted
+fn comparison(v_merge_file: &mut VMergeFile, ui: &mut Ui) {
+    ScrollArea::vertical().max_height(350.).show(ui, |ui| {
+        for (title, id, compared, selected) in [
+            (
+                t!("Structured entries"),
+                "merge_bundles",
+                &v_merge_file.comparison.bundles,
+                &mut v_merge_file.bundles,
+            ),
+            (
+                t!("Documents"),
+                "merge_documents",
+                &v_merge_file.comparison.documents,
+                &mut v_merge_file.documents,
+            ),
+        ] {
+            if compared.is_empty() {
+                continue;
+            }
+            ui.label(RichText::new(title).strong());
+            Grid::new(id).num_columns(5).striped(true).show(ui, |ui| {
+                ui.label("");
+                ui.label("");
+                ui.label("");
+                ui.label(RichText::new(t!("_merge_here")).italics());
+                ui.label(RichText::new(t!("_merge_there")).italics());
+                ui.end_row();
+                for c in compared {
+                    compared_row(c, selected, ui);
+                }
+            });
+            ui.add_space(10.);
+        }
+    });
+}
+
+fn compared_row(c: &Compared, selected: &mut BTreeSet<Key>, ui: &mut Ui) {
+    let mut is_selected = selected.contains(&c.key);
+    if ui
+        .add_enabled(
+            c.is_selectable(),
+            egui::Checkbox::without_text(&mut is_selected),
+        )
+        .changed()
+    {
+        if is_selected {
+            selected.insert(c.key.clone());
+        } else {
+            selected.remove(&c.key);
+        }
+    }
+    ui.label(c.key.as_str());
+    let (text, color) = match c.difference {
+        Difference::OnlyMine => (t!("_merge_only_mine"), Color32::GRAY),
+        Difference::OnlyTheirs => (t!("_merge_only_theirs"), Color32::DARK_GREEN),
+        Difference::Identical => (t!("_merge_identical"), Color32::GRAY),
+        Difference::Differing => (t!("_merge_differing"), Color32::DARK_RED),
+    };
+    ui.label(RichText::new(text).color(color));
+    let is_differing = c.difference == Difference::Differing;
+    changed_at(
+        c.mine_changed_at.as_ref(),
+        is_differing && !c.theirs_is_newer(),
+        ui,
+    );
+    changed_at(
+        c.theirs_changed_at.as_ref(),
+        is_differing && c.theirs_is_newer(),
+        ui,
+    );
+    ui.end_row();
+}
+
+fn changed_at(o_changed_at: Option<&Zoned>, is_newer: bool, ui: &mut Ui) {
+    let text = o_changed_at.map_or_else(String::new, |changed_at| {
+        changed_at.strftime("%Y-%m-%d %H:%M").to_string()
+    });
+    if is_newer {
+        ui.label(RichText::new(text).strong().color(Color32::DARK_GREEN));
+    } else {
+        ui.label(text);
+    }
+}
@@ -0,0 +1,96 @@
+use crate::{
+    data::LeftoverTempFile,
+    ui::{
+        controller::{Action, Controller},
+        show_error,
+        sizes::MODAL_WIDTH,
+    },
+};
+use egui::{Button, Color32, Context, FontFamily, FontId, Grid, Modal, RichText, Sides};
+
+// Is shown after unlocking, if a previous save was interrupted;
+// the user has to decide, the modal cannot be closed otherwise
+pub fn recover_temp_file(
+    leftover: &LeftoverTempFile,
+    o_update_counter: Option<usize>,
+    error: Option<&str>,
+    controller: &mut Controller,
+    ctx: &Context,
+) {
+    Modal::new("recover_temp_file".into()).show(ctx, |ui| {
+        ui.set_width(MODAL_WIDTH);
+
+        ui.horizontal(|ui| {
+            ui.vertical(|ui| {
+                ui.set_width(140.);
+                ui.set_height(140.);
+                ui.add_space(50.);
+                ui.label(RichText::new("⚠").font(FontId::new(128., FontFamily::Proportional)));
+            });
+            ui.vertical(|ui| {
+                ui.add_space(50.);
+                ui.label(RichText::new(t!("_leftover_temp_file")).size(24.));
+                ui.add_space(10.);
+                ui.label(t!("_leftover_temp_file_hint"));
+                ui.add_space(15.);
+
+                let show = |o_counter: Option<usize>| {
+                    o_counter.map_or_else(|| "–".to_string(), |counter| counter.to_string())
+                };
+                Grid::new("Leftover temp file")
+                    .num_columns(2)
+                    .show(ui, |ui| {
+                        ui.label(t!("_leftover_temp_file_counter_file"));
+                        ui.label(show(o_update_counter));
+                        ui.end_row();
+                        ui.label(t!("_leftover_temp_file_counter_temp"));
+                        ui.label(show(leftover.o_update_counter));
+                        ui.end_row();
+                    });
+                ui.add_space(10.);
+
+                ui.label(if !leftover.is_decryptable {
+                    t!("_leftover_temp_file_not_decryptable")
+                } else if leftover.is_newer {
+                    t!("_leftover_temp_file_is_newer")
+                } else {
+                    t!("_leftover_temp_file_is_older")
+                });
+
+                if let Some(e) = error {
+                    show_error(e, ui);
+                }
+            });
+        });
+
+        ui.add_space(15.);
+        ui.separator();
+
+        Sides::new().show(
+            ui,
+            |_ui| {},
+            |ui| {
+                if ui
+                    .add_enabled(
+                        leftover.is_decryptable && leftover.is_newer,
+                        Button::new(
+                            RichText::new(t!("_leftover_temp_file_recover"))
+                                .color(Color32::DARK_GREEN),
+                        ),
+                    )
+                    .clicked()
+                {
+                    controller.set_action(Action::FinalizeRecoverTempFile);
+                }
+                if ui
+                    .button(
+                        RichText::new(t!("_leftover_temp_file_discard")).color(Color32::DARK_RED),
+                    )
+                    .clicked()
+                {
+                    controller.set_action(Action::FinalizeDiscardTempFile);
+                }
+            },
+        );
+    });
+}
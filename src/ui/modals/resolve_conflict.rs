@@ -0,0 +1,120 @@
+use crate::{
+    data::{MergeConflict, Version},
+    ui::{
+        controller::{Action, Controller},
+        show_error,
+        sizes::MODAL_WIDTH,
+    },
+};
+use egui::{Color32, Context, FontFamily, FontId, Grid, Modal, RichText, ScrollArea, Sides, Ui};
+
+// Is shown if a change cannot be saved because a concurrent update of the file
+// changed the same entry or document; the user has to decide which version to keep
+pub fn resolve_conflict(
+    conflict: &MergeConflict,
+    mine: &Version,
+    show_secrets: &mut bool,
+    error: Option<&str>,
+    controller: &mut Controller,
+    ctx: &Context,
+) {
+    Modal::new("resolve_conflict".into()).show(ctx, |ui| {
+        ui.set_width(2. * MODAL_WIDTH);
+
+        ui.horizontal(|ui| {
+            ui.vertical(|ui| {
+                ui.set_width(140.);
+                ui.add_space(30.);
+                ui.label(RichText::new("⚠").font(FontId::new(128., FontFamily::Proportional)));
+            });
+            ui.vertical(|ui| {
+                ui.add_space(30.);
+                ui.label(RichText::new(t!("_merge_conflict_title")).size(24.));
+                ui.add_space(10.);
+                ui.label(t!("_merge_conflict %{name}", name = conflict.key));
+                ui.label(t!("_merge_conflict_hint"));
+                ui.add_space(10.);
+                ui.checkbox(show_secrets, t!("_merge_conflict_show_secrets"));
+            });
+        });
+        ui.add_space(15.);
+
+        ui.columns(2, |columns| {
+            columns[0].label(RichText::new(t!("_merge_conflict_mine")).strong());
+            show_version("mine", mine, *show_secrets, &mut columns[0]);
+            columns[1].label(RichText::new(t!("_merge_conflict_theirs")).strong());
+            show_version("theirs", &conflict.theirs, *show_secrets, &mut columns[1]);
+        });
+
+        if let Some(e) = error {
+            show_error(e, ui);
+        }
+
+        ui.add_space(15.);
+        ui.separator();
+
+        Sides::new().show(
+            ui,
+            |_ui| {},
+            |ui| {
+                if ui
+                    .button(
+                        RichText::new(t!("_merge_conflict_take_theirs")).color(Color32::DARK_RED),
+                    )
+                    .clicked()
+                {
+                    controller.set_action(Action::FinalizeTakeTheirs);
+                }
+                if ui
+                    .button(
+                        RichText::new(t!("_merge_conflict_keep_mine")).color(Color32::DARK_GREEN),
+                    )
+                    .clicked()
+                {
+                    controller.set_action(Action::FinalizeKeepMine);
+                }
+            },
+        );
+    });
+}
+
+fn show_version(id: &str, version: &Version, show_secrets: bool, ui: &mut Ui) {
+    let hide = |secret: &str| {
+        if show_secrets {
+            secret.to_string()
+        } else {
+            "•".repeat(secret.chars().count().min(12))
+        }
+    };
+    ScrollArea::vertical()
+        .id_salt(id)
+        .max_height(250.)
+        .show(ui, |ui| match version {
+            Version::Bundle(None) | Version::Document(None) => {
+                ui.label(RichText::new(t!("_merge_conflict_deleted")).italics());
+            }
+            Version::Bundle(Some(v_edit_bundle)) => {
+                Grid::new(id).num_columns(2).show(ui, |ui| {
+                    ui.label(t!("Name of the entry"));
+                    ui.label(v_edit_bundle.key.as_str());
+                    ui.end_row();
+                    ui.label(t!("Description"));
+                    ui.label(&v_edit_bundle.description);
+                    ui.end_row();
+                    for v_edit_cred in v_edit_bundle
+                        .v_edit_creds
+                        .iter()
+                        .filter(|cred| !cred.name.is_empty() || !cred.secret.is_empty())
+                    {
+                        ui.label(&v_edit_cred.name);
+                        ui.label(hide(&v_edit_cred.secret));
+                        ui.end_row();
+                    }
+                });
+            }
+            Version::Document(Some(v_edit_document)) => {
+                ui.label(RichText::new(v_edit_document.key.as_str()).strong());
+                ui.label(hide(&v_edit_document.text));
+            }
+        });
+}
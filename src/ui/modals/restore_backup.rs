@@ -0,0 +1,107 @@
+use crate::ui::{
+    controller::{Action, Controller},
+    show_error,
+    sizes::MODAL_WIDTH,
+    viz::VBackups,
+};
+use egui::{
+    Button, Color32, Context, DragValue, FontFamily, FontId, Grid, Modal, RichText, ScrollArea,
+    Sides,
+};
+
+pub fn restore_backup(v_backups: &mut VBackups, controller: &mut Controller, ctx: &Context) {
+    let modal_response = Modal::new("restore_backup".into()).show(ctx, |ui| {
+        ui.set_width(MODAL_WIDTH);
+
+        ui.horizontal(|ui| {
+            ui.vertical(|ui| {
+                ui.set_width(140.);
+                ui.set_height(140.);
+                ui.add_space(50.);
+                ui.label(RichText::new("🗄").font(FontId::new(128., FontFamily::Proportional)));
+            });
+            ui.vertical(|ui| {
+                ui.add_space(50.);
+                ui.label(RichText::new(t!("_restore_from_backup")).size(24.));
+                ui.add_space(10.);
+                ui.label(t!("_restore_from_backup_hint"));
+                ui.add_space(15.);
+
+                if v_backups.backups.is_empty() {
+                    ui.label(RichText::new(t!("_no_backups")).italics());
+                } else {
+                    ScrollArea::vertical().max_height(200.).show(ui, |ui| {
+                        Grid::new("Backups")
+                            .num_columns(3)
+                            .striped(true)
+                            .show(ui, |ui| {
+                                ui.label(RichText::new(t!("_backup_created_at")).strong());
+                                ui.label(RichText::new(t!("_backup_update_counter")).strong());
+                                ui.label(RichText::new(t!("_backup_entries")).strong());
+                                ui.end_row();
+                                for (idx, backup) in v_backups.backups.iter().enumerate() {
+                                    ui.radio_value(
+                                        &mut v_backups.selected,
+                                        Some(idx),
+                                        backup.created_at.strftime("%Y-%m-%d %H:%M:%S").to_string(),
+                                    );
+                                    ui.label(backup.update_counter.to_string());
+                                    ui.label(
+                                        backup
+                                            .o_entries
+                                            .map_or_else(|| "–".to_string(), |n| n.to_string()),
+                                    );
+                                    ui.end_row();
+                                }
+                            });
+                    });
+                }
+
+                ui.add_space(15.);
+                ui.horizontal(|ui| {
+                    ui.label(t!("_backup_generations"));
+                    if ui
+                        .add(DragValue::new(&mut v_backups.generations).range(0..=100))
+                        .changed()
+                    {
+                        controller.set_action(Action::SetBackupGenerations(v_backups.generations));
+                    }
+                });
+
+                if let Some(e) = &v_backups.error {
+                    show_error(e, ui);
+                }
+            });
+        });
+
+        ui.add_space(15.);
+        ui.separator();
+
+        Sides::new().show(
+            ui,
+            |_ui| {},
+            |ui| {
+                if ui
+                    .add_enabled(
+                        v_backups.selected.is_some(),
+                        Button::new(RichText::new(t!("_restore")).color(Color32::DARK_GREEN)),
+                    )
+                    .clicked()
+                {
+                    if let Some(idx) = v_backups.selected {
+                        controller.set_action(Action::FinalizeRestoreBackup(idx));
+                    }
+                }
+                if ui
+                    .button(RichText::new(t!("_cancel_with_icon")).color(Color32::DARK_RED))
+                    .clicked()
+                {
+                    controller.set_action(Action::CloseModal);
+                }
+            },
+        );
+    });
+    if modal_response.should_close() {
+        controller.set_action(Action::CloseModal);
+    }
+}
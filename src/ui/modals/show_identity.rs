@@ -0,0 +1,80 @@
+use crate::ui::{
+    controller::{Action, Controller},
+    show_error,
+    sizes::MODAL_WIDTH,
+};
+use egui::{Button, Color32, Context, FontFamily, FontId, Modal, RichText, Sides};
+
+// Shows the public key that others need for sending packages,
+// or offers to generate the identity if the file has none yet
+pub fn show_identity(
+    o_public_key: Option<&str>,
+    error: Option<&str>,
+    controller: &mut Controller,
+    ctx: &Context,
+) {
+    let modal_response = Modal::new("show_identity".into()).show(ctx, |ui| {
+        ui.set_width(MODAL_WIDTH);
+
+        ui.horizontal(|ui| {
+            ui.vertical(|ui| {
+                ui.set_width(140.);
+                ui.set_height(140.);
+                ui.add_space(50.);
+                ui.label(RichText::new("🔑").font(FontId::new(128., FontFamily::Proportional)));
+            });
+            ui.vertical(|ui| {
+                ui.add_space(50.);
+                ui.label(RichText::new(t!("_identity")).size(24.));
+                ui.add_space(15.);
+                if let Some(public_key) = o_public_key {
+                    ui.label(t!("_identity_hint"));
+                    ui.add_space(20.);
+                    ui.label(
+                        RichText::new(public_key)
+                            .font(FontId::new(14., FontFamily::Monospace))
+                            .strong(),
+                    );
+                    ui.add_space(20.);
+                    if ui.button(format!("📋 {}", t!("_copy").trim())).clicked() {
+                        ui.ctx().copy_text(public_key.to_string());
+                    }
+                } else {
+                    ui.label(t!("_identity_missing"));
+                    ui.add_space(20.);
+                    if ui
+                        .add(Button::new(
+                            RichText::new(t!("_identity_generate")).color(Color32::DARK_GREEN),
+                        ))
+                        .clicked()
+                    {
+                        controller.set_action(Action::GenerateIdentity);
+                    }
+                }
+
+                if let Some(e) = error {
+                    show_error(e, ui);
+                }
+            });
+        });
+
+        ui.add_space(15.);
+        ui.separator();
+
+        Sides::new().show(
+            ui,
+            |_ui| {},
+            |ui| {
+                if ui
+                    .button(RichText::new(t!("_ok_with_icon")).color(Color32::DARK_GREEN))
+                    .clicked()
+                {
+                    controller.set_action(Action::CloseModal);
+                }
+            },
+        );
+    });
+    if modal_response.should_close() {
+        controller.set_action(Action::CloseModal);
+    }
+}
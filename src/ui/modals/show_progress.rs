@@ -0,0 +1,36 @@
+use crate::ui::controller::Job;
+use egui::{Context, Modal, RichText, Spinner};
+
+pub fn show_progress(job: Job, ctx: &Context) {
+    Modal::new("show_progress".into()).show(ctx, |ui| {
+        ui.horizontal(|ui| {
+            ui.add_space(10.);
+            ui.add(Spinner::new().size(24.));
+            ui.add_space(10.);
+            ui.label(
+                RichText::new(match job {
+                    Job::Unlock | Job::ReadImport | Job::OpenMergeFile => t!("_job_unlock"),
+                    Job::Reload => t!("_job_reload"),
+                    Job::ChangePassword | Job::AddKeySlot | Job::RegenerateRecoveryCode => {
+                        t!("_job_change_password")
+                    }
+                    Job::Export | Job::ExportVault | Job::ExportPackage => t!("_job_export"),
+                    Job::SetConcealment
+                    | Job::RevokeKeySlot
+                    | Job::AddBundle
+                    | Job::ModifyBundle
+                    | Job::DeleteBundle
+                    | Job::AddDocument
+                    | Job::ModifyDocument
+                    | Job::DeleteDocument
+                    | Job::ResolveConflict
+                    | Job::Import
+                    | Job::GenerateIdentity
+                    | Job::MergeFile => t!("_job_save"),
+                })
+                .size(16.),
+            );
+            ui.add_space(10.);
+        });
+    });
+}
@@ -0,0 +1,87 @@
+use crate::ui::{
+    controller::{Action, Controller},
+    show_error,
+    sizes::MODAL_WIDTH,
+};
+use egui::{Color32, Context, FontFamily, FontId, Modal, RichText, Sides};
+
+// Is shown only once, right after the recovery code was generated
+pub fn show_recovery_code(
+    recovery_code: &str,
+    error: &mut Option<String>,
+    controller: &mut Controller,
+    ctx: &Context,
+) {
+    Modal::new("show_recovery_code".into()).show(ctx, |ui| {
+        ui.set_width(MODAL_WIDTH);
+
+        ui.horizontal(|ui| {
+            ui.vertical(|ui| {
+                ui.set_width(140.);
+                ui.set_height(140.);
+                ui.add_space(50.);
+                ui.label(RichText::new("🛟").font(FontId::new(128., FontFamily::Proportional)));
+            });
+            ui.vertical(|ui| {
+                ui.add_space(50.);
+                ui.label(RichText::new(t!("_recovery_code")).size(24.));
+                ui.add_space(15.);
+                ui.label(t!("_recovery_code_hint"));
+                ui.add_space(20.);
+                ui.label(
+                    RichText::new(recovery_code)
+                        .font(FontId::new(22., FontFamily::Monospace))
+                        .strong(),
+                );
+                ui.add_space(20.);
+
+                ui.horizontal(|ui| {
+                    if ui.button(format!("📋 {}", t!("_copy").trim())).clicked() {
+                        ui.ctx().copy_text(recovery_code.to_string());
+                    }
+                    if ui
+                        .button(format!("💾 {}…", t!("_save_recovery_code")))
+                        .clicked()
+                    {
+                        *error = save_to_file(recovery_code).err();
+                    }
+                });
+
+                if let Some(e) = error {
+                    show_error(e, ui);
+                }
+            });
+        });
+
+        ui.add_space(15.);
+        ui.separator();
+
+        Sides::new().show(
+            ui,
+            |_ui| {},
+            |ui| {
+                if ui
+                    .button(RichText::new(t!("_recovery_code_noted")).color(Color32::DARK_GREEN))
+                    .clicked()
+                {
+                    controller.set_action(Action::CloseModal);
+                }
+            },
+        );
+    });
+}
+
+// writes a text file, e.g. for printing
+fn save_to_file(recovery_code: &str) -> Result<(), String> {
+    if let Some(path) = rfd::FileDialog::new()
+        .set_file_name("prolock_recovery_code.txt")
+        .save_file()
+    {
+        std::fs::write(
+            &path,
+            format!("{}\n\n{recovery_code}\n", t!("_recovery_code_file_header")),
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
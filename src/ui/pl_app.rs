@@ -1,12 +1,14 @@
 use crate::{
     data::{PlFile, Settings},
     ui::{
-        controller::Controller,
+        controller::{Controller, FILE_WATCH_INTERVAL},
         main_ui::main_ui,
         modals::{
             ask_for_password_to_open, change_file, change_language, change_password,
             configure_password_generation, create_bundle, create_document, delete_bundle,
-            delete_document, show_about, show_log,
+            delete_document, export_json, export_package, export_vault, import, manage_key_slots,
+            merge_file, recover_temp_file, resolve_conflict, restore_backup, show_about,
+            show_identity, show_log, show_progress, show_recovery_code,
         },
         top_panel::top_panel,
         viz::{ModalState, V},
@@ -25,11 +27,17 @@ pub struct PlApp {
     logger_handle: LoggerHandle,
 }
 impl PlApp {
-    pub fn new(logger_handle: LoggerHandle, settings: Settings) -> Result<Self> {
+    pub fn new(
+        logger_handle: LoggerHandle,
+        settings: Settings,
+        o_key_file: Option<String>,
+    ) -> Result<Self> {
         let mut v = V::default();
+        v.pw.key_file = o_key_file.unwrap_or_default();
         v.file_selection.reset(settings.current_file);
-        let pl_file =
+        let mut pl_file =
             PlFile::read_or_create(settings.current_file()).context("PlFile open error")?;
+        pl_file.set_backup_generations(settings.backup_generations);
         log::info!("{} {}", t!("Starting with file"), pl_file.file_path());
         Ok(PlApp {
             pl_file,
@@ -39,18 +47,9 @@ impl PlApp {
             logger_handle,
         })
     }
-}
 
-impl App for PlApp {
-    fn update(&mut self, ctx: &Context, _frame: &mut Frame) {
-        // execute actions
-        self.controller
-            .act(&mut self.pl_file, &mut self.v, &mut self.settings);
-
-        // render the UI
-        top_panel(&self.pl_file, &mut self.v, &mut self.controller, ctx);
-
-        // show modal if desired
+    #[allow(clippy::too_many_lines)]
+    fn show_modal(&mut self, ctx: &Context) {
         match self.v.modal_state {
             ModalState::None => {}
 
@@ -88,6 +87,49 @@ impl App for PlApp {
             ModalState::ChangePassword => {
                 change_password(&mut self.v.pw, &mut self.controller, ctx);
             }
+            ModalState::ShowRecoveryCode {
+                ref recovery_code,
+                ref mut error,
+            } => {
+                show_recovery_code(recovery_code, error, &mut self.controller, ctx);
+            }
+            ModalState::RecoverTempFile { ref error } => {
+                if let Some(leftover) = self.pl_file.leftover_temp_file() {
+                    recover_temp_file(
+                        leftover,
+                        self.pl_file.update_counter().peek(),
+                        error.as_deref(),
+                        &mut self.controller,
+                        ctx,
+                    );
+                }
+            }
+            ModalState::ResolveConflict {
+                ref conflict,
+                ref mine,
+                ref mut show_secrets,
+                ref error,
+            } => {
+                resolve_conflict(
+                    conflict,
+                    mine,
+                    show_secrets,
+                    error.as_deref(),
+                    &mut self.controller,
+                    ctx,
+                );
+            }
+            ModalState::RestoreBackup => {
+                restore_backup(&mut self.v.backups, &mut self.controller, ctx);
+            }
+            ModalState::ManageKeySlots => {
+                manage_key_slots(
+                    &self.pl_file,
+                    &mut self.v.key_slots,
+                    &mut self.controller,
+                    ctx,
+                );
+            }
             ModalState::ChangeFile => {
                 change_file(
                     &mut self.settings,
@@ -102,7 +144,54 @@ impl App for PlApp {
             ModalState::GeneratePassword => {
                 configure_password_generation(&mut self.v.generate_pw, &mut self.controller, ctx);
             }
+            ModalState::Import => {
+                import(&mut self.v.import, &mut self.controller, ctx);
+            }
+            ModalState::ExportJson => {
+                export_json(&mut self.v.export, &mut self.controller, ctx);
+            }
+            ModalState::MergeFile => {
+                merge_file(&mut self.v.merge_file, &mut self.controller, ctx);
+            }
+            ModalState::ExportPackage => {
+                export_package(
+                    &mut self.v.export_package,
+                    &self.pl_file,
+                    &mut self.controller,
+                    ctx,
+                );
+            }
+            ModalState::ShowIdentity { ref error } => {
+                show_identity(
+                    self.pl_file.public_key().as_deref(),
+                    error.as_deref(),
+                    &mut self.controller,
+                    ctx,
+                );
+            }
+            ModalState::ExportVault => {
+                export_vault(
+                    &mut self.v.export_vault,
+                    &self.pl_file,
+                    &mut self.controller,
+                    ctx,
+                );
+            }
         }
+    }
+}
+
+impl App for PlApp {
+    fn update(&mut self, ctx: &Context, _frame: &mut Frame) {
+        // execute actions
+        self.controller
+            .act(&mut self.pl_file, &mut self.v, &mut self.settings);
+
+        // render the UI
+        top_panel(&self.pl_file, &mut self.v, &mut self.controller, ctx);
+
+        // show modal if desired
+        self.show_modal(ctx);
 
         // show the log
         if self.v.show_log {
@@ -114,6 +203,11 @@ impl App for PlApp {
             );
         }
 
+        // show the progress of a background job on top of everything
+        if let Some(job) = self.v.running_job {
+            show_progress(job, ctx);
+        }
+
         // show the main UI
         if let Some(transient) = self.pl_file.transient() {
             main_ui(
@@ -126,7 +220,18 @@ impl App for PlApp {
             );
         } else {
             let is_first_start = self.pl_file.update_counter().peek() == Some(0);
-            ask_for_password_to_open(is_first_start, &mut self.v, &mut self.controller, ctx);
+            ask_for_password_to_open(
+                is_first_start,
+                self.pl_file.requires_key_file(),
+                &mut self.v,
+                &mut self.controller,
+                ctx,
+            );
         }
+
+        // look for changes by others
+        self.controller
+            .watch_file(self.settings.current_file(), &self.v);
+        ctx.request_repaint_after(FILE_WATCH_INTERVAL);
     }
 }
@@ -4,7 +4,7 @@ use crate::{
         IMG_BURGER, IMG_LOGO, LIGHT_GRAY, VERY_LIGHT_GRAY,
         assets::IMG_CHANGE_FILE,
         controller::{Action, Controller},
-        viz::V,
+        viz::{ImportSource, V},
     },
 };
 use egui::{Button, Color32, Context, FontFamily, Image, MenuBar, RichText, TopBottomPanel};
@@ -53,6 +53,22 @@ pub fn top_panel(pl_file: &PlFile, v: &mut V, controller: &mut Controller, ctx:
                 });
         });
         ui.add_space(2.);
+
+        // changes by others can only be taken over after the local edits are done
+        if v.file_changed_externally {
+            ui.horizontal(|ui| {
+                ui.add_space(20.);
+                ui.label(RichText::new(t!("_file_changed_externally")).color(Color32::DARK_RED));
+                if ui
+                    .button(t!("_reload_file"))
+                    .on_hover_text(t!("_reload_file_hint"))
+                    .clicked()
+                {
+                    controller.set_action(Action::ReloadFile);
+                }
+            });
+            ui.add_space(2.);
+        }
     });
 }
 
@@ -98,13 +114,16 @@ fn burger_menu_button(pl_file: &PlFile, v: &mut V, controller: &mut Controller,
             if ui
                 .add_enabled(
                     pl_file.is_actionable() && v.modal_state.is_ready_for_modal(),
-                    Button::new(format!("🔐 {}…", t!("Change password"))),
+                    Button::new(format!("🗄 {}…", t!("_restore_from_backup"))),
                 )
                 .clicked()
             {
-                controller.set_action(Action::StartChangePassword);
+                controller.set_action(Action::StartRestoreBackup);
             }
 
+            transfer_menu_items(pl_file, v, controller, ui);
+            security_menu_items(pl_file, v, controller, ui);
+
             if ui
                 .add(Button::new(format!("📄 {}", t!("Show log"))))
                 .clicked()
@@ -122,3 +141,139 @@ fn burger_menu_button(pl_file: &PlFile, v: &mut V, controller: &mut Controller,
         });
     });
 }
+
+// the menu items that move entries and documents into or out of the file
+fn transfer_menu_items(pl_file: &PlFile, v: &V, controller: &mut Controller, ui: &mut egui::Ui) {
+    for (source, text) in [
+        (ImportSource::Csv, t!("_import_csv")),
+        (ImportSource::KeePass, t!("_import_keepass")),
+        (ImportSource::Json, t!("_import_json")),
+        (ImportSource::Package, t!("_import_package")),
+    ] {
+        if ui
+            .add_enabled(
+                pl_file.is_actionable() && v.modal_state.is_ready_for_modal(),
+                Button::new(format!("📥 {text}…")),
+            )
+            .clicked()
+        {
+            controller.set_action(Action::StartImport(source));
+        }
+    }
+    if ui
+        .add_enabled(
+            pl_file.is_actionable() && v.modal_state.is_ready_for_modal(),
+            Button::new(format!("📤 {}…", t!("_export_json"))),
+        )
+        .clicked()
+    {
+        controller.set_action(Action::StartExportJson);
+    }
+    if ui
+        .add_enabled(
+            pl_file.is_actionable() && v.modal_state.is_ready_for_modal(),
+            Button::new(format!("📤 {}…", t!("_export_vault"))),
+        )
+        .clicked()
+    {
+        controller.set_action(Action::StartExportVault);
+    }
+    if ui
+        .add_enabled(
+            pl_file.is_actionable() && v.modal_state.is_ready_for_modal(),
+            Button::new(format!("📤 {}…", t!("_export_package"))),
+        )
+        .clicked()
+    {
+        controller.set_action(Action::StartExportPackage);
+    }
+    if ui
+        .add_enabled(
+            pl_file.is_actionable() && v.modal_state.is_ready_for_modal(),
+            Button::new(format!("🔑 {}…", t!("_identity"))),
+        )
+        .clicked()
+    {
+        controller.set_action(Action::StartShowIdentity);
+    }
+    if ui
+        .add_enabled(
+            pl_file.is_actionable() && v.modal_state.is_ready_for_modal(),
+            Button::new(format!("🔀 {}…", t!("_merge_file"))),
+        )
+        .clicked()
+    {
+        controller.set_action(Action::StartMergeFile);
+    }
+}
+
+// the menu items that deal with the protection of the file
+fn security_menu_items(pl_file: &PlFile, v: &V, controller: &mut Controller, ui: &mut egui::Ui) {
+    if ui
+        .add_enabled(
+            pl_file.is_actionable() && v.modal_state.is_ready_for_modal(),
+            Button::new(format!("🔐 {}…", t!("Change password"))),
+        )
+        .clicked()
+    {
+        controller.set_action(Action::StartChangePassword);
+    }
+
+    if ui
+        .add_enabled(
+            pl_file.is_actionable() && v.modal_state.is_ready_for_modal(),
+            Button::new(format!("🔑 {}…", t!("_key_slots"))),
+        )
+        .clicked()
+    {
+        controller.set_action(Action::StartManageKeySlots);
+    }
+
+    if ui
+        .add_enabled(
+            pl_file.is_actionable() && v.modal_state.is_ready_for_modal(),
+            Button::new(if pl_file.has_recovery_code() {
+                format!("🛟 {}", t!("_regenerate_recovery_code"))
+            } else {
+                format!("🛟 {}", t!("_generate_recovery_code"))
+            }),
+        )
+        .on_hover_ui(|ui| {
+            ui.label(t!("_regenerate_recovery_code_hint"));
+        })
+        .clicked()
+    {
+        controller.set_action(Action::RegenerateRecoveryCode);
+    }
+
+    if ui
+        .add_enabled(
+            pl_file.is_actionable() && v.modal_state.is_ready_for_modal(),
+            Button::new(if pl_file.is_concealed() {
+                format!("👁 {}", t!("_reveal_names_in_file"))
+            } else {
+                format!("🕶 {}", t!("_conceal_names_in_file"))
+            }),
+        )
+        .on_hover_ui(|ui| {
+            ui.label(t!("_conceal_names_in_file_hint"));
+        })
+        .clicked()
+    {
+        controller.set_action(Action::SetConcealment(!pl_file.is_concealed()));
+    }
+
+    if cfg!(unix)
+        && ui
+            .add_enabled(
+                pl_file.is_actionable() && v.modal_state.is_ready_for_modal(),
+                Button::new(format!("⌨ {}", t!("_register_with_agent"))),
+            )
+            .on_hover_ui(|ui| {
+                ui.label(t!("_register_with_agent_hint"));
+            })
+            .clicked()
+    {
+        controller.set_action(Action::RegisterWithAgent);
+    }
+}
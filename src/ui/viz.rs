@@ -1,29 +1,73 @@
 use crate::{
     DEFAULT_LANGUAGE, SUPPORTED_LANGUAGES,
-    data::{Bundle, Bundles, Cred, Document, Documents, Key, Secret, Transient},
+    data::{
+        Backup, Bundle, Bundles, Comparison, Cred, Document, Documents, Import, Key, MergeConflict,
+        OnConflict, PlFile, Plan, Secret, Totp, Transient, Version,
+    },
+    import::{CsvMapping, CsvTable},
+    ui::controller::Job,
 };
 use flexi_logger::Snapshot;
 use fuzzy_matcher::clangd::fuzzy_match;
-use std::{collections::BTreeMap, time::Instant};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
 
 #[derive(Default)]
 pub struct V {
     pub main_state: MainState,
     pub modal_state: ModalState,
     pub show_log: bool,
+    // is shown on top of everything else, and blocks further input
+    pub running_job: Option<Job>,
 
     pub bundles: BTreeMap<Key, VBundle>,
     pub documents: BTreeMap<Key, VDocument>,
 
     pub file_selection: FileSelection,
+    pub backups: VBackups,
+    pub import: VImport,
+    pub export: VExport,
+    pub export_vault: VExportVault,
+    pub export_package: VExportPackage,
+    pub merge_file: VMergeFile,
     pub pw: Pw,
+    pub key_slots: VKeySlots,
     pub find: Find,
     pub lang: Lang,
 
     pub generate_pw: VGeneratePassword,
     pub logger_snapshot: Snapshot,
+
+    // the file was updated by someone else while local edits were pending
+    pub file_changed_externally: bool,
 }
 impl V {
+    pub fn has_pending_edits(&self) -> bool {
+        !(self.main_state.tabs_and_create_ok() && self.modal_state.no_modal_is_open())
+    }
+
+    // Discards all pending edits, e.g. after the file was reloaded
+    pub fn stop_editing(&mut self, documents: &Documents) {
+        self.modal_state.close_modal();
+        self.main_state = match &self.main_state {
+            MainState::Bundles(_) => MainState::Bundles(BundleState::Default),
+            MainState::Documents(
+                DocumentState::Default(Some(key))
+                | DocumentState::ModifyDocument {
+                    v_edit_document: VEditDocument { orig_key: key, .. },
+                    ..
+                },
+            ) if documents.contains_key(key) => {
+                MainState::Documents(DocumentState::Default(Some(key.clone())))
+            }
+            MainState::Documents(_) => MainState::Documents(DocumentState::Default(None)),
+        };
+    }
+
     pub fn reset_bundles(&mut self, bundles: &Bundles, o_scroll_to: Option<&Key>) {
         self.bundles = bundles
             .iter()
@@ -153,9 +197,32 @@ pub enum ModalState {
     },
     About,
     ChangePassword,
+    ManageKeySlots,
+    RestoreBackup,
+    RecoverTempFile {
+        error: Option<String>,
+    },
+    ShowRecoveryCode {
+        recovery_code: String,
+        error: Option<String>,
+    },
+    ResolveConflict {
+        conflict: MergeConflict,
+        mine: Version,
+        show_secrets: bool,
+        error: Option<String>,
+    },
     ChangeFile,
     ChangeLanguage,
     GeneratePassword,
+    Import,
+    ExportJson,
+    ExportVault,
+    ExportPackage,
+    MergeFile,
+    ShowIdentity {
+        error: Option<String>,
+    },
 }
 impl Default for MainState {
     fn default() -> Self {
@@ -181,9 +248,20 @@ impl ModalState {
             Self::DeleteDocument { .. } => "ModalState::DeleteDocument".to_string(),
             Self::About => "ModalState::About".to_string(),
             Self::ChangePassword => "ModalState::ChangePassword".to_string(),
+            Self::ManageKeySlots => "ModalState::ManageKeySlots".to_string(),
+            Self::RestoreBackup => "ModalState::RestoreBackup".to_string(),
+            Self::RecoverTempFile { .. } => "ModalState::RecoverTempFile".to_string(),
+            Self::ShowRecoveryCode { .. } => "ModalState::ShowRecoveryCode".to_string(),
+            Self::ResolveConflict { .. } => "ModalState::ResolveConflict".to_string(),
             Self::ChangeFile => "ModalState::ChangeFile".to_string(),
             Self::ChangeLanguage => "ModalState::ChangeLanguage".to_string(),
             Self::GeneratePassword => "ModalState::GeneratePassword".to_string(),
+            Self::Import => "ModalState::Import".to_string(),
+            Self::ExportJson => "ModalState::ExportJson".to_string(),
+            Self::ExportVault => "ModalState::ExportVault".to_string(),
+            Self::ExportPackage => "ModalState::ExportPackage".to_string(),
+            Self::MergeFile => "ModalState::MergeFile".to_string(),
+            Self::ShowIdentity { .. } => "ModalState::ShowIdentity".to_string(),
         }
     }
 }
@@ -223,15 +301,26 @@ impl Lang {
 // - plain PW entry
 // - initial PW entry (twice)
 // - changing the PW (old, and twice new)
+// key_file is the path of the optional key file, or empty
 #[derive(Default)]
 pub struct Pw {
     pub pw1: String,
     pub pw2: String,
     pub pw3: String,
+    pub key_file: String,
+    // pw1 contains the recovery code
+    pub with_recovery_code: bool,
     pub error: Option<String>,
     pub focus: PwFocus,
 }
 
+// Is used for adding a key slot; its password is entered twice (pw1 and pw2)
+#[derive(Default)]
+pub struct VKeySlots {
+    pub label: String,
+    pub pw: Pw,
+}
+
 #[derive(Default)]
 pub enum PwFocus {
     None,
@@ -247,6 +336,102 @@ pub struct FileSelection {
     pub current: usize,
     pub new: String,
 }
+// The backups are read when the modal is opened
+#[derive(Default)]
+pub struct VBackups {
+    pub backups: Vec<Backup>,
+    pub selected: Option<usize>,
+    pub generations: usize,
+    pub error: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ImportSource {
+    #[default]
+    Csv,
+    KeePass,
+    Json,
+    Package,
+}
+
+// A CSV file is read when it is chosen, the preview is updated with every change
+// of the mapping; a KeePass file is decrypted in a background job, which hands over
+// its result in `decrypted`
+#[derive(Default)]
+pub struct VImport {
+    pub source: ImportSource,
+    pub path: String,
+    pub table: CsvTable,
+    pub mapping: CsvMapping,
+    pub needs_password: bool,
+    pub password: String,
+    pub key_file: String,
+    pub decrypted: Arc<Mutex<Option<Import>>>,
+    pub on_conflict: OnConflict,
+    pub import: Import,
+    pub plan: Plan,
+    pub error: Option<String>,
+}
+impl VImport {
+    pub fn new(source: ImportSource) -> Self {
+        Self {
+            source,
+            ..Self::default()
+        }
+    }
+
+    pub fn update_preview(&mut self, pl_file: &PlFile) {
+        if self.source == ImportSource::Csv {
+            self.import = self.mapping.apply(&self.table);
+        }
+        self.plan = pl_file.plan_import(&self.import, self.on_conflict);
+    }
+}
+
+// The password has to be entered again before the secrets are written in plain text
+#[derive(Default)]
+pub struct VExport {
+    pub path: String,
+    pub password: String,
+    pub error: Option<String>,
+}
+
+// The selection for a new file, and its password (pw1, pw2) and key file;
+// the background job that creates the file hands over its recovery code in `recovery_code`
+#[derive(Default)]
+pub struct VExportVault {
+    pub bundles: BTreeSet<Key>,
+    pub documents: BTreeSet<Key>,
+    pub path: String,
+    pub pw: Pw,
+    pub register: bool,
+    pub recovery_code: Arc<Mutex<Option<String>>>,
+}
+
+// The selection for a package, and the public keys of its recipients, one per line
+#[derive(Default)]
+pub struct VExportPackage {
+    pub bundles: BTreeSet<Key>,
+    pub documents: BTreeSet<Key>,
+    pub recipients: String,
+    pub path: String,
+    pub error: Option<String>,
+}
+
+// The other file is opened with its password (pw1) and key file in a background job,
+// which hands it over in `opened`; then it is compared with the own file,
+// and the selected entries and documents are taken over
+#[derive(Default)]
+pub struct VMergeFile {
+    pub path: String,
+    pub pw: Pw,
+    pub opened: Arc<Mutex<Option<PlFile>>>,
+    pub theirs: Option<PlFile>,
+    pub comparison: Comparison,
+    pub bundles: BTreeSet<Key>,
+    pub documents: BTreeSet<Key>,
+}
+
 impl FileSelection {
     pub fn reset(&mut self, current: usize) {
         self.error = None;
@@ -285,6 +470,7 @@ pub struct VCred {
     pub copied_at: Option<Instant>,
 }
 
+#[derive(Clone)]
 pub struct VEditBundle {
     pub orig_key: Key,
     pub key: Key,
@@ -338,6 +524,17 @@ impl VEditBundle {
         result
     }
 
+    // Stores the seed from the QR code as secret of the cred; an empty name becomes the label
+    pub fn read_totp_qr_image(&mut self, cred_idx: usize, path: &Path) -> anyhow::Result<()> {
+        let totp = Totp::from_qr_image(path)?;
+        let v_edit_cred = &mut self.v_edit_creds[cred_idx];
+        if v_edit_cred.name.trim().is_empty() {
+            v_edit_cred.name.clone_from(&totp.label);
+        }
+        v_edit_cred.secret = totp.to_uri();
+        Ok(())
+    }
+
     pub fn as_oldkey_newkey_bundle(&self, transient: &mut Transient) -> (Key, Key, Bundle) {
         (
             Key::new(self.orig_key.to_string()),
@@ -359,7 +556,7 @@ impl VEditBundle {
     }
 }
 
-#[derive(Default)]
+#[derive(Clone, Default)]
 pub struct VEditDocument {
     pub orig_key: Key,
     pub key: Key,
@@ -1,2 +1,4 @@
 mod generate_password;
+mod private_file;
 pub use generate_password::generate_password;
+pub use private_file::write_private_file;
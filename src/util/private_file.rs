@@ -0,0 +1,17 @@
+use anyhow::{Context, Result};
+use std::{fs::OpenOptions, io::Write, path::Path};
+
+// The file is only readable and writable for the user, also if it existed before
+pub fn write_private_file(path: &Path, content: &str) -> Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options
+        .open(path)
+        .context(format!("opening {}", path.display()))?;
+    #[cfg(unix)]
+    file.set_permissions(std::os::unix::fs::PermissionsExt::from_mode(0o600))?;
+    file.write_all(content.as_bytes())?;
+    Ok(())
+}